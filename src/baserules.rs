@@ -21,11 +21,28 @@
  *  (C) Copyright 2022-3, Gabor Kecskemeti
  */
 pub mod board;
+pub mod board_diff;
 pub mod board_rep;
 pub mod castling;
+pub mod chess960;
+#[cfg(test)]
+pub mod differential;
+pub mod evaluation;
+pub mod game;
+pub mod material;
+pub mod mobility;
 pub mod move_gen;
+pub mod move_list;
+#[cfg(feature = "nnue")]
+pub mod nnue;
+pub mod pawn_structure;
+pub mod perft;
 pub mod piece_color;
 pub mod piece_kind;
+pub mod piece_square;
 pub mod piece_state;
 pub mod positions;
 pub mod rawboard;
+pub mod score;
+pub mod validation;
+pub mod zobrist;
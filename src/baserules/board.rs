@@ -24,21 +24,51 @@
 extern crate rand;
 
 use crate::baserules::board_rep::PossibleMove;
+use crate::baserules::evaluation::{evaluate, Evaluator, Imbalance};
+#[cfg(feature = "nnue")]
+use crate::baserules::nnue;
 use crate::baserules::piece_color::PieceColor;
 use crate::baserules::piece_color::PieceColor::{Black, White};
 use crate::baserules::piece_kind::PieceKind::{King, Pawn, Rook};
 use crate::baserules::piece_state::PieceState;
 use crate::baserules::rawboard::RawBoard;
+use crate::baserules::score::Score;
 use std::ops::Deref;
 
-use crate::baserules::castling::Castling;
+use crate::baserules::castling::{Castling, CastlingFiles};
 use crate::baserules::positions::AbsoluteBoardPos;
+use crate::baserules::zobrist;
 use crate::util::TryWithPanic;
+use enum_map::EnumMap;
 use enumset::EnumSet;
 
 use super::move_gen::{KingMove, CASTLE_ALLOWED, CASTLE_FORBIDDEN};
 
+/// The half moves without a pawn move or a capture that draw the game
+pub const FIFTY_MOVE_RULE: u16 = 100;
+
+/// What a move made in place changed besides the squares of the moved pieces, to take it back
+pub struct UndoState {
+    the_move: PossibleMove,
+    /// The piece that moved, before its promotion
+    moved: PieceState,
+    /// The captured piece with its square, which is not the target of the en passant captures
+    captured: Option<(AbsoluteBoardPos, PieceState)>,
+    castling: EnumSet<Castling>,
+    king_move_gen: &'static dyn KingMove,
+    ep: Option<AbsoluteBoardPos>,
+    half_moves_since_pawn: u16,
+    score: Score,
+    material: Score,
+    kings: EnumMap<PieceColor, Option<AbsoluteBoardPos>>,
+    in_check: bool,
+    zobrist: u64,
+    #[cfg(feature = "nnue")]
+    accumulator: Option<Box<nnue::Accumulator>>,
+}
+
 /// The internal representation of the chessboard after a given move.
+#[derive(Clone)]
 pub struct PSBoard {
     /// The actual board with the 8x8 squares
     pub raw: RawBoard,
@@ -46,16 +76,29 @@ pub struct PSBoard {
     pub who_moves: PieceColor,
     /// Tells what kind of castling is allowed
     pub castling: EnumSet<Castling>,
+    /// Where the king and the rooks castle from, the corners but in Chess960
+    pub(crate) castling_files: CastlingFiles,
     /// move resolver
     pub(crate) king_move_gen: &'static dyn KingMove,
     /// Tells if there is an en-passant move possible at the given location
     pub ep: Option<AbsoluteBoardPos>,
-    /// The number of moves done so far
+    /// The number of the move, starting from 1 and incremented after the moves of black
     pub move_count: u16,
     /// allows draw condition check
     pub half_moves_since_pawn: u16,
     /// The estimated score of this board, without considering its possible continuations
-    pub score: f32,
+    pub score: Score,
+    /// White's material minus black's, maintained incrementally as moves are made
+    pub(crate) material: Score,
+    /// The squares of the kings, maintained as moves are made
+    pub(crate) kings: EnumMap<PieceColor, Option<AbsoluteBoardPos>>,
+    /// Tells if the king of the side to move is attacked
+    pub(crate) in_check: bool,
+    /// The zobrist hash of the position, maintained incrementally as moves are made
+    pub(crate) zobrist: u64,
+    /// The hidden layers of the network of the evaluation, when it evaluates with one
+    #[cfg(feature = "nnue")]
+    pub(crate) accumulator: Option<Box<nnue::Accumulator>>,
 }
 
 //rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1
@@ -86,19 +129,30 @@ impl Default for PSBoard {
     /// ```
     /// use dbce::baserules::board::PSBoard;
     /// let starting_position = PSBoard::default();
-    /// assert_eq!("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 0", starting_position.to_fen());
+    /// assert_eq!("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", starting_position.to_fen());
     /// ```
     fn default() -> Self {
-        PSBoard {
-            raw: RawBoard::default(),
+        let raw = RawBoard::default();
+        let castling = EnumSet::ALL;
+        let mut board = PSBoard {
+            zobrist: zobrist::full_key(&raw, White, castling, None),
+            raw,
             who_moves: White,
-            castling: EnumSet::ALL,
+            castling,
+            castling_files: CastlingFiles::STANDARD,
             ep: None,
-            move_count: 0,
+            move_count: 1,
             half_moves_since_pawn: 0,
-            score: 0f32,
+            score: Score::DRAW,
+            material: raw.material(),
+            kings: raw.find_kings(),
+            in_check: false,
             king_move_gen: &CASTLE_ALLOWED,
-        }
+            #[cfg(feature = "nnue")]
+            accumulator: None,
+        };
+        board.in_check = board.king_attacked();
+        board
     }
 }
 
@@ -120,63 +174,245 @@ impl PSBoard {
     /// assert_eq!("r1bqkbnr/pppp1ppp/2n5/1B2p3/4P3/5N2/PPPP1PPP/RNBQK2R b KQkq - 3 3", ruy_lopez.to_fen());
     /// ```
     pub async fn make_move_noncached(&self, the_move: &PossibleMove) -> Self {
-        let mut raw_board = self.raw;
-        // The move for almost all the cases
-        let piece_before_move = self[the_move.the_move.from];
-        let piece_before_unwrapped = piece_before_move.as_ref().unwrap();
-        let piece_potentially_taken = self[the_move.the_move.to];
-        if let Some(ep) = &self.ep {
-            if piece_before_unwrapped.kind == Pawn && ep == &the_move.the_move.to {
-                // En passant was done, the long move pawn was taken
-                raw_board.set_loc(
-                    (the_move.the_move.from.0, the_move.the_move.to.1).transform(),
-                    &None,
-                );
+        self.make_move_with(the_move, &Imbalance).await
+    }
+
+    /// Makes the move like `make_move_noncached`, scoring the new board with the evaluator
+    pub async fn make_move_with(&self, the_move: &PossibleMove, evaluator: &dyn Evaluator) -> Self {
+        let mut board = self.copy_position();
+        board.apply_move(the_move);
+        #[cfg(feature = "nnue")]
+        {
+            board.accumulator = nnue::next_accumulator(
+                evaluator,
+                self.accumulator
+                    .as_deref()
+                    .map(|parent| (parent, &self.raw)),
+                &board.raw,
+            );
+        }
+        board.score = evaluate(&board, evaluator);
+        board
+    }
+
+    /// Makes the move on this board instead of a new one, the searches not keeping the boards
+    /// after the moves can take it back with `unmake_move` instead of copying the board.
+    /// Like with `make_move_noncached`, the move is not checked for validity.
+    /// # Panics
+    /// If there is a request to make a move for a piece that does not exist on the board
+    pub fn make_move_in_place(&mut self, the_move: &PossibleMove) -> UndoState {
+        self.make_move_in_place_with(the_move, &Imbalance)
+    }
+
+    /// Makes the move in place like `make_move_in_place`, scoring the board with the evaluator
+    pub fn make_move_in_place_with(
+        &mut self,
+        the_move: &PossibleMove,
+        evaluator: &dyn Evaluator,
+    ) -> UndoState {
+        #[cfg(feature = "nnue")]
+        let before = self.raw;
+        let undo = self.apply_move(the_move);
+        #[cfg(feature = "nnue")]
+        let undo = {
+            let parent = self.accumulator.take();
+            self.accumulator = nnue::next_accumulator(
+                evaluator,
+                parent.as_deref().map(|parent| (parent, &before)),
+                &self.raw,
+            );
+            UndoState {
+                accumulator: parent,
+                ..undo
             }
+        };
+        self.score = evaluate(self, evaluator);
+        undo
+    }
+
+    /// Scores the board again with the evaluator, for boards created before the engine got it
+    pub fn rescore(&mut self, evaluator: &dyn Evaluator) {
+        #[cfg(feature = "nnue")]
+        {
+            self.accumulator = nnue::next_accumulator(evaluator, None, &self.raw);
+        }
+        self.score = evaluate(self, evaluator);
+    }
+
+    /// Takes back the move made with `make_move_in_place`, restoring the board as it was before
+    pub fn unmake_move(&mut self, undo: UndoState) {
+        let the_move = undo.the_move;
+        self.raw.clear_loc(the_move.the_move.to);
+        if let Some(rook_move) = &the_move.rook {
+            // The king is already lifted, in Chess960 the rook may go back to its square
+            let rook = self.raw[rook_move.to];
+            self.raw.clear_loc(rook_move.to);
+            self.raw.set_loc(rook_move.from, &rook);
+        }
+        self.raw.set_loc(the_move.the_move.from, &Some(undo.moved));
+        if let Some((position, piece)) = undo.captured {
+            self.raw.set_loc(position, &Some(piece));
+        }
+        self.who_moves = undo.moved.color;
+        self.move_count -= u16::from(undo.moved.color == Black);
+        self.castling = undo.castling;
+        self.king_move_gen = undo.king_move_gen;
+        self.ep = undo.ep;
+        self.half_moves_since_pawn = undo.half_moves_since_pawn;
+        self.score = undo.score;
+        self.material = undo.material;
+        self.kings = undo.kings;
+        self.in_check = undo.in_check;
+        self.zobrist = undo.zobrist;
+        #[cfg(feature = "nnue")]
+        {
+            self.accumulator = undo.accumulator;
+        }
+    }
+
+    /// A copy of the position, the hidden layers of the network are left for the board after the
+    /// move to compute from the ones of this board
+    fn copy_position(&self) -> Self {
+        PSBoard {
+            #[cfg(feature = "nnue")]
+            accumulator: None,
+            ..*self
+        }
+    }
+
+    /// Tells if the move would leave the king of the side to move attacked, by trying it on a
+    /// copy of the position that is not evaluated
+    pub(crate) fn leaves_king_attacked(&self, the_move: &PossibleMove) -> bool {
+        let mut board = self.copy_position();
+        board.apply_move(the_move);
+        board.can_capture_king()
+    }
+
+    /// Moves the pieces and updates the state of the game and the hash, the score and the hidden
+    /// layers of the network are left to the callers
+    pub(crate) fn apply_move(&mut self, the_move: &PossibleMove) -> UndoState {
+        let (from, to) = (the_move.the_move.from, the_move.the_move.to);
+        // The move for almost all the cases
+        let piece_before_move = self[from];
+        let moved = piece_before_move.unwrap();
+        // The king castling in Chess960 may land on its own rook, which is not taken
+        let piece_potentially_taken = self[to].filter(|_| the_move.rook.is_none());
+        let mut captured = piece_potentially_taken.map(|piece| (to, piece));
+        let mut hash = self.zobrist
+            ^ zobrist::piece_key(&piece_before_move, from)
+            ^ zobrist::piece_key(&piece_potentially_taken, to);
+        if moved.kind == Pawn && self.ep == Some(to) {
+            // En passant was done, the long move pawn was taken
+            let taken_pos = (from.0, to.1).transform();
+            hash ^= zobrist::piece_key(&self[taken_pos], taken_pos);
+            captured = self[taken_pos].map(|piece| (taken_pos, piece));
+            self.raw.clear_loc(taken_pos);
         }
         //Changing pieces if we need to convert a pawn to something
         let current_piece_opt = the_move
             .pawn_promotion
             .map_or(&piece_before_move, |promotion| {
-                piece_before_unwrapped.pawn_promote(promotion)
+                moved.pawn_promote(promotion)
             });
-        raw_board.make_move_with(&the_move.the_move, current_piece_opt);
+        if let Some(rook_move) = &the_move.rook {
+            // when we are castling, the rook move is also stored. Both pieces are lifted before
+            // they are put down, in Chess960 they may land on each other's squares
+            let rook = self[rook_move.from];
+            self.raw.clear_loc(from);
+            self.raw.clear_loc(rook_move.from);
+            self.raw.set_loc(to, current_piece_opt);
+            self.raw.set_loc(rook_move.to, &rook);
+            hash ^=
+                zobrist::piece_key(&rook, rook_move.from) ^ zobrist::piece_key(&rook, rook_move.to);
+        } else {
+            self.raw
+                .make_move_with(&the_move.the_move, current_piece_opt);
+        }
+        hash ^= zobrist::piece_key(current_piece_opt, to);
 
         let current_piece = current_piece_opt.as_ref().unwrap();
-        if let Some(rook_move) = &the_move.rook {
-            // when we are castling, the rook move is also stored
-            raw_board.make_move_with(rook_move, &self[rook_move.from]);
+        let mut material = self.material;
+        let mut kings = self.kings;
+        if moved.kind == King {
+            kings[moved.color] = Some(to);
+        }
+        if let Some((_, taken)) = captured {
+            if taken.kind == King {
+                kings[taken.color] = None;
+            }
+            material =
+                material - Score::centipawns(taken.color.mate_multiplier() * taken.kind.value());
+        }
+        if let Some(promotion) = the_move.pawn_promotion {
+            let gain = promotion.value() - Pawn.value();
+            material = material + Score::centipawns(moved.color.mate_multiplier() * gain);
         }
         let (castling, king_move_gen) =
             self.determine_castling_rights(current_piece, the_move, &piece_potentially_taken);
-        PSBoard {
-            score: raw_board.score().await,
-            raw: raw_board,
-            who_moves: current_piece.color.invert(),
-            ep: if current_piece.kind == Pawn
-                && (the_move.the_move.from.0 as i8 - the_move.the_move.to.0 as i8).abs() == 2
-            {
-                Some(
-                    (
-                        (the_move.the_move.from.0 + the_move.the_move.to.0) >> 1,
-                        the_move.the_move.to.1,
-                    )
-                        .transform(),
-                )
-            } else {
-                None
-            },
-            castling,
-            king_move_gen,
-            half_moves_since_pawn: if let Pawn = piece_before_unwrapped.kind {
-                0
-            } else if piece_potentially_taken.is_some() {
-                0
-            } else {
-                self.half_moves_since_pawn + 1
-            },
-            move_count: self.move_count + u16::from(current_piece.color == Black),
-        }
+        let ep = if current_piece.kind == Pawn && (from.0 as i8 - to.0 as i8).abs() == 2 {
+            Some(((from.0 + to.0) >> 1, to.1).transform())
+        } else {
+            None
+        };
+        let who_moves = current_piece.color.invert();
+        hash ^= zobrist::who_moves_key(self.who_moves)
+            ^ zobrist::who_moves_key(who_moves)
+            ^ zobrist::castling_key(self.castling)
+            ^ zobrist::castling_key(castling)
+            ^ zobrist::ep_key(self.ep)
+            ^ zobrist::ep_key(ep);
+        let undo = UndoState {
+            the_move: *the_move,
+            moved,
+            captured,
+            castling: self.castling,
+            king_move_gen: self.king_move_gen,
+            ep: self.ep,
+            half_moves_since_pawn: self.half_moves_since_pawn,
+            score: self.score,
+            material: self.material,
+            kings: self.kings,
+            in_check: self.in_check,
+            zobrist: self.zobrist,
+            #[cfg(feature = "nnue")]
+            accumulator: None,
+        };
+        self.who_moves = who_moves;
+        self.ep = ep;
+        self.castling = castling;
+        self.king_move_gen = king_move_gen;
+        self.half_moves_since_pawn = if moved.kind == Pawn || piece_potentially_taken.is_some() {
+            0
+        } else {
+            self.half_moves_since_pawn + 1
+        };
+        self.move_count += u16::from(current_piece.color == Black);
+        self.zobrist = hash;
+        self.material = material;
+        self.kings = kings;
+        self.in_check = self.king_attacked();
+        debug_assert_eq!(self.raw.material(), self.material);
+        debug_assert_eq!(self.raw.find_kings(), self.kings);
+        undo
+    }
+
+    /// White's material minus black's, by the usual values of the pieces, without recounting the
+    /// pieces on the board
+    #[inline]
+    pub fn material(&self) -> Score {
+        self.material
+    }
+
+    /// The half moves made since the last pawn move or capture
+    #[inline]
+    pub fn halfmove_clock(&self) -> u16 {
+        self.half_moves_since_pawn
+    }
+
+    /// Tells if the game is drawn by the fifty-move rule. The last of those moves could still
+    /// checkmate, so the boards in check are left for the searches to decide.
+    pub fn is_fifty_move_draw(&self) -> bool {
+        self.half_moves_since_pawn >= FIFTY_MOVE_RULE && !self.is_in_check()
     }
 
     fn determine_castling_rights(
@@ -191,30 +427,19 @@ impl PSBoard {
             let mut changed = false;
             if current_piece.kind == King {
                 changed = true;
-                new_castling ^= current_piece.color.all_castling();
-            } else if current_piece.kind == Rook {
-                for a_castling_side in current_piece.color.all_castling() {
-                    let the_castling_move: &PossibleMove = a_castling_side.into();
-                    if the_castling_move.rook.unwrap().from == the_move.the_move.from {
-                        changed = true;
-                        new_castling ^= a_castling_side;
-                    }
-                }
+                new_castling -= current_piece.color.all_castling();
             }
-            if (the_move.the_move.to.0 == 0 || the_move.the_move.to.0 == 7)
-                && (the_move.the_move.to.1 == 0 || the_move.the_move.to.1 == 7)
-            {
-                if let Some(taken) = possible_capture {
-                    if taken.kind == Rook {
-                        let opponent_color = current_piece.color.invert();
-                        if the_move.the_move.to.1 == 0 {
-                            changed = true;
-                            new_castling ^= opponent_color.queen_side_castling();
-                        } else {
-                            changed = true;
-                            new_castling ^= opponent_color.king_side_castling();
-                        }
-                    }
+            for a_castling in self.castling {
+                let rook_square = self.castling_move(a_castling).rook.unwrap().from;
+                // Moving the rook or taking it on its starting square ends the castling
+                let rook_moved = current_piece.kind == Rook
+                    && current_piece.color == a_castling.color()
+                    && the_move.the_move.from == rook_square;
+                let rook_taken = possible_capture.is_some_and(|taken| taken.kind == Rook)
+                    && the_move.the_move.to == rook_square;
+                if rook_moved || rook_taken {
+                    changed = true;
+                    new_castling -= a_castling;
                 }
             }
             if changed {
@@ -240,6 +465,7 @@ mod test {
     use crate::baserules::piece_color::PieceColor::White;
     use crate::baserules::piece_kind::PieceKind::{King, Queen, Rook};
     use crate::baserules::piece_state::PieceState;
+    use crate::baserules::score::Score;
     use crate::util::TryWithPanic;
     use enumset::enum_set;
 
@@ -353,4 +579,136 @@ mod test {
             after_move["f1"].unwrap()
         );
     }
+
+    #[tokio::test]
+    async fn lost_castling_rights_stay_lost() {
+        let board = PSBoard::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w Kk - 0 1")
+            .await
+            .unwrap();
+        let play = |uci| {
+            let board = &board;
+            async move {
+                board
+                    .make_move_noncached(&PossibleMove::simple_from_uci(uci).unwrap())
+                    .await
+                    .to_fen()
+            }
+        };
+        assert_eq!("r3k2r/8/8/8/8/8/8/1R2K2R b Kk - 1 1", play("a1b1").await);
+        assert_eq!("r3k2r/8/8/8/8/8/8/R4K1R b k - 1 1", play("e1f1").await);
+        // The rook taken on a8 was not the one castling on the king side
+        assert_eq!("R3k2r/8/8/8/8/8/8/4K2R b Kk - 0 1", play("a1a8").await);
+    }
+
+    #[tokio::test]
+    async fn fifty_move_rule() {
+        let shuffling = PSBoard::from_fen("4k3/8/8/8/8/8/4P3/R3K3 w - - 99 80")
+            .await
+            .unwrap();
+        assert_eq!(99, shuffling.halfmove_clock());
+        assert!(!shuffling.is_fifty_move_draw());
+        let play = |uci| {
+            let board = &shuffling;
+            async move {
+                board
+                    .make_move_noncached(&PossibleMove::simple_from_uci(uci).unwrap())
+                    .await
+            }
+        };
+        let drawn = play("a1a7").await;
+        assert_eq!(100, drawn.halfmove_clock());
+        assert!(drawn.is_fifty_move_draw());
+        // The king could be mated, it is up to the next move
+        assert!(!play("a1a8").await.is_fifty_move_draw());
+        assert_eq!(0, play("e2e4").await.halfmove_clock());
+    }
+
+    #[tokio::test]
+    async fn moves_made_in_place_are_taken_back() {
+        let castle = PossibleMove {
+            the_move: BaseMove::from_uci("e1g1").unwrap(),
+            pawn_promotion: None,
+            rook: Some(BaseMove::from_uci("h1f1").unwrap()),
+        };
+        let cases = [
+            (
+                "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+                "e5f6",
+            ),
+            ("r3k2r/1P6/8/8/8/8/8/R3K2R w KQkq - 0 1", "b7a8q"),
+            ("r3k2r/1P6/8/8/8/8/8/R3K2R w KQkq - 0 1", "a1a8"),
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                "g1f3",
+            ),
+        ];
+        for (fen, uci) in cases {
+            let mut board = PSBoard::from_fen(fen).await.unwrap();
+            let a_move = PossibleMove::simple_from_uci(uci).unwrap();
+            let expected = board.make_move_noncached(&a_move).await;
+            let undo = board.make_move_in_place(&a_move);
+            assert_eq!(expected.to_fen(), board.to_fen());
+            assert_eq!(expected.zobrist(), board.zobrist());
+            assert_eq!(expected.score, board.score);
+            board.unmake_move(undo);
+            assert_eq!(fen, board.to_fen());
+            assert_eq!(
+                PSBoard::from_fen(fen).await.unwrap().zobrist(),
+                board.zobrist()
+            );
+        }
+        let fen = "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 3 10";
+        let mut board = PSBoard::from_fen(fen).await.unwrap();
+        let undo = board.make_move_in_place(&castle);
+        assert_eq!("r3k2r/8/8/8/8/8/8/R4RK1 b kq - 4 10", board.to_fen());
+        board.unmake_move(undo);
+        assert_eq!(fen, board.to_fen());
+    }
+
+    #[tokio::test]
+    async fn material_follows_the_captures_and_promotions() {
+        let cases = [
+            (
+                "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+                "e5f6",
+                100,
+            ),
+            ("r3k2r/1P6/8/8/8/8/8/R3K2R w KQkq - 0 1", "b7a8q", 1300),
+            ("r3k2r/1P6/8/8/8/8/8/R3K2R w KQkq - 0 1", "b7b8n", 200),
+            ("4k3/8/8/8/8/8/1p6/R3K3 b - - 0 1", "b2a1r", -900),
+        ];
+        for (fen, uci, change) in cases {
+            let mut board = PSBoard::from_fen(fen).await.unwrap();
+            let before = board.material();
+            let a_move = board.find_uci_move(uci).unwrap();
+            let undo = board.make_move_in_place(&a_move);
+            assert_eq!(
+                Score::centipawns(change),
+                board.material() - before,
+                "{uci}"
+            );
+            assert_eq!(board.raw.material(), board.material());
+            board.unmake_move(undo);
+            assert_eq!(before, board.material());
+        }
+    }
+
+    #[tokio::test]
+    async fn kings_and_check_follow_the_moves() {
+        let fen = "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1";
+        let mut board = PSBoard::from_fen(fen).await.unwrap();
+        assert!(!board.is_in_check());
+        let castle = board.find_uci_move("e1c1").unwrap();
+        let undo = board.make_move_in_place(&castle);
+        assert_eq!(Some((0, 2).transform()), board.king_position(White));
+        let check = board.find_uci_move("a8a1").unwrap();
+        let check_undo = board.make_move_in_place(&check);
+        assert!(board.is_in_check());
+        assert_eq!(board.king_attacked(), board.is_in_check());
+        board.unmake_move(check_undo);
+        assert!(!board.is_in_check());
+        board.unmake_move(undo);
+        assert_eq!(Some((0, 4).transform()), board.king_position(White));
+        assert_eq!(board.raw.find_kings(), board.kings);
+    }
 }
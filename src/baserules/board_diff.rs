@@ -0,0 +1,176 @@
+/*
+ *  ========================================================================
+ *  DBCE chess bot, differences between boards
+ *  ========================================================================
+ *
+ *  This file is part of DBCE.
+ *
+ *  DBCE is free software: you can redistribute it and/or
+ *  modify it under the terms of the GNU General Public License as published
+ *  by the Free Software Foundation, either version 3 of the License, or (at
+ *  your option) any later version.
+ *
+ *  DBCE is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ *  General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License along
+ *  with DBCE.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ *  (C) Copyright 2022-3, Gabor Kecskemeti
+ */
+use crate::baserules::board::PSBoard;
+use crate::baserules::castling::Castling;
+use crate::baserules::piece_state::PieceState;
+use crate::baserules::positions::AbsoluteBoardPos;
+use enumset::EnumSet;
+use std::fmt::{Display, Formatter};
+
+/// A square whose content differs between two boards
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct SquareChange {
+    pub pos: AbsoluteBoardPos,
+    pub before: Option<PieceState>,
+    pub after: Option<PieceState>,
+}
+
+/// The differences between two boards, see `PSBoard::diff`. The move counters and the score are
+/// not compared, as they do not change how the position looks or plays.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct BoardDiff {
+    /// The changed squares in a1, b1, .., h8 order
+    pub squares: Vec<SquareChange>,
+    /// Castling rights present only on the first board
+    pub castling_lost: EnumSet<Castling>,
+    /// Castling rights present only on the second board
+    pub castling_gained: EnumSet<Castling>,
+    /// The en passant squares of the two boards, if they differ
+    pub ep: Option<(Option<AbsoluteBoardPos>, Option<AbsoluteBoardPos>)>,
+    pub side_to_move_changed: bool,
+}
+
+impl BoardDiff {
+    /// Tells if the two boards represent the same position
+    pub fn is_empty(&self) -> bool {
+        self.squares.is_empty()
+            && self.castling_lost.is_empty()
+            && self.castling_gained.is_empty()
+            && self.ep.is_none()
+            && !self.side_to_move_changed
+    }
+
+    /// The positions of the changed squares
+    pub fn changed_squares(&self) -> impl Iterator<Item = AbsoluteBoardPos> + '_ {
+        self.squares.iter().map(|change| change.pos)
+    }
+}
+
+fn piece_or_empty(piece: &Option<PieceState>) -> String {
+    piece.map_or("-".to_string(), |piece| piece.to_string())
+}
+
+impl Display for BoardDiff {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.is_empty() {
+            return f.write_str("no difference");
+        }
+        let mut parts: Vec<String> = self
+            .squares
+            .iter()
+            .map(|change| {
+                format!(
+                    "{}: {} -> {}",
+                    change.pos,
+                    piece_or_empty(&change.before),
+                    piece_or_empty(&change.after)
+                )
+            })
+            .collect();
+        if !self.castling_lost.is_empty() {
+            parts.push(format!("castling lost: {:?}", self.castling_lost));
+        }
+        if !self.castling_gained.is_empty() {
+            parts.push(format!("castling gained: {:?}", self.castling_gained));
+        }
+        if let Some((before, after)) = self.ep {
+            let ep_or_empty =
+                |ep: Option<AbsoluteBoardPos>| ep.map_or("-".to_string(), |pos| pos.to_string());
+            parts.push(format!(
+                "en passant: {} -> {}",
+                ep_or_empty(before),
+                ep_or_empty(after)
+            ));
+        }
+        if self.side_to_move_changed {
+            parts.push("side to move changed".to_string());
+        }
+        f.write_str(&parts.join(", "))
+    }
+}
+
+impl PSBoard {
+    /// Lists what changed between this board and the other one
+    ///
+    /// # Example
+    /// ```
+    /// use dbce::baserules::board::PSBoard;
+    /// let board = PSBoard::default();
+    /// assert!(board.diff(&PSBoard::default()).is_empty());
+    /// ```
+    pub fn diff(&self, other: &PSBoard) -> BoardDiff {
+        let squares = (0..8u8)
+            .flat_map(|row| (0..8u8).map(move |col| AbsoluteBoardPos(row, col)))
+            .filter(|pos| self[*pos] != other[*pos])
+            .map(|pos| SquareChange {
+                pos,
+                before: self[pos],
+                after: other[pos],
+            })
+            .collect();
+        BoardDiff {
+            squares,
+            castling_lost: self.castling - other.castling,
+            castling_gained: other.castling - self.castling,
+            ep: (self.ep != other.ep).then_some((self.ep, other.ep)),
+            side_to_move_changed: self.who_moves != other.who_moves,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::baserules::board::PSBoard;
+    use crate::baserules::board_rep::PossibleMove;
+    use crate::baserules::castling::Castling::*;
+    use crate::baserules::positions::AbsoluteBoardPos;
+    use tokio::test;
+
+    #[test]
+    async fn diff_after_moves() {
+        let start = PSBoard::default();
+        let after = start
+            .make_move_noncached(&PossibleMove::simple_from_uci("e2e4").unwrap())
+            .await;
+        let diff = start.diff(&after);
+        assert_eq!(
+            vec![AbsoluteBoardPos(1, 4), AbsoluteBoardPos(3, 4)],
+            diff.changed_squares().collect::<Vec<_>>()
+        );
+        assert_eq!(Some((None, Some(AbsoluteBoardPos(2, 4)))), diff.ep);
+        assert!(diff.side_to_move_changed);
+        assert!(diff.castling_lost.is_empty());
+
+        let rooks = PSBoard::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1")
+            .await
+            .unwrap();
+        let rook_moved = rooks
+            .make_move_noncached(&PossibleMove::simple_from_uci("a1a2").unwrap())
+            .await;
+        let diff = rooks.diff(&rook_moved);
+        assert_eq!(WhiteQueenSide, diff.castling_lost.iter().next().unwrap());
+        assert_eq!(1, diff.castling_lost.len());
+        assert_eq!(diff.castling_lost, rook_moved.diff(&rooks).castling_gained);
+        assert_eq!(2, diff.squares.len());
+    }
+}
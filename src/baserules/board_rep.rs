@@ -21,8 +21,10 @@
  *  (C) Copyright 2022-3, Gabor Kecskemeti
  */
 
+use crate::baserules::castling::castling_move;
 use crate::baserules::piece_kind::PieceKind;
-use crate::baserules::positions::AbsoluteBoardPos;
+use crate::baserules::piece_kind::PieceKind::{Bishop, Knight, Queen, Rook};
+use crate::baserules::positions::{AbsoluteBoardPos, Square};
 use crate::util::{AnyError, IntResult, TryWithPanic};
 use std::fmt::{Debug, Display, Formatter};
 
@@ -158,11 +160,83 @@ impl Display for PossibleMove {
     }
 }
 
+/// A 16 bit encoding of `PossibleMove` for the places where lots of moves are stored.
+/// The bits from the lowest: 6 bits for the from square, 6 bits for the to square,
+/// 2 bits for the promoted piece kind and 2 bits of flags telling about promotions and castling.
+/// The castling is packed as the king taking its rook, which tells the moves of both pieces in
+/// Chess960 too.
+#[derive(Copy, Clone, Eq, Hash, PartialEq, Default, Debug)]
+pub struct PackedMove(pub u16);
+
+const PACKED_PROMOTION: u16 = 1 << 14;
+const PACKED_CASTLING: u16 = 2 << 14;
+static PACKED_PROMOTION_KINDS: [PieceKind; 4] = [Knight, Bishop, Rook, Queen];
+
+impl PackedMove {
+    #[inline]
+    fn pack_pos(pos: AbsoluteBoardPos) -> u16 {
+        u16::from(u8::from(Square::from(pos)))
+    }
+
+    #[inline]
+    fn unpack_pos(bits: u16) -> AbsoluteBoardPos {
+        Square::from_bits(bits).into()
+    }
+
+    #[inline]
+    pub fn base_move(self) -> BaseMove {
+        BaseMove::from_two_pos(Self::unpack_pos(self.0), Self::unpack_pos(self.0 >> 6))
+    }
+}
+
+impl From<&PossibleMove> for PackedMove {
+    /// # Example
+    /// ```
+    /// use dbce::baserules::board_rep::{PackedMove, PossibleMove};
+    /// let a_move = PossibleMove::simple_from_uci("g1f3").unwrap();
+    /// let packed: PackedMove = (&a_move).into();
+    /// assert_eq!(a_move, packed.into());
+    /// ```
+    fn from(a_move: &PossibleMove) -> Self {
+        let to = a_move
+            .rook
+            .map_or(a_move.the_move.to, |rook_move| rook_move.from);
+        let squares = Self::pack_pos(a_move.the_move.from) | Self::pack_pos(to) << 6;
+        let flags = if let Some(promotion) = a_move.pawn_promotion {
+            let kind_idx = PACKED_PROMOTION_KINDS
+                .iter()
+                .position(|kind| *kind == promotion)
+                .unwrap() as u16;
+            PACKED_PROMOTION | kind_idx << 12
+        } else if a_move.rook.is_some() {
+            PACKED_CASTLING
+        } else {
+            0
+        };
+        PackedMove(squares | flags)
+    }
+}
+
+impl From<PackedMove> for PossibleMove {
+    fn from(packed: PackedMove) -> Self {
+        let the_move = packed.base_move();
+        match packed.0 & (0b11 << 14) {
+            PACKED_PROMOTION => PossibleMove {
+                the_move,
+                pawn_promotion: Some(PACKED_PROMOTION_KINDS[((packed.0 >> 12) & 0b11) as usize]),
+                rook: None,
+            },
+            PACKED_CASTLING => castling_move(the_move.from, the_move.to),
+            _ => the_move.into(),
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
-    use crate::baserules::board_rep::{BaseMove, PossibleMove};
+    use crate::baserules::board_rep::{BaseMove, PackedMove, PossibleMove};
     use crate::baserules::castling::Castling;
-    use crate::baserules::piece_kind::PieceKind::Queen;
+    use crate::baserules::piece_kind::PieceKind::{Knight, Queen};
     use crate::baserules::positions::AbsoluteBoardPos;
 
     #[test]
@@ -198,4 +272,21 @@ mod test {
         assert_eq!("e8g8", format!("{black_short_castles}"));
         assert_eq!("g8f6", format!("{knight_moves}"));
     }
+
+    #[test]
+    fn packed_round_trip() {
+        let under_promotion = PossibleMove {
+            the_move: BaseMove::from_uci("g2h1").unwrap(),
+            pawn_promotion: Some(Knight),
+            rook: None,
+        };
+        let white_long_castles: &PossibleMove = Castling::WhiteQueenSide.into();
+        let rook_lift = PossibleMove::simple_from_uci("h8h6").unwrap();
+        for a_move in [under_promotion, *white_long_castles, rook_lift] {
+            let packed: PackedMove = (&a_move).into();
+            assert_eq!(a_move, packed.into());
+        }
+        let packed_lift: PackedMove = (&rook_lift).into();
+        assert_eq!(rook_lift.the_move, packed_lift.base_move());
+    }
 }
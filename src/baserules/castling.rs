@@ -1,12 +1,15 @@
+use crate::baserules::board::PSBoard;
 use crate::baserules::board_rep::{BaseMove, PossibleMove};
 use crate::baserules::castling::Castling::{
     BlackKingSide, BlackQueenSide, WhiteKingSide, WhiteQueenSide,
 };
 use crate::baserules::piece_color::PieceColor;
+use crate::baserules::piece_color::PieceColor::{Black, White};
 use crate::baserules::piece_kind::PieceKind::{King, Queen};
 use crate::baserules::piece_state::PieceState;
-use crate::util::{AnyError, IntResult, TryWithPanic};
-use enum_map::{enum_map, Enum, EnumMap};
+use crate::baserules::positions::AbsoluteBoardPos;
+use crate::util::{AnyError, IntResult};
+use enum_map::{Enum, EnumMap};
 use enumset::{enum_set, EnumSet, EnumSetType};
 use lazy_static::lazy_static;
 
@@ -27,7 +30,21 @@ impl Castling {
             BlackQueenSide => 'q',
         }
     }
-    
+
+    #[inline]
+    pub fn color(&self) -> PieceColor {
+        if white_can_castle().contains(*self) {
+            White
+        } else {
+            Black
+        }
+    }
+
+    #[inline]
+    pub fn is_king_side(&self) -> bool {
+        kingside_castle().contains(*self)
+    }
+
     pub fn from_notation(notation: &str, color: PieceColor) -> IntResult<Castling> {
         let castle_type = notation.split('-').count();
         let piece_rep = match castle_type {
@@ -37,48 +54,149 @@ impl Castling {
         };
         format!("{piece_rep}").chars().next().unwrap().try_into()
     }
-    
-    pub fn move_via_king_move(&self, king_move: BaseMove) -> Option<PossibleMove> {
-        let castling_move: &PossibleMove = (*self).into();
-        if king_move == castling_move.the_move {
-            Some(*castling_move)
+}
+
+/// The castling of the king with the rook on the given squares of the same rank. Whichever
+/// files they start from, the king ends up on the g or the c file and the rook next to it, on
+/// the f or the d file, as in standard chess.
+pub(crate) fn castling_move(king: AbsoluteBoardPos, rook: AbsoluteBoardPos) -> PossibleMove {
+    let (king_to, rook_to) = if rook.1 > king.1 { (6, 5) } else { (2, 3) };
+    PossibleMove {
+        the_move: BaseMove::from_two_pos(king, AbsoluteBoardPos(king.0, king_to)),
+        pawn_promotion: None,
+        rook: Some(BaseMove::from_two_pos(
+            rook,
+            AbsoluteBoardPos(rook.0, rook_to),
+        )),
+    }
+}
+
+/// The files the king and the rooks castle from. Standard chess starts them on the e file and in
+/// the corners, Chess960 on any files with the king between the rooks. In Chess960 the king may
+/// not even move when castling, so the uci notation writes its castling as the king taking its
+/// own rook.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct CastlingFiles {
+    pub king: u8,
+    pub king_side_rook: u8,
+    pub queen_side_rook: u8,
+    /// Castling is written as the king taking its rook, as Chess960 games are
+    pub chess960: bool,
+}
+
+impl CastlingFiles {
+    pub const STANDARD: CastlingFiles = CastlingFiles {
+        king: 4,
+        king_side_rook: 7,
+        queen_side_rook: 0,
+        chess960: false,
+    };
+
+    #[inline]
+    pub fn rook(&self, castling: Castling) -> u8 {
+        if castling.is_king_side() {
+            self.king_side_rook
         } else {
-            None
+            self.queen_side_rook
         }
     }
+
+    /// The king and the rook move of the castling with these files
+    ///
+    /// # Example
+    /// ```
+    /// use dbce::baserules::castling::{Castling, CastlingFiles};
+    /// let files = CastlingFiles {king: 1, king_side_rook: 2, queen_side_rook: 0, chess960: true};
+    /// let castling = files.castling_move(Castling::WhiteKingSide);
+    /// assert_eq!("b1g1", castling.to_string());
+    /// assert_eq!("c1f1", castling.rook.unwrap().to_string());
+    /// ```
+    pub fn castling_move(&self, castling: Castling) -> PossibleMove {
+        let row = castling.color().piece_row();
+        castling_move(
+            AbsoluteBoardPos(row, self.king),
+            AbsoluteBoardPos(row, self.rook(castling)),
+        )
+    }
+}
+
+impl Default for CastlingFiles {
+    fn default() -> Self {
+        Self::STANDARD
+    }
 }
 
 lazy_static! {
-    static ref CASTLING_MOVE_MAP: EnumMap<Castling, PossibleMove> = enum_map! {
-        WhiteKingSide => PossibleMove {
-            the_move: "e1g1".transform(),
-            pawn_promotion: None,
-            rook: Some("h1f1".transform()),
-        },
-        WhiteQueenSide => PossibleMove {
-            the_move: "e1c1".transform(),
-            pawn_promotion: None,
-            rook: Some("a1d1".transform()),
-        },
-        BlackKingSide => PossibleMove {
-            the_move: "e8g8".transform(),
-            pawn_promotion: None,
-            rook: Some("h8f8".transform()),
-        },
-        BlackQueenSide => PossibleMove {
-            the_move: "e8c8".transform(),
-            pawn_promotion: None,
-            rook: Some("a8d8".transform()),
-        },
-    };
+    static ref CASTLING_MOVE_MAP: EnumMap<Castling, PossibleMove> =
+        EnumMap::from_fn(|castling| CastlingFiles::STANDARD.castling_move(castling));
 }
 
+/// The castling moves of standard chess, the boards know their moves for Chess960 as well
 impl From<Castling> for &PossibleMove {
     fn from(value: Castling) -> Self {
         &CASTLING_MOVE_MAP[value]
     }
 }
 
+impl PSBoard {
+    #[inline]
+    pub fn castling_files(&self) -> CastlingFiles {
+        self.castling_files
+    }
+
+    /// Writes the castling of the game as the king taking its rook, as the Chess960 games do,
+    /// even when the pieces start from the squares of standard chess
+    pub fn set_chess960(&mut self, chess960: bool) {
+        self.castling_files.chess960 = chess960;
+    }
+
+    /// The king and the rook move of the castling, from the files of the game
+    #[inline]
+    pub fn castling_move(&self, castling: Castling) -> PossibleMove {
+        self.castling_files.castling_move(castling)
+    }
+
+    /// The castling of the side to move written with the uci move of its king. Chess960 writes it
+    /// as the king taking its rook, standard chess as the king moving two squares.
+    pub fn castling_of_king_move(&self, king_move: BaseMove) -> Option<PossibleMove> {
+        self.castling
+            .iter()
+            .filter(|castling| castling.color() == self.who_moves)
+            .map(|castling| self.castling_move(castling))
+            .find(|castling_move| {
+                if self.castling_files.chess960 {
+                    king_move
+                        == BaseMove::from_two_pos(
+                            castling_move.the_move.from,
+                            castling_move.rook.unwrap().from,
+                        )
+                } else {
+                    king_move == castling_move.the_move
+                }
+            })
+    }
+
+    /// The uci notation of the move, which writes the castling of Chess960 games as the king
+    /// taking its rook
+    ///
+    /// # Example
+    /// ```
+    /// use dbce::baserules::board::PSBoard;
+    /// let mut board = PSBoard::try_from_fen("4k3/8/8/8/8/8/8/4K2R w K - 0 1").unwrap();
+    /// let castling = board.find_uci_move("e1g1").unwrap();
+    /// board.set_chess960(true);
+    /// assert_eq!("e1h1", board.uci(&castling));
+    /// ```
+    pub fn uci(&self, a_move: &PossibleMove) -> String {
+        match a_move.rook {
+            Some(rook_move) if self.castling_files.chess960 => {
+                BaseMove::from_two_pos(a_move.the_move.from, rook_move.from).to_string()
+            }
+            _ => a_move.to_string(),
+        }
+    }
+}
+
 impl TryFrom<char> for Castling {
     type Error = AnyError;
 
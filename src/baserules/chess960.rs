@@ -0,0 +1,170 @@
+/*
+ *  ========================================================================
+ *  DBCE chess bot, the starting positions of Chess960
+ *  ========================================================================
+ *
+ *  This file is part of DBCE.
+ *
+ *  DBCE is free software: you can redistribute it and/or
+ *  modify it under the terms of the GNU General Public License as published
+ *  by the Free Software Foundation, either version 3 of the License, or (at
+ *  your option) any later version.
+ *
+ *  DBCE is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ *  General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License along
+ *  with DBCE.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ *  (C) Copyright 2022-3, Gabor Kecskemeti
+ */
+use crate::baserules::board::PSBoard;
+use rand::Rng;
+
+/// The number of the Chess960 starting positions
+pub const CHESS960_POSITIONS: u16 = 960;
+
+/// The number of the starting position of standard chess amongst them
+pub const STANDARD_POSITION: u16 = 518;
+
+/// The two squares of the knights amongst the five left empty by the bishops and the queen
+const KNIGHT_SQUARES: [(usize, usize); 10] = [
+    (0, 1),
+    (0, 2),
+    (0, 3),
+    (0, 4),
+    (1, 2),
+    (1, 3),
+    (1, 4),
+    (2, 3),
+    (2, 4),
+    (3, 4),
+];
+
+/// The white pieces of the first rank of the starting position, by the numbering of Scharnagl:
+/// the bishops on a light and a dark square, the queen and the knights on the empty squares
+/// left, and the king between the rooks on the last three
+fn back_rank(number: u16) -> [char; 8] {
+    let mut rank = [' '; 8];
+    let place = |rank: &mut [char; 8], nth: u16, piece: char| {
+        let col = (0..8).filter(|col| rank[*col] == ' ').nth(nth.into());
+        rank[col.unwrap()] = piece;
+    };
+    let (number, light) = (number / 4, number % 4);
+    rank[usize::from(light) * 2 + 1] = 'B';
+    let (number, dark) = (number / 4, number % 4);
+    rank[usize::from(dark) * 2] = 'B';
+    let (knights, queen) = (number / 6, number % 6);
+    place(&mut rank, queen, 'Q');
+    let (first, second) = KNIGHT_SQUARES[usize::from(knights)];
+    // The second knight first, the first one is counted on the same empty squares then
+    place(&mut rank, second as u16, 'N');
+    place(&mut rank, first as u16, 'N');
+    for piece in ['R', 'K', 'R'] {
+        place(&mut rank, 0, piece);
+    }
+    rank
+}
+
+impl PSBoard {
+    /// The Chess960 starting position with the given number, 518 being the one of standard
+    /// chess. Its castling is written as the king taking its rook.
+    /// See also: <https://en.wikipedia.org/wiki/Fischer_random_chess_numbering_scheme>
+    /// # Panics
+    /// When the number is not below 960
+    ///
+    /// # Example
+    /// ```
+    /// use dbce::baserules::board::PSBoard;
+    /// let board = PSBoard::chess960(0);
+    /// assert_eq!("bbqnnrkr/pppppppp/8/8/8/8/PPPPPPPP/BBQNNRKR w KQkq - 0 1", board.to_fen());
+    /// assert!(board.to_shredder_fen().contains(" HFhf "));
+    /// ```
+    pub fn chess960(number: u16) -> PSBoard {
+        assert!(
+            number < CHESS960_POSITIONS,
+            "There is no Chess960 position {number}"
+        );
+        let white: String = back_rank(number).iter().collect();
+        let fen = format!(
+            "{}/pppppppp/8/8/8/8/PPPPPPPP/{white} w KQkq - 0 1",
+            white.to_lowercase()
+        );
+        let mut board = PSBoard::try_from_fen(&fen).unwrap();
+        board.set_chess960(true);
+        board
+    }
+
+    /// One of the Chess960 starting positions, each of them with the same chance
+    pub fn random_chess960() -> PSBoard {
+        PSBoard::chess960(rand::thread_rng().gen_range(0..CHESS960_POSITIONS))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::baserules::board::PSBoard;
+    use crate::baserules::board_rep::BaseMove;
+    use crate::baserules::chess960::{CHESS960_POSITIONS, STANDARD_POSITION};
+    use std::collections::HashSet;
+
+    #[test]
+    fn numbered_positions_are_all_different() {
+        let standard = PSBoard::chess960(STANDARD_POSITION);
+        assert_eq!(PSBoard::default().to_fen(), standard.to_fen());
+        assert!(standard.castling_files().chess960);
+        assert_eq!(
+            "rkrnnqbb/pppppppp/8/8/8/8/PPPPPPPP/RKRNNQBB w KQkq - 0 1",
+            PSBoard::chess960(959).to_fen()
+        );
+        let placements: HashSet<String> = (0..CHESS960_POSITIONS)
+            .map(|number| {
+                let board = PSBoard::chess960(number);
+                assert_eq!(Ok(()), board.validate());
+                board.to_fen()
+            })
+            .collect();
+        assert_eq!(960, placements.len());
+    }
+
+    #[test]
+    fn castling_lands_on_the_standard_squares() {
+        // The king on b1 and the rooks on a1 and e1
+        let mut board =
+            PSBoard::try_from_fen("rk2r3/pppppppp/8/8/8/8/PPPPPPPP/RK2R3 w KQkq - 0 1").unwrap();
+        let long = board.castling_of_king_move(BaseMove::from_uci("b1a1").unwrap());
+        let mut long_castled = board.clone();
+        long_castled.make_move_in_place(&long.unwrap());
+        assert_eq!(
+            "rk2r3/pppppppp/8/8/8/8/PPPPPPPP/2KRR3 b kq - 1 1",
+            long_castled.to_fen()
+        );
+        let short = board.find_uci_move("b1e1").unwrap();
+        assert_eq!("b1g1", short.to_string());
+        assert_eq!("b1e1", board.uci(&short));
+        let undo = board.make_move_in_place(&short);
+        assert_eq!(
+            "rk2r3/pppppppp/8/8/8/8/PPPPPPPP/R4RK1 b kq - 1 1",
+            board.to_fen()
+        );
+        board.unmake_move(undo);
+        assert_eq!(
+            "rk2r3/pppppppp/8/8/8/8/PPPPPPPP/RK2R3 w KQkq - 0 1",
+            board.to_fen()
+        );
+        // The king and the rook swapping their squares
+        let mut swap = PSBoard::chess960(3);
+        assert_eq!(
+            "bqnnrkrb/pppppppp/8/8/8/8/PPPPPPPP/BQNNRKRB w KQkq - 0 1",
+            swap.to_fen()
+        );
+        let castling = swap.find_uci_move("f1g1").unwrap();
+        swap.make_move_in_place(&castling);
+        assert_eq!(
+            "bqnnrkrb/pppppppp/8/8/8/8/PPPPPPPP/BQNNRRKB b kq - 1 1",
+            swap.to_fen()
+        );
+    }
+}
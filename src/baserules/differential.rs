@@ -0,0 +1,495 @@
+/*
+ *  ========================================================================
+ *  DBCE chess bot, differential testing of the move generation
+ *  ========================================================================
+ *
+ *  This file is part of DBCE.
+ *
+ *  DBCE is free software: you can redistribute it and/or
+ *  modify it under the terms of the GNU General Public License as published
+ *  by the Free Software Foundation, either version 3 of the License, or (at
+ *  your option) any later version.
+ *
+ *  DBCE is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ *  General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License along
+ *  with DBCE.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ *  (C) Copyright 2022-3, Gabor Kecskemeti
+ */
+use crate::baserules::board::PSBoard;
+use crate::baserules::move_list::MoveList;
+use crate::baserules::perft::{Divergence, DivergenceKind};
+use crate::util::IntResult;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::BTreeSet;
+
+/// The rules our move generation and our moves are compared against, working on FENs only
+pub trait MoveRules {
+    /// The uci form of the legal moves in the position
+    fn legal_moves(&self, fen: &str) -> IntResult<BTreeSet<String>>;
+    /// The position after the uci move
+    fn play(&self, fen: &str, uci: &str) -> IntResult<String>;
+}
+
+/// Plays random games from the position, comparing our legal moves, i.e. the potential moves
+/// not losing the king, and the positions after our moves with the reference in every position.
+/// Returns the first position where they differ with the moves leading to it from the start.
+pub async fn compare_random_playouts(
+    reference: &impl MoveRules,
+    start_fen: &str,
+    games: usize,
+    plies: usize,
+    seed: u64,
+) -> IntResult<Option<Divergence>> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    for _ in 0..games {
+        let mut board = PSBoard::from_fen(start_fen).await?;
+        let mut line = Vec::new();
+        for _ in 0..plies {
+            let fen = board.to_fen();
+            let mut potential = MoveList::new();
+            board.gen_potential_moves(&mut potential);
+            let mut ours = Vec::new();
+            for a_move in potential {
+                let next_board = board.make_move_noncached(&a_move).await;
+                if !next_board.can_capture_king() {
+                    ours.push((a_move.to_string(), next_board));
+                }
+            }
+            let our_moves: BTreeSet<_> = ours.iter().map(|(uci, _)| uci.clone()).collect();
+            let theirs = reference.legal_moves(&fen)?;
+            let kind = theirs
+                .difference(&our_moves)
+                .next()
+                .map(|m| DivergenceKind::MissingMove(m.clone()))
+                .or_else(|| {
+                    our_moves
+                        .difference(&theirs)
+                        .next()
+                        .map(|m| DivergenceKind::ExtraMove(m.clone()))
+                });
+            if let Some(kind) = kind {
+                return Ok(Some(Divergence { fen, line, kind }));
+            }
+            if ours.is_empty() {
+                break;
+            }
+            let (uci, next_board) = ours.swap_remove(rng.gen_range(0..ours.len()));
+            let expected = reference.play(&fen, &uci)?;
+            if next_board.to_fen() != expected {
+                let kind = DivergenceKind::WrongPosition {
+                    the_move: uci,
+                    expected,
+                };
+                return Ok(Some(Divergence { fen, line, kind }));
+            }
+            line.push(uci);
+            board = next_board;
+        }
+    }
+    Ok(None)
+}
+
+/// A straightforward implementation of the rules on an 8x8 array of FEN letters, sharing no code
+/// with our move generation. It is slow, but simple enough to be checked by reading it.
+pub struct NaiveRules;
+
+/// A position of the naive rules, the rows are counted from the first rank
+struct NaivePosition {
+    squares: [[Option<char>; 8]; 8],
+    white_moves: bool,
+    castling: BTreeSet<char>,
+    ep: Option<(i8, i8)>,
+    halfmove_clock: u16,
+    move_number: u16,
+}
+
+/// The move of a piece from square to square, with the promotion letter of the pawns
+type NaiveMove = ((i8, i8), (i8, i8), Option<char>);
+
+const KNIGHT_STEPS: [(i8, i8); 8] = [
+    (1, 2),
+    (2, 1),
+    (2, -1),
+    (1, -2),
+    (-1, -2),
+    (-2, -1),
+    (-2, 1),
+    (-1, 2),
+];
+const KING_STEPS: [(i8, i8); 8] = [
+    (1, 0),
+    (1, 1),
+    (0, 1),
+    (-1, 1),
+    (-1, 0),
+    (-1, -1),
+    (0, -1),
+    (1, -1),
+];
+const ROOK_STEPS: [(i8, i8); 4] = [(1, 0), (0, 1), (-1, 0), (0, -1)];
+const BISHOP_STEPS: [(i8, i8); 4] = [(1, 1), (1, -1), (-1, 1), (-1, -1)];
+
+fn on_board((row, col): (i8, i8)) -> bool {
+    (0..8).contains(&row) && (0..8).contains(&col)
+}
+
+fn square_name((row, col): (i8, i8)) -> String {
+    format!("{}{}", (b'a' + col as u8) as char, row + 1)
+}
+
+fn is_white(piece: char) -> bool {
+    piece.is_ascii_uppercase()
+}
+
+impl NaivePosition {
+    fn parse(fen: &str) -> IntResult<Self> {
+        let fields: Vec<_> = fen.split_ascii_whitespace().collect();
+        if fields.len() != 6 {
+            return Err(format!("Not a complete FEN: {fen}").into());
+        }
+        let mut squares = [[None; 8]; 8];
+        for (rank, pieces) in fields[0].split('/').enumerate() {
+            let mut col = 0;
+            for piece in pieces.chars() {
+                if let Some(empty) = piece.to_digit(10) {
+                    col += empty as usize;
+                } else {
+                    squares[7 - rank][col] = Some(piece);
+                    col += 1;
+                }
+            }
+        }
+        let ep = fields[3].as_bytes();
+        Ok(Self {
+            squares,
+            white_moves: fields[1] == "w",
+            castling: fields[2].chars().filter(|c| *c != '-').collect(),
+            ep: (ep.len() == 2).then(|| ((ep[1] - b'1') as i8, (ep[0] - b'a') as i8)),
+            halfmove_clock: fields[4].parse()?,
+            move_number: fields[5].parse()?,
+        })
+    }
+
+    fn to_fen(&self) -> String {
+        let ranks: Vec<String> = (0..8)
+            .rev()
+            .map(|row| {
+                let mut rank = String::new();
+                let mut empty = 0;
+                for piece in self.squares[row] {
+                    if let Some(piece) = piece {
+                        if empty > 0 {
+                            rank.push_str(&empty.to_string());
+                            empty = 0;
+                        }
+                        rank.push(piece);
+                    } else {
+                        empty += 1;
+                    }
+                }
+                if empty > 0 {
+                    rank.push_str(&empty.to_string());
+                }
+                rank
+            })
+            .collect();
+        let castling: String = if self.castling.is_empty() {
+            "-".into()
+        } else {
+            self.castling.iter().collect()
+        };
+        format!(
+            "{} {} {castling} {} {} {}",
+            ranks.join("/"),
+            if self.white_moves { 'w' } else { 'b' },
+            self.ep.map_or("-".into(), square_name),
+            self.halfmove_clock,
+            self.move_number
+        )
+    }
+
+    fn at(&self, (row, col): (i8, i8)) -> Option<char> {
+        self.squares[row as usize][col as usize]
+    }
+
+    fn own(&self, square: (i8, i8), white: bool) -> bool {
+        self.at(square)
+            .is_some_and(|piece| is_white(piece) == white)
+    }
+
+    /// Tells if a piece of the color attacks the square
+    fn attacked(&self, (row, col): (i8, i8), by_white: bool) -> bool {
+        let has = |square: (i8, i8), kinds: &str| {
+            on_board(square)
+                && self.at(square).is_some_and(|piece| {
+                    is_white(piece) == by_white && kinds.contains(piece.to_ascii_lowercase())
+                })
+        };
+        let pawn_row = if by_white { row - 1 } else { row + 1 };
+        if has((pawn_row, col - 1), "p") || has((pawn_row, col + 1), "p") {
+            return true;
+        }
+        if KNIGHT_STEPS
+            .iter()
+            .any(|(dr, dc)| has((row + dr, col + dc), "n"))
+            || KING_STEPS
+                .iter()
+                .any(|(dr, dc)| has((row + dr, col + dc), "k"))
+        {
+            return true;
+        }
+        for (steps, sliders) in [(ROOK_STEPS, "rq"), (BISHOP_STEPS, "bq")] {
+            for (dr, dc) in steps {
+                let mut square = (row + dr, col + dc);
+                while on_board(square) && self.at(square).is_none() {
+                    square = (square.0 + dr, square.1 + dc);
+                }
+                if has(square, sliders) {
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
+    fn king_attacked(&self, white: bool) -> bool {
+        let king = if white { 'K' } else { 'k' };
+        (0..8)
+            .flat_map(|row| (0..8).map(move |col| (row, col)))
+            .find(|square| self.at(*square) == Some(king))
+            .is_some_and(|square| self.attacked(square, !white))
+    }
+
+    /// The moves of the side to move following the movement of the pieces, not caring about the
+    /// safety of the king apart from castling
+    fn pseudo_legal_moves(&self) -> Vec<NaiveMove> {
+        let white = self.white_moves;
+        let mut moves = Vec::new();
+        for row in 0..8 {
+            for col in 0..8 {
+                let from = (row, col);
+                let Some(piece) = self.at(from).filter(|piece| is_white(*piece) == white) else {
+                    continue;
+                };
+                let mut targets = Vec::new();
+                let mut slide = |steps: &[(i8, i8)], once: bool| {
+                    for (dr, dc) in steps {
+                        let mut to = (row + dr, col + dc);
+                        while on_board(to) && !self.own(to, white) {
+                            targets.push(to);
+                            if once || self.at(to).is_some() {
+                                break;
+                            }
+                            to = (to.0 + dr, to.1 + dc);
+                        }
+                    }
+                };
+                match piece.to_ascii_lowercase() {
+                    'n' => slide(&KNIGHT_STEPS, true),
+                    'k' => slide(&KING_STEPS, true),
+                    'r' => slide(&ROOK_STEPS, false),
+                    'b' => slide(&BISHOP_STEPS, false),
+                    'q' => {
+                        slide(&ROOK_STEPS, false);
+                        slide(&BISHOP_STEPS, false);
+                    }
+                    _ => {
+                        let forward = if white { 1 } else { -1 };
+                        let start_row = if white { 1 } else { 6 };
+                        let one = (row + forward, col);
+                        if on_board(one) && self.at(one).is_none() {
+                            targets.push(one);
+                            let two = (row + 2 * forward, col);
+                            if row == start_row && self.at(two).is_none() {
+                                targets.push(two);
+                            }
+                        }
+                        for side in [-1, 1] {
+                            let diagonal = (row + forward, col + side);
+                            let takes = on_board(diagonal)
+                                && (self.own(diagonal, !white) || self.ep == Some(diagonal));
+                            if takes {
+                                targets.push(diagonal);
+                            }
+                        }
+                    }
+                }
+                for to in targets {
+                    if piece.eq_ignore_ascii_case(&'p') && (to.0 == 0 || to.0 == 7) {
+                        moves.extend("qrbn".chars().map(|promotion| (from, to, Some(promotion))));
+                    } else {
+                        moves.push((from, to, None));
+                    }
+                }
+            }
+        }
+        // Castling with the rights, the rook in its corner, the squares between them empty and
+        // the king not passing through an attacked square
+        let row = if white { 0 } else { 7 };
+        for (right, rook_col, king_to, between) in
+            [('k', 7, 6, &[5, 6][..]), ('q', 0, 2, &[1, 2, 3][..])]
+        {
+            let right = if white {
+                right.to_ascii_uppercase()
+            } else {
+                right
+            };
+            let rook = if white { 'R' } else { 'r' };
+            let king = if white { 'K' } else { 'k' };
+            let allowed = self.castling.contains(&right)
+                && self.at((row, 4)) == Some(king)
+                && self.at((row, rook_col)) == Some(rook)
+                && between.iter().all(|col| self.at((row, *col)).is_none())
+                && [4, (4 + king_to) / 2, king_to]
+                    .iter()
+                    .all(|col| !self.attacked((row, *col), !white));
+            if allowed {
+                moves.push(((row, 4), (row, king_to), None));
+            }
+        }
+        moves
+    }
+
+    fn play(&self, (from, to, promotion): NaiveMove) -> Self {
+        let mut next = Self {
+            squares: self.squares,
+            white_moves: !self.white_moves,
+            castling: self.castling.clone(),
+            ep: None,
+            halfmove_clock: self.halfmove_clock + 1,
+            move_number: self.move_number + u16::from(!self.white_moves),
+        };
+        let piece = self.at(from).unwrap();
+        let pawn = piece.eq_ignore_ascii_case(&'p');
+        if pawn || self.at(to).is_some() {
+            next.halfmove_clock = 0;
+        }
+        if pawn && self.ep == Some(to) {
+            next.squares[from.0 as usize][to.1 as usize] = None;
+        }
+        if pawn && (to.0 - from.0).abs() == 2 {
+            next.ep = Some(((from.0 + to.0) / 2, from.1));
+        }
+        if piece.eq_ignore_ascii_case(&'k') && (to.1 - from.1).abs() == 2 {
+            let (rook_from, rook_to) = if to.1 == 6 { (7, 5) } else { (0, 3) };
+            next.squares[from.0 as usize][rook_to] = next.squares[from.0 as usize][rook_from];
+            next.squares[from.0 as usize][rook_from] = None;
+        }
+        next.squares[from.0 as usize][from.1 as usize] = None;
+        next.squares[to.0 as usize][to.1 as usize] = Some(promotion.map_or(piece, |promotion| {
+            if is_white(piece) {
+                promotion.to_ascii_uppercase()
+            } else {
+                promotion
+            }
+        }));
+        // Moving the king or anything from or to a corner ends the castling of that corner
+        for (square, rights) in [
+            ((0, 4), "KQ"),
+            ((0, 0), "Q"),
+            ((0, 7), "K"),
+            ((7, 4), "kq"),
+            ((7, 0), "q"),
+            ((7, 7), "k"),
+        ] {
+            if from == square || to == square {
+                next.castling.retain(|right| !rights.contains(*right));
+            }
+        }
+        next
+    }
+
+    fn legal_moves(&self) -> Vec<NaiveMove> {
+        self.pseudo_legal_moves()
+            .into_iter()
+            .filter(|a_move| !self.play(*a_move).king_attacked(self.white_moves))
+            .collect()
+    }
+}
+
+fn naive_uci((from, to, promotion): NaiveMove) -> String {
+    let mut uci = square_name(from) + &square_name(to);
+    uci.extend(promotion);
+    uci
+}
+
+impl MoveRules for NaiveRules {
+    fn legal_moves(&self, fen: &str) -> IntResult<BTreeSet<String>> {
+        Ok(NaivePosition::parse(fen)?
+            .legal_moves()
+            .into_iter()
+            .map(naive_uci)
+            .collect())
+    }
+
+    fn play(&self, fen: &str, uci: &str) -> IntResult<String> {
+        let position = NaivePosition::parse(fen)?;
+        let a_move = position
+            .legal_moves()
+            .into_iter()
+            .find(|a_move| naive_uci(*a_move) == uci)
+            .ok_or_else(|| format!("Illegal move {uci} in {fen}"))?;
+        Ok(position.play(a_move).to_fen())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::baserules::differential::{compare_random_playouts, MoveRules, NaiveRules};
+    use crate::baserules::perft::DivergenceKind;
+    use crate::util::IntResult;
+    use std::collections::BTreeSet;
+    use tokio::test;
+
+    #[test]
+    async fn random_games_follow_the_reference() {
+        for (seed, fen) in [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let divergence = compare_random_playouts(&NaiveRules, fen, 8, 80, seed as u64)
+                .await
+                .unwrap();
+            assert!(divergence.is_none(), "{}", divergence.unwrap());
+        }
+    }
+
+    /// Pretends that castling is never allowed
+    struct NoCastling;
+
+    impl MoveRules for NoCastling {
+        fn legal_moves(&self, fen: &str) -> IntResult<BTreeSet<String>> {
+            let mut moves = NaiveRules.legal_moves(fen)?;
+            moves.retain(|uci| !["e1g1", "e1c1", "e8g8", "e8c8"].contains(&uci.as_str()));
+            Ok(moves)
+        }
+
+        fn play(&self, fen: &str, uci: &str) -> IntResult<String> {
+            NaiveRules.play(fen, uci)
+        }
+    }
+
+    #[test]
+    async fn the_first_difference_is_reported() {
+        let fen = "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1";
+        let divergence = compare_random_playouts(&NoCastling, fen, 1, 10, 0)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(fen, divergence.fen);
+        assert!(divergence.line.is_empty());
+        assert!(matches!(divergence.kind, DivergenceKind::ExtraMove(_)));
+    }
+}
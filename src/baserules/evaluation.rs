@@ -0,0 +1,429 @@
+/*
+ *  ========================================================================
+ *  DBCE chess bot, static evaluation of the boards
+ *  ========================================================================
+ *
+ *  This file is part of DBCE.
+ *
+ *  DBCE is free software: you can redistribute it and/or
+ *  modify it under the terms of the GNU General Public License as published
+ *  by the Free Software Foundation, either version 3 of the License, or (at
+ *  your option) any later version.
+ *
+ *  DBCE is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ *  General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License along
+ *  with DBCE.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ *  (C) Copyright 2022-3, Gabor Kecskemeti
+ */
+use crate::baserules::board::{PSBoard, FIFTY_MOVE_RULE};
+#[cfg(feature = "nnue")]
+use crate::baserules::nnue::{Network, Nnue};
+use crate::baserules::piece_color::PieceColor::{Black, White};
+use crate::baserules::score::{Score, MATE};
+use crate::util::IntResult;
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// Scores the boards without looking at their continuations, the score of each board is
+/// calculated when the board is created. The moves are made with `Imbalance` unless the engine
+/// making them has another evaluator.
+pub trait Evaluator: Send + Sync {
+    /// The score of a board with both kings on it, from white's perspective
+    fn eval(&self, board: &PSBoard) -> Score;
+
+    /// The terms the score of the board is added up from. The evaluators without their own
+    /// breakdown give their score as a single term.
+    fn explain(&self, board: &PSBoard) -> EvalBreakdown {
+        EvalBreakdown::default().with("evaluation", self.eval(board))
+    }
+
+    /// The network the evaluator scores the boards with, the new boards keep its accumulators
+    #[cfg(feature = "nnue")]
+    fn network(&self) -> Option<Arc<Network>> {
+        None
+    }
+}
+
+/// The terms of an evaluation by their names, in the order they are added up
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct EvalBreakdown {
+    pub terms: Vec<(&'static str, Score)>,
+}
+
+impl EvalBreakdown {
+    /// Adds a term after the existing ones
+    pub fn with(mut self, name: &'static str, score: Score) -> Self {
+        self.terms.push((name, score));
+        self
+    }
+
+    /// The score of the term, if the evaluation has one with the name
+    pub fn term(&self, name: &str) -> Option<Score> {
+        self.terms
+            .iter()
+            .find(|(term, _)| *term == name)
+            .map(|(_, score)| *score)
+    }
+
+    /// The sum of the terms, the same as the score of the evaluation
+    pub fn total(&self) -> Score {
+        self.terms.iter().map(|(_, score)| *score).sum()
+    }
+}
+
+impl Display for EvalBreakdown {
+    /// One line for each term and one for the total, with the scores in pawns aligned, e.g.
+    /// `material        +1.00`
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for (name, score) in &self.terms {
+            writeln!(f, "{name:<15} {score:>+7.2}")?;
+        }
+        write!(f, "{:<15} {:>+7.2}", "total", self.total())
+    }
+}
+
+/// Counts up the pieces and pawns based on their usual values
+pub struct MaterialCount;
+
+impl Evaluator for MaterialCount {
+    fn eval(&self, board: &PSBoard) -> Score {
+        board.material()
+    }
+
+    fn explain(&self, board: &PSBoard) -> EvalBreakdown {
+        EvalBreakdown::default().with("material", board.material())
+    }
+}
+
+/// Adds the bonuses of the piece-square tables to the material, so the pieces head for their
+/// usual squares instead of shuffling around aimlessly
+pub struct PieceSquareTables;
+
+impl Evaluator for PieceSquareTables {
+    fn eval(&self, board: &PSBoard) -> Score {
+        board.material() + board.raw.piece_squares()
+    }
+
+    fn explain(&self, board: &PSBoard) -> EvalBreakdown {
+        MaterialCount
+            .explain(board)
+            .with("piece-square", board.raw.piece_squares())
+    }
+}
+
+/// The piece-square tables with the doubled, isolated, backward and passed pawns
+pub struct PawnStructure;
+
+impl Evaluator for PawnStructure {
+    fn eval(&self, board: &PSBoard) -> Score {
+        PieceSquareTables.eval(board) + board.raw.pawn_structure()
+    }
+
+    fn explain(&self, board: &PSBoard) -> EvalBreakdown {
+        PieceSquareTables
+            .explain(board)
+            .with("pawn structure", board.raw.pawn_structure())
+    }
+}
+
+/// The pawn structure with the weighted number of moves of the pieces
+pub struct Mobility;
+
+impl Evaluator for Mobility {
+    fn eval(&self, board: &PSBoard) -> Score {
+        PawnStructure.eval(board) + board.raw.mobility()
+    }
+
+    fn explain(&self, board: &PSBoard) -> EvalBreakdown {
+        PawnStructure
+            .explain(board)
+            .with("mobility", board.raw.mobility())
+    }
+}
+
+/// The mobility with the corrections of the flat piece values for the combination of the pieces
+pub struct Imbalance;
+
+impl Evaluator for Imbalance {
+    fn eval(&self, board: &PSBoard) -> Score {
+        Mobility.eval(board) + board.material_key().imbalance()
+    }
+
+    fn explain(&self, board: &PSBoard) -> EvalBreakdown {
+        Mobility
+            .explain(board)
+            .with("imbalance", board.material_key().imbalance())
+    }
+}
+
+/// The multipliers of the terms of an evaluation by their names, the terms without one are
+/// counted as they are. Written like a toml table, one term per line:
+/// ```toml
+/// "material" = 1.000
+/// "pawn structure" = 0.850
+/// ```
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct TermWeights(pub BTreeMap<String, f64>);
+
+impl TermWeights {
+    pub fn weight(&self, term: &str) -> f64 {
+        self.0.get(term).copied().unwrap_or(1.0)
+    }
+
+    /// Reads the weights written by the tuning
+    ///
+    /// # Errors
+    /// When the file cannot be read or it has a line that is not a weight
+    pub fn load(file: &Path) -> IntResult<Self> {
+        let text = std::fs::read_to_string(file)
+            .map_err(|error| format!("Cannot read {}: {error}", file.display()))?;
+        text.parse()
+            .map_err(|error| format!("{}: {error}", file.display()).into())
+    }
+}
+
+impl Display for TermWeights {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for (term, weight) in &self.0 {
+            writeln!(f, "\"{term}\" = {weight:.3}")?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for TermWeights {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut weights = BTreeMap::new();
+        for (line_number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let parsed = line.split_once('=').and_then(|(term, weight)| {
+                let term = term.trim().strip_prefix('"')?.strip_suffix('"')?;
+                Some((term.to_string(), weight.trim().parse().ok()?))
+            });
+            let Some((term, weight)) = parsed else {
+                return Err(format!(
+                    "Line {} is not a \"term\" = weight pair: {line}",
+                    line_number + 1
+                ));
+            };
+            weights.insert(term, weight);
+        }
+        Ok(TermWeights(weights))
+    }
+}
+
+/// Scales the terms of another evaluation, e.g. with the weights found by tuning them
+pub struct Weighted {
+    pub base: Arc<dyn Evaluator>,
+    pub weights: TermWeights,
+}
+
+impl Evaluator for Weighted {
+    fn eval(&self, board: &PSBoard) -> Score {
+        self.explain(board).total()
+    }
+
+    fn explain(&self, board: &PSBoard) -> EvalBreakdown {
+        let mut breakdown = self.base.explain(board);
+        for (term, score) in &mut breakdown.terms {
+            let centipawns = f64::from(score.as_pawns()) * 100.0 * self.weights.weight(term);
+            *score = Score::centipawns(centipawns.round() as i32);
+        }
+        breakdown
+    }
+
+    #[cfg(feature = "nnue")]
+    fn network(&self) -> Option<Arc<Network>> {
+        self.base.network()
+    }
+}
+
+/// An evaluator with the NNUE network of the file, for `Engine::set_evaluator`
+///
+/// # Errors
+/// When the file is not a network, or the crate was built without the `nnue` feature
+pub fn network_evaluator(file: &Path) -> IntResult<Arc<dyn Evaluator>> {
+    #[cfg(feature = "nnue")]
+    {
+        Ok(Arc::new(Nnue::load(file)?))
+    }
+    #[cfg(not(feature = "nnue"))]
+    Err(format!(
+        "Cannot evaluate with {}, the nnue feature is not built in",
+        file.display()
+    )
+    .into())
+}
+
+/// Scores the board with the evaluator, the boards without one of the kings are mates
+pub fn evaluate(board: &PSBoard, evaluator: &dyn Evaluator) -> Score {
+    match board.raw.captured_king() {
+        Some(White) => -MATE,
+        Some(Black) => MATE,
+        None => towards_fifty_move_draw(evaluator.eval(board), board),
+    }
+}
+
+/// Pulls the score towards a draw as the fifty-move rule gets closer, halving it by the time the
+/// rule draws the game, so the winning side prefers the pawn moves and captures resetting it
+fn towards_fifty_move_draw(score: Score, board: &PSBoard) -> Score {
+    let rule = i32::from(FIFTY_MOVE_RULE);
+    let remaining = i32::from(FIFTY_MOVE_RULE.saturating_sub(board.halfmove_clock()));
+    score * (rule + remaining) / (2 * rule)
+}
+
+/// The terms of the score of the board with the evaluator, to show where it comes from
+pub fn explain(board: &PSBoard, evaluator: &dyn Evaluator) -> EvalBreakdown {
+    match board.raw.captured_king() {
+        Some(White) => EvalBreakdown::default().with("king captured", -MATE),
+        Some(Black) => EvalBreakdown::default().with("king captured", MATE),
+        None => {
+            let breakdown = evaluator.explain(board);
+            let total = breakdown.total();
+            let scaled = towards_fifty_move_draw(total, board);
+            if scaled == total {
+                breakdown
+            } else {
+                breakdown.with("fifty-move rule", scaled - total)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::baserules::board::PSBoard;
+    use crate::baserules::board_rep::PossibleMove;
+    use crate::baserules::evaluation::{
+        evaluate, explain, Evaluator, Imbalance, MaterialCount, Mobility, PawnStructure,
+        PieceSquareTables, TermWeights, Weighted,
+    };
+    use crate::baserules::score::{Score, MATE};
+    use std::env;
+    use std::sync::Arc;
+    use tokio::test;
+
+    /// Prefers white's central pawns
+    struct CentralPawns;
+
+    impl Evaluator for CentralPawns {
+        fn eval(&self, board: &PSBoard) -> Score {
+            let central = ["d4", "e4"]
+                .iter()
+                .filter(|square| board.raw[**square].is_some())
+                .count();
+            MaterialCount.eval(board) + Score::centipawns(50 * central as i32)
+        }
+    }
+
+    #[test]
+    async fn custom_evaluation_scores_new_boards() {
+        let fen = "4k3/p7/8/8/3PP3/8/8/4K3 w - - 0 1";
+        let default = PSBoard::from_fen(fen).await.unwrap();
+        assert_eq!(Imbalance.eval(&default), default.score);
+
+        assert_eq!(Score::centipawns(200), evaluate(&default, &CentralPawns));
+
+        let advance = PossibleMove::simple_from_uci("d4d5").unwrap();
+        let central = default.make_move_with(&advance, &CentralPawns).await;
+        assert_eq!(Score::centipawns(150), central.score);
+        assert_eq!(
+            Imbalance.eval(&central),
+            default.make_move_noncached(&advance).await.score
+        );
+
+        let king_taken = PSBoard::from_fen("8/p7/8/8/3PP3/8/8/4K3 w - - 0 1")
+            .await
+            .unwrap();
+        assert_eq!(MATE, evaluate(&king_taken, &Imbalance));
+    }
+
+    #[test]
+    async fn breakdowns_add_up_to_the_scores() {
+        let board = PSBoard::from_fen(
+            "r1bqk2r/pppp1ppp/2n2n2/2b1p3/2B1P3/3P1N2/PPP2PPP/RNBQK2R w KQkq - 0 5",
+        )
+        .await
+        .unwrap();
+        let evaluators: [&dyn Evaluator; 5] = [
+            &MaterialCount,
+            &PieceSquareTables,
+            &PawnStructure,
+            &Mobility,
+            &Imbalance,
+        ];
+        for (terms, evaluator) in evaluators.into_iter().enumerate() {
+            let breakdown = evaluator.explain(&board);
+            assert_eq!(terms + 1, breakdown.terms.len());
+            assert_eq!(evaluator.eval(&board), breakdown.total());
+        }
+
+        let breakdown = explain(&board, &Imbalance);
+        assert_eq!(board.score, breakdown.total());
+        assert_eq!(Some(board.raw.mobility()), breakdown.term("mobility"));
+        assert_eq!(None, breakdown.term("king safety"));
+        let shown = breakdown.to_string();
+        assert_eq!(6, shown.lines().count());
+        assert!(shown.starts_with("material          +0.00\n"), "{shown}");
+        assert!(shown.ends_with(&format!("{:+.2}", board.score)), "{shown}");
+    }
+
+    #[test]
+    async fn scores_fade_towards_the_fifty_move_rule() {
+        let fresh = PSBoard::from_fen("4k3/8/8/8/8/8/8/R3K3 w - - 0 60")
+            .await
+            .unwrap();
+        let shuffled = PSBoard::from_fen("4k3/8/8/8/8/8/8/R3K3 w - - 80 60")
+            .await
+            .unwrap();
+        assert!(fresh.score > shuffled.score);
+        assert!(shuffled.score > Score::DRAW);
+        assert_eq!(
+            evaluate(&fresh, &Imbalance) * 6 / 10,
+            evaluate(&shuffled, &Imbalance)
+        );
+        let breakdown = explain(&shuffled, &Imbalance);
+        assert_eq!(shuffled.score, breakdown.total());
+        assert!(breakdown.term("fifty-move rule").unwrap() < Score::DRAW);
+        assert_eq!(None, explain(&fresh, &Imbalance).term("fifty-move rule"));
+    }
+
+    #[test]
+    async fn weights_are_loaded_from_files() {
+        let file = env::temp_dir().join(format!("dbce_weights_{}.toml", std::process::id()));
+        std::fs::write(&file, "# tuned\n\"material\" = 2.000\n").unwrap();
+        let weights = TermWeights::load(&file);
+        std::fs::write(&file, "material = 2").unwrap();
+        let malformed = TermWeights::load(&file).unwrap_err().to_string();
+        std::fs::remove_file(&file).unwrap();
+        assert!(
+            malformed.starts_with(&file.display().to_string()),
+            "{malformed}"
+        );
+        assert!(TermWeights::load(&file).is_err());
+
+        let weighted = Weighted {
+            base: Arc::new(Imbalance),
+            weights: weights.unwrap(),
+        };
+        let board = PSBoard::from_fen("4k3/pp6/8/8/8/8/PPP5/4K3 w - - 0 1")
+            .await
+            .unwrap();
+        let breakdown = explain(&board, &Imbalance);
+        let material = breakdown.term("material").unwrap();
+        assert_eq!(breakdown.total() + material, weighted.eval(&board));
+    }
+}
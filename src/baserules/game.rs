@@ -0,0 +1,413 @@
+/*
+ *  ========================================================================
+ *  DBCE chess bot, games played by the rules from their start to their result
+ *  ========================================================================
+ *
+ *  This file is part of DBCE.
+ *
+ *  DBCE is free software: you can redistribute it and/or
+ *  modify it under the terms of the GNU General Public License as published
+ *  by the Free Software Foundation, either version 3 of the License, or (at
+ *  your option) any later version.
+ *
+ *  DBCE is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ *  General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License along
+ *  with DBCE.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ *  (C) Copyright 2022-3, Gabor Kecskemeti
+ */
+use crate::baserules::board::{PSBoard, FIFTY_MOVE_RULE};
+use crate::baserules::board_rep::PossibleMove;
+use crate::baserules::material::EndgameClass;
+use crate::baserules::piece_color::PieceColor;
+use crate::baserules::piece_color::PieceColor::{Black, White};
+use crate::util::EmptyResult;
+use enum_map::{enum_map, EnumMap};
+use futures_util::future::ready;
+use futures_util::StreamExt;
+use std::fmt::{Display, Formatter};
+use std::pin::pin;
+use std::time::Duration;
+
+/// The number of times a position has to occur for the repetition to draw the game
+const REPETITIONS_FOR_DRAW: u8 = 3;
+
+/// Why the game was drawn, besides a stalemate
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DrawReason {
+    ThreefoldRepetition,
+    FiftyMoveRule,
+    /// Neither side has the pieces left to mate
+    InsufficientMaterial,
+}
+
+/// Where the game stands after its last move
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum GameStatus {
+    Ongoing,
+    /// The winner mated the other side
+    Checkmate(PieceColor),
+    Stalemate,
+    DrawBy(DrawReason),
+    /// The winner's opponent ran out of time
+    Timeout(PieceColor),
+}
+
+impl GameStatus {
+    pub fn is_over(&self) -> bool {
+        *self != GameStatus::Ongoing
+    }
+}
+
+impl Display for GameStatus {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let name = |color: &PieceColor| match color {
+            White => "white",
+            Black => "black",
+        };
+        match self {
+            GameStatus::Ongoing => write!(f, "the game is on"),
+            GameStatus::Checkmate(winner) => write!(f, "{} won by checkmate", name(winner)),
+            GameStatus::Stalemate => write!(f, "draw by stalemate"),
+            GameStatus::DrawBy(DrawReason::ThreefoldRepetition) => {
+                write!(f, "draw by threefold repetition")
+            }
+            GameStatus::DrawBy(DrawReason::FiftyMoveRule) => {
+                write!(f, "draw by the fifty-move rule")
+            }
+            GameStatus::DrawBy(DrawReason::InsufficientMaterial) => {
+                write!(f, "draw by insufficient material")
+            }
+            GameStatus::Timeout(winner) => write!(f, "{} won on time", name(winner)),
+        }
+    }
+}
+
+/// The time left on the clocks of the players, each of them gets the increment after its moves
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Clocks {
+    pub remaining: EnumMap<PieceColor, Duration>,
+    pub increment: Duration,
+}
+
+/// The state of the game after one of its moves, kept to step back and forth in the game
+#[derive(Clone)]
+struct Position {
+    board: PSBoard,
+    clocks: Option<Clocks>,
+    status: GameStatus,
+}
+
+/// A game from its starting position with the moves played so far. Only legal moves are accepted
+/// and the status is updated after each of them, so the players only need to ask whether the
+/// game is over. The moves can be taken back and played again, the positions of the game are
+/// kept for this instead of replaying the moves. `human_facing::game::GameRecord` is the record
+/// of a finished game, e.g. to write it as pgn.
+pub struct Game {
+    history: Vec<PossibleMove>,
+    /// The position at the start and after each move of the history
+    positions: Vec<Position>,
+    /// The moves taken back with their positions, the last taken back at the end
+    undone: Vec<(PossibleMove, Position)>,
+}
+
+impl Game {
+    pub async fn new(start: PSBoard) -> Game {
+        let mut game = Game {
+            history: Vec::new(),
+            positions: vec![Position {
+                board: start,
+                clocks: None,
+                status: GameStatus::Ongoing,
+            }],
+            undone: Vec::new(),
+        };
+        game.current_mut().status = game.find_status().await;
+        game
+    }
+
+    /// Plays the game with clocks starting with the given time for both players
+    pub fn set_clocks(&mut self, time: Duration, increment: Duration) {
+        self.current_mut().clocks = Some(Clocks {
+            remaining: enum_map! { _ => time },
+            increment,
+        });
+    }
+
+    #[inline]
+    pub fn start(&self) -> &PSBoard {
+        &self.positions[0].board
+    }
+
+    /// The board after the last move
+    #[inline]
+    pub fn board(&self) -> &PSBoard {
+        &self.current().board
+    }
+
+    /// The moves played from the start
+    #[inline]
+    pub fn history(&self) -> &[PossibleMove] {
+        &self.history
+    }
+
+    #[inline]
+    pub fn clocks(&self) -> Option<&Clocks> {
+        self.current().clocks.as_ref()
+    }
+
+    #[inline]
+    pub fn status(&self) -> GameStatus {
+        self.current().status
+    }
+
+    #[inline]
+    fn current(&self) -> &Position {
+        self.positions.last().unwrap()
+    }
+
+    #[inline]
+    fn current_mut(&mut self) -> &mut Position {
+        self.positions.last_mut().unwrap()
+    }
+
+    /// How many times the current position occurred since the last pawn move or capture
+    pub fn repetitions(&self) -> u8 {
+        let current_hash = self.board().zobrist();
+        let mut repetitions = 0;
+        for position in self.positions.iter().rev() {
+            if position.board.zobrist() == current_hash {
+                repetitions += 1;
+            }
+            if position.board.halfmove_clock() == 0 {
+                break;
+            }
+        }
+        repetitions
+    }
+
+    /// Plays the move on the board. The moves taken back can not be played again with `redo`
+    /// afterwards.
+    ///
+    /// # Errors
+    /// When the game is over or the move is not legal in the position
+    pub async fn make_move(&mut self, a_move: &PossibleMove) -> EmptyResult {
+        let status = self.status();
+        if status.is_over() {
+            return Err(format!("The game is over, {status}").into());
+        }
+        let board = self.board();
+        let Some((_, next_board)) = pin!(board
+            .successors()
+            .filter(|(legal_move, _)| ready(legal_move == a_move)))
+        .next()
+        .await
+        else {
+            return Err(format!("Illegal move {a_move} in {board}").into());
+        };
+        self.undone.clear();
+        self.history.push(*a_move);
+        let clocks = self.current().clocks;
+        self.positions.push(Position {
+            board: next_board,
+            clocks,
+            status: GameStatus::Ongoing,
+        });
+        self.current_mut().status = self.find_status().await;
+        Ok(())
+    }
+
+    /// Plays the move on the board after the mover spent the given time on it. A mover running
+    /// out of time loses without the move being played. Games without clocks ignore the time.
+    ///
+    /// # Errors
+    /// When the game is over or the move is not legal in the position
+    pub async fn make_timed_move(&mut self, a_move: &PossibleMove, spent: Duration) -> EmptyResult {
+        let mover = self.board().who_moves;
+        let status = self.status();
+        let Some(clocks) = self
+            .current_mut()
+            .clocks
+            .as_mut()
+            .filter(|_| !status.is_over())
+        else {
+            return self.make_move(a_move).await;
+        };
+        if spent >= clocks.remaining[mover] {
+            clocks.remaining[mover] = Duration::ZERO;
+            self.current_mut().status = GameStatus::Timeout(mover.invert());
+            return Ok(());
+        }
+        let remaining = clocks.remaining[mover] - spent + clocks.increment;
+        self.make_move(a_move).await?;
+        if let Some(clocks) = self.current_mut().clocks.as_mut() {
+            clocks.remaining[mover] = remaining;
+        }
+        Ok(())
+    }
+
+    /// Takes back the last move, returns it unless the game is at its start
+    pub fn undo(&mut self) -> Option<PossibleMove> {
+        let a_move = self.history.pop()?;
+        let position = self.positions.pop().unwrap();
+        self.undone.push((a_move, position));
+        Some(a_move)
+    }
+
+    /// Plays the last move taken back again, returns it unless there was none
+    pub fn redo(&mut self) -> Option<PossibleMove> {
+        let (a_move, position) = self.undone.pop()?;
+        self.history.push(a_move);
+        self.positions.push(position);
+        Some(a_move)
+    }
+
+    async fn find_status(&self) -> GameStatus {
+        let board = self.board();
+        if !board.has_legal_move() {
+            if board.is_in_check() {
+                GameStatus::Checkmate(board.who_moves.invert())
+            } else {
+                GameStatus::Stalemate
+            }
+        } else if board.material_key().endgame_class() == Some(EndgameClass::InsufficientMaterial) {
+            GameStatus::DrawBy(DrawReason::InsufficientMaterial)
+        } else if self.repetitions() >= REPETITIONS_FOR_DRAW {
+            GameStatus::DrawBy(DrawReason::ThreefoldRepetition)
+        } else if board.halfmove_clock() >= FIFTY_MOVE_RULE {
+            GameStatus::DrawBy(DrawReason::FiftyMoveRule)
+        } else {
+            GameStatus::Ongoing
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::baserules::board::PSBoard;
+    use crate::baserules::board_rep::PossibleMove;
+    use crate::baserules::game::{DrawReason, Game, GameStatus};
+    use crate::baserules::piece_color::PieceColor::{Black, White};
+    use std::time::Duration;
+    use tokio::test;
+
+    async fn play(fen: &str, moves: &[&str]) -> Game {
+        let mut game = Game::new(PSBoard::from_fen(fen).await.unwrap()).await;
+        for a_move in moves {
+            game.make_move(&PossibleMove::simple_from_uci(a_move).unwrap())
+                .await
+                .unwrap();
+        }
+        game
+    }
+
+    #[test]
+    async fn games_end_by_the_rules() {
+        let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        let mut fools_mate = play(start, &["f2f3", "e7e5", "g2g4", "d8h4"]).await;
+        assert_eq!(GameStatus::Checkmate(Black), fools_mate.status());
+        assert_eq!(4, fools_mate.history().len());
+        let e2e4 = PossibleMove::simple_from_uci("e2e4").unwrap();
+        assert!(fools_mate.make_move(&e2e4).await.is_err());
+
+        let knight_dance = ["g1f3", "g8f6", "f3g1", "f6g8"];
+        let repeated = play(start, &[knight_dance, knight_dance].concat()).await;
+        assert_eq!(3, repeated.repetitions());
+        assert_eq!(
+            GameStatus::DrawBy(DrawReason::ThreefoldRepetition),
+            repeated.status()
+        );
+
+        let stalemate = play("k7/8/1K6/8/8/8/8/2Q5 w - - 0 1", &["c1c7"]).await;
+        assert_eq!(GameStatus::Stalemate, stalemate.status());
+        let bare_kings = play("4k3/8/8/8/8/8/3q4/4K3 w - - 0 1", &["e1d2"]).await;
+        assert_eq!(
+            GameStatus::DrawBy(DrawReason::InsufficientMaterial),
+            bare_kings.status()
+        );
+        let shuffled = play("4k3/8/8/8/8/8/4P3/R3K3 w - - 99 80", &["a1a7"]).await;
+        assert_eq!(
+            GameStatus::DrawBy(DrawReason::FiftyMoveRule),
+            shuffled.status()
+        );
+        assert_eq!("draw by the fifty-move rule", shuffled.status().to_string());
+    }
+
+    #[test]
+    async fn only_legal_moves_are_played() {
+        let mut game = play("4k3/8/8/8/8/8/4r3/4K3 w - - 0 1", &[]).await;
+        let into_check = PossibleMove::simple_from_uci("e1d2").unwrap();
+        assert!(game.make_move(&into_check).await.is_err());
+        let takes = PossibleMove::simple_from_uci("e1e2").unwrap();
+        game.make_move(&takes).await.unwrap();
+        assert_eq!(vec![takes], game.history());
+        assert_eq!(
+            GameStatus::DrawBy(DrawReason::InsufficientMaterial),
+            game.status()
+        );
+    }
+
+    #[test]
+    async fn the_clocks_run_out() {
+        let mut game = play("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1", &[]).await;
+        game.set_clocks(Duration::from_secs(1), Duration::from_millis(100));
+        let e2e4 = PossibleMove::simple_from_uci("e2e4").unwrap();
+        game.make_timed_move(&e2e4, Duration::from_millis(400))
+            .await
+            .unwrap();
+        assert_eq!(
+            Duration::from_millis(700),
+            game.clocks().unwrap().remaining[White]
+        );
+        let e8e7 = PossibleMove::simple_from_uci("e8e7").unwrap();
+        game.make_timed_move(&e8e7, Duration::from_secs(2))
+            .await
+            .unwrap();
+        assert_eq!(GameStatus::Timeout(White), game.status());
+        assert_eq!(1, game.history().len());
+        assert_eq!("white won on time", game.status().to_string());
+    }
+
+    #[test]
+    async fn moves_are_taken_back_and_played_again() {
+        let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        let mut game = play(start, &["f2f3", "e7e5", "g2g4", "d8h4"]).await;
+        game.set_clocks(Duration::from_secs(60), Duration::ZERO);
+        let mate = game.board().to_fen();
+        let d8h4 = PossibleMove::simple_from_uci("d8h4").unwrap();
+        assert_eq!(Some(d8h4), game.undo());
+        assert_eq!(GameStatus::Ongoing, game.status());
+        assert_eq!(3, game.history().len());
+        assert_eq!(None, game.clocks());
+        assert_eq!(Some(d8h4), game.redo());
+        assert_eq!(None, game.redo());
+        assert_eq!(mate, game.board().to_fen());
+        assert_eq!(GameStatus::Checkmate(Black), game.status());
+        assert!(game.clocks().is_some());
+
+        while game.undo().is_some() {}
+        assert_eq!(start, game.board().to_fen());
+        assert_eq!(game.start().to_fen(), game.board().to_fen());
+        let e2e4 = PossibleMove::simple_from_uci("e2e4").unwrap();
+        game.make_move(&e2e4).await.unwrap();
+        assert_eq!(None, game.redo());
+        assert_eq!(vec![e2e4], game.history());
+    }
+
+    #[test]
+    async fn repetitions_are_counted_after_taking_moves_back() {
+        let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        let knight_dance = ["g1f3", "g8f6", "f3g1", "f6g8"];
+        let mut game = play(start, &[knight_dance, knight_dance].concat()).await;
+        game.undo();
+        game.undo();
+        game.undo();
+        game.undo();
+        assert_eq!(2, game.repetitions());
+        assert_eq!(GameStatus::Ongoing, game.status());
+    }
+}
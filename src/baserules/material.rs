@@ -0,0 +1,312 @@
+/*
+ *  ========================================================================
+ *  DBCE chess bot, material signatures and endgame classes
+ *  ========================================================================
+ *
+ *  This file is part of DBCE.
+ *
+ *  DBCE is free software: you can redistribute it and/or
+ *  modify it under the terms of the GNU General Public License as published
+ *  by the Free Software Foundation, either version 3 of the License, or (at
+ *  your option) any later version.
+ *
+ *  DBCE is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ *  General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License along
+ *  with DBCE.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ *  (C) Copyright 2022-3, Gabor Kecskemeti
+ */
+use crate::baserules::board::PSBoard;
+use crate::baserules::piece_color::PieceColor;
+use crate::baserules::piece_color::PieceColor::{Black, White};
+use crate::baserules::piece_kind::PieceKind;
+use crate::baserules::piece_kind::PieceKind::*;
+use crate::baserules::score::Score;
+use enum_iterator::all;
+use enum_map::EnumMap;
+use std::fmt::{Display, Formatter};
+
+/// The bonus of the side with both of its bishops, in centipawns
+const BISHOP_PAIR_BONUS: i32 = 30;
+/// The knights gain this much with each pawn of their side above five, as they need outposts
+const KNIGHT_PAWN_SCALE: i32 = 6;
+/// The rooks lose this much with each pawn of their side above five, as they need open files
+const ROOK_PAWN_SCALE: i32 = 12;
+/// The penalty of the second rook, and of the rooks beside a queen, doing the same work
+const MAJOR_REDUNDANCY: i32 = 10;
+/// The bonus of each extra minor piece against the extra rooks or queens of the other side,
+/// which the flat piece values underrate in the middlegame
+const MINOR_FOR_MAJOR_BONUS: i32 = 25;
+
+/// The pieces on the board by their colors and kinds, regardless of their squares
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+pub struct MaterialKey {
+    counts: EnumMap<PieceColor, EnumMap<PieceKind, u8>>,
+}
+
+/// The kinds of endgames handled differently by the evaluation, the tablebases or the reports
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum EndgameClass {
+    /// Neither side has enough material left to mate
+    InsufficientMaterial,
+    /// One side only has its king left against at least one piece
+    LoneKing,
+    /// Only kings and pawns
+    Pawn,
+    /// Bishops and knights besides the kings and pawns
+    MinorPiece,
+    /// Rooks besides the kings and pawns
+    Rook,
+    /// Queens besides the kings and pawns
+    Queen,
+    /// At most two pieces of different kinds per side besides the kings and pawns
+    Mixed,
+}
+
+impl MaterialKey {
+    pub fn count(&self, color: PieceColor, kind: PieceKind) -> u8 {
+        self.counts[color][kind]
+    }
+
+    /// The number of pieces on the board, including the kings and pawns
+    pub fn piece_count(&self) -> usize {
+        self.counts
+            .values()
+            .flat_map(|counts| counts.values())
+            .map(|&count| usize::from(count))
+            .sum()
+    }
+
+    /// The pieces of the side besides the king and pawns
+    fn officers(&self, color: PieceColor) -> u8 {
+        self.count(color, Queen)
+            + self.count(color, Rook)
+            + self.count(color, Bishop)
+            + self.count(color, Knight)
+    }
+
+    fn minors(&self, color: PieceColor) -> i32 {
+        i32::from(self.count(color, Bishop) + self.count(color, Knight))
+    }
+
+    fn majors(&self, color: PieceColor) -> i32 {
+        i32::from(self.count(color, Rook) + self.count(color, Queen))
+    }
+
+    /// The corrections of the flat piece values for the combination of the pieces, white's minus
+    /// black's: the bishop pair, the knights and rooks scaled by the pawns of their side, the
+    /// redundant major pieces and the minor pieces traded for the major ones
+    pub fn imbalance(&self) -> Score {
+        Score::centipawns(self.side_imbalance(White) - self.side_imbalance(Black))
+    }
+
+    fn side_imbalance(&self, color: PieceColor) -> i32 {
+        let count = |kind| i32::from(self.count(color, kind));
+        let pawns_above_five = count(Pawn) - 5;
+        let mut bonus = count(Knight) * KNIGHT_PAWN_SCALE * pawns_above_five
+            - count(Rook) * ROOK_PAWN_SCALE * pawns_above_five;
+        if count(Bishop) >= 2 {
+            bonus += BISHOP_PAIR_BONUS;
+        }
+        if count(Rook) >= 2 {
+            bonus -= MAJOR_REDUNDANCY;
+        }
+        if count(Queen) >= 1 {
+            bonus -= MAJOR_REDUNDANCY * count(Rook);
+        }
+        let extra_minors = self.minors(color) - self.minors(color.invert());
+        let extra_majors = self.majors(color.invert()) - self.majors(color);
+        if extra_minors > 0 && extra_majors > 0 {
+            bonus += MINOR_FOR_MAJOR_BONUS * extra_minors.min(2 * extra_majors);
+        }
+        bonus
+    }
+
+    /// The material of the side in pawns, by the usual values of the pieces
+    pub fn material_value(&self, color: PieceColor) -> u32 {
+        let values = [(Queen, 9), (Rook, 5), (Bishop, 3), (Knight, 3), (Pawn, 1)];
+        values
+            .iter()
+            .map(|&(kind, value)| u32::from(self.count(color, kind)) * value)
+            .sum()
+    }
+
+    /// The side listed first in the signature: the one with more material, or the one whose
+    /// pieces come first in the signature order when the material is equal
+    pub fn stronger_side(&self) -> PieceColor {
+        let by_value = self.material_value(White).cmp(&self.material_value(Black));
+        if by_value.then_with(|| self.side_signature(White).cmp(&self.side_signature(Black)))
+            == std::cmp::Ordering::Less
+        {
+            Black
+        } else {
+            White
+        }
+    }
+
+    /// The letters of the pieces of the side from the king to the pawns, e.g. "KRPP"
+    fn side_signature(&self, color: PieceColor) -> String {
+        all::<PieceKind>()
+            .flat_map(|kind| {
+                let letter = kind.to_char().to_ascii_uppercase();
+                std::iter::repeat_n(letter, usize::from(self.count(color, kind)))
+            })
+            .collect()
+    }
+
+    /// Classifies the endgame, there is none while the sides have too many pieces
+    pub fn endgame_class(&self) -> Option<EndgameClass> {
+        let has_only = |kinds: &[PieceKind]| {
+            [White, Black].into_iter().all(|color| {
+                [Queen, Rook, Bishop, Knight]
+                    .iter()
+                    .filter(|kind| !kinds.contains(kind))
+                    .all(|&kind| self.count(color, kind) == 0)
+            })
+        };
+        let pawns = self.count(White, Pawn) + self.count(Black, Pawn);
+        let officers = self.officers(White) + self.officers(Black);
+        if pawns == 0 && has_only(&[Bishop, Knight]) && officers <= 1 {
+            Some(EndgameClass::InsufficientMaterial)
+        } else if officers == 0 {
+            Some(EndgameClass::Pawn)
+        } else if [White, Black]
+            .into_iter()
+            .any(|color| self.officers(color) + self.count(color, Pawn) == 0)
+        {
+            Some(EndgameClass::LoneKing)
+        } else if has_only(&[Queen]) {
+            Some(EndgameClass::Queen)
+        } else if has_only(&[Rook]) {
+            Some(EndgameClass::Rook)
+        } else if has_only(&[Bishop, Knight]) {
+            Some(EndgameClass::MinorPiece)
+        } else if [White, Black]
+            .into_iter()
+            .all(|color| self.officers(color) <= 2)
+        {
+            Some(EndgameClass::Mixed)
+        } else {
+            None
+        }
+    }
+}
+
+impl Display for MaterialKey {
+    /// The canonical signature of the material, the stronger side first, e.g. "KRPPvKRP"
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let stronger = self.stronger_side();
+        write!(
+            f,
+            "{}v{}",
+            self.side_signature(stronger),
+            self.side_signature(stronger.invert())
+        )
+    }
+}
+
+impl PSBoard {
+    /// The material of the board, regardless of the squares of the pieces
+    ///
+    /// # Example:
+    /// ```
+    /// use dbce::baserules::board::PSBoard;
+    /// let start = PSBoard::default().material_key();
+    /// assert_eq!("KQRRBBNNPPPPPPPPvKQRRBBNNPPPPPPPP", start.to_string());
+    /// ```
+    pub fn material_key(&self) -> MaterialKey {
+        let mut key = MaterialKey::default();
+        for color in [White, Black] {
+            for (kind, _) in self.pieces(color) {
+                key.counts[color][kind] += 1;
+            }
+        }
+        key
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::baserules::board::PSBoard;
+    use crate::baserules::material::EndgameClass;
+    use crate::baserules::piece_color::PieceColor::{Black, White};
+    use crate::baserules::score::Score;
+    use tokio::test;
+
+    async fn material(fen: &str) -> (String, Option<EndgameClass>) {
+        let key = PSBoard::from_fen(fen).await.unwrap().material_key();
+        (key.to_string(), key.endgame_class())
+    }
+
+    #[test]
+    async fn signatures_put_the_stronger_side_first() {
+        let start = PSBoard::default().material_key();
+        assert_eq!("KQRRBBNNPPPPPPPPvKQRRBBNNPPPPPPPP", start.to_string());
+        assert_eq!(32, start.piece_count());
+        assert_eq!(None, start.endgame_class());
+
+        let (signature, class) = material("8/8/4k3/8/8/2K5/1p6/1R6 w - - 0 1").await;
+        assert_eq!("KRvKP", signature);
+        assert_eq!(Some(EndgameClass::Rook), class);
+        let black_rook = PSBoard::from_fen("8/8/4k3/8/8/2K5/1P6/1r6 w - - 0 1")
+            .await
+            .unwrap()
+            .material_key();
+        assert_eq!("KRvKP", black_rook.to_string());
+        assert_eq!(Black, black_rook.stronger_side());
+        assert_eq!(White, start.stronger_side());
+    }
+
+    #[test]
+    async fn endgames_are_classified() {
+        for (fen, expected) in [
+            (
+                "8/8/4k3/8/8/2K5/8/8 w - - 0 1",
+                EndgameClass::InsufficientMaterial,
+            ),
+            (
+                "8/8/4k3/8/8/2K5/8/6n1 w - - 0 1",
+                EndgameClass::InsufficientMaterial,
+            ),
+            ("8/8/4k3/8/8/2K5/8/5bn1 w - - 0 1", EndgameClass::LoneKing),
+            ("8/8/4k3/8/8/2K5/8/3Q4 w - - 0 1", EndgameClass::LoneKing),
+            ("8/5p2/4k3/8/8/2K5/1P6/8 w - - 0 1", EndgameClass::Pawn),
+            ("8/5p2/4k3/8/8/2K5/1P6/2R2r2 w - - 0 1", EndgameClass::Rook),
+            ("8/5p2/4k3/8/8/2K5/1P6/2Q2q2 w - - 0 1", EndgameClass::Queen),
+            (
+                "8/5p2/4k3/8/8/2K5/1P6/2B2n2 w - - 0 1",
+                EndgameClass::MinorPiece,
+            ),
+            ("8/5p2/4k3/8/8/2K5/1P6/2R2b2 w - - 0 1", EndgameClass::Mixed),
+        ] {
+            assert_eq!(Some(expected), material(fen).await.1, "{fen}");
+        }
+    }
+
+    #[test]
+    async fn imbalances_adjust_the_flat_values() {
+        let imbalance = |fen: &'static str| async move {
+            PSBoard::from_fen(fen)
+                .await
+                .unwrap()
+                .material_key()
+                .imbalance()
+        };
+        assert_eq!(Score::DRAW, PSBoard::default().material_key().imbalance());
+        // The bishop pair against a bishop and a knight, which gains with the pawns
+        assert_eq!(
+            Score::centipawns(30 - 3 * 6),
+            imbalance("2bnk3/pppppppp/8/8/8/8/PPPPPPPP/2B1KB2 w - - 0 1").await
+        );
+        // The knight gains and the rook loses with the pawns of their side
+        let closed = imbalance("3rk3/pppppppp/8/8/8/8/PPPPPPPP/3NK3 w - - 0 1").await;
+        let open = imbalance("3rk3/ppp5/8/8/8/8/PPP5/3NK3 w - - 0 1").await;
+        assert!(closed > open);
+        // Two minor pieces against a rook
+        assert!(imbalance("3rk3/pppppppp/8/8/8/8/PPPPPPPP/2BNK3 w - - 0 1").await > closed);
+    }
+}
@@ -0,0 +1,105 @@
+/*
+ *  ========================================================================
+ *  DBCE chess bot, mobility term of the evaluation
+ *  ========================================================================
+ *
+ *  This file is part of DBCE.
+ *
+ *  DBCE is free software: you can redistribute it and/or
+ *  modify it under the terms of the GNU General Public License as published
+ *  by the Free Software Foundation, either version 3 of the License, or (at
+ *  your option) any later version.
+ *
+ *  DBCE is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ *  General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License along
+ *  with DBCE.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ *  (C) Copyright 2022-3, Gabor Kecskemeti
+ */
+use crate::baserules::piece_kind::PieceKind;
+use crate::baserules::piece_kind::PieceKind::*;
+use crate::baserules::positions::AbsoluteBoardPos;
+use crate::baserules::rawboard::{bits, RawBoard};
+use crate::baserules::score::Score;
+
+/// The centipawns each pseudo-legal move of the piece is worth. An extra move matters the most
+/// for the minor pieces and the least for the queen, which has plenty anyway.
+fn move_weight(kind: PieceKind) -> i32 {
+    match kind {
+        Knight | Bishop => 4,
+        Rook => 2,
+        Queen => 1,
+        King | Pawn => 0,
+    }
+}
+
+impl RawBoard {
+    /// The weighted number of the pseudo-legal moves of white's pieces minus black's, regardless
+    /// of whose turn it is. The moves are only counted, not generated.
+    pub fn mobility(&self) -> Score {
+        Score::centipawns(
+            bits(self.occupied())
+                .filter_map(|square| {
+                    self[AbsoluteBoardPos::from(square)].map(|piece| (square, piece))
+                })
+                .map(|(square, piece)| {
+                    let moves = piece.kind.count_moves(self, square.into(), piece.color) as i32;
+                    piece.color.mate_multiplier() * move_weight(piece.kind) * moves
+                })
+                .sum(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::baserules::board::PSBoard;
+    use crate::baserules::move_list::MoveList;
+    use crate::baserules::piece_kind::PieceKind::{King, Pawn};
+    use crate::baserules::positions::{AbsoluteBoardPos, Square};
+    use crate::baserules::score::Score;
+    use tokio::test;
+
+    #[test]
+    async fn counted_moves_match_the_generated_ones() {
+        let board = PSBoard::from_fen(
+            "r1bqk2r/pppp1ppp/2n2n2/2b1p3/2B1P3/3P1N2/PPP2PPP/RNBQK2R w KQkq - 1 5",
+        )
+        .await
+        .unwrap();
+        let mut moves = MoveList::new();
+        board.gen_potential_moves(&mut moves);
+        for square in Square::all() {
+            let pos: AbsoluteBoardPos = square.into();
+            let Some(piece) = board.raw[pos] else {
+                continue;
+            };
+            if piece.color != board.who_moves || matches!(piece.kind, Pawn | King) {
+                continue;
+            }
+            let generated = moves
+                .iter()
+                .filter(|a_move| a_move.the_move.from == pos)
+                .count();
+            assert_eq!(
+                generated as u32,
+                piece.kind.count_moves(&board.raw, pos, piece.color),
+                "{piece:?} on {square}"
+            );
+        }
+    }
+
+    #[test]
+    async fn developed_pieces_are_more_mobile() {
+        assert_eq!(Score::DRAW, PSBoard::default().raw.mobility());
+        let developed =
+            PSBoard::from_fen("rnbqkbnr/pppppppp/8/8/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2")
+                .await
+                .unwrap();
+        assert!(developed.raw.mobility() > Score::DRAW);
+    }
+}
@@ -23,21 +23,20 @@
 
 use crate::baserules::board::PSBoard;
 use crate::baserules::board_rep::{BaseMove, PossibleMove};
+use crate::baserules::move_list::MoveList;
+use crate::baserules::piece_color::PieceColor;
 use crate::baserules::piece_kind::PieceKind;
 use crate::baserules::piece_kind::PieceKind::*;
-use crate::baserules::positions::{AbsoluteBoardPos, RelativeBoardPos};
-use crate::util::TryWithPanic;
+use crate::baserules::piece_kind::{KING_ATTACKS, KNIGHT_ATTACKS};
+use crate::baserules::positions::{AbsoluteBoardPos, RelativeBoardPos, Square};
+use crate::baserules::rawboard::bits;
+use futures_util::{stream, Stream, StreamExt};
 use lazy_static::lazy_static;
 use std::cell::RefCell;
 use std::cmp::{max, min};
 
 pub(crate) trait KingMove: Sync {
-    fn gen_king_moves(
-        &self,
-        board: &PSBoard,
-        ab: AbsoluteBoardPos,
-        the_moves: &mut Vec<PossibleMove>,
-    );
+    fn gen_king_moves(&self, board: &PSBoard, ab: AbsoluteBoardPos, the_moves: &mut MoveList);
 }
 
 pub struct Castle(pub &'static NotCastle);
@@ -45,46 +44,39 @@ pub struct Castle(pub &'static NotCastle);
 pub static CASTLE_ALLOWED: Castle = Castle(&CASTLE_FORBIDDEN);
 
 impl KingMove for Castle {
-    fn gen_king_moves(
-        &self,
-        board: &PSBoard,
-        ab: AbsoluteBoardPos,
-        the_moves: &mut Vec<PossibleMove>,
-    ) {
+    fn gen_king_moves(&self, board: &PSBoard, ab: AbsoluteBoardPos, the_moves: &mut MoveList) {
         self.0.gen_king_moves(board, ab, the_moves);
         // Castling:
         // the king is in its original location we need a more in depth check on castling
-        if ab == board.who_moves.starting_king_pos() {
+        if ab == AbsoluteBoardPos(board.who_moves.piece_row(), board.castling_files.king) {
             // A particular castling direction is allowed:
             for current_castling in board
                 .castling
                 .iter()
                 .filter(|a_castling| board.who_moves.all_castling().contains(*a_castling))
             {
-                let castling_move: &PossibleMove = current_castling.into();
-                let mincol = min(castling_move.the_move.from.1, castling_move.the_move.to.1);
-                let maxcol = max(castling_move.the_move.from.1, castling_move.the_move.to.1);
-                // castling range is free of chess pieces
-                if (mincol..=maxcol).all(|in_between_col| {
-                    board[(castling_move.the_move.from.0, in_between_col)].is_none()
+                let castling_move = board.castling_move(current_castling);
+                let (king, rook) = (castling_move.the_move, castling_move.rook.unwrap());
+                let mincol = min(king.from.1, king.to.1);
+                let maxcol = max(king.from.1, king.to.1);
+                // the squares the king and the rook pass are free of other chess pieces, in
+                // standard chess the squares between them
+                let cols = [king.from.1, king.to.1, rook.from.1, rook.to.1];
+                let row = king.from.0;
+                if (*cols.iter().min().unwrap()..=*cols.iter().max().unwrap()).all(|col| {
+                    col == king.from.1 || col == rook.from.1 || board[(row, col)].is_none()
                 }) {
                     // Is it free of potential checks on our king?
                     // Let's see if we would cross a check
-                    let otherside = board.switch_sides();
-                    let crosses_check = {
-                        let mut other_side_moves = Vec::new();
-                        {
-                            otherside.gen_potential_moves(&mut other_side_moves);
-                        }
-                        other_side_moves.iter().any(|m| {
-                            m.the_move.to.0 == castling_move.the_move.from.0
-                                && m.the_move.to.1 <= maxcol
-                                && m.the_move.to.1 >= mincol
-                        })
-                    };
+                    let crosses_check = (mincol..=maxcol).any(|col| {
+                        board.is_square_attacked(
+                            AbsoluteBoardPos(castling_move.the_move.from.0, col),
+                            board.who_moves.invert(),
+                        )
+                    });
                     if !crosses_check {
                         // would not cross check, the move is ok to emit
-                        the_moves.push(*castling_move);
+                        the_moves.push(castling_move);
                     }
                 }
             }
@@ -100,35 +92,13 @@ impl KingMove for NotCastle {
         &self,
         board: &PSBoard,
         position: AbsoluteBoardPos,
-        the_moves: &mut Vec<PossibleMove>,
+        the_moves: &mut MoveList,
     ) {
-        board.gen_moves_from_dirs(
-            position,
-            &PSBoard::piece_move_rule,
-            King.vec_moves(),
-            the_moves,
-        );
+        board.gen_moves_from_table(position, &KING_ATTACKS, the_moves);
     }
 }
 
 impl PSBoard {
-    /// does a dirty side switch to allow seeing castling issues
-    pub(crate) fn switch_sides(&self) -> PSBoard {
-        PSBoard {
-            who_moves: self.who_moves.invert(),
-            king_move_gen: &CASTLE_FORBIDDEN,
-            ..*self
-        }
-    }
-
-    /// Pieces can move and take till they hit another piece, assuming it is not the same colour
-    #[inline]
-    pub(crate) fn piece_move_rule(&self, pos: AbsoluteBoardPos) -> bool {
-        self[pos]
-            .as_ref()
-            .map_or(true, |other_piece| other_piece.color != self.who_moves)
-    }
-
     /// Pawns can only move to empty spaces, cannot take in forward movement
     #[inline]
     pub(crate) fn pawn_move_rule(&self, pos: AbsoluteBoardPos) -> bool {
@@ -180,7 +150,7 @@ impl PSBoard {
         position: AbsoluteBoardPos,
         on_board_rule: &J,
         possible_moves: I,
-        out: &mut Vec<PossibleMove>,
+        out: &mut MoveList,
     ) where
         J: Fn(&Self, AbsoluteBoardPos) -> bool,
     {
@@ -201,7 +171,7 @@ impl PSBoard {
         position: AbsoluteBoardPos,
         on_board_rule: &J,
         possible_moves: I,
-        out: &mut Vec<PossibleMove>,
+        out: &mut MoveList,
     ) where
         I: IntoIterator<Item = &'a RelativeBoardPos>,
         J: Fn(&Self, AbsoluteBoardPos) -> bool,
@@ -214,12 +184,27 @@ impl PSBoard {
         );
     }
 
+    /// The moves to the squares of the table entry of the position that are not taken by the
+    /// pieces of the side to move (useful for knights and kings)
+    pub(crate) fn gen_moves_from_table(
+        &self,
+        position: AbsoluteBoardPos,
+        table: &[u64; Square::COUNT],
+        out: &mut MoveList,
+    ) {
+        let targets = table[Square::from(position).index()] & !self.raw.occupied_by(self.who_moves);
+        out.extend(
+            bits(targets)
+                .map(|square| PossibleMove::from(BaseMove::from_two_pos(position, square.into()))),
+        );
+    }
+
     /// This generates moves based on directional vectors (useful for rooks, bishops and queens)
     pub(crate) fn gen_moves_from_vecs<'a, I: IntoIterator<Item = &'a RelativeBoardPos>>(
         &self,
         position: AbsoluteBoardPos,
         vecs: I,
-        out: &mut Vec<PossibleMove>,
+        out: &mut MoveList,
     ) {
         // transform vec rel board
         for RelativeBoardPos(x, y) in vecs {
@@ -267,43 +252,340 @@ impl PSBoard {
     /// ```
     /// use dbce::baserules::board::PSBoard;
     /// use dbce::baserules::board_rep::BaseMove;
+    /// use dbce::baserules::move_list::MoveList;
     /// let board = PSBoard::default();
-    /// let mut moves = Vec::new();
+    /// let mut moves = MoveList::new();
     /// board.gen_potential_moves(&mut moves);
     /// let van_geet_opening = BaseMove::from_uci("b1c3").unwrap();
     /// let van_geet_opening_found = moves.iter().any(|m| m.the_move == van_geet_opening);
     /// assert!(van_geet_opening_found);
     /// ```
-    pub fn gen_potential_moves(&self, the_moves: &mut Vec<PossibleMove>) {
-        self.raw
+    pub fn gen_potential_moves(&self, the_moves: &mut MoveList) {
+        for square in bits(self.raw.occupied_by(self.who_moves)) {
+            let pos = AbsoluteBoardPos::from(square);
+            if let Some(piece) = self[pos] {
+                piece.kind.gen_moves(self, pos, the_moves);
+            }
+        }
+    }
+
+    /// Tells if the side to move could take the opponent's king, i.e. the previous move was illegal
+    pub(crate) fn can_capture_king(&self) -> bool {
+        self.king_position(self.who_moves.invert())
+            .is_some_and(|king| self.is_square_attacked(king, self.who_moves))
+    }
+
+    /// The potential moves not leaving the king of the side to move attacked. The pieces pinned
+    /// to the king only keep their moves along the pin, the other pieces move freely. In check
+    /// only the evasions of `gen_evasions` are generated. Only the moves of the king and the en
+    /// passant captures are tried on the board. Without a king there are no legal moves.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use dbce::baserules::board::PSBoard;
+    /// use dbce::baserules::move_list::MoveList;
+    /// let mut moves = MoveList::new();
+    /// PSBoard::default().gen_legal_moves(&mut moves);
+    /// assert_eq!(20, moves.len());
+    /// ```
+    pub fn gen_legal_moves(&self, the_moves: &mut MoveList) {
+        let Some(king) = self.king_position(self.who_moves) else {
+            return;
+        };
+        let checkers: Vec<_> = self.checkers().collect();
+        let pins = self.pins(king);
+        if !checkers.is_empty() {
+            self.gen_evasions_from(king, &checkers, &pins, the_moves);
+            return;
+        }
+        let mut candidates = MoveList::new();
+        self.gen_potential_moves(&mut candidates);
+        the_moves.extend(candidates.into_iter().filter(|a_move| {
+            let BaseMove { from, to } = a_move.the_move;
+            let en_passant = self.ep == Some(to) && self[from].is_some_and(|p| p.kind == Pawn);
+            if from == king || en_passant {
+                !self.leaves_king_attacked(a_move)
+            } else {
+                keeps_pin(king, &pins, a_move)
+            }
+        }));
+    }
+
+    /// The legal moves out of the check of the side to move: the moves of the king, and unless
+    /// two pieces give the check, the moves taking the checking piece or stepping in front of it.
+    /// Only the pieces able to reach these squares are asked for their moves, the rest of the
+    /// pieces are not looked at. Nothing is generated when the king is not in check.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use dbce::baserules::board::PSBoard;
+    /// use dbce::baserules::move_list::MoveList;
+    /// let mut moves = MoveList::new();
+    /// PSBoard::default().gen_evasions(&mut moves);
+    /// assert!(moves.is_empty());
+    /// ```
+    pub fn gen_evasions(&self, the_moves: &mut MoveList) {
+        let Some(king) = self.king_position(self.who_moves) else {
+            return;
+        };
+        let checkers: Vec<_> = self.checkers().collect();
+        if !checkers.is_empty() {
+            self.gen_evasions_from(king, &checkers, &self.pins(king), the_moves);
+        }
+    }
+
+    /// The moves the searches try on the board: the evasions in check, all the potential moves
+    /// otherwise
+    pub fn gen_search_moves(&self, the_moves: &mut MoveList) {
+        if self.is_in_check() {
+            self.gen_evasions(the_moves);
+        } else {
+            self.gen_potential_moves(the_moves);
+        }
+    }
+
+    /// The potential moves of the side to move taking a piece, en passant and the promotions
+    /// taking a piece included. The quiet moves are not generated, so the searches trying the
+    /// captures first do not pay for them.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use dbce::baserules::board::PSBoard;
+    /// use dbce::baserules::move_list::MoveList;
+    /// let mut moves = MoveList::new();
+    /// PSBoard::default().gen_captures(&mut moves);
+    /// assert!(moves.is_empty());
+    /// ```
+    pub fn gen_captures(&self, the_moves: &mut MoveList) {
+        let opponents = self.raw.occupied_by(self.who_moves.invert());
+        for square in bits(self.raw.occupied_by(self.who_moves)) {
+            let pos = AbsoluteBoardPos::from(square);
+            let Some(piece) = self[pos] else {
+                continue;
+            };
+            match piece.kind {
+                Knight | King => {
+                    let table = if piece.kind == Knight {
+                        &KNIGHT_ATTACKS
+                    } else {
+                        &KING_ATTACKS
+                    };
+                    the_moves.extend(bits(table[square.index()] & opponents).map(|target| {
+                        PossibleMove::from(BaseMove::from_two_pos(pos, target.into()))
+                    }));
+                }
+                // The pawns capture diagonally, the pushes keep their column
+                Pawn => {
+                    let mut pawn_moves = MoveList::new();
+                    self.gen_pawn_moves(pos, &mut pawn_moves);
+                    the_moves.extend(
+                        pawn_moves
+                            .into_iter()
+                            .filter(|a_move| a_move.the_move.from.1 != a_move.the_move.to.1),
+                    );
+                }
+                Bishop | Rook | Queen => {
+                    for &RelativeBoardPos(row_step, col_step) in piece.kind.vec_moves() {
+                        let mut target = pos;
+                        while let Some(next) = step(target, row_step, col_step) {
+                            target = next;
+                            if let Some(other) = self[target] {
+                                if other.color != self.who_moves {
+                                    the_moves.push(BaseMove::from_two_pos(pos, target).into());
+                                }
+                                break;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn gen_evasions_from(
+        &self,
+        king: AbsoluteBoardPos,
+        checkers: &[AbsoluteBoardPos],
+        pins: &[(AbsoluteBoardPos, (i8, i8))],
+        the_moves: &mut MoveList,
+    ) {
+        let mut king_moves = MoveList::new();
+        self.gen_king_moves(king, &mut king_moves);
+        the_moves.extend(
+            king_moves
+                .into_iter()
+                .filter(|a_move| !self.leaves_king_attacked(a_move)),
+        );
+        let [checker] = checkers else {
+            return;
+        };
+        let mut targets = squares_between(king, *checker);
+        targets.push(*checker);
+        // The pieces attacking a target and the pawns pushed to it, the en passant captures may
+        // remove a checking pawn
+        let pawn_row = self.who_moves.pawn_single_step()[0].0;
+        let mut origins = Vec::new();
+        for &target in targets.iter().chain(self.ep.iter()) {
+            origins.extend(self.attackers_of(target, self.who_moves));
+            origins.extend((1..=2).filter_map(|steps| step(target, -pawn_row * steps, 0)));
+        }
+        origins.sort_unstable_by_key(|square| (square.0, square.1));
+        origins.dedup();
+        for origin in origins {
+            let Some(piece) = self[origin].filter(|p| p.color == self.who_moves && p.kind != King)
+            else {
+                continue;
+            };
+            let mut moves = MoveList::new();
+            piece.kind.gen_moves(self, origin, &mut moves);
+            the_moves.extend(moves.into_iter().filter(|a_move| {
+                let to = a_move.the_move.to;
+                if piece.kind == Pawn && self.ep == Some(to) {
+                    !self.leaves_king_attacked(a_move)
+                } else {
+                    targets.contains(&to) && keeps_pin(king, pins, a_move)
+                }
+            }));
+        }
+    }
+
+    /// The squares of the pieces checking the king of the side to move
+    pub fn checkers(&self) -> impl Iterator<Item = AbsoluteBoardPos> + '_ {
+        self.king_position(self.who_moves)
             .into_iter()
-            .enumerate()
-            .filter_map(|(idx, possible_piece)| {
-                possible_piece.map_or(None, |piece| {
+            .flat_map(|king| self.attackers_of(king, self.who_moves.invert()))
+    }
+
+    /// The squares of the pieces of the side to move that can not leave the line between their
+    /// king and the opponent's bishop, rook or queen attacking it through them
+    pub fn pinned_pieces(&self) -> impl Iterator<Item = AbsoluteBoardPos> {
+        self.king_position(self.who_moves)
+            .map(|king| self.pins(king))
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(pinned, _)| pinned)
+    }
+
+    /// The square of the king of the color, if it is on the board
+    #[inline]
+    pub(crate) fn king_position(&self, color: PieceColor) -> Option<AbsoluteBoardPos> {
+        self.kings[color]
+    }
+
+    /// The pieces of the side to move pinned to its king, with the direction of the pin from
+    /// the king
+    fn pins(&self, king: AbsoluteBoardPos) -> Vec<(AbsoluteBoardPos, (i8, i8))> {
+        let mut pins = Vec::new();
+        for &RelativeBoardPos(row_step, col_step) in Queen.vec_moves() {
+            let diagonal = row_step != 0 && col_step != 0;
+            let mut pinned = None;
+            let mut square = king;
+            while let Some(next) = step(square, row_step, col_step) {
+                square = next;
+                if let Some(piece) = self[square] {
                     if piece.color == self.who_moves {
-                        Some((idx, piece))
+                        if pinned.is_some() {
+                            break;
+                        }
+                        pinned = Some(square);
                     } else {
-                        None
+                        let slides_here = piece.kind == Queen
+                            || piece.kind == if diagonal { Bishop } else { Rook };
+                        if let Some(pinned) = pinned.filter(|_| slides_here) {
+                            pins.push((pinned, (row_step, col_step)));
+                        }
+                        break;
                     }
-                })
-            })
-            .for_each(|(idx, piece)| piece.kind.gen_moves(self, idx.transform(), the_moves));
+                }
+            }
+        }
+        pins
     }
 
-    pub(crate) fn gen_king_moves(
+    /// The squares of the pieces of the color attacking the position, whoever is to move. The
+    /// attackers pinned to their own king are listed too.
+    pub fn attackers_of(
         &self,
-        position: AbsoluteBoardPos,
-        the_moves: &mut Vec<PossibleMove>,
-    ) {
+        pos: AbsoluteBoardPos,
+        color: PieceColor,
+    ) -> impl Iterator<Item = AbsoluteBoardPos> {
+        let mut attackers = Vec::new();
+        let attacks = |square: AbsoluteBoardPos, kinds: &[PieceKind]| {
+            self[square].is_some_and(|piece| piece.color == color && kinds.contains(&piece.kind))
+        };
+        let square = Square::from(pos).index();
+        let leapers = KNIGHT_ATTACKS[square] & self.raw.pieces_of(Knight, color)
+            | KING_ATTACKS[square] & self.raw.pieces_of(King, color);
+        attackers.extend(bits(leapers).map(AbsoluteBoardPos::from));
+        // The pawns attack the position from the row behind it
+        let pawn_row = -color.pawn_single_step()[0].0;
+        for col_step in [-1, 1] {
+            if let Some(square) = step(pos, pawn_row, col_step).filter(|sq| attacks(*sq, &[Pawn])) {
+                attackers.push(square);
+            }
+        }
+        for &RelativeBoardPos(row_step, col_step) in Queen.vec_moves() {
+            let slider = if row_step != 0 && col_step != 0 {
+                Bishop
+            } else {
+                Rook
+            };
+            let mut square = pos;
+            while let Some(next) = step(square, row_step, col_step) {
+                square = next;
+                if self[square].is_some() {
+                    if attacks(square, &[slider, Queen]) {
+                        attackers.push(square);
+                    }
+                    break;
+                }
+            }
+        }
+        attackers.into_iter()
+    }
+
+    /// Tells if a piece of the color attacks the position, whoever is to move
+    pub fn is_square_attacked(&self, pos: AbsoluteBoardPos, by_color: PieceColor) -> bool {
+        self.attackers_of(pos, by_color).next().is_some()
+    }
+
+    /// The legal moves of the side to move with the boards they lead to. A board is only made
+    /// when the stream gets to its move, so consumers stopping early do not pay for the rest.
+    pub fn successors(&self) -> impl Stream<Item = (PossibleMove, PSBoard)> + '_ {
+        let mut moves = MoveList::new();
+        self.gen_legal_moves(&mut moves);
+        stream::iter(moves)
+            .then(move |a_move| async move { (a_move, self.make_move_noncached(&a_move).await) })
+    }
+
+    /// Tells if the side to move has a move not leaving its king in check
+    pub fn has_legal_move(&self) -> bool {
+        let mut moves = MoveList::new();
+        self.gen_legal_moves(&mut moves);
+        !moves.is_empty()
+    }
+
+    /// Tells if the king of the side to move is attacked, as found when the board was made
+    #[inline]
+    pub fn is_in_check(&self) -> bool {
+        self.in_check
+    }
+
+    /// Looks for the attacks on the king of the side to move, `is_in_check` keeps the result
+    pub(crate) fn king_attacked(&self) -> bool {
+        self.king_position(self.who_moves)
+            .is_some_and(|king| self.is_square_attacked(king, self.who_moves.invert()))
+    }
+
+    pub(crate) fn gen_king_moves(&self, position: AbsoluteBoardPos, the_moves: &mut MoveList) {
         self.king_move_gen.gen_king_moves(self, position, the_moves);
     }
 
     /// Pawn moves, takes and promotions
-    pub(crate) fn gen_pawn_moves(
-        &self,
-        position: AbsoluteBoardPos,
-        the_moves: &mut Vec<PossibleMove>,
-    ) {
+    pub(crate) fn gen_pawn_moves(&self, position: AbsoluteBoardPos, the_moves: &mut MoveList) {
         // normal pawn move
         let prelen = the_moves.len();
         let pawn_move_now = if position.0 == self.who_moves.pawn_starting_row() {
@@ -353,6 +635,44 @@ impl PSBoard {
     }
 }
 
+/// The squares on the line strictly between the two positions, the positions not on a common
+/// rank, file or diagonal have none between them
+fn squares_between(from: AbsoluteBoardPos, to: AbsoluteBoardPos) -> Vec<AbsoluteBoardPos> {
+    let (rows, cols) = (to.0 as i8 - from.0 as i8, to.1 as i8 - from.1 as i8);
+    let mut squares = Vec::new();
+    if rows != 0 && cols != 0 && rows.abs() != cols.abs() {
+        return squares;
+    }
+    let mut current = from;
+    while let Some(next) = step(current, rows.signum(), cols.signum()).filter(|sq| *sq != to) {
+        squares.push(next);
+        current = next;
+    }
+    squares
+}
+
+/// Tells if the move keeps a piece pinned to the king along its pin, between the king and the
+/// pinning piece or taking it. The pieces not pinned move freely.
+fn keeps_pin(
+    king: AbsoluteBoardPos,
+    pins: &[(AbsoluteBoardPos, (i8, i8))],
+    a_move: &PossibleMove,
+) -> bool {
+    let BaseMove { from, to } = a_move.the_move;
+    let Some((_, (row_step, col_step))) = pins.iter().find(|(pinned, _)| *pinned == from) else {
+        return true;
+    };
+    let (rows, cols) = (to.0 as i8 - king.0 as i8, to.1 as i8 - king.1 as i8);
+    rows * col_step == cols * row_step && rows.signum() == *row_step && cols.signum() == *col_step
+}
+
+/// The square the given steps away from the position, if it is still on the board
+fn step(pos: AbsoluteBoardPos, row_step: i8, col_step: i8) -> Option<AbsoluteBoardPos> {
+    let (row, col) = (pos.0 as i8 + row_step, pos.1 as i8 + col_step);
+    ((0..8).contains(&row) && (0..8).contains(&col))
+        .then_some(AbsoluteBoardPos(row as u8, col as u8))
+}
+
 fn directional_mapper<F>(mapper: F) -> Vec<RelativeBoardPos>
 where
     F: Fn(i8) -> (i8, i8),
@@ -364,7 +684,11 @@ where
 mod test {
     use crate::baserules::board::PSBoard;
     use crate::baserules::board_rep::{BaseMove, PossibleMove};
+    use crate::baserules::move_list::MoveList;
+    use crate::baserules::piece_color::PieceColor;
     use crate::baserules::piece_kind::PieceKind;
+    use crate::baserules::positions::AbsoluteBoardPos;
+    use futures_util::StreamExt;
     use std::collections::HashSet;
 
     use crate::util::TryWithPanic;
@@ -375,7 +699,7 @@ mod test {
             PSBoard::from_fen("r1b1kbnr/pppn1ppp/4p3/7Q/4Pq2/8/PPPP1PPP/RNB1K1NR w KQkq - 2 5")
                 .await
                 .unwrap();
-        let mut moves = Vec::new();
+        let mut moves = MoveList::new();
         board.gen_king_moves("e1".transform(), &mut moves);
         let unacceptable_moves = [
             PossibleMove {
@@ -400,7 +724,7 @@ mod test {
         let board = PSBoard::from_fen("2b2rk1/p2p1ppp/8/P7/R2PPP2/2K5/7r/1R6 w - - 0 28")
             .await
             .unwrap();
-        let mut moves = Vec::new();
+        let mut moves = MoveList::new();
         PieceKind::Rook.gen_moves(&board, "b1".transform(), &mut moves);
         let expected_moves: HashSet<String> = HashSet::from([
             "b1a1".into(),
@@ -421,4 +745,188 @@ mod test {
         let found_moves: HashSet<_> = moves.iter().map(|amove| format!("{amove}")).collect();
         assert_eq!(expected_moves, found_moves);
     }
+
+    #[tokio::test]
+    async fn successors_are_the_legal_moves() {
+        let initial: Vec<_> = PSBoard::default().successors().collect().await;
+        assert_eq!(20, initial.len());
+        assert!(initial
+            .iter()
+            .all(|(_, next_board)| next_board.who_moves == PieceColor::Black));
+
+        // Only the king can move out of the rook's check, and not along the rank
+        let checked = PSBoard::from_fen("4k3/8/8/8/8/8/8/r3K3 w - - 0 1")
+            .await
+            .unwrap();
+        let escapes: HashSet<String> = checked
+            .successors()
+            .map(|(a_move, _)| a_move.to_string())
+            .collect()
+            .await;
+        assert_eq!(
+            HashSet::from(["e1e2".into(), "e1d2".into(), "e1f2".into()]),
+            escapes
+        );
+    }
+
+    #[tokio::test]
+    async fn legal_moves_keep_the_king_safe() {
+        for fen in [
+            // The rook is pinned along the file
+            "4k3/8/8/8/4r3/8/4R3/4K3 w - - 0 1",
+            // The bishop is pinned along the diagonal, the knight by the queen
+            "4k3/8/8/q7/8/2B5/8/4KN1r w - - 0 1",
+            // Taking en passant would open the rank to the rook
+            "8/8/8/KPp4r/8/8/8/4k3 w - c6 0 1",
+            // Blocking, taking or escaping the check
+            "4k3/8/8/1b6/8/8/3P4/R3K1N1 w - - 0 1",
+            "4k3/8/8/b7/4N3/8/8/R3K3 w - - 0 1",
+            // Only the king can escape the double check
+            "4k3/8/8/8/8/5n2/8/r3K2R w - - 0 1",
+            // Taking the checking pawn en passant
+            "8/8/8/2k5/3Pp3/8/8/4K3 b - d3 0 1",
+            "r1bqkbnr/pppp1ppp/2n5/4p3/2B1P3/5Q2/PPPP1PPP/RNB1K1NR w KQkq - 2 3",
+        ] {
+            let board = PSBoard::from_fen(fen).await.unwrap();
+            let mut legal = MoveList::new();
+            board.gen_legal_moves(&mut legal);
+            let mut potential = MoveList::new();
+            board.gen_potential_moves(&mut potential);
+            let mut expected = HashSet::new();
+            for a_move in potential {
+                let next_board = board.make_move_noncached(&a_move).await;
+                if !next_board.can_capture_king() {
+                    expected.insert(a_move.to_string());
+                }
+            }
+            assert_eq!(legal.len(), expected.len(), "{fen}");
+            assert_eq!(
+                expected,
+                legal.iter().map(|a_move| a_move.to_string()).collect(),
+                "{fen}"
+            );
+        }
+        let pinned = PSBoard::from_fen("4k3/8/8/8/4r3/8/4R3/4K3 w - - 0 1")
+            .await
+            .unwrap();
+        let mut moves = MoveList::new();
+        pinned.gen_legal_moves(&mut moves);
+        assert!(moves.contains(&PossibleMove::simple_from_uci("e2e4").unwrap()));
+        assert!(!moves.contains(&PossibleMove::simple_from_uci("e2d2").unwrap()));
+    }
+
+    #[tokio::test]
+    async fn captures_are_the_potential_moves_taking_a_piece() {
+        for fen in [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+            "r3k2r/1P6/8/8/8/8/8/R3K2R w KQkq - 0 1",
+        ] {
+            let board = PSBoard::from_fen(fen).await.unwrap();
+            let mut captures = MoveList::new();
+            board.gen_captures(&mut captures);
+            let mut potential = MoveList::new();
+            board.gen_potential_moves(&mut potential);
+            let expected: HashSet<PossibleMove> = potential
+                .into_iter()
+                .filter(|a_move| board.is_capture(a_move))
+                .collect();
+            assert_eq!(expected.len(), captures.len(), "{fen}");
+            assert_eq!(expected, captures.into_iter().collect(), "{fen}");
+        }
+    }
+
+    #[tokio::test]
+    async fn evasions_get_out_of_the_check() {
+        let moves = |board: &PSBoard| -> HashSet<String> {
+            let mut moves = MoveList::new();
+            board.gen_evasions(&mut moves);
+            moves.iter().map(|a_move| a_move.to_string()).collect()
+        };
+        // The bishop's check is blocked by the pawn or escaped by the king, the rook does not
+        // reach the diagonal
+        let blocked = PSBoard::from_fen("4k3/8/8/8/1b6/8/2P5/R3K3 w - - 0 1")
+            .await
+            .unwrap();
+        assert_eq!(
+            HashSet::from([
+                "c2c3".into(),
+                "e1d1".into(),
+                "e1e2".into(),
+                "e1f1".into(),
+                "e1f2".into()
+            ]),
+            moves(&blocked)
+        );
+        // Promoting the pawn while taking the checking rook
+        let promotion = PSBoard::from_fen("1r2k3/P7/8/8/8/8/8/1K6 w - - 0 1")
+            .await
+            .unwrap();
+        assert_eq!(
+            HashSet::from([
+                "a7b8q".into(),
+                "a7b8r".into(),
+                "a7b8b".into(),
+                "a7b8n".into(),
+                "b1a1".into(),
+                "b1a2".into(),
+                "b1c1".into(),
+                "b1c2".into(),
+            ]),
+            moves(&promotion)
+        );
+        // The checked side runs out of evasions
+        let mated = PSBoard::from_fen("R5k1/5ppp/8/8/8/8/8/6K1 b - - 0 1")
+            .await
+            .unwrap();
+        assert!(mated.is_in_check());
+        assert!(moves(&mated).is_empty());
+    }
+
+    #[tokio::test]
+    async fn checkers_and_pinned_pieces_are_found() {
+        let squares = |squares: &[&str]| -> HashSet<String> {
+            squares.iter().map(|square| square.to_string()).collect()
+        };
+        let double_check = PSBoard::from_fen("4k3/8/8/8/8/5n2/8/r3K2R w - - 0 1")
+            .await
+            .unwrap();
+        assert_eq!(
+            squares(&["a1", "f3"]),
+            double_check.checkers().map(|sq| sq.to_string()).collect()
+        );
+        assert_eq!(0, double_check.pinned_pieces().count());
+        let pinned = PSBoard::from_fen("4k3/8/8/q7/8/2B5/8/4KN1r w - - 0 1")
+            .await
+            .unwrap();
+        assert_eq!(0, pinned.checkers().count());
+        assert_eq!(
+            squares(&["c3", "f1"]),
+            pinned.pinned_pieces().map(|sq| sq.to_string()).collect()
+        );
+    }
+
+    #[tokio::test]
+    async fn attackers_are_found_behind_the_empty_squares() {
+        let board = PSBoard::from_fen("4k3/8/8/4r3/8/2NP1B2/8/4QK2 w - - 0 1")
+            .await
+            .unwrap();
+        let e4: AbsoluteBoardPos = "e4".transform();
+        let white: HashSet<String> = board
+            .attackers_of(e4, PieceColor::White)
+            .map(|square| square.to_string())
+            .collect();
+        assert_eq!(
+            HashSet::from(["c3".into(), "d3".into(), "f3".into(), "e1".into()]),
+            white
+        );
+        let e5: AbsoluteBoardPos = "e5".transform();
+        let black: Vec<_> = board.attackers_of(e4, PieceColor::Black).collect();
+        assert_eq!(vec![e5], black);
+        assert!(board.is_square_attacked("a8".transform(), PieceColor::White));
+        assert!(!board.is_square_attacked("h8".transform(), PieceColor::White));
+        // The rook is in front of the queen
+        assert!(!board.is_square_attacked("e6".transform(), PieceColor::White));
+        assert!(!board.is_in_check());
+    }
 }
@@ -0,0 +1,176 @@
+/*
+ *  ========================================================================
+ *  DBCE chess bot, fixed capacity lists of moves kept on the stack
+ *  ========================================================================
+ *
+ *  This file is part of DBCE.
+ *
+ *  DBCE is free software: you can redistribute it and/or
+ *  modify it under the terms of the GNU General Public License as published
+ *  by the Free Software Foundation, either version 3 of the License, or (at
+ *  your option) any later version.
+ *
+ *  DBCE is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ *  General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License along
+ *  with DBCE.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ *  (C) Copyright 2022-3, Gabor Kecskemeti
+ */
+use crate::baserules::board_rep::PossibleMove;
+use std::fmt::{Debug, Formatter};
+use std::ops::{Deref, DerefMut};
+
+/// More moves than any chess position has, the most known is 218
+pub const MAX_MOVES: usize = 256;
+
+/// The moves generated for a board, kept in place instead of on the heap. The searches can clear
+/// and refill a list for each ply instead of allocating new ones for every board.
+///
+/// # Example
+///
+/// ```
+/// use dbce::baserules::board::PSBoard;
+/// use dbce::baserules::move_list::MoveList;
+/// let mut moves = MoveList::new();
+/// PSBoard::default().gen_potential_moves(&mut moves);
+/// assert_eq!(20, moves.len());
+/// moves.clear();
+/// assert!(moves.is_empty());
+/// ```
+#[derive(Clone)]
+pub struct MoveList {
+    moves: [PossibleMove; MAX_MOVES],
+    len: usize,
+}
+
+impl MoveList {
+    pub fn new() -> Self {
+        MoveList {
+            moves: [PossibleMove::default(); MAX_MOVES],
+            len: 0,
+        }
+    }
+
+    /// Adds the move to the end of the list
+    /// # Panics
+    /// When the list already has `MAX_MOVES` moves
+    #[inline]
+    pub fn push(&mut self, a_move: PossibleMove) {
+        self.moves[self.len] = a_move;
+        self.len += 1;
+    }
+
+    #[inline]
+    pub fn pop(&mut self) -> Option<PossibleMove> {
+        self.len = self.len.checked_sub(1)?;
+        Some(self.moves[self.len])
+    }
+
+    #[inline]
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    /// Keeps the first moves of the list, at most `len` of them
+    #[inline]
+    pub fn truncate(&mut self, len: usize) {
+        self.len = self.len.min(len);
+    }
+
+    /// Keeps only the moves the predicate accepts, in their original order
+    pub fn retain(&mut self, mut keep: impl FnMut(&PossibleMove) -> bool) {
+        let mut kept = 0;
+        for idx in 0..self.len {
+            if keep(&self.moves[idx]) {
+                self.moves[kept] = self.moves[idx];
+                kept += 1;
+            }
+        }
+        self.len = kept;
+    }
+}
+
+impl Default for MoveList {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Deref for MoveList {
+    type Target = [PossibleMove];
+
+    fn deref(&self) -> &Self::Target {
+        &self.moves[..self.len]
+    }
+}
+
+impl DerefMut for MoveList {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.moves[..self.len]
+    }
+}
+
+impl Debug for MoveList {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl Extend<PossibleMove> for MoveList {
+    fn extend<T: IntoIterator<Item = PossibleMove>>(&mut self, iter: T) {
+        for a_move in iter {
+            self.push(a_move);
+        }
+    }
+}
+
+impl FromIterator<PossibleMove> for MoveList {
+    fn from_iter<T: IntoIterator<Item = PossibleMove>>(iter: T) -> Self {
+        let mut moves = MoveList::new();
+        moves.extend(iter);
+        moves
+    }
+}
+
+impl IntoIterator for MoveList {
+    type Item = PossibleMove;
+    type IntoIter = std::iter::Take<std::array::IntoIter<PossibleMove, MAX_MOVES>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.moves.into_iter().take(self.len)
+    }
+}
+
+impl<'a> IntoIterator for &'a MoveList {
+    type Item = &'a PossibleMove;
+    type IntoIter = std::slice::Iter<'a, PossibleMove>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::baserules::board_rep::PossibleMove;
+    use crate::baserules::move_list::MoveList;
+
+    #[test]
+    fn moves_are_kept_in_order() {
+        let ucis = ["e2e4", "d2d4", "g1f3", "c2c4"];
+        let mut moves: MoveList = ucis
+            .iter()
+            .map(|uci| PossibleMove::simple_from_uci(uci).unwrap())
+            .collect();
+        assert_eq!(4, moves.len());
+        moves.retain(|a_move| a_move.the_move.from.1 != 3);
+        let kept: Vec<String> = moves.iter().map(ToString::to_string).collect();
+        assert_eq!(vec!["e2e4", "g1f3", "c2c4"], kept);
+        assert_eq!("c2c4", moves.pop().unwrap().to_string());
+        assert_eq!(2, moves.into_iter().count());
+    }
+}
@@ -0,0 +1,350 @@
+/*
+ *  ========================================================================
+ *  DBCE chess bot, evaluation with an efficiently updatable neural network
+ *  ========================================================================
+ *
+ *  This file is part of DBCE.
+ *
+ *  DBCE is free software: you can redistribute it and/or
+ *  modify it under the terms of the GNU General Public License as published
+ *  by the Free Software Foundation, either version 3 of the License, or (at
+ *  your option) any later version.
+ *
+ *  DBCE is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ *  General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License along
+ *  with DBCE.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ *  (C) Copyright 2022-3, Gabor Kecskemeti
+ */
+use crate::baserules::board::PSBoard;
+use crate::baserules::evaluation::{EvalBreakdown, Evaluator};
+use crate::baserules::piece_color::PieceColor;
+use crate::baserules::piece_color::PieceColor::{Black, White};
+use crate::baserules::piece_state::PieceState;
+use crate::baserules::positions::Square;
+use crate::baserules::rawboard::RawBoard;
+use crate::baserules::score::Score;
+use crate::util::{FileFormat, IntResult};
+use enum_map::{Enum, EnumMap};
+use std::path::Path;
+use std::sync::Arc;
+
+/// The files written by `Network::to_bytes`
+const NETWORK_FORMAT: FileFormat = FileFormat {
+    name: "an NNUE network",
+    magic: b"DBCENNUE",
+    version: 1,
+};
+
+/// One input for each kind of piece of each side on each square
+const INPUTS: usize = 2 * 6 * Square::COUNT;
+/// The activations of the hidden layer are clipped to 0..=QA
+const QA: i64 = 255;
+/// The output weights are quantized to 1/QB
+const QB: i64 = 64;
+/// The output of the network times this is the score in centipawns
+const SCALE: i64 = 400;
+
+/// A network with one hidden layer, seen from both sides' perspectives: the pieces are the
+/// inputs, from the side's own point of view with its pieces first and the board mirrored for
+/// black. The output combines the hidden layers of the side to move and of the other side.
+/// The weights are quantized to i16, as the trainers usually export them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Network {
+    hidden: usize,
+    /// The weights of each input to the hidden layer, one row per input
+    feature_weights: Vec<i16>,
+    feature_bias: Vec<i16>,
+    /// The weights of the side to move's hidden layer, then the other side's
+    output_weights: Vec<i16>,
+    output_bias: i16,
+}
+
+impl Network {
+    /// Creates a network from its weights, e.g. the ones of a trainer
+    ///
+    /// # Errors
+    /// When the number of the weights does not match the size of the hidden layer
+    pub fn new(
+        feature_weights: Vec<i16>,
+        feature_bias: Vec<i16>,
+        output_weights: Vec<i16>,
+        output_bias: i16,
+    ) -> IntResult<Self> {
+        let hidden = feature_bias.len();
+        if feature_weights.len() != INPUTS * hidden || output_weights.len() != 2 * hidden {
+            return Err(format!(
+                "{} input and {} output weights do not fit a hidden layer of {hidden}",
+                feature_weights.len(),
+                output_weights.len()
+            )
+            .into());
+        }
+        Ok(Network {
+            hidden,
+            feature_weights,
+            feature_bias,
+            output_weights,
+            output_bias,
+        })
+    }
+
+    /// The header, the size of the hidden layer as u16, then the input weights, the hidden
+    /// biases, the output weights and the output bias as little endian i16
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = NETWORK_FORMAT.header();
+        bytes.extend_from_slice(&(self.hidden as u16).to_le_bytes());
+        for weight in self
+            .feature_weights
+            .iter()
+            .chain(&self.feature_bias)
+            .chain(&self.output_weights)
+            .chain([&self.output_bias])
+        {
+            bytes.extend_from_slice(&weight.to_le_bytes());
+        }
+        bytes
+    }
+
+    /// Reads a network written by `to_bytes`
+    ///
+    /// # Errors
+    /// When the bytes are not a network, or it is cut short
+    pub fn from_bytes(bytes: &[u8]) -> IntResult<Self> {
+        let (_, contents) = NETWORK_FORMAT.read(bytes, |_| false)?;
+        let (hidden, weights) = contents
+            .split_first_chunk::<2>()
+            .ok_or("Missing the size of the hidden layer")?;
+        let hidden = usize::from(u16::from_le_bytes(*hidden));
+        let expected = (INPUTS + 1 + 2) * hidden + 1;
+        if weights.len() != 2 * expected {
+            return Err(format!(
+                "A network with a hidden layer of {hidden} has {expected} weights, not {}",
+                weights.len() / 2
+            )
+            .into());
+        }
+        let mut weights = weights
+            .chunks_exact(2)
+            .map(|pair| i16::from_le_bytes([pair[0], pair[1]]));
+        let mut take = |count| weights.by_ref().take(count).collect::<Vec<_>>();
+        let feature_weights = take(INPUTS * hidden);
+        let feature_bias = take(hidden);
+        let output_weights = take(2 * hidden);
+        let output_bias = take(1)[0];
+        Network::new(feature_weights, feature_bias, output_weights, output_bias)
+    }
+
+    /// Reads the network file
+    ///
+    /// # Errors
+    /// When the file cannot be read or it is not a network
+    pub fn load(file: &Path) -> IntResult<Self> {
+        let bytes = std::fs::read(file)
+            .map_err(|error| format!("Cannot read {}: {error}", file.display()))?;
+        Network::from_bytes(&bytes).map_err(|error| format!("{}: {error}", file.display()).into())
+    }
+
+    /// The row of the input weights of the piece on the square, from the side's perspective
+    fn feature_row(&self, perspective: PieceColor, piece: PieceState, square: Square) -> &[i16] {
+        let (theirs, square) = if perspective == White {
+            (piece.color != White, square.index())
+        } else {
+            (piece.color == White, square.index() ^ 0b111_000)
+        };
+        let input = (usize::from(theirs) * 6 + piece.kind.into_usize()) * Square::COUNT + square;
+        &self.feature_weights[input * self.hidden..(input + 1) * self.hidden]
+    }
+
+    /// The score of the hidden layers, from white's perspective
+    fn output(&self, hidden: &EnumMap<PieceColor, Vec<i16>>, who_moves: PieceColor) -> Score {
+        let activations = hidden[who_moves]
+            .iter()
+            .chain(&hidden[who_moves.invert()])
+            .map(|activation| i64::from(*activation).clamp(0, QA));
+        let sum: i64 = activations
+            .zip(&self.output_weights)
+            .map(|(activation, weight)| activation * i64::from(*weight))
+            .sum();
+        let centipawns = (sum + i64::from(self.output_bias) * QA) * SCALE / (QA * QB);
+        Score::centipawns(who_moves.mate_multiplier() * centipawns as i32)
+    }
+}
+
+/// The hidden layers of a board for both perspectives, kept with the board so the boards made
+/// from it only update the inputs of the squares the move changed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Accumulator {
+    network: Arc<Network>,
+    hidden: EnumMap<PieceColor, Vec<i16>>,
+}
+
+impl Accumulator {
+    /// The hidden layers calculated from all the pieces of the board
+    pub fn new(network: Arc<Network>, raw: &RawBoard) -> Self {
+        let mut accumulator = Accumulator {
+            hidden: EnumMap::from_fn(|_| network.feature_bias.clone()),
+            network,
+        };
+        for color in [White, Black] {
+            for (kind, square) in raw.pieces(color) {
+                accumulator.apply(PieceState { kind, color }, square, i16::wrapping_add);
+            }
+        }
+        accumulator
+    }
+
+    /// The hidden layers after the move turning the board before into the board after
+    pub fn updated(&self, before: &RawBoard, after: &RawBoard) -> Self {
+        let mut accumulator = self.clone();
+        for ((square, was), is) in Square::all().zip(before).zip(after) {
+            if was == is {
+                continue;
+            }
+            if let Some(piece) = was {
+                accumulator.apply(*piece, square, i16::wrapping_sub);
+            }
+            if let Some(piece) = is {
+                accumulator.apply(*piece, square, i16::wrapping_add);
+            }
+        }
+        accumulator
+    }
+
+    fn apply(&mut self, piece: PieceState, square: Square, change: fn(i16, i16) -> i16) {
+        for (perspective, hidden) in &mut self.hidden {
+            let row = self.network.feature_row(perspective, piece, square);
+            for (activation, weight) in hidden.iter_mut().zip(row) {
+                *activation = change(*activation, *weight);
+            }
+        }
+    }
+}
+
+/// The accumulator of a new board for the network of the evaluator, if it has one.
+/// It is updated from the accumulator of the board the move was made on when there is one.
+pub(crate) fn next_accumulator(
+    evaluator: &dyn Evaluator,
+    parent: Option<(&Accumulator, &RawBoard)>,
+    raw: &RawBoard,
+) -> Option<Box<Accumulator>> {
+    let network = evaluator.network()?;
+    Some(Box::new(match parent {
+        Some((accumulator, before)) if Arc::ptr_eq(&accumulator.network, &network) => {
+            accumulator.updated(before, raw)
+        }
+        _ => Accumulator::new(network, raw),
+    }))
+}
+
+/// Evaluates the boards with a network, using the accumulators kept with the boards
+pub struct Nnue {
+    pub network: Arc<Network>,
+}
+
+impl Nnue {
+    /// # Errors
+    /// When the file cannot be read or it is not a network
+    pub fn load(file: &Path) -> IntResult<Self> {
+        Ok(Nnue {
+            network: Arc::new(Network::load(file)?),
+        })
+    }
+}
+
+impl Evaluator for Nnue {
+    fn eval(&self, board: &PSBoard) -> Score {
+        match &board.accumulator {
+            Some(accumulator) if Arc::ptr_eq(&accumulator.network, &self.network) => {
+                self.network.output(&accumulator.hidden, board.who_moves)
+            }
+            _ => {
+                let accumulator = Accumulator::new(self.network.clone(), &board.raw);
+                self.network.output(&accumulator.hidden, board.who_moves)
+            }
+        }
+    }
+
+    fn explain(&self, board: &PSBoard) -> EvalBreakdown {
+        EvalBreakdown::default().with("network", self.eval(board))
+    }
+
+    fn network(&self) -> Option<Arc<Network>> {
+        Some(self.network.clone())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::baserules::board::PSBoard;
+    use crate::baserules::evaluation::Evaluator;
+    use crate::baserules::move_list::MoveList;
+    use crate::baserules::nnue::{Accumulator, Network, Nnue, INPUTS};
+    use std::sync::Arc;
+    use tokio::test;
+
+    /// A small network with made up weights
+    fn network() -> Network {
+        let hidden = 8;
+        let weight = |index: usize, modulus: usize| (index * 7919 % modulus) as i16 - 40;
+        Network::new(
+            (0..INPUTS * hidden).map(|i| weight(i, 97)).collect(),
+            (0..hidden).map(|i| weight(i, 53) + 60).collect(),
+            (0..2 * hidden).map(|i| weight(i, 89)).collect(),
+            25,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    async fn networks_are_written_and_read_back() {
+        let network = network();
+        let bytes = network.to_bytes();
+        assert_eq!(network, Network::from_bytes(&bytes).unwrap());
+        assert!(Network::from_bytes(&bytes[..bytes.len() - 2]).is_err());
+        assert!(Network::from_bytes(b"DBCEBOOK").is_err());
+    }
+
+    #[test]
+    async fn updated_accumulators_match_the_recalculated_ones() {
+        let network = Arc::new(network());
+        // En passant, captures and a promotion
+        let mut board = PSBoard::from_fen("r3k2r/1P6/8/8/3p4/8/4P3/R3K2R w KQkq - 0 1")
+            .await
+            .unwrap();
+        let mut accumulator = Accumulator::new(network.clone(), &board.raw);
+        for uci in ["e2e4", "d4e3", "b7a8q", "e3e2", "h1h8"] {
+            let mut moves = MoveList::new();
+            board.gen_potential_moves(&mut moves);
+            let a_move = moves.iter().find(|a_move| a_move.to_string() == uci);
+            let next = board.make_move_noncached(a_move.unwrap()).await;
+            accumulator = accumulator.updated(&board.raw, &next.raw);
+            assert_eq!(
+                Accumulator::new(network.clone(), &next.raw),
+                accumulator,
+                "{uci}"
+            );
+            board = next;
+        }
+        assert_eq!("Q3k2R/8/8/8/8/8/4p3/R3K3 b Q - 0 3", board.to_fen());
+    }
+
+    #[test]
+    async fn scores_are_symmetric() {
+        let nnue = Nnue {
+            network: Arc::new(network()),
+        };
+        let board = PSBoard::from_fen("4k3/pp6/8/3n4/8/8/PPP5/4K1R1 w - - 0 1")
+            .await
+            .unwrap();
+        let mirrored = PSBoard::from_fen("4k1r1/ppp5/8/8/3N4/8/PP6/4K3 b - - 0 1")
+            .await
+            .unwrap();
+        assert_eq!(nnue.eval(&board), -nnue.eval(&mirrored));
+        assert_eq!(nnue.eval(&board), nnue.explain(&board).total());
+    }
+}
@@ -0,0 +1,239 @@
+/*
+ *  ========================================================================
+ *  DBCE chess bot, pawn structure terms of the evaluation
+ *  ========================================================================
+ *
+ *  This file is part of DBCE.
+ *
+ *  DBCE is free software: you can redistribute it and/or
+ *  modify it under the terms of the GNU General Public License as published
+ *  by the Free Software Foundation, either version 3 of the License, or (at
+ *  your option) any later version.
+ *
+ *  DBCE is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ *  General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License along
+ *  with DBCE.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ *  (C) Copyright 2022-3, Gabor Kecskemeti
+ */
+use crate::baserules::piece_color::PieceColor;
+use crate::baserules::piece_color::PieceColor::{Black, White};
+use crate::baserules::piece_kind::PieceKind::Pawn;
+use crate::baserules::rawboard::{bits, RawBoard};
+use crate::baserules::score::Score;
+use lazy_static::lazy_static;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering::Relaxed;
+
+/// The penalty of each pawn behind another one of its side on the same file, in centipawns
+const DOUBLED_PENALTY: i32 = 15;
+/// The penalty of each pawn without pawns of its side on the neighbouring files
+const ISOLATED_PENALTY: i32 = 15;
+/// The penalty of each pawn which cannot be supported by the pawns of its side, and cannot
+/// advance either as an enemy pawn controls the square in front of it
+const BACKWARD_PENALTY: i32 = 10;
+/// The bonus of the passed pawns by their rank, counted from their own side
+const PASSED_BONUS: [i32; 8] = [0, 5, 10, 20, 35, 60, 100, 0];
+
+/// The squares of the a file
+const FILE_A: u64 = 0x0101_0101_0101_0101;
+
+/// The number of slots of the pawn hash, a power of two
+const PAWN_HASH_SLOTS: usize = 1 << 14;
+/// Marks the used slots, so an all zero slot is never mistaken for a stored score
+const OCCUPIED: u64 = 1 << 32;
+
+lazy_static! {
+    /// The scores of the pawn structures seen, as the same structures come up in most boards of
+    /// a search. Each slot holds the key xor-ed with the data and the data, like the slots of the
+    /// transposition table, so the threads can share it without locking.
+    static ref PAWN_HASH: Vec<[AtomicU64; 2]> = (0..PAWN_HASH_SLOTS)
+        .map(|_| [AtomicU64::new(0), AtomicU64::new(0)])
+        .collect();
+}
+
+/// The pawns of one side with weaknesses or strengths, as bitboards indexed by `Square`
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct PawnFeatures {
+    pub doubled: u64,
+    pub isolated: u64,
+    pub backward: u64,
+    pub passed: u64,
+}
+
+/// The squares of the file and its neighbours
+fn file_and_neighbours(col: u8) -> u64 {
+    let file = FILE_A << col;
+    let left = if col > 0 { file >> 1 } else { 0 };
+    let right = if col < 7 { file << 1 } else { 0 };
+    left | file | right
+}
+
+/// The squares of the neighbouring files
+fn neighbour_files(col: u8) -> u64 {
+    file_and_neighbours(col) & !(FILE_A << col)
+}
+
+/// The squares on the ranks ahead of the row, in the direction the pawns of the side move
+fn ahead(color: PieceColor, row: u8) -> u64 {
+    match color {
+        White => u64::MAX.checked_shl(8 * (u32::from(row) + 1)).unwrap_or(0),
+        Black => (1u64 << (8 * u32::from(row))) - 1,
+    }
+}
+
+impl RawBoard {
+    /// The pawns of the side as a bitboard indexed by `Square`
+    pub fn pawns(&self, color: PieceColor) -> u64 {
+        self.pieces_of(Pawn, color)
+    }
+
+    /// The number of the side's pawns on each file, from the a file
+    pub fn pawns_per_file(&self, color: PieceColor) -> [u8; 8] {
+        let pawns = self.pawns(color);
+        std::array::from_fn(|col| (pawns & FILE_A << col).count_ones() as u8)
+    }
+
+    /// The doubled, isolated, backward and passed pawns of the side
+    pub fn pawn_features(&self, color: PieceColor) -> PawnFeatures {
+        features(self.pawns(color), self.pawns(color.invert()), color)
+    }
+
+    /// The bonuses of the passed pawns minus the penalties of the weak ones, white's minus
+    /// black's. The scores of the structures are kept in the pawn hash.
+    pub fn pawn_structure(&self) -> Score {
+        let (white, black) = (self.pawns(White), self.pawns(Black));
+        let key = (white.wrapping_mul(0x9E37_79B9_7F4A_7C15) ^ black.rotate_left(29))
+            .wrapping_mul(0xBF58_476D_1CE4_E5B9)
+            | 1;
+        let slot = &PAWN_HASH[(key >> 50) as usize % PAWN_HASH_SLOTS];
+        let data = slot[1].load(Relaxed);
+        if slot[0].load(Relaxed) ^ data == key && data & OCCUPIED != 0 {
+            return Score::centipawns(data as u32 as i32);
+        }
+        let score = structure_score(features(white, black, White), White)
+            - structure_score(features(black, white, Black), Black);
+        let data = u64::from(score as u32) | OCCUPIED;
+        slot[0].store(key ^ data, Relaxed);
+        slot[1].store(data, Relaxed);
+        Score::centipawns(score)
+    }
+}
+
+fn features(own: u64, enemy: u64, color: PieceColor) -> PawnFeatures {
+    let mut features = PawnFeatures::default();
+    for square in bits(own) {
+        let (row, col) = (square.row(), square.col());
+        let pawn = 1 << square.index();
+        let file = FILE_A << col;
+        if own & file & ahead(color, row) != 0 {
+            features.doubled |= pawn;
+        }
+        if enemy & file_and_neighbours(col) & ahead(color, row) == 0 {
+            features.passed |= pawn;
+        }
+        if own & neighbour_files(col) == 0 {
+            features.isolated |= pawn;
+            continue;
+        }
+        // Only the neighbouring pawns level with or behind it could defend it
+        let supporters = own & neighbour_files(col) & !ahead(color, row);
+        // The enemy pawns two ranks ahead on the neighbouring files control its stop square
+        let guards_row = if color == White {
+            row.checked_add(2).filter(|row| *row < 8)
+        } else {
+            row.checked_sub(2)
+        };
+        let stop_attacked = guards_row.is_some_and(|guards_row| {
+            enemy & neighbour_files(col) & 0xFFu64 << (8 * u32::from(guards_row)) != 0
+        });
+        if supporters == 0 && stop_attacked {
+            features.backward |= pawn;
+        }
+    }
+    features
+}
+
+/// The side's bonuses minus its penalties
+fn structure_score(features: PawnFeatures, color: PieceColor) -> i32 {
+    let passed: i32 = bits(features.passed)
+        .map(|square| {
+            let rank = if color == White {
+                square.row()
+            } else {
+                7 - square.row()
+            };
+            PASSED_BONUS[rank as usize]
+        })
+        .sum();
+    passed
+        - DOUBLED_PENALTY * features.doubled.count_ones() as i32
+        - ISOLATED_PENALTY * features.isolated.count_ones() as i32
+        - BACKWARD_PENALTY * features.backward.count_ones() as i32
+}
+
+#[cfg(test)]
+mod test {
+    use crate::baserules::board::PSBoard;
+    use crate::baserules::pawn_structure::PawnFeatures;
+    use crate::baserules::piece_color::PieceColor::{Black, White};
+    use crate::baserules::positions::{AbsoluteBoardPos, Square};
+    use crate::baserules::score::Score;
+    use crate::util::TryWithPanic;
+    use tokio::test;
+
+    fn squares(bitboard: u64) -> Vec<String> {
+        Square::all()
+            .filter(|square| bitboard & 1 << square.index() != 0)
+            .map(|square| square.to_string())
+            .collect()
+    }
+
+    fn squares_bits(squares: &[&str]) -> u64 {
+        squares.iter().fold(0, |bits, square| {
+            let pos: AbsoluteBoardPos = (*square).transform();
+            bits | 1 << Square::from(pos).index()
+        })
+    }
+
+    #[test]
+    async fn weak_and_passed_pawns_are_found() {
+        // White: doubled isolated c pawns, a backward e pawn, an isolated passed h pawn
+        let board = PSBoard::from_fen("4k3/3p4/8/2P2p2/2P2P1P/4P3/8/4K3 w - - 0 1")
+            .await
+            .unwrap();
+        assert_eq!([0, 0, 2, 0, 1, 1, 0, 1], board.raw.pawns_per_file(White));
+        let white = board.raw.pawn_features(White);
+        assert_eq!(vec!["c4"], squares(white.doubled));
+        assert_eq!(vec!["c4", "h4", "c5"], squares(white.isolated));
+        assert_eq!(vec!["e3"], squares(white.backward));
+        assert_eq!(vec!["h4"], squares(white.passed));
+        assert_eq!(
+            PawnFeatures {
+                isolated: squares_bits(&["d7", "f5"]),
+                ..Default::default()
+            },
+            board.raw.pawn_features(Black)
+        );
+    }
+
+    #[test]
+    async fn structures_are_scored_symmetrically() {
+        assert_eq!(Score::DRAW, PSBoard::default().raw.pawn_structure());
+        let passed = PSBoard::from_fen("4k3/8/1P6/8/8/8/8/4K3 w - - 0 1")
+            .await
+            .unwrap();
+        // Counted again from the pawn hash
+        for _ in 0..2 {
+            assert_eq!(Score::centipawns(60 - 15), passed.raw.pawn_structure());
+        }
+        let mirrored = PSBoard::from_fen("4k3/8/8/8/8/1p6/8/4K3 w - - 0 1")
+            .await
+            .unwrap();
+        assert_eq!(Score::centipawns(-45), mirrored.raw.pawn_structure());
+    }
+}
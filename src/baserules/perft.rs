@@ -0,0 +1,457 @@
+/*
+ *  ========================================================================
+ *  DBCE chess bot, move generation verification via perft
+ *  ========================================================================
+ *
+ *  This file is part of DBCE.
+ *
+ *  DBCE is free software: you can redistribute it and/or
+ *  modify it under the terms of the GNU General Public License as published
+ *  by the Free Software Foundation, either version 3 of the License, or (at
+ *  your option) any later version.
+ *
+ *  DBCE is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ *  General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License along
+ *  with DBCE.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ *  (C) Copyright 2022-3, Gabor Kecskemeti
+ */
+use crate::baserules::board::PSBoard;
+use crate::baserules::move_list::MoveList;
+use crate::util::IntResult;
+use async_trait::async_trait;
+use futures_util::future::ready;
+use futures_util::StreamExt;
+use rayon::prelude::*;
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::pin::pin;
+use std::process::Stdio;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering::Relaxed;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+/// Per root move node counts, keyed by the uci form of the move
+pub type DivideResult = BTreeMap<String, u64>;
+
+/// The number of entries of the table shared by a divide run, 16 MB of memory
+const PERFT_TABLE_ENTRIES: usize = 1 << 20;
+
+/// The node counts of the positions already counted to a given depth, shared by the threads
+/// counting the root moves. A slot holds the key xor-ed with the count next to the count, so a
+/// slot written by two threads at once does not verify and is counted again.
+struct PerftTable {
+    slots: Vec<[AtomicU64; 2]>,
+}
+
+impl PerftTable {
+    fn new(entries: usize) -> Self {
+        Self {
+            slots: (0..entries)
+                .map(|_| [AtomicU64::new(0), AtomicU64::new(0)])
+                .collect(),
+        }
+    }
+
+    /// The same position counted to different depths is stored under different keys
+    fn key(zobrist: u64, depth: u8) -> u64 {
+        zobrist ^ u64::from(depth).wrapping_mul(0x9e37_79b9_7f4a_7c15)
+    }
+
+    fn slot(&self, key: u64) -> &[AtomicU64; 2] {
+        &self.slots[(key % self.slots.len() as u64) as usize]
+    }
+
+    fn probe(&self, zobrist: u64, depth: u8) -> Option<u64> {
+        let key = Self::key(zobrist, depth);
+        let [checked, count] = self.slot(key);
+        let count = count.load(Relaxed);
+        (checked.load(Relaxed) ^ count == key).then_some(count)
+    }
+
+    fn store(&self, zobrist: u64, depth: u8, count: u64) {
+        let key = Self::key(zobrist, depth);
+        let [checked, stored] = self.slot(key);
+        checked.store(key ^ count, Relaxed);
+        stored.store(count, Relaxed);
+    }
+}
+
+impl PSBoard {
+    /// Counts the leaf nodes reachable with legal moves in the given number of plies. The moves
+    /// are made and taken back on a single copy of the board, without evaluating the positions.
+    ///
+    /// # Example
+    /// ```
+    /// use dbce::baserules::board::PSBoard;
+    /// assert_eq!(400, PSBoard::default().perft(2));
+    /// ```
+    pub fn perft(&self, depth: u8) -> u64 {
+        self.clone().perft_in_place(depth)
+    }
+
+    fn perft_in_place(&mut self, depth: u8) -> u64 {
+        if depth == 0 {
+            return 1;
+        }
+        let mut moves = MoveList::new();
+        self.gen_legal_moves(&mut moves);
+        if depth == 1 {
+            return moves.len() as u64;
+        }
+        let mut total = 0;
+        for a_move in &moves {
+            let undo = self.apply_move(a_move);
+            total += self.perft_in_place(depth - 1);
+            self.unmake_move(undo);
+        }
+        total
+    }
+
+    /// The same count as `perft`, the positions reached on several paths are only counted once
+    fn perft_hashed(&mut self, depth: u8, table: &PerftTable) -> u64 {
+        if depth <= 1 {
+            return self.perft_in_place(depth);
+        }
+        if let Some(count) = table.probe(self.zobrist(), depth) {
+            return count;
+        }
+        let mut moves = MoveList::new();
+        self.gen_legal_moves(&mut moves);
+        let mut total = 0;
+        for a_move in &moves {
+            let undo = self.apply_move(a_move);
+            total += self.perft_hashed(depth - 1, table);
+            self.unmake_move(undo);
+        }
+        table.store(self.zobrist(), depth, total);
+        total
+    }
+
+    /// Counts the leaf nodes reachable with legal moves after each of the root moves. The root
+    /// moves are counted in parallel on the rayon thread pool, the threads share the counts of
+    /// the positions they have already seen.
+    pub fn divide(&self, depth: u8) -> DivideResult {
+        assert_ne!(depth, 0, "Cannot divide at depth 0");
+        let mut moves = MoveList::new();
+        self.gen_legal_moves(&mut moves);
+        let table = PerftTable::new(PERFT_TABLE_ENTRIES);
+        moves
+            .par_iter()
+            .map(|a_move| {
+                let mut board = self.clone();
+                board.apply_move(a_move);
+                (a_move.to_string(), board.perft_hashed(depth - 1, &table))
+            })
+            .collect()
+    }
+
+    /// The same count as `perft`, computed with the threads and the shared counts of `divide`,
+    /// so deeper counts finish in reasonable time
+    pub fn perft_parallel(&self, depth: u8) -> u64 {
+        if depth == 0 {
+            return 1;
+        }
+        self.divide(depth).values().sum()
+    }
+
+    /// Prints the counts of `divide` in the format of the other engines (e.g. `e2e4: 20`),
+    /// followed by their total, which is returned as well
+    pub fn perft_divide(&self, depth: u8) -> u64 {
+        let divide = self.divide(depth);
+        for (the_move, count) in &divide {
+            println!("{the_move}: {count}");
+        }
+        let total = divide.values().sum();
+        println!("\nNodes searched: {total}\n");
+        total
+    }
+}
+
+/// Reads the output of a `perft divide` run in the format used by most engines (e.g. `e2e4: 20`).
+/// Lines not following this format (like the total node count) are ignored.
+///
+/// # Example
+/// ```
+/// use dbce::baserules::perft::parse_divide_output;
+/// let divide = parse_divide_output("a2a3: 380\nb7b8q: 12\n\nNodes searched: 392\n");
+/// assert_eq!(Some(&380), divide.get("a2a3"));
+/// assert_eq!(Some(&12), divide.get("b7b8q"));
+/// assert_eq!(2, divide.len());
+/// ```
+pub fn parse_divide_output(output: &str) -> DivideResult {
+    output
+        .lines()
+        .filter_map(|line| {
+            let (the_move, count) = line.trim().split_once(':')?;
+            let well_formed = (4..=5).contains(&the_move.len())
+                && the_move.chars().all(|c| c.is_ascii_alphanumeric());
+            if well_formed {
+                Some((the_move.to_string(), count.trim().parse().ok()?))
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+/// The source of the node counts our move generator is compared against
+#[async_trait]
+pub trait PerftReference {
+    async fn divide(&mut self, fen: &str, depth: u8) -> IntResult<DivideResult>;
+}
+
+/// A reference engine (e.g. stockfish or another dbce build's perft binary) that understands
+/// the `position fen` and `go perft` commands on its standard input.
+pub struct ExternalPerft {
+    pub command: String,
+    pub args: Vec<String>,
+}
+
+#[async_trait]
+impl PerftReference for ExternalPerft {
+    async fn divide(&mut self, fen: &str, depth: u8) -> IntResult<DivideResult> {
+        let mut child = Command::new(&self.command)
+            .args(&self.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let mut stdin = child.stdin.take().ok_or("Reference engine has no stdin")?;
+        stdin
+            .write_all(format!("position fen {fen}\ngo perft {depth}\nquit\n").as_bytes())
+            .await?;
+        drop(stdin);
+        let output = child.wait_with_output().await?;
+        Ok(parse_divide_output(&String::from_utf8(output.stdout)?))
+    }
+}
+
+/// Describes how the two move generators disagree
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DivergenceKind {
+    /// Only the reference engine generated this move
+    MissingMove(String),
+    /// Only we generated this move
+    ExtraMove(String),
+    /// The move leads to a different position than the expected one
+    WrongPosition { the_move: String, expected: String },
+}
+
+/// The position where our move generation first differs from the reference
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    pub fen: String,
+    /// The moves leading from the compared position to the divergent one
+    pub line: Vec<String>,
+    pub kind: DivergenceKind,
+}
+
+impl Display for Divergence {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let description = match &self.kind {
+            DivergenceKind::MissingMove(m) => format!("we do not generate {m}"),
+            DivergenceKind::ExtraMove(m) => format!("we wrongly generate {m}"),
+            DivergenceKind::WrongPosition { the_move, expected } => {
+                format!("{the_move} does not lead to {expected}")
+            }
+        };
+        write!(
+            f,
+            "In {} (reached via: {}) {description}",
+            self.fen,
+            self.line.join(" ")
+        )
+    }
+}
+
+/// Descends into the first move with differing node counts until the position is found where
+/// the set of generated moves differs from the reference.
+/// Returns `None` if the node counts agree at the given depth.
+pub async fn find_divergence(
+    board: &PSBoard,
+    depth: u8,
+    reference: &mut impl PerftReference,
+) -> IntResult<Option<Divergence>> {
+    let mut line = Vec::new();
+    let mut descended: Option<PSBoard> = None;
+    for current_depth in (1..=depth).rev() {
+        let current = descended.as_ref().unwrap_or(board);
+        let fen = current.to_fen();
+        let ours = current.divide(current_depth);
+        let theirs = reference.divide(&fen, current_depth).await?;
+        let kind = theirs
+            .keys()
+            .find(|m| !ours.contains_key(*m))
+            .map(|m| DivergenceKind::MissingMove(m.clone()))
+            .or_else(|| {
+                ours.keys()
+                    .find(|m| !theirs.contains_key(*m))
+                    .map(|m| DivergenceKind::ExtraMove(m.clone()))
+            });
+        if let Some(kind) = kind {
+            return Ok(Some(Divergence { fen, line, kind }));
+        }
+        let differing = ours.iter().find(|(m, count)| theirs[*m] != **count);
+        if let Some((uci, _)) = differing {
+            let (_, next_board) = pin!(current
+                .successors()
+                .filter(|(a_move, _)| ready(a_move.to_string() == *uci)))
+            .next()
+            .await
+            .unwrap();
+            line.push(uci.clone());
+            descended = Some(next_board);
+        } else {
+            return Ok(None);
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::baserules::board::PSBoard;
+    use crate::baserules::board_rep::PossibleMove;
+    use crate::baserules::perft::{find_divergence, DivergenceKind, DivideResult, PerftReference};
+    use crate::util::IntResult;
+    use async_trait::async_trait;
+    use tokio::test;
+
+    /// Pretends to be a reference engine that does not know about a move after a given opening move
+    struct ForgetfulReference {
+        opening: &'static str,
+        forgotten: &'static str,
+    }
+
+    #[async_trait]
+    impl PerftReference for ForgetfulReference {
+        async fn divide(&mut self, fen: &str, depth: u8) -> IntResult<DivideResult> {
+            let board = PSBoard::from_fen(fen).await?;
+            let mut result = board.divide(depth);
+            let opening = PossibleMove::simple_from_uci(self.opening)?;
+            if board.who_moves == PSBoard::default().who_moves {
+                *result.get_mut(self.opening).unwrap() -= 1;
+            } else if fen
+                == PSBoard::default()
+                    .make_move_noncached(&opening)
+                    .await
+                    .to_fen()
+            {
+                result.remove(self.forgotten);
+            }
+            Ok(result)
+        }
+    }
+
+    #[test]
+    async fn divide_starting_position() {
+        let divide = PSBoard::default().divide(2);
+        assert_eq!(20, divide.len());
+        assert_eq!(400u64, divide.values().sum::<u64>());
+        assert_eq!(400, PSBoard::default().perft_divide(2));
+    }
+
+    /// The published node counts of the positions commonly used for validating move generators
+    #[test]
+    async fn perft_standard_positions() {
+        for (fen, counts) in [
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                &[20, 400, 8902, 197281][..],
+            ),
+            // Kiwipete
+            (
+                "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+                &[48, 2039, 97862],
+            ),
+            (
+                "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+                &[14, 191, 2812, 43238],
+            ),
+            (
+                "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+                &[6, 264, 9467],
+            ),
+            (
+                "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+                &[44, 1486, 62379],
+            ),
+            (
+                "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+                &[46, 2079, 89890],
+            ),
+        ] {
+            let board = PSBoard::from_fen(fen).await.unwrap();
+            for (depth, expected) in counts.iter().enumerate() {
+                assert_eq!(*expected, board.perft(depth as u8 + 1), "{fen} at {depth}");
+            }
+        }
+    }
+
+    /// The published node counts of Chess960 positions, castling with the pieces on other files
+    #[test]
+    async fn perft_chess960_positions() {
+        for (fen, counts) in [
+            (
+                "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9",
+                &[21, 528, 12189, 326672][..],
+            ),
+            (
+                "2nnrbkr/p1qppppp/8/1ppb4/6PP/3PP3/PPP2P2/BQNNRBKR w HEhe - 1 9",
+                &[21, 807, 18002, 667366],
+            ),
+            (
+                "b1q1rrkb/pppppppp/3nn3/8/P7/1PPP4/4PPPP/BQNNRKRB w GE - 1 9",
+                &[20, 479, 10471, 273318],
+            ),
+            (
+                "qbbnnrkr/2pp2pp/p7/1p2pp2/8/P3PP2/1PPP1KPP/QBBNNR1R w hf - 0 9",
+                &[22, 593, 13440, 382958],
+            ),
+            (
+                "1nbbnrkr/p1p1ppp1/3p4/1p3P1p/3Pq2P/8/PPP1P1P1/QNBBNRKR w HFhf - 0 9",
+                &[28, 1120, 31058, 1171749],
+            ),
+        ] {
+            let board = PSBoard::from_fen(fen).await.unwrap();
+            for (depth, expected) in counts.iter().enumerate() {
+                assert_eq!(*expected, board.perft(depth as u8 + 1), "{fen} at {depth}");
+            }
+        }
+    }
+
+    #[test]
+    async fn parallel_perft_agrees() {
+        for (fen, depth, expected) in [
+            (
+                "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+                3,
+                97862,
+            ),
+            ("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1", 5, 674624),
+        ] {
+            let board = PSBoard::from_fen(fen).await.unwrap();
+            assert_eq!(expected, board.perft_parallel(depth), "{fen}");
+        }
+        assert_eq!(1, PSBoard::default().perft_parallel(0));
+    }
+
+    #[test]
+    async fn isolates_divergent_move() {
+        let mut reference = ForgetfulReference {
+            opening: "a2a3",
+            forgotten: "h7h6",
+        };
+        let divergence = find_divergence(&PSBoard::default(), 2, &mut reference)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(DivergenceKind::ExtraMove("h7h6".into()), divergence.kind);
+        assert_eq!(vec![String::from("a2a3")], divergence.line);
+    }
+}
@@ -25,6 +25,7 @@ use crate::baserules::castling::Castling::{
 };
 use crate::baserules::castling::{black_can_castle, white_can_castle, Castling};
 use crate::baserules::positions::{AbsoluteBoardPos, RelativeBoardPos};
+use crate::baserules::score::Score;
 use crate::util::{AnyError, IntResult};
 use enum_map::{enum_map, Enum, EnumMap};
 use enumset::EnumSet;
@@ -166,23 +167,23 @@ impl PieceColor {
     }
 
     #[inline]
-    pub const fn mate_multiplier(&self) -> f32 {
+    pub const fn mate_multiplier(&self) -> i32 {
         match self {
-            White => 1.0,
-            Black => -1.0,
+            White => 1,
+            Black => -1,
         }
     }
 
     #[inline]
-    pub const fn score_comparator(&self) -> impl FnMut(&f32, &f32) -> Ordering {
+    pub const fn score_comparator(&self) -> impl FnMut(&Score, &Score) -> Ordering {
         match self {
-            White => |a: &f32, b: &f32| a.partial_cmp(b).unwrap(),
-            Black => |a: &f32, b: &f32| b.partial_cmp(a).unwrap(),
+            White => |a: &Score, b: &Score| a.cmp(b),
+            Black => |a: &Score, b: &Score| b.cmp(a),
         }
     }
 
     #[inline]
-    pub fn is_better_score(&self, good: f32, candidate: f32) -> bool {
+    pub fn is_better_score(&self, good: Score, candidate: Score) -> bool {
         match self {
             White => good < candidate,
             Black => good > candidate,
@@ -190,10 +191,10 @@ impl PieceColor {
     }
 
     #[inline]
-    pub const fn worst_score(&self) -> f32 {
+    pub const fn worst_score(&self) -> Score {
         match self {
-            White => f32::NEG_INFINITY,
-            Black => f32::INFINITY,
+            White => Score::NEG_INFINITY,
+            Black => Score::INFINITY,
         }
     }
 }
@@ -219,36 +220,41 @@ impl TryFrom<char> for PieceColor {
 #[cfg(test)]
 mod test {
     use crate::baserules::piece_color::PieceColor::{Black, White};
+    use crate::baserules::score::Score;
+
+    fn pawns<const N: usize>(scores: [f32; N]) -> [Score; N] {
+        scores.map(Score::from_pawns)
+    }
 
     #[test]
     fn test_better_score_white() {
-        assert!(White.is_better_score(2.0, 3.0));
+        assert!(White.is_better_score(Score::from_pawns(2.0), Score::from_pawns(3.0)));
     }
     #[test]
     fn test_worse_score_white() {
-        assert!(!White.is_better_score(3.0, 2.0));
+        assert!(!White.is_better_score(Score::from_pawns(3.0), Score::from_pawns(2.0)));
     }
 
     #[test]
     fn test_better_score_black() {
-        assert!(Black.is_better_score(-2.0, -3.0));
+        assert!(Black.is_better_score(Score::from_pawns(-2.0), Score::from_pawns(-3.0)));
     }
     #[test]
     fn test_worse_score_black() {
-        assert!(!Black.is_better_score(-3.0, -2.0));
+        assert!(!Black.is_better_score(Score::from_pawns(-3.0), Score::from_pawns(-2.0)));
     }
 
     #[test]
     fn test_score_sorting_white() {
-        let mut sample_scores = [-2., -5., 11., -33.3, 1.2];
+        let mut sample_scores = pawns([-2., -5., 11., -33.3, 1.2]);
         sample_scores.sort_by(White.score_comparator());
-        assert_eq!([-33.3, -5., -2., 1.2, 11.], sample_scores);
+        assert_eq!(pawns([-33.3, -5., -2., 1.2, 11.]), sample_scores);
     }
 
     #[test]
     fn test_score_sorting_black() {
-        let mut sample_scores = [-2., -5., 11., -33.3, 1.2];
+        let mut sample_scores = pawns([-2., -5., 11., -33.3, 1.2]);
         sample_scores.sort_by(Black.score_comparator());
-        assert_eq!([11., 1.2, -2., -5., -33.3], sample_scores);
+        assert_eq!(pawns([11., 1.2, -2., -5., -33.3]), sample_scores);
     }
 }
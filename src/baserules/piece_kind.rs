@@ -21,8 +21,10 @@
  *  (C) Copyright 2022-3, Gabor Kecskemeti
  */
 use crate::baserules::board::PSBoard;
-use crate::baserules::board_rep::PossibleMove;
-use crate::baserules::positions::{AbsoluteBoardPos, RelativeBoardPos};
+use crate::baserules::move_list::MoveList;
+use crate::baserules::piece_color::PieceColor;
+use crate::baserules::positions::{AbsoluteBoardPos, RelativeBoardPos, Square};
+use crate::baserules::rawboard::RawBoard;
 use crate::util::{AnyError, IntResult};
 use enum_iterator::{all, Sequence};
 use enum_map::{enum_map, Enum, EnumMap};
@@ -61,6 +63,49 @@ static CHAR_PIECE_MAP: [Option<PieceKind>; 17] = [
     Some(Rook),   //r
 ];
 
+/// The squares a knight attacks from each square, as bitboards indexed by `Square`
+pub static KNIGHT_ATTACKS: [u64; Square::COUNT] = leaper_attacks(&[
+    (-1, -2),
+    (-1, 2),
+    (-2, -1),
+    (-2, 1),
+    (1, -2),
+    (1, 2),
+    (2, -1),
+    (2, 1),
+]);
+
+/// The squares a king attacks from each square, as bitboards indexed by `Square`
+pub static KING_ATTACKS: [u64; Square::COUNT] = leaper_attacks(&[
+    (-1, -1),
+    (1, 1),
+    (-1, 1),
+    (1, -1),
+    (-1, 0),
+    (1, 0),
+    (0, 1),
+    (0, -1),
+]);
+
+/// The squares reached with one of the steps from each square, computed at compile time
+const fn leaper_attacks(steps: &[(i8, i8); 8]) -> [u64; Square::COUNT] {
+    let mut attacks = [0; Square::COUNT];
+    let mut index = 0;
+    while index < Square::COUNT {
+        let (row, col) = ((index / 8) as i8, (index % 8) as i8);
+        let mut step = 0;
+        while step < steps.len() {
+            let (to_row, to_col) = (row + steps[step].0, col + steps[step].1);
+            if to_row >= 0 && to_row < 8 && to_col >= 0 && to_col < 8 {
+                attacks[index] |= 1 << (to_row * 8 + to_col);
+            }
+            step += 1;
+        }
+        index += 1;
+    }
+    attacks
+}
+
 lazy_static! {
     /// Lists all possible moves for the pieces or all possible directions if pieces can slide across the board
     /// For kings and knights it is all possible relative moves compared to their current square
@@ -85,7 +130,10 @@ impl TryFrom<char> for PieceKind {
 
     fn try_from(piece: char) -> IntResult<Self> {
         let idx: usize = (piece.to_ascii_lowercase() as i8 - b'b' as i8).try_into()?;
-        CHAR_PIECE_MAP[idx]
+        CHAR_PIECE_MAP
+            .get(idx)
+            .copied()
+            .flatten()
             .ok_or_else(|| format!("Unexpected chess piece character {piece}").into())
     }
 }
@@ -104,6 +152,19 @@ impl PieceKind {
         }
     }
 
+    /// The usual value of the piece in centipawns, the king is not counted in the material
+    #[inline]
+    pub fn value(self) -> i32 {
+        match self {
+            Pawn => 100,
+            Knight => 300,
+            Bishop => 310,
+            Rook => 500,
+            Queen => 900,
+            King => 0,
+        }
+    }
+
     /// Allows querying the kinds of moves we can make with a particular piece. Note pawn moves are handled differently as they depend on the colour and state of the pawn
     #[inline]
     pub fn vec_moves(self) -> &'static Vec<RelativeBoardPos> {
@@ -128,24 +189,47 @@ impl PieceKind {
     }
 
     #[inline]
-    pub fn gen_moves(
-        &self,
-        board: &PSBoard,
-        pos: AbsoluteBoardPos,
-        the_moves: &mut Vec<PossibleMove>,
-    ) {
+    pub fn gen_moves(&self, board: &PSBoard, pos: AbsoluteBoardPos, the_moves: &mut MoveList) {
         match self {
             Pawn => board.gen_pawn_moves(pos, the_moves),
             King => board.gen_king_moves(pos, the_moves),
-            // The knight moves are not directional, so we handle them specifically
-            Knight => board.gen_moves_from_dirs(
-                pos,
-                &PSBoard::piece_move_rule,
-                self.vec_moves(),
-                the_moves,
-            ),
+            // The knight moves are not directional, so they are looked up
+            Knight => board.gen_moves_from_table(pos, &KNIGHT_ATTACKS, the_moves),
             // All other pieces have simple directional movement, so we just use their directions to generate their possible moves
             Bishop | Rook | Queen => board.gen_moves_from_vecs(pos, self.vec_moves(), the_moves),
         }
     }
+
+    /// Counts the squares the piece of the side could move to from the position, the same as the
+    /// potential moves generated for it, but without creating them. Only the knights, bishops,
+    /// rooks and queens are counted, the pawns and kings have none.
+    pub fn count_moves(self, raw: &RawBoard, pos: AbsoluteBoardPos, color: PieceColor) -> u32 {
+        match self {
+            Bishop | Rook | Queen => {}
+            Knight => {
+                let targets = KNIGHT_ATTACKS[Square::from(pos).index()];
+                return (targets & !raw.occupied_by(color)).count_ones();
+            }
+            King | Pawn => return 0,
+        }
+        let mut count = 0;
+        for RelativeBoardPos(row_step, col_step) in self.vec_moves() {
+            let (mut row, mut col) = (pos.0 as i8, pos.1 as i8);
+            loop {
+                row += row_step;
+                col += col_step;
+                if !(0..8).contains(&row) || !(0..8).contains(&col) {
+                    break;
+                }
+                match raw[AbsoluteBoardPos(row as u8, col as u8)] {
+                    None => count += 1,
+                    Some(piece) => {
+                        count += u32::from(piece.color != color);
+                        break;
+                    }
+                }
+            }
+        }
+        count
+    }
 }
@@ -0,0 +1,195 @@
+/*
+ *  ========================================================================
+ *  DBCE chess bot, piece-square tables for the evaluation
+ *  ========================================================================
+ *
+ *  This file is part of DBCE.
+ *
+ *  DBCE is free software: you can redistribute it and/or
+ *  modify it under the terms of the GNU General Public License as published
+ *  by the Free Software Foundation, either version 3 of the License, or (at
+ *  your option) any later version.
+ *
+ *  DBCE is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ *  General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License along
+ *  with DBCE.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ *  (C) Copyright 2022-3, Gabor Kecskemeti
+ */
+use crate::baserules::piece_color::PieceColor::{Black, White};
+use crate::baserules::piece_kind::PieceKind::*;
+use crate::baserules::piece_state::PieceState;
+use crate::baserules::positions::Square;
+use crate::baserules::rawboard::RawBoard;
+use crate::baserules::score::Score;
+
+/// The bonuses of a piece in centipawns by its square, as seen from white's side of the board:
+/// the first row is the eighth rank. Black's pieces use the mirrored table.
+type Table = [[i32; 8]; 8];
+
+const PAWN: Table = [
+    [0, 0, 0, 0, 0, 0, 0, 0],
+    [50, 50, 50, 50, 50, 50, 50, 50],
+    [10, 10, 20, 30, 30, 20, 10, 10],
+    [5, 5, 10, 25, 25, 10, 5, 5],
+    [0, 0, 0, 20, 20, 0, 0, 0],
+    [5, -5, -10, 0, 0, -10, -5, 5],
+    [5, 10, 10, -20, -20, 10, 10, 5],
+    [0, 0, 0, 0, 0, 0, 0, 0],
+];
+
+const KNIGHT: Table = [
+    [-50, -40, -30, -30, -30, -30, -40, -50],
+    [-40, -20, 0, 0, 0, 0, -20, -40],
+    [-30, 0, 10, 15, 15, 10, 0, -30],
+    [-30, 5, 15, 20, 20, 15, 5, -30],
+    [-30, 0, 15, 20, 20, 15, 0, -30],
+    [-30, 5, 10, 15, 15, 10, 5, -30],
+    [-40, -20, 0, 5, 5, 0, -20, -40],
+    [-50, -40, -30, -30, -30, -30, -40, -50],
+];
+
+const BISHOP: Table = [
+    [-20, -10, -10, -10, -10, -10, -10, -20],
+    [-10, 0, 0, 0, 0, 0, 0, -10],
+    [-10, 0, 5, 10, 10, 5, 0, -10],
+    [-10, 5, 5, 10, 10, 5, 5, -10],
+    [-10, 0, 10, 10, 10, 10, 0, -10],
+    [-10, 10, 10, 10, 10, 10, 10, -10],
+    [-10, 5, 0, 0, 0, 0, 5, -10],
+    [-20, -10, -10, -10, -10, -10, -10, -20],
+];
+
+const ROOK: Table = [
+    [0, 0, 0, 0, 0, 0, 0, 0],
+    [5, 10, 10, 10, 10, 10, 10, 5],
+    [-5, 0, 0, 0, 0, 0, 0, -5],
+    [-5, 0, 0, 0, 0, 0, 0, -5],
+    [-5, 0, 0, 0, 0, 0, 0, -5],
+    [-5, 0, 0, 0, 0, 0, 0, -5],
+    [-5, 0, 0, 0, 0, 0, 0, -5],
+    [0, 0, 0, 5, 5, 0, 0, 0],
+];
+
+const QUEEN: Table = [
+    [-20, -10, -10, -5, -5, -10, -10, -20],
+    [-10, 0, 0, 0, 0, 0, 0, -10],
+    [-10, 0, 5, 5, 5, 5, 0, -10],
+    [-5, 0, 5, 5, 5, 5, 0, -5],
+    [0, 0, 5, 5, 5, 5, 0, -5],
+    [-10, 5, 5, 5, 5, 5, 0, -10],
+    [-10, 0, 5, 0, 0, 0, 0, -10],
+    [-20, -10, -10, -5, -5, -10, -10, -20],
+];
+
+/// The king hides behind its pawns after castling while there are enough pieces to attack it
+const KING_MIDDLEGAME: Table = [
+    [-30, -40, -40, -50, -50, -40, -40, -30],
+    [-30, -40, -40, -50, -50, -40, -40, -30],
+    [-30, -40, -40, -50, -50, -40, -40, -30],
+    [-30, -40, -40, -50, -50, -40, -40, -30],
+    [-20, -30, -30, -40, -40, -30, -30, -20],
+    [-10, -20, -20, -20, -20, -20, -20, -10],
+    [20, 20, 0, 0, 0, 0, 20, 20],
+    [20, 30, 10, 0, 0, 10, 30, 20],
+];
+
+/// The king joins the fight from the centre once the pieces are traded off
+const KING_ENDGAME: Table = [
+    [-50, -40, -30, -20, -20, -30, -40, -50],
+    [-30, -20, -10, 0, 0, -10, -20, -30],
+    [-30, -10, 20, 30, 30, 20, -10, -30],
+    [-30, -10, 30, 40, 40, 30, -10, -30],
+    [-30, -10, 30, 40, 40, 30, -10, -30],
+    [-30, -10, 20, 30, 30, 20, -10, -30],
+    [-30, -30, 0, 0, 0, 0, -30, -30],
+    [-50, -30, -30, -30, -30, -30, -30, -50],
+];
+
+/// The value of the queens, rooks, bishops and knights of both sides, at or below which the
+/// kings use their endgame table
+const ENDGAME_PIECES: i32 = 2600;
+
+/// The bonus of the piece on the square from its own side's perspective
+fn bonus(piece: PieceState, square: Square, endgame: bool) -> i32 {
+    let table = match piece.kind {
+        Pawn => &PAWN,
+        Knight => &KNIGHT,
+        Bishop => &BISHOP,
+        Rook => &ROOK,
+        Queen => &QUEEN,
+        King if endgame => &KING_ENDGAME,
+        King => &KING_MIDDLEGAME,
+    };
+    let row = if piece.color == White {
+        7 - square.row()
+    } else {
+        square.row()
+    };
+    table[row as usize][square.col() as usize]
+}
+
+impl RawBoard {
+    /// White's piece-square bonuses minus black's, the kings switch to their endgame table when
+    /// few pieces are left
+    pub fn piece_squares(&self) -> Score {
+        let pieces = [White, Black]
+            .into_iter()
+            .flat_map(|color| self.pieces(color))
+            .filter(|(kind, _)| *kind != Pawn)
+            .map(|(kind, _)| kind.value())
+            .sum::<i32>();
+        let endgame = pieces <= ENDGAME_PIECES;
+        Score::centipawns(
+            [White, Black]
+                .into_iter()
+                .flat_map(|color| {
+                    self.pieces(color).map(move |(kind, square)| {
+                        let piece = PieceState { kind, color };
+                        color.mate_multiplier() * bonus(piece, square, endgame)
+                    })
+                })
+                .sum(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::baserules::board::PSBoard;
+    use crate::baserules::score::Score;
+    use tokio::test;
+
+    async fn piece_squares(fen: &str) -> Score {
+        PSBoard::from_fen(fen).await.unwrap().raw.piece_squares()
+    }
+
+    #[test]
+    async fn tables_are_mirrored_for_black() {
+        assert_eq!(Score::DRAW, PSBoard::default().raw.piece_squares());
+        let knights = piece_squares("4k3/8/8/8/3N4/8/8/n3K3 w - - 0 1").await;
+        assert_eq!(Score::centipawns(20 + 50), knights);
+        let mirrored = piece_squares("N3k3/8/8/3n4/8/8/8/4K3 w - - 0 1").await;
+        assert_eq!(-knights, mirrored);
+    }
+
+    #[test]
+    async fn pieces_prefer_their_squares() {
+        let castled = piece_squares("r2q1rk1/ppp2ppp/8/8/8/8/PPP2PPP/R2Q1RK1 w - - 0 1").await;
+        let uncastled = piece_squares("r2q1rk1/ppp2ppp/8/8/8/8/PPP2PPP/R2QKR2 w - - 0 1").await;
+        assert!(castled > uncastled);
+
+        let seventh = piece_squares("6k1/1R6/8/8/8/8/8/6K1 w - - 0 1").await;
+        let first = piece_squares("6k1/8/8/8/8/8/8/1R4K1 w - - 0 1").await;
+        assert!(seventh > first);
+
+        // Without the pieces the king heads for the centre
+        let central = piece_squares("6k1/8/8/8/4K3/8/8/8 w - - 0 1").await;
+        let cornered = piece_squares("6k1/8/8/8/8/8/8/6K1 w - - 0 1").await;
+        assert!(central > cornered);
+    }
+}
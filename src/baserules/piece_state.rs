@@ -131,18 +131,6 @@ lazy_static! {
             .enumerate()
             .map(|(idx, ps)| (ps, idx))
             .collect();
-    static ref ALL_POSSIBLE_PIECE_STATES_SPARSE: FxHashMap<u32, &'static Option<PieceState>> =
-        ALL_POSSIBLE_PIECE_STATES
-            .iter()
-            .enumerate()
-            .flat_map(shift)
-            .collect();
-}
-
-fn shift(
-    (idx, ps): (usize, &'static Option<PieceState>),
-) -> impl Iterator<Item = (u32, &'static Option<PieceState>)> {
-    (0..u32::BITS / 4).map(move |shift_amount| ((idx as u32) << (shift_amount * 4), ps))
 }
 
 impl TryFrom<char> for PieceState {
@@ -182,7 +170,9 @@ impl PieceState {
 
     #[inline]
     pub fn from_u32(bit_repr: u32) -> &'static Option<Self> {
-        ALL_POSSIBLE_PIECE_STATES_SPARSE[&bit_repr]
+        // The piece may be in any of the nibbles of a row, shifted down by the empty ones below it
+        let shift = bit_repr.trailing_zeros() & !3;
+        &ALL_POSSIBLE_PIECE_STATES[bit_repr.checked_shr(shift).unwrap_or(0) as usize]
     }
 
     #[inline]
@@ -67,7 +67,104 @@ impl TryFrom<usize> for AbsoluteBoardPos {
     type Error = AnyError;
     /// Allows loading absolute board coordinates to a BoardPos struct
     fn try_from(idx: usize) -> Result<Self, Self::Error> {
-        ((idx >> 3) as u8, (idx & 0b111) as u8).try_into()
+        Ok(Square::try_from(idx)?.into())
+    }
+}
+
+/// A square of the board by its index: a1 = 0, b1 = 1, ..., h1 = 7, a2 = 8, ..., h8 = 63.
+/// Tables with an entry per square are indexed with it, so the row and column arithmetic is
+/// only done here.
+///
+/// # Example
+/// ```
+/// use dbce::baserules::positions::{AbsoluteBoardPos, Square};
+/// use dbce::util::TryWithPanic;
+/// let e4: AbsoluteBoardPos = "e4".transform();
+/// let square = Square::from(e4);
+/// assert_eq!(28, square.index());
+/// assert_eq!(e4, square.into());
+/// assert_eq!("e4", square.to_string());
+/// assert_eq!(None, Square::new(64));
+/// ```
+#[derive(Eq, Hash, Copy, Clone, PartialEq, PartialOrd, Ord, Debug, Default)]
+pub struct Square(u8);
+
+impl Square {
+    /// The number of squares on the board, the size of the tables indexed by squares
+    pub const COUNT: usize = 64;
+
+    #[inline]
+    pub const fn new(index: u8) -> Option<Self> {
+        if (index as usize) < Self::COUNT {
+            Some(Square(index))
+        } else {
+            None
+        }
+    }
+
+    /// The square stored in the lowest 6 bits, the other bits are ignored
+    #[inline]
+    pub const fn from_bits(bits: u16) -> Self {
+        Square((bits & 0b11_1111) as u8)
+    }
+
+    #[inline]
+    pub const fn index(self) -> usize {
+        self.0 as usize
+    }
+
+    #[inline]
+    pub const fn row(self) -> u8 {
+        self.0 >> 3
+    }
+
+    #[inline]
+    pub const fn col(self) -> u8 {
+        self.0 & 0b111
+    }
+
+    /// All squares in index order
+    pub fn all() -> impl Iterator<Item = Self> {
+        (0..Self::COUNT as u8).map(Square)
+    }
+}
+
+impl From<AbsoluteBoardPos> for Square {
+    #[inline]
+    fn from(AbsoluteBoardPos(row, col): AbsoluteBoardPos) -> Self {
+        Square(row << 3 | col)
+    }
+}
+
+impl From<Square> for AbsoluteBoardPos {
+    #[inline]
+    fn from(square: Square) -> Self {
+        AbsoluteBoardPos(square.row(), square.col())
+    }
+}
+
+impl From<Square> for u8 {
+    #[inline]
+    fn from(square: Square) -> Self {
+        square.0
+    }
+}
+
+impl TryFrom<usize> for Square {
+    type Error = AnyError;
+
+    fn try_from(idx: usize) -> Result<Self, Self::Error> {
+        u8::try_from(idx)
+            .ok()
+            .and_then(Square::new)
+            .ok_or_else(|| format!("Square index out of range: {idx}").into())
+    }
+}
+
+impl Display for Square {
+    /// Formats the square in uci cell notation, like its position
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(&AbsoluteBoardPos::from(*self), f)
     }
 }
 
@@ -22,23 +22,38 @@
  */
 
 use crate::baserules::board_rep::BaseMove;
+use crate::baserules::piece_color::PieceColor;
 use crate::baserules::piece_color::PieceColor::{Black, White};
+use crate::baserules::piece_kind::PieceKind;
 use crate::baserules::piece_kind::PieceKind::{Bishop, King, Knight, Pawn, Queen, Rook};
 use crate::baserules::piece_state::PieceState;
-use crate::baserules::positions::AbsoluteBoardPos;
-use crate::util::{IntResult, TryWithPanic};
+use crate::baserules::positions::{AbsoluteBoardPos, Square};
+use crate::baserules::score::{Score, MATE};
+use crate::util::TryWithPanic;
+use enum_map::EnumMap;
 use lazy_static::lazy_static;
-use std::fmt::{Display, Formatter};
 use std::ops;
 
-pub static MATE: f32 = 1000.0;
-
-pub fn is_mate(score: f32) -> bool {
-    (score.abs() - MATE).abs() < 50.0
+/// The pieces of the board, both nibble-packed per square for looking up a square and as
+/// bitboards per kind and per color for the queries about whole sets of squares. The bit of the
+/// `Square` of index n is the bit n of the bitboards.
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub struct RawBoard {
+    /// A row of the board per entry, 4 bits per square
+    cells: [u32; 8],
+    kinds: EnumMap<PieceKind, u64>,
+    colors: EnumMap<PieceColor, u64>,
 }
 
-#[derive(Clone, Copy, Eq, PartialEq, Debug)]
-pub struct RawBoard([u32; 8]);
+/// The squares of the bits set in the bitboard, in index order
+pub fn bits(bitboard: u64) -> impl Iterator<Item = Square> {
+    let mut remaining = bitboard;
+    std::iter::from_fn(move || {
+        let square = Square::new(remaining.trailing_zeros() as u8)?;
+        remaining &= remaining - 1;
+        Some(square)
+    })
+}
 
 impl RawBoard {
     /// Creates a brand new raw board with no pieces on it
@@ -51,7 +66,57 @@ impl RawBoard {
     /// ```
     #[inline]
     pub fn empty() -> Self {
-        RawBoard([0; 8])
+        RawBoard {
+            cells: [0; 8],
+            kinds: EnumMap::default(),
+            colors: EnumMap::default(),
+        }
+    }
+
+    /// The squares with a piece on them
+    #[inline]
+    pub fn occupied(&self) -> u64 {
+        self.colors[White] | self.colors[Black]
+    }
+
+    /// The squares of the pieces of the color
+    #[inline]
+    pub fn occupied_by(&self, color: PieceColor) -> u64 {
+        self.colors[color]
+    }
+
+    /// The squares of the pieces of the kind and the color
+    ///
+    /// # Example use:
+    /// ```
+    /// use dbce::baserules::piece_color::PieceColor::White;
+    /// use dbce::baserules::piece_kind::PieceKind::Knight;
+    /// use dbce::baserules::rawboard::RawBoard;
+    /// let knights = RawBoard::default().pieces_of(Knight, White);
+    /// assert_eq!(1 << 1 | 1 << 6, knights);
+    /// ```
+    #[inline]
+    pub fn pieces_of(&self, kind: PieceKind, color: PieceColor) -> u64 {
+        self.kinds[kind] & self.colors[color]
+    }
+
+    /// The pieces of the color with their squares, found in the bitboards instead of going
+    /// through all the squares of the board
+    ///
+    /// # Example
+    /// ```
+    /// use dbce::baserules::board::PSBoard;
+    /// use dbce::baserules::piece_color::PieceColor::Black;
+    /// use dbce::baserules::piece_kind::PieceKind::King;
+    /// let board = PSBoard::default();
+    /// assert_eq!(16, board.pieces(Black).count());
+    /// assert!(board.pieces(Black).any(|(kind, square)| kind == King && square.to_string() == "e8"));
+    /// ```
+    pub fn pieces(&self, color: PieceColor) -> impl Iterator<Item = (PieceKind, Square)> + '_ {
+        let own = self.colors[color];
+        self.kinds
+            .iter()
+            .flat_map(move |(kind, pieces)| bits(pieces & own).map(move |square| (kind, square)))
     }
 
     /// Determines what piece is at a particular location of the board
@@ -95,9 +160,28 @@ impl RawBoard {
         piece: &Option<PieceState>,
     ) {
         let (shift_amount, piece_mask, us_row) = Self::shift_and_mask(row, col);
-        let a_row = self.0[us_row] & piece_mask;
+        let a_row = self.cells[us_row] & piece_mask;
         let a_bit_piece = PieceState::bits_u32(piece) << shift_amount;
-        self.0[us_row] = a_row | a_bit_piece;
+        self.cells[us_row] = a_row | a_bit_piece;
+        self.clear_bits(row, col);
+        if let Some(piece) = piece {
+            let bit = 1 << (row << 3 | col);
+            self.kinds[piece.kind] |= bit;
+            self.colors[piece.color] |= bit;
+        }
+    }
+
+    #[inline]
+    fn clear_bits(&mut self, row: u8, col: u8) {
+        let bit = 1u64 << (row << 3 | col);
+        if self.occupied() & bit != 0 {
+            self.kinds
+                .values_mut()
+                .for_each(|bitboard| *bitboard &= !bit);
+            self.colors
+                .values_mut()
+                .for_each(|bitboard| *bitboard &= !bit);
+        }
     }
 
     /// Helper method to get the row and column information in forms usable by location based methods
@@ -111,7 +195,8 @@ impl RawBoard {
     #[inline]
     pub fn clear_loc(&mut self, AbsoluteBoardPos(row, col): AbsoluteBoardPos) {
         let (_, piece_mask, us_row) = Self::shift_and_mask(row, col);
-        self.0[us_row] &= piece_mask;
+        self.cells[us_row] &= piece_mask;
+        self.clear_bits(row, col);
     }
 
     /// Makes a rudimentary move, sets the new loc with the piece given and erases the previous.
@@ -121,74 +206,56 @@ impl RawBoard {
         self.clear_loc(the_move.from);
     }
 
-    /// A simple scoring mechanism which just counts up the pieces and pawns based on their usual values
+    /// A simple scoring mechanism which just counts up the pieces and pawns based on their usual
+    /// values, boards without one of the kings are mates
     ///
     /// # Example use
-    /// Each `PSBoard` has its score automatically calculated with this method during creation, so this is an indirect demonstration.
     /// ```
     /// use dbce::baserules::board::PSBoard;
-    /// use dbce::baserules::rawboard::{MATE};
+    /// use dbce::baserules::score::MATE;
     /// let scholars_mate = PSBoard::from_fen("1rbqQb1r/pppp2pp/2n2n2/4p3/2B1P3/8/PPPP1PPP/RNB1K1NR b QKqk - 9 5").unwrap();
     /// assert_eq!(MATE, scholars_mate.raw.score());
     /// ```
-    pub async fn score(&self) -> f32 {
-        let (loc_score, white_king_found, black_king_found) = self
-            .into_iter()
-            .filter_map(|c_p| *c_p)
-            .map(|curr_piece| match (curr_piece.kind, curr_piece.color) {
-                (Pawn, White) => (1f32, false, false),
-                (Pawn, Black) => (-1f32, false, false),
-                (Knight, White) => (3f32, false, false),
-                (Knight, Black) => (-3f32, false, false),
-                (Bishop, White) => (3.1f32, false, false),
-                (Bishop, Black) => (-3.1f32, false, false),
-                (Rook, White) => (5f32, false, false),
-                (Rook, Black) => (-5f32, false, false),
-                (Queen, White) => (9f32, false, false),
-                (Queen, Black) => (-9f32, false, false),
-                (King, White) => (0f32, true, false),
-                (King, Black) => (0f32, false, true),
-            })
-            .fold(
-                (0f32, false, false),
-                |(curr_score, curr_white_king, curr_black_king),
-                 (score_adjust, white_king, black_king)| {
-                    (
-                        curr_score + score_adjust,
-                        curr_white_king | white_king,
-                        curr_black_king | black_king,
-                    )
-                },
-            );
-        if white_king_found {
-            if black_king_found {
-                loc_score
-            } else {
-                MATE
-            }
-        } else {
-            -MATE
+    pub async fn score(&self) -> Score {
+        match self.captured_king() {
+            Some(White) => -MATE,
+            Some(Black) => MATE,
+            None => self.material(),
         }
     }
 
-    pub fn from_fen_prefix(fen: impl AsRef<str>) -> IntResult<Self> {
-        let mut new_board = RawBoard::empty();
-        let fen_part = fen.as_ref();
-        let mut row = 7;
-        let mut col = 0;
-        for piece_info in fen_part.chars() {
-            if piece_info.is_ascii_digit() {
-                col += piece_info as u8 - b'0';
-            } else if piece_info == '/' {
-                col = 0;
-                assert_ne!(row, 0, "Should not have more rows on the chessboard!");
-                row -= 1;
-            } else {
-                new_board.set_loc((row, col).transform(), &Some(piece_info.try_into()?));
-                col += 1;
-            }
+    /// The squares of the kings found on the board
+    pub fn find_kings(&self) -> EnumMap<PieceColor, Option<AbsoluteBoardPos>> {
+        EnumMap::from_fn(|color| {
+            bits(self.pieces_of(King, color))
+                .next()
+                .map(AbsoluteBoardPos::from)
+        })
+    }
+
+    /// The color of the king missing from the board, if one of them has been captured
+    pub fn captured_king(&self) -> Option<PieceColor> {
+        if self.pieces_of(King, White) == 0 {
+            Some(White)
+        } else if self.pieces_of(King, Black) == 0 {
+            Some(Black)
+        } else {
+            None
         }
-        Ok(new_board)
+    }
+
+    /// The value of white's pieces and pawns minus black's, by their usual values
+    pub fn material(&self) -> Score {
+        Score::centipawns(
+            self.kinds
+                .iter()
+                .map(|(kind, pieces)| {
+                    let balance = (pieces & self.colors[White]).count_ones() as i32
+                        - (pieces & self.colors[Black]).count_ones() as i32;
+                    kind.value() * balance
+                })
+                .sum(),
+        )
     }
 
     pub fn to_fen_prefix(&self) -> String {
@@ -270,46 +337,6 @@ impl Default for RawBoard {
     }
 }
 
-impl Display for RawBoard {
-    /// This allows a simple text display of the raw board on your console. Good for debugging purposes
-    ///
-    /// # Example:
-    /// ```
-    /// use dbce::baserules::board::PSBoard;
-    /// use dbce::baserules::rawboard::RawBoard;
-    /// let empty_board = RawBoard::empty();
-    /// let formatted = format!("{empty_board}");
-    /// assert_eq!("  abcdefgh\n8 -------- 8\n7 -------- 7\n6 -------- 6\n5 -------- 5\n4 -------- 4\n3 -------- 3\n2 -------- 2\n1 -------- 1\n  abcdefgh\n",formatted);
-    /// ```
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        static COL: &str = "  abcdefgh\n";
-        let mut return_string = String::new();
-        return_string.push_str(COL);
-        for row in (0..8u8).rev() {
-            for col in 0..8 {
-                let row_as_str = (row + 1).to_string();
-                let (prefix, suffix) = if col == 0 {
-                    (format!("{} ", row_as_str), String::new())
-                } else if col == 7 {
-                    (String::new(), format!(" {}", row_as_str))
-                } else {
-                    (String::new(), String::new())
-                };
-                return_string.push_str(&format!(
-                    "{}{}{}",
-                    prefix,
-                    &self[(row, col)]
-                        .map_or('-', |ps| format!("{}", ps.to_unicode()).pop().unwrap()),
-                    suffix
-                ));
-            }
-            return_string.push('\n');
-        }
-        return_string.push_str(COL);
-        write!(f, "{return_string}")
-    }
-}
-
 impl ops::Index<AbsoluteBoardPos> for RawBoard {
     type Output = Option<PieceState>;
 
@@ -328,7 +355,7 @@ impl ops::Index<AbsoluteBoardPos> for RawBoard {
     /// ```
     #[inline]
     fn index(&self, AbsoluteBoardPos(row, col): AbsoluteBoardPos) -> &Self::Output {
-        PieceState::masked_ps_conversion(col as usize, self.0[row as usize])
+        PieceState::masked_ps_conversion(col as usize, self.cells[row as usize])
     }
 }
 
@@ -403,7 +430,7 @@ impl<'a> IntoIterator for &'a RawBoard {
 /// Should be created with RawBoard::into_iter.
 pub struct RawBoardIterator<'a> {
     raw_board: &'a RawBoard,
-    curr_idx: usize,
+    curr_idx: u8,
 }
 
 impl<'a> Iterator for RawBoardIterator<'a> {
@@ -411,19 +438,16 @@ impl<'a> Iterator for RawBoardIterator<'a> {
 
     #[inline]
     fn next(&mut self) -> Option<Self::Item> {
-        if self.curr_idx == 64 {
-            None
-        } else {
-            let ret = PieceState::masked_ps_conversion(self.curr_idx & 0b111, unsafe {
-                *self.raw_board.0.get_unchecked(self.curr_idx >> 3)
-            });
-            self.curr_idx += 1;
-            Some(ret)
-        }
+        let square = Square::new(self.curr_idx)?;
+        self.curr_idx += 1;
+        Some(PieceState::masked_ps_conversion(
+            square.col() as usize,
+            unsafe { *self.raw_board.cells.get_unchecked(square.row() as usize) },
+        ))
     }
 
     fn size_hint(&self) -> (usize, Option<usize>) {
-        let remainder = 64 - self.curr_idx;
+        let remainder = Square::COUNT - self.curr_idx as usize;
         (remainder, Some(remainder))
     }
 }
@@ -432,10 +456,79 @@ impl<'a> ExactSizeIterator for RawBoardIterator<'a> {}
 
 #[cfg(test)]
 mod test {
-    use crate::baserules::rawboard::MATE;
+    use crate::baserules::board_rep::PossibleMove;
+    use crate::baserules::piece_color::PieceColor::{Black, White};
+    use crate::baserules::piece_kind::PieceKind;
+    use crate::baserules::positions::Square;
+    use crate::baserules::rawboard::RawBoard;
+    use crate::baserules::score::{Score, MATE};
     use crate::baserules::{board::PSBoard, board_rep::BaseMove};
+    use enum_iterator::all;
     use tokio::test;
 
+    /// Checks the bitboards against the squares of the board
+    fn assert_bitboards_match(raw: &RawBoard) {
+        let mut occupied = 0;
+        for (square, c_p) in Square::all().zip(raw) {
+            let bit = 1 << square.index();
+            for kind in all::<PieceKind>() {
+                for color in [White, Black] {
+                    let here = c_p.is_some_and(|piece| piece.kind == kind && piece.color == color);
+                    assert_eq!(here, raw.pieces_of(kind, color) & bit != 0, "{square}");
+                }
+            }
+            if c_p.is_some() {
+                occupied |= bit;
+            }
+        }
+        assert_eq!(occupied, raw.occupied());
+        for color in [White, Black] {
+            let listed: Vec<(PieceKind, Square)> = raw.pieces(color).collect();
+            let scanned: Vec<(PieceKind, Square)> = Square::all()
+                .zip(raw)
+                .filter_map(|(square, c_p)| {
+                    c_p.filter(|piece| piece.color == color)
+                        .map(|piece| (piece.kind, square))
+                })
+                .collect();
+            assert_eq!(scanned.len(), listed.len());
+            assert!(scanned.iter().all(|piece| listed.contains(piece)));
+        }
+    }
+
+    #[test]
+    async fn bitboards_follow_the_pieces() {
+        let mut board = PSBoard::from_fen("r3k2r/1P6/8/3pP3/8/8/8/R3K2R w KQkq d6 0 1")
+            .await
+            .unwrap();
+        assert_bitboards_match(&board.raw);
+        let castle = PossibleMove {
+            the_move: BaseMove::from_uci("e1g1").unwrap(),
+            pawn_promotion: None,
+            rook: Some(BaseMove::from_uci("h1f1").unwrap()),
+        };
+        // En passant, promotion with a capture and castling
+        for a_move in [
+            PossibleMove::simple_from_uci("e5d6").unwrap(),
+            PossibleMove::simple_from_uci("e8d8").unwrap(),
+            PossibleMove {
+                pawn_promotion: Some(PieceKind::Queen),
+                ..PossibleMove::simple_from_uci("b7a8").unwrap()
+            },
+            PossibleMove::simple_from_uci("h8h2").unwrap(),
+            castle,
+        ] {
+            board = board.make_move_noncached(&a_move).await;
+            assert_bitboards_match(&board.raw);
+        }
+        let undo = board.make_move_in_place(&PossibleMove::simple_from_uci("h2a2").unwrap());
+        assert_bitboards_match(&board.raw);
+        board.unmake_move(undo);
+        assert_bitboards_match(&board.raw);
+        assert_eq!(0, board.raw.pieces_of(PieceKind::Pawn, Black));
+        assert_eq!(1, board.raw.pieces_of(PieceKind::Queen, White).count_ones());
+    }
+
     #[test]
     async fn iterator_test() {
         let psboard = PSBoard::default();
@@ -466,6 +559,6 @@ mod test {
             mate_score,
             impossible_board.who_moves.mate_multiplier() * MATE
         );
-        assert_eq!(score, 0.0);
+        assert_eq!(score, Score::DRAW);
     }
 }
@@ -0,0 +1,248 @@
+/*
+ *  ========================================================================
+ *  DBCE chess bot, scores of boards
+ *  ========================================================================
+ *
+ *  This file is part of DBCE.
+ *
+ *  DBCE is free software: you can redistribute it and/or
+ *  modify it under the terms of the GNU General Public License as published
+ *  by the Free Software Foundation, either version 3 of the License, or (at
+ *  your option) any later version.
+ *
+ *  DBCE is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ *  General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License along
+ *  with DBCE.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ *  (C) Copyright 2022-3, Gabor Kecskemeti
+ */
+use crate::baserules::piece_color::PieceColor;
+use std::fmt::{Display, Formatter};
+use std::iter::Sum;
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+/// The score of a board where a king has been captured, 1000 pawns
+const MATE_VALUE: i32 = 100_000;
+/// Scores this close to the mate value are mates, the difference being the plies to the mate
+const MAX_MATE_PLIES: i32 = 256;
+
+/// The evaluation of a board in centipawns, or the number of plies until a king is captured.
+/// Positive scores favour white, apart from the negamax searches, where they favour the side
+/// to move. Mates compare above all centipawn scores, shorter mates above the longer ones.
+///
+/// # Example
+/// ```
+/// use dbce::baserules::piece_color::PieceColor::White;
+/// use dbce::baserules::score::Score;
+/// let mate_in_two = Score::mate(White, 5);
+/// assert!(Score::mate(White, 3) > mate_in_two);
+/// assert!(mate_in_two > Score::centipawns(900));
+/// assert_eq!("#2", mate_in_two.to_string());
+/// assert_eq!(Score::mate(White, 6), mate_in_two.backed_up());
+/// ```
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Score(i32);
+
+/// The score of a board where the black king has been captured
+pub const MATE: Score = Score(MATE_VALUE);
+
+impl Score {
+    pub const DRAW: Score = Score(0);
+    /// Below all other scores, the starting point of the maximum searches
+    pub const NEG_INFINITY: Score = Score(-MATE_VALUE - 1);
+    /// Above all other scores, the starting point of the minimum searches
+    pub const INFINITY: Score = Score(MATE_VALUE + 1);
+
+    /// A positional score, kept below the mates
+    pub const fn centipawns(centipawns: i32) -> Self {
+        let limit = MATE_VALUE - MAX_MATE_PLIES;
+        Score(if centipawns > limit {
+            limit
+        } else if centipawns < -limit {
+            -limit
+        } else {
+            centipawns
+        })
+    }
+
+    pub fn from_pawns(pawns: f32) -> Self {
+        Self::centipawns((pawns * 100.0).round() as i32)
+    }
+
+    /// The winner captures the king of the other side after the given number of plies
+    pub fn mate(winner: PieceColor, plies: u8) -> Self {
+        winner.mate_multiplier() * Score(MATE_VALUE - i32::from(plies))
+    }
+
+    #[inline]
+    pub const fn is_mate(&self) -> bool {
+        self.0.abs() > MATE_VALUE - MAX_MATE_PLIES && self.is_finite()
+    }
+
+    /// Tells if the score is not one of the infinite search bounds
+    #[inline]
+    pub const fn is_finite(&self) -> bool {
+        self.0.abs() <= MATE_VALUE
+    }
+
+    /// The number of plies until the king is captured, if the score is a mate
+    pub fn mate_plies(&self) -> Option<u8> {
+        self.is_mate().then(|| (MATE_VALUE - self.0.abs()) as u8)
+    }
+
+    /// The number of moves of the winner until the mate, negative when black (or the side not
+    /// to move in the negamax searches) mates, like the mate scores of uci
+    pub fn mate_in(&self) -> Option<i8> {
+        self.mate_plies().map(|plies| {
+            let moves = ((i32::from(plies) - 1) / 2) as i8;
+            if self.0 > 0 {
+                moves
+            } else {
+                -moves
+            }
+        })
+    }
+
+    #[inline]
+    pub const fn as_centipawns(&self) -> i32 {
+        self.0
+    }
+
+    /// The score in pawns, the mates count as 1000 pawns
+    pub fn as_pawns(&self) -> f32 {
+        self.0 as f32 / 100.0
+    }
+
+    /// The raw representation of the score, e.g. to pack it into a transposition table entry
+    #[inline]
+    pub const fn to_bits(self) -> u32 {
+        self.0 as u32
+    }
+
+    #[inline]
+    pub const fn from_bits(bits: u32) -> Self {
+        Score(bits as i32)
+    }
+
+    /// The score as seen from the board before, i.e. with the mates one ply further away
+    #[inline]
+    pub fn backed_up(self) -> Self {
+        if self.is_mate() {
+            Score(self.0 - self.0.signum())
+        } else {
+            self
+        }
+    }
+
+    #[inline]
+    pub fn abs(self) -> Self {
+        Score(self.0.abs())
+    }
+}
+
+impl Neg for Score {
+    type Output = Score;
+
+    #[inline]
+    fn neg(self) -> Self::Output {
+        Score(-self.0)
+    }
+}
+
+/// Changes the perspective of a score with `PieceColor::mate_multiplier`
+impl Mul<Score> for i32 {
+    type Output = Score;
+
+    #[inline]
+    fn mul(self, score: Score) -> Self::Output {
+        Score(self * score.0)
+    }
+}
+
+impl Mul<i32> for Score {
+    type Output = Score;
+
+    #[inline]
+    fn mul(self, weight: i32) -> Self::Output {
+        Score(self.0 * weight)
+    }
+}
+
+impl Div<i32> for Score {
+    type Output = Score;
+
+    #[inline]
+    fn div(self, divisor: i32) -> Self::Output {
+        Score(self.0 / divisor)
+    }
+}
+
+impl Add for Score {
+    type Output = Score;
+
+    #[inline]
+    fn add(self, other: Score) -> Self::Output {
+        Score(self.0 + other.0)
+    }
+}
+
+impl Sub for Score {
+    type Output = Score;
+
+    #[inline]
+    fn sub(self, other: Score) -> Self::Output {
+        Score(self.0 - other.0)
+    }
+}
+
+impl Sum for Score {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(Score::DRAW, Add::add)
+    }
+}
+
+/// Mates are shown with the moves until the mate, e.g. #3 or #-2, other scores in pawns
+/// following the formatting options, e.g. `{:+.2}`
+impl Display for Score {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.mate_in() {
+            Some(moves) if self.0 < 0 => write!(f, "#-{}", moves.abs()),
+            Some(moves) => write!(f, "#{moves}"),
+            None => Display::fmt(&self.as_pawns(), f),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::baserules::piece_color::PieceColor::{Black, White};
+    use crate::baserules::score::{Score, MATE};
+    use tokio::test;
+
+    #[test]
+    async fn mates_compare_by_distance() {
+        let quick_mate = Score::mate(White, 3);
+        let slow_mate = Score::mate(White, 17);
+        assert!(quick_mate > slow_mate);
+        assert!(slow_mate > Score::centipawns(i32::MAX));
+        assert!(Score::mate(Black, 17) < Score::mate(Black, 19));
+        assert!(Score::mate(Black, 19) < Score::centipawns(i32::MIN));
+        assert_eq!(Some(1), quick_mate.mate_in());
+        assert_eq!(Some(-8), Score::mate(Black, 17).mate_in());
+        assert_eq!(Some(18), (-slow_mate).backed_up().mate_plies());
+        assert_eq!(None, Score::centipawns(150).mate_in());
+        assert!(!Score::INFINITY.is_mate() && MATE.is_mate());
+    }
+
+    #[test]
+    async fn displays_pawns_and_mates() {
+        assert_eq!("+1.50", format!("{:+.2}", Score::from_pawns(1.5)));
+        assert_eq!("-0.3", Score::centipawns(-30).to_string());
+        assert_eq!("#-3", Score::mate(Black, 7).to_string());
+        assert_eq!("#0", MATE.to_string());
+    }
+}
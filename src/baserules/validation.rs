@@ -0,0 +1,216 @@
+/*
+ *  ========================================================================
+ *  DBCE chess bot, checking that positions could occur in a game
+ *  ========================================================================
+ *
+ *  This file is part of DBCE.
+ *
+ *  DBCE is free software: you can redistribute it and/or
+ *  modify it under the terms of the GNU General Public License as published
+ *  by the Free Software Foundation, either version 3 of the License, or (at
+ *  your option) any later version.
+ *
+ *  DBCE is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ *  General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License along
+ *  with DBCE.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ *  (C) Copyright 2022-3, Gabor Kecskemeti
+ */
+use crate::baserules::board::PSBoard;
+use crate::baserules::castling::Castling;
+use crate::baserules::piece_color::PieceColor;
+use crate::baserules::piece_color::PieceColor::{Black, White};
+use crate::baserules::piece_kind::PieceKind::{King, Pawn, Rook};
+use crate::baserules::piece_state::PieceState;
+use crate::baserules::positions::AbsoluteBoardPos;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+
+/// Why a position could not occur in a game
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IllegalPosition {
+    /// The side does not have exactly one king
+    KingCount { color: PieceColor, count: u32 },
+    /// A pawn on the first or the eighth rank, where it would have been promoted
+    PawnOnBackRank(AbsoluteBoardPos),
+    /// The king of the side not to move can be taken
+    OpponentInCheck,
+    /// The castling is allowed, but the king or the rook has left its starting square
+    CastlingWithoutPieces(Castling),
+    /// No pawn could have just passed the en passant square
+    BadEnPassant(AbsoluteBoardPos),
+}
+
+impl Display for IllegalPosition {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IllegalPosition::KingCount { color, count } => {
+                write!(f, "{color:?} should have one king instead of {count}")
+            }
+            IllegalPosition::PawnOnBackRank(square) => {
+                write!(f, "there is a pawn on the back rank at {square}")
+            }
+            IllegalPosition::OpponentInCheck => {
+                write!(f, "the king of the side not to move is in check")
+            }
+            IllegalPosition::CastlingWithoutPieces(castling) => write!(
+                f,
+                "castling {} is allowed without the king and the rook on their squares",
+                castling.fen_char()
+            ),
+            IllegalPosition::BadEnPassant(square) => {
+                write!(
+                    f,
+                    "no pawn could have just passed the en passant square {square}"
+                )
+            }
+        }
+    }
+}
+
+impl Error for IllegalPosition {}
+
+impl PSBoard {
+    /// Checks the position for what could not happen in a game: the number of the kings, the
+    /// pawns on the back ranks, the king of the side not to move left in check, the castling
+    /// rights without the king or the rook on its square and the en passant squares not behind
+    /// a pawn of the opponent. The positions coming from the users or the network should be
+    /// checked before searching them.
+    ///
+    /// # Example
+    /// ```
+    /// use dbce::baserules::board::PSBoard;
+    /// use dbce::baserules::validation::IllegalPosition;
+    /// assert_eq!(Ok(()), PSBoard::default().validate());
+    /// let board = PSBoard::try_from_fen("4k3/8/8/8/8/8/8/4K2R w Q - 0 1").unwrap();
+    /// assert!(matches!(board.validate(), Err(IllegalPosition::CastlingWithoutPieces(_))));
+    /// ```
+    pub fn validate(&self) -> Result<(), IllegalPosition> {
+        for color in [White, Black] {
+            let count = self.raw.pieces_of(King, color).count_ones();
+            if count != 1 {
+                return Err(IllegalPosition::KingCount { color, count });
+            }
+        }
+        for color in [White, Black] {
+            for (kind, square) in self.raw.pieces(color) {
+                let pos = AbsoluteBoardPos::from(square);
+                if kind == Pawn && (pos.0 == 0 || pos.0 == 7) {
+                    return Err(IllegalPosition::PawnOnBackRank(pos));
+                }
+            }
+        }
+        if self.can_capture_king() {
+            return Err(IllegalPosition::OpponentInCheck);
+        }
+        for castling in self.castling {
+            let color = castling.color();
+            let castling_move = self.castling_move(castling);
+            let king = PieceState { kind: King, color };
+            let rook = PieceState { kind: Rook, color };
+            if self[castling_move.the_move.from] != Some(king)
+                || castling_move
+                    .rook
+                    .is_some_and(|rook_move| self[rook_move.from] != Some(rook))
+            {
+                return Err(IllegalPosition::CastlingWithoutPieces(castling));
+            }
+        }
+        if let Some(ep) = self.ep {
+            // The pawn of the opponent has moved two squares through the en passant square
+            let (ep_row, from_row, to_row) = match self.who_moves {
+                White => (5, 6, 4),
+                Black => (2, 1, 3),
+            };
+            let pawn = PieceState {
+                kind: Pawn,
+                color: self.who_moves.invert(),
+            };
+            if ep.0 != ep_row
+                || self[ep].is_some()
+                || self[(from_row, ep.1)].is_some()
+                || self[(to_row, ep.1)] != Some(pawn)
+            {
+                return Err(IllegalPosition::BadEnPassant(ep));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::baserules::board::PSBoard;
+    use crate::baserules::castling::Castling::{BlackKingSide, BlackQueenSide};
+    use crate::baserules::piece_color::PieceColor::{Black, White};
+    use crate::baserules::validation::IllegalPosition;
+    use crate::util::TryWithPanic;
+
+    #[test]
+    fn impossible_positions_are_found() {
+        let cases = [
+            (
+                "4k3/8/8/8/8/8/8/8 w - - 0 1",
+                IllegalPosition::KingCount {
+                    color: White,
+                    count: 0,
+                },
+            ),
+            (
+                "4k2k/8/8/8/8/8/8/4K3 w - - 0 1",
+                IllegalPosition::KingCount {
+                    color: Black,
+                    count: 2,
+                },
+            ),
+            (
+                "4k3/8/8/8/8/8/8/P3K3 w - - 0 1",
+                IllegalPosition::PawnOnBackRank("a1".transform()),
+            ),
+            (
+                "4r1k1/8/8/8/8/8/8/4K3 b - - 0 1",
+                IllegalPosition::OpponentInCheck,
+            ),
+            (
+                "r3k3/8/8/8/8/8/8/4K3 w k - 0 1",
+                IllegalPosition::CastlingWithoutPieces(BlackKingSide),
+            ),
+            (
+                "4k2r/8/8/8/8/8/8/4K3 w q - 0 1",
+                IllegalPosition::CastlingWithoutPieces(BlackQueenSide),
+            ),
+            (
+                "4k3/8/8/3p4/8/8/8/4K3 w - e6 0 1",
+                IllegalPosition::BadEnPassant("e6".transform()),
+            ),
+            (
+                "4k3/8/8/8/8/8/4P3/4K3 b - e3 0 1",
+                IllegalPosition::BadEnPassant("e3".transform()),
+            ),
+        ];
+        for (fen, expected) in cases {
+            let board = PSBoard::try_from_fen(fen).unwrap();
+            assert_eq!(Err(expected), board.validate(), "{fen}");
+        }
+    }
+
+    #[test]
+    fn game_positions_are_accepted() {
+        for fen in [
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9",
+        ] {
+            assert_eq!(
+                Ok(()),
+                PSBoard::try_from_fen(fen).unwrap().validate(),
+                "{fen}"
+            );
+        }
+    }
+}
@@ -0,0 +1,197 @@
+/*
+ *  ========================================================================
+ *  DBCE chess bot, zobrist hashing of boards
+ *  ========================================================================
+ *
+ *  This file is part of DBCE.
+ *
+ *  DBCE is free software: you can redistribute it and/or
+ *  modify it under the terms of the GNU General Public License as published
+ *  by the Free Software Foundation, either version 3 of the License, or (at
+ *  your option) any later version.
+ *
+ *  DBCE is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ *  General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License along
+ *  with DBCE.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ *  (C) Copyright 2022-3, Gabor Kecskemeti
+ */
+use crate::baserules::board::PSBoard;
+use crate::baserules::castling::Castling;
+use crate::baserules::piece_color::PieceColor;
+use crate::baserules::piece_color::PieceColor::Black;
+use crate::baserules::piece_state::PieceState;
+use crate::baserules::positions::{AbsoluteBoardPos, Square};
+use crate::baserules::rawboard::RawBoard;
+use enum_map::EnumMap;
+use enumset::EnumSet;
+use lazy_static::lazy_static;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+/// The random bit strings xor-ed together to form the hash of a board
+struct ZobristKeys {
+    /// Indexed with the bit representation of the piece and the square index, empty squares have no key
+    pieces: [[u64; Square::COUNT]; 16],
+    black_to_move: u64,
+    castling: EnumMap<Castling, u64>,
+    /// Indexed with the column of the en passant square
+    ep_col: [u64; 8],
+}
+
+lazy_static! {
+    /// Fixed seed, so the hashes stay the same across runs
+    static ref KEYS: ZobristKeys = {
+        let mut rng = StdRng::seed_from_u64(0xdbce);
+        let mut pieces = [[0; Square::COUNT]; 16];
+        pieces
+            .iter_mut()
+            .flat_map(|per_piece| per_piece.iter_mut())
+            .for_each(|key| *key = rng.gen());
+        pieces[PieceState::bits_usize(&None)] = [0; Square::COUNT];
+        ZobristKeys {
+            pieces,
+            black_to_move: rng.gen(),
+            castling: EnumMap::from_fn(|_| rng.gen()),
+            ep_col: rng.gen(),
+        }
+    };
+}
+
+#[inline]
+pub(crate) fn piece_key(piece: &Option<PieceState>, pos: AbsoluteBoardPos) -> u64 {
+    KEYS.pieces[PieceState::bits_usize(piece)][Square::from(pos).index()]
+}
+
+#[inline]
+pub(crate) fn who_moves_key(who_moves: PieceColor) -> u64 {
+    if who_moves == Black {
+        KEYS.black_to_move
+    } else {
+        0
+    }
+}
+
+#[inline]
+pub(crate) fn castling_key(castling: EnumSet<Castling>) -> u64 {
+    castling
+        .iter()
+        .fold(0, |key, a_castling| key ^ KEYS.castling[a_castling])
+}
+
+#[inline]
+pub(crate) fn ep_key(ep: Option<AbsoluteBoardPos>) -> u64 {
+    ep.map_or(0, |AbsoluteBoardPos(_, col)| KEYS.ep_col[col as usize])
+}
+
+/// Calculates the hash of a board from scratch, only needed when a board is not created by a move
+pub(crate) fn full_key(
+    raw: &RawBoard,
+    who_moves: PieceColor,
+    castling: EnumSet<Castling>,
+    ep: Option<AbsoluteBoardPos>,
+) -> u64 {
+    raw.into_iter().enumerate().fold(0, |key, (idx, piece)| {
+        key ^ KEYS.pieces[PieceState::bits_usize(piece)][idx]
+    }) ^ who_moves_key(who_moves)
+        ^ castling_key(castling)
+        ^ ep_key(ep)
+}
+
+impl PSBoard {
+    /// The zobrist hash of the board, identifies the position for transposition and repetition checks
+    ///
+    /// # Example
+    /// ```
+    /// use dbce::baserules::board::PSBoard;
+    /// assert_eq!(PSBoard::default().zobrist(), PSBoard::default().zobrist());
+    /// ```
+    #[inline]
+    pub fn zobrist(&self) -> u64 {
+        self.zobrist
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::baserules::board::PSBoard;
+    use crate::baserules::board_rep::{BaseMove, PossibleMove};
+    use crate::baserules::castling::Castling;
+    use crate::baserules::zobrist::full_key;
+    use crate::util::TryWithPanic;
+    use tokio::test;
+
+    async fn assert_incremental(fen: &str, moves: &[&str]) {
+        let mut board = PSBoard::from_fen(fen).await.unwrap();
+        for a_move in moves {
+            let the_move = PossibleMove {
+                the_move: BaseMove::from_uci(a_move).unwrap(),
+                pawn_promotion: a_move.chars().nth(4).map(|promotion| promotion.transform()),
+                rook: None,
+            };
+            board = board.make_move_noncached(&the_move).await;
+            assert_eq!(
+                full_key(&board.raw, board.who_moves, board.castling, board.ep),
+                board.zobrist(),
+                "Hash mismatch after {a_move} in {}",
+                board.to_fen()
+            );
+        }
+    }
+
+    #[test]
+    async fn incremental_matches_full() {
+        assert_incremental(
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            &[
+                "e2e4", "d7d5", "e4d5", "g8f6", "f1b5", "c7c5", "d5c6", "d8c7", "c6b7", "e8d8",
+                "b7a8q",
+            ],
+        )
+        .await;
+        // rook captures affecting castling of the opponent
+        assert_incremental(
+            "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1",
+            &["a1a8", "e8e7", "h1h8"],
+        )
+        .await;
+    }
+
+    #[test]
+    async fn transposition_same_hash() {
+        let start = PSBoard::default();
+        let mut first = start
+            .make_move_noncached(&PossibleMove::simple_from_uci("g1f3").unwrap())
+            .await;
+        for a_move in ["g8f6", "b1c3"] {
+            first = first
+                .make_move_noncached(&PossibleMove::simple_from_uci(a_move).unwrap())
+                .await;
+        }
+        let mut second = start
+            .make_move_noncached(&PossibleMove::simple_from_uci("b1c3").unwrap())
+            .await;
+        for a_move in ["g8f6", "g1f3"] {
+            second = second
+                .make_move_noncached(&PossibleMove::simple_from_uci(a_move).unwrap())
+                .await;
+        }
+        assert_eq!(first.zobrist(), second.zobrist());
+        assert_ne!(start.zobrist(), first.zobrist());
+    }
+
+    #[test]
+    async fn castling_hash() {
+        let board = PSBoard::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1")
+            .await
+            .unwrap();
+        let castle: &PossibleMove = Castling::WhiteKingSide.into();
+        let castled = board.make_move_noncached(castle).await;
+        let reloaded = PSBoard::from_fen(&castled.to_fen()).await.unwrap();
+        assert_eq!(reloaded.zobrist(), castled.zobrist());
+    }
+}
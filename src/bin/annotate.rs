@@ -0,0 +1,63 @@
+/*
+ *  ========================================================================
+ *  DBCE chess bot, annotation of lichess games from the command line
+ *  ========================================================================
+ *
+ *  This file is part of DBCE.
+ *
+ *  DBCE is free software: you can redistribute it and/or
+ *  modify it under the terms of the GNU General Public License as published
+ *  by the Free Software Foundation, either version 3 of the License, or (at
+ *  your option) any later version.
+ *
+ *  DBCE is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ *  General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License along
+ *  with DBCE.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ *  (C) Copyright 2022-3, Gabor Kecskemeti
+ */
+use dbce::engine::{Engine, Verbosity};
+use dbce::human_facing::analysis::{
+    analyse_game_in_passes, mark_only_moves, AnalysisPasses, LOSING_MARGIN, ONLY_MOVE_DEPTH,
+};
+use dbce::human_facing::game::fetch_lichess_game;
+use dbce::util::EmptyResult;
+use reqwest::Client;
+use std::env;
+
+/*
+Downloads a game from lichess, analyses it and prints its moves with the evaluations, marking the
+mistakes, the blunders and the only moves:
+annotate --lichess <game id>
+ */
+#[tokio::main]
+async fn main() -> EmptyResult {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let game_id = match args.as_slice() {
+        [source, game_id] if source == "--lichess" => game_id,
+        _ => return Err("Usage: annotate --lichess <game id>".into()),
+    };
+    let game = fetch_lichess_game(&Client::new(), game_id).await?;
+    println!(
+        "Analysing {} moves of https://lichess.org/{game_id}",
+        game.moves.len()
+    );
+    let (mut engine, _) = Engine::new();
+    engine.set_verbosity(Verbosity::Silent);
+    let passes = AnalysisPasses::default();
+    let mut analysis = analyse_game_in_passes(&engine, &game.start, &game.moves, &passes).await;
+    mark_only_moves(
+        &engine,
+        &game.start,
+        &mut analysis,
+        ONLY_MOVE_DEPTH,
+        LOSING_MARGIN,
+    )
+    .await;
+    println!("{}", game.annotate(&analysis, passes.suspicion_threshold));
+    Ok(())
+}
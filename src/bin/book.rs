@@ -0,0 +1,65 @@
+/*
+ *  ========================================================================
+ *  DBCE chess bot, building opening books from pgn files
+ *  ========================================================================
+ *
+ *  This file is part of DBCE.
+ *
+ *  DBCE is free software: you can redistribute it and/or
+ *  modify it under the terms of the GNU General Public License as published
+ *  by the Free Software Foundation, either version 3 of the License, or (at
+ *  your option) any later version.
+ *
+ *  DBCE is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ *  General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License along
+ *  with DBCE.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ *  (C) Copyright 2022-3, Gabor Kecskemeti
+ */
+use dbce::human_facing::book::{build_book_from, DEFAULT_BOOK_PLIES};
+use dbce::human_facing::pgn::PgnReader;
+use dbce::util::EmptyResult;
+use std::env;
+use std::fs::File;
+use std::io::BufReader;
+use std::thread::available_parallelism;
+use std::time::Instant;
+
+/*
+Builds an opening book from the games of a pgn file, skipping the duplicate games:
+book <pgn file> <book file> [plies per game] [workers]
+The lichess bot plays the moves of opening_book.bin in its data directory.
+ */
+#[tokio::main]
+async fn main() -> EmptyResult {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let (pgn_file, book_file) = match args.as_slice() {
+        [pgn_file, book_file, ..] if args.len() <= 4 => (pgn_file, book_file),
+        _ => return Err("Usage: book <pgn file> <book file> [plies per game] [workers]".into()),
+    };
+    let max_plies = match args.get(2) {
+        Some(plies) => plies.parse()?,
+        None => DEFAULT_BOOK_PLIES,
+    };
+    let workers = match args.get(3) {
+        Some(workers) => workers.parse()?,
+        None => available_parallelism()?.get(),
+    };
+    let started = Instant::now();
+    let pgn = PgnReader::new(BufReader::new(File::open(pgn_file)?));
+    let build = build_book_from(pgn, workers, max_plies)?;
+    std::fs::write(book_file, build.book.to_bytes())?;
+    println!(
+        "{} games read in {:?} on {workers} workers, {} duplicates and {} invalid ones skipped",
+        build.games,
+        started.elapsed(),
+        build.duplicates,
+        build.invalid
+    );
+    println!("{} positions written to {book_file}", build.book.len());
+    Ok(())
+}
@@ -0,0 +1,68 @@
+/*
+ *  ========================================================================
+ *  DBCE chess bot, animated gif export of a game from the command line
+ *  ========================================================================
+ *
+ *  This file is part of DBCE.
+ *
+ *  DBCE is free software: you can redistribute it and/or
+ *  modify it under the terms of the GNU General Public License as published
+ *  by the Free Software Foundation, either version 3 of the License, or (at
+ *  your option) any later version.
+ *
+ *  DBCE is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ *  General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License along
+ *  with DBCE.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ *  (C) Copyright 2022-3, Gabor Kecskemeti
+ */
+use dbce::baserules::board::PSBoard;
+use dbce::human_facing::animation::{encode_gif, record_game, RasterRenderer};
+use dbce::human_facing::render::RenderOptions;
+use dbce::util::EmptyResult;
+use std::env;
+use std::io::{self, Read};
+
+/*
+Reads the uci moves of a game from the standard input (e.g. the move list of a lichess game) and
+writes them as an animated gif:
+gif <output file> [milliseconds per move] [fen of the starting position]
+ */
+#[tokio::main]
+async fn main() -> EmptyResult {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let output = args
+        .first()
+        .ok_or("Usage: gif <output file> [milliseconds per move] [fen]")?;
+    let delay_ms: u16 = args.get(1).map_or(Ok(1000), |delay| delay.parse())?;
+    let start = match args.get(2) {
+        Some(fen) => PSBoard::try_from_fen(fen)?,
+        None => PSBoard::default(),
+    };
+    let mut move_list = String::new();
+    io::stdin().read_to_string(&mut move_list)?;
+    let mut moves = Vec::new();
+    let mut board = None;
+    for uci in move_list.split_whitespace() {
+        let current: &PSBoard = board.as_ref().unwrap_or(&start);
+        let a_move = current
+            .find_uci_move(uci)
+            .ok_or_else(|| format!("Illegal move {uci} in {}", current.to_fen()))?;
+        board = Some(current.make_move_noncached(&a_move).await);
+        moves.push(a_move);
+    }
+    let frames = record_game(
+        &start,
+        &moves,
+        &RasterRenderer::default(),
+        &RenderOptions::default(),
+    )
+    .await;
+    std::fs::write(output, encode_gif(&frames, delay_ms / 10))?;
+    println!("Written {} frames to {output}", frames.len());
+    Ok(())
+}
@@ -21,7 +21,15 @@
  *  (C) Copyright 2022-3, Gabor Kecskemeti
  */
 use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fs::OpenOptions;
 use std::io;
+use std::io::Write;
+use std::mem;
+use std::path::Path;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering::Relaxed;
+use std::sync::{Arc, Mutex};
 use std::thread::sleep;
 use std::time::{Duration, Instant};
 
@@ -30,15 +38,53 @@ use rand::{thread_rng, Rng};
 use reqwest::header::HeaderMap;
 use reqwest::{Client, RequestBuilder, Response, StatusCode};
 
+use dbce::baserules::board::PSBoard;
+use dbce::baserules::evaluation::{network_evaluator, Evaluator, Imbalance, TermWeights, Weighted};
 use dbce::baserules::piece_color::PieceColor::{Black, White};
-use dbce::engine::Engine;
+use dbce::baserules::score::Score;
+use dbce::engine::continuation::BoardContinuation;
+use dbce::engine::gamestate::GameState;
+use dbce::engine::prefetch::Prefetch;
+use dbce::engine::strength::StrengthSettings;
+use dbce::engine::tablebase::LichessTablebase;
+use dbce::engine::timing::GameClock;
+use dbce::engine::{Engine, InfoCallback};
+use dbce::human_facing::book::OpeningBook;
+use dbce::human_facing::config::{BotConfig, ShutdownPolicy, DEFAULT_CONFIG_FILE};
+use dbce::human_facing::game::GameResult;
 use dbce::human_facing::helper;
+use dbce::human_facing::opponents::{is_blunder_swing, FinishedGame, OpponentMemory};
 use dbce::util::{DurationAverage, EmptyResult, IntResult};
 use serde_json::Value;
 
-async fn play_a_game(gameid: &str, botid: &str, client: &Client) -> IntResult<Option<String>> {
+/// The tree the games start from, if it was precomputed with the tree binary into the data directory
+const OPENING_TREE_FILE: &str = "opening_tree.bin";
+/// The book the moves are played from while it knows the position, if it was built with the book
+/// binary into the data directory
+const OPENING_BOOK_FILE: &str = "opening_book.bin";
+/// The book moves played in fewer games are left for the search
+const MIN_BOOK_GAMES: u32 = 3;
+
+/// What the games share, loaded once at the start: the precomputed openings found in the data
+/// directory and the evaluator of the engine
+struct Preloaded {
+    tree: Option<BoardContinuation>,
+    book: Option<OpeningBook>,
+    evaluator: Arc<dyn Evaluator>,
+}
+
+async fn play_a_game(
+    gameid: &str,
+    botid: &str,
+    client: &Client,
+    config: &BotConfig,
+    opponents: &mut OpponentMemory,
+    preloaded: &Preloaded,
+    shutdown: &AtomicBool,
+) -> IntResult<Option<String>> {
     let resignwithgameid = format!("https://lichess.org/api/bot/game/{gameid}/resign/");
     let movewithgameid = format!("https://lichess.org/api/bot/game/{gameid}/move/");
+    let drawwithgameid = format!("https://lichess.org/api/bot/game/{gameid}/draw/yes");
     let getrq = client.get(format!("https://lichess.org/api/bot/game/stream/{gameid}"));
     let mut resp = lichess_api_call(getrq).await?.bytes_stream();
     let mut ourcolor = None;
@@ -47,15 +93,79 @@ async fn play_a_game(gameid: &str, botid: &str, client: &Client) -> IntResult<Op
     let mut opponent = None;
     let mut toignore = None;
     let mut impossiblemove = None;
-    let (engine, mut state) = Engine::new();
+    // Our evaluation after our previous move, to spot the opponent's blunders
+    let mut our_last_score = None;
+    let mut opponent_blunders = 0;
+    let mut left_the_game = false;
+    let (mut engine, mut state) = Engine::new();
+    // The position the moves of the game are played from, the standard one but in Chess960
+    let mut initial_board = PSBoard::default();
+    engine.set_evaluator(preloaded.evaluator.clone());
+    if let Some(tree) = preloaded
+        .tree
+        .as_ref()
+        .filter(|tree| tree.zobrist() == state.psboard().zobrist())
+    {
+        state = GameState::from_tree(tree.clone());
+    }
+    if config.tablebase {
+        engine.set_tablebase(Arc::new(LichessTablebase::new(client.clone())));
+    }
+    if let Some(node_budget) = config.node_budget {
+        engine.set_node_budget(node_budget);
+    }
+    let strength = config.strength_settings();
+    engine.set_verbosity(config.verbosity.unwrap_or_default());
+    engine.set_info_callback(match &config.log_file {
+        Some(log_file) => log_to(log_file)?,
+        None => Arc::new(|info| println!("{info}")),
+    });
+    engine.set_strength(strength);
+    engine.set_search_algorithm(config.algorithm);
+    engine.set_threads(config.threads);
     let mut lichesstiming = DurationAverage::new(50, || Duration::from_secs(1));
+    // Holds the game while the likely replies are expanded on the opponent's time
+    let mut prefetching: Option<Prefetch> = None;
     while let Some(Ok(bytes)) = resp.next().await {
         let start = Instant::now();
+        // Keeps expanding on the keep-alives, while the opponent is still thinking
+        let mut keep_prefetching = false;
+        if let Some(prefetch) = prefetching.take() {
+            state = prefetch.stop().await;
+            keep_prefetching = true;
+        }
+        if shutdown.load(Relaxed) && config.on_shutdown == ShutdownPolicy::Resign && !left_the_game
+        {
+            // The server still sends the end of the game, so its result is recorded as usual
+            let op = if state.psboard().move_count <= 1 {
+                "abort"
+            } else {
+                "resign"
+            };
+            println!("Shutting down, leaving the game with {op}");
+            lichess_api_call(
+                client.post(format!("https://lichess.org/api/bot/game/{gameid}/{op}")),
+            )
+            .await?;
+            left_the_game = true;
+        }
         if let Ok(gamestate) = serde_json::from_slice(&bytes) {
             let gamestate: Value = gamestate;
             let gamestate = if gamestate["type"] == "gameFull" {
-                assert_eq!(gamestate["variant"]["short"], "Std");
-                assert_eq!(gamestate["initialFen"], "startpos");
+                if let Some(fen) = gamestate["initialFen"]
+                    .as_str()
+                    .filter(|fen| *fen != "startpos")
+                {
+                    initial_board = PSBoard::try_from_fen(fen)?;
+                }
+                if gamestate["variant"]["key"] == "chess960" {
+                    initial_board.set_chess960(true);
+                }
+                if initial_board.zobrist() != state.psboard().zobrist()
+                    || initial_board.castling_files().chess960
+                {
+                    state = GameState::new(initial_board.clone());
+                }
                 let whiteplayer = gamestate["white"]["id"].as_str().unwrap();
                 let blackplayer = gamestate["black"]["id"].as_str().unwrap();
                 if whiteplayer.contains(botid) {
@@ -68,11 +178,30 @@ async fn play_a_game(gameid: &str, botid: &str, client: &Client) -> IntResult<Op
                     println!("WARNING: We are not even playing the game {gameid}!");
                     break;
                 }
+                // Draws are avoided against the lower rated opponents
+                let (ours, theirs) = if ourcolor == Some(White) {
+                    ("white", "black")
+                } else {
+                    ("black", "white")
+                };
+                let rating = |player: &str| gamestate[player]["rating"].as_u64();
+                if matches!((rating(ours), rating(theirs)), (Some(our), Some(their)) if our > their)
+                {
+                    engine.set_contempt(config.contempt, ourcolor.unwrap());
+                }
+                if let Some(record) = opponents.get(opponent.as_ref().unwrap()) {
+                    engine.set_strength(StrengthSettings {
+                        randomness: record.variety(strength.randomness),
+                        ..strength
+                    });
+                }
                 &gamestate["state"]
             } else {
                 &gamestate
             };
             if gamestate["type"] == "gameState" {
+                // Only the opponent's turn is prefetched
+                keep_prefetching = false;
                 if gamestate["status"] == "started" || gamestate["status"] == "created" {
                     if impossiblemove.is_some() {
                         lichess_api_call(client.post(resignwithgameid.clone())).await?;
@@ -89,34 +218,58 @@ async fn play_a_game(gameid: &str, botid: &str, client: &Client) -> IntResult<Op
                     if let Some(lastmove) = allmoves.split_ascii_whitespace().last() {
                         state.make_an_uci_move(lastmove).await?;
                     }
+                    let server_state = helper::replay_uci_moves(&initial_board, &allmoves).await?;
+                    let mismatch = state.psboard().diff(server_state.psboard());
+                    if !mismatch.is_empty() {
+                        // We rather continue from the position lichess knows about
+                        println!("Local board differs from the server's: {mismatch}");
+                        state = server_state;
+                    }
                     let currentboard = state.psboard();
-                    // we make sure we still have at least 20 moves to do before we run out of time.
-                    let deadline_divisor = 20
-                        * if currentboard.move_count == 0 {
-                            10 // Make the first move very quick to avoid aborts
-                        } else if currentboard.move_count < 10 {
-                            2 // Make the next few moves a bit quicker to allow more thought in late games
-                        } else {
-                            1 // Let's just allow as much thought now as we can go for
-                        };
                     let detected_color = *ourcolor.as_ref().unwrap();
                     if currentboard.who_moves == detected_color {
-                        let our_rem_time = (i128::from(if currentboard.who_moves == White {
-                            white_rem_time
-                        } else {
-                            black_rem_time
-                        }) - lichesstiming.calc_average().as_millis() as i128)
-                            .max(1) as u64;
-
-                        let deadline =
-                            Duration::from_millis(1.max(our_rem_time / deadline_divisor).min(5000));
+                        let mut clock = GameClock::from_uci(
+                            detected_color,
+                            white_rem_time,
+                            black_rem_time,
+                            gamestate["winc"].as_u64().unwrap_or(0),
+                            gamestate["binc"].as_u64().unwrap_or(0),
+                            None,
+                        );
+                        // The time lichess takes to pass the moves is lost from our clock too
+                        clock.remaining =
+                            clock.remaining.saturating_sub(lichesstiming.calc_average());
+                        let deadline = clock.allocate(currentboard.move_count);
+                        let mut book_move = preloaded
+                            .book
+                            .as_ref()
+                            .and_then(|book| book.best_move(currentboard, MIN_BOOK_GAMES));
 
                         for _ in 0..3 {
                             // it is our turn, let's see what we can come up with
-                            let (dur, mymove) =
-                                helper::calculate_move_for_console(&engine, &mut state, &deadline)
-                                    .await;
+                            let (dur, mymove) = if let Some(book_move) = book_move.take() {
+                                println!("Playing {book_move} from the book");
+                                // The book moves keep our evaluation as it was
+                                let score = our_last_score.unwrap_or(Score::DRAW);
+                                (Duration::ZERO, (Some(book_move), score))
+                            } else {
+                                helper::calculate_move_for_console(&engine, &mut state, deadline)
+                                    .await
+                            };
                             ourmovetime = dur;
+                            if our_last_score.is_some_and(|before| {
+                                is_blunder_swing(before, mymove.1, detected_color)
+                            }) {
+                                opponent_blunders += 1;
+                            }
+                            our_last_score = Some(mymove.1);
+                            if let Some(claim) = state.claimable_draw() {
+                                if detected_color.is_draw_preferable(mymove.1) {
+                                    // If lichess does not accept the claim, we just play on with our move
+                                    println!("Claiming a draw due to {claim:?}");
+                                    lichess_api_call(client.post(drawwithgameid.clone())).await?;
+                                }
+                            }
                             if detected_color.is_this_resignable(mymove.1) {
                                 println!("Resign...");
                                 lichess_api_call(client.post(resignwithgameid.clone())).await?;
@@ -126,7 +279,7 @@ async fn play_a_game(gameid: &str, botid: &str, client: &Client) -> IntResult<Op
                                 if let Ok(res) = lichess_api_call(client.post(format!(
                                     "{}{}",
                                     movewithgameid,
-                                    mymove.0.as_ref().unwrap()
+                                    state.psboard().uci(mymove.0.as_ref().unwrap())
                                 )))
                                 .await
                                 {
@@ -166,6 +319,7 @@ async fn play_a_game(gameid: &str, botid: &str, client: &Client) -> IntResult<Op
                         // This was sent to us to inform about the opponent, we just remember when this
                         // happened so we can decide what to do if there is a parsing error
                         prevopponentmove = Instant::now();
+                        keep_prefetching = true;
                     }
                 } else {
                     // The game has most likely ended in a mate etc.
@@ -173,6 +327,26 @@ async fn play_a_game(gameid: &str, botid: &str, client: &Client) -> IntResult<Op
                         "Cannot play the game {}, as its status is: {}",
                         gameid, gamestate["status"]
                     );
+                    let result = GameResult::from_lichess(
+                        gamestate["winner"].as_str(),
+                        gamestate["status"].as_str().unwrap_or_default(),
+                    );
+                    if let (Some(our_color), Some(name), Ok(result)) =
+                        (ourcolor, opponent.as_ref(), result)
+                    {
+                        opponents.record_game(
+                            name,
+                            &FinishedGame {
+                                our_color,
+                                result,
+                                uci_moves: gamestate["moves"].as_str().unwrap_or_default(),
+                                opponent_blunders,
+                            },
+                        );
+                        if let Err(problem) = opponents.save() {
+                            println!("Could not save what we learnt about {name}: {problem}");
+                        }
+                    }
                     break;
                 }
             }
@@ -200,6 +374,14 @@ async fn play_a_game(gameid: &str, botid: &str, client: &Client) -> IntResult<Op
             }
         }
         lichesstiming.add(start.elapsed().saturating_sub(ourmovetime));
+        if let Some(cpu_percent) = config.prefetch_cpu.filter(|_| keep_prefetching) {
+            // The game is given back before the next event is handled
+            let game = mem::replace(&mut state, GameState::new(PSBoard::default()));
+            prefetching = Some(engine.prefetch(game, cpu_percent));
+        }
+    }
+    if let Some(prefetch) = prefetching {
+        prefetch.stop().await;
     }
     Ok(toignore)
 }
@@ -227,33 +409,141 @@ async fn best_rating_on_lichess(user: &str, client: &Client) -> IntResult<Option
         .max())
 }
 
+/// Waits for SIGINT, or SIGTERM where there is one
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        let mut terminate =
+            signal(SignalKind::terminate()).expect("SIGTERM should be possible to listen to");
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => {}
+            _ = terminate.recv() => {}
+        }
+    }
+    #[cfg(not(unix))]
+    tokio::signal::ctrl_c()
+        .await
+        .expect("Ctrl+C should be possible to listen to");
+}
+
+/// Prints the engine's reports and appends them to the log file as well
+fn log_to(log_file: &Path) -> IntResult<InfoCallback> {
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(log_file)
+        .map_err(|error| format!("Cannot open the log {}: {error}", log_file.display()))?;
+    let file = Mutex::new(file);
+    Ok(Arc::new(move |info| {
+        println!("{info}");
+        if let Ok(mut file) = file.lock() {
+            // Losing a line of the log is not worth stopping the game for
+            let _ = writeln!(file, "{info}");
+        }
+    }))
+}
+
+/// Asks for a setting missing from the configuration file
+fn ask(question: &str) -> String {
+    println!("{question}");
+    let mut line = String::new();
+    io::stdin().read_line(&mut line).unwrap();
+    line.trim().to_owned()
+}
+
 /*
 When running this bot, you first need to create a lichess bot account, then ask for an authorization
 token for it. After these you can just run this executable and let it play. It will respond to
 challenges (one at a time), also it will challenge bots currently available on lichess. By default
 it does a 2.5 move deep full search.
+The settings are read from dbce.toml or the file given as the only argument, the ones missing from
+there are asked for on the console:
+lichess [configuration file]
  */
 #[tokio::main]
 async fn main() -> EmptyResult {
-    let input = io::stdin();
-    println!("What is the auth token?");
-    let mut line = String::new();
-    {
-        input.read_line(&mut line).unwrap();
+    let mut config = match env::args().nth(1) {
+        Some(config_file) => BotConfig::load(config_file.as_ref())?,
+        None if Path::new(DEFAULT_CONFIG_FILE).exists() => {
+            BotConfig::load(DEFAULT_CONFIG_FILE.as_ref())?
+        }
+        None => BotConfig::default(),
+    };
+    let mut evaluator = match &config.nnue {
+        Some(network) => network_evaluator(network)?,
+        None => Arc::new(Imbalance),
+    };
+    if let Some(weights) = &config.weights {
+        evaluator = Arc::new(Weighted {
+            base: evaluator,
+            weights: TermWeights::load(weights)?,
+        });
     }
-    let authtoken = line.trim();
-    println!("What is the bot id?");
-    let mut line = String::new();
-    {
-        input.read_line(&mut line).unwrap();
+    let authtoken = match config.token.take() {
+        Some(token) => token,
+        None => ask("What is the auth token?"),
+    };
+    let botid = match config.bot_id.clone() {
+        Some(botid) => botid,
+        None => ask("What is the bot id?"),
+    };
+    let botid = botid.as_str();
+    let resume = config.resume.unwrap_or_else(|| {
+        ask("Try resuming previous game?")
+            .to_lowercase()
+            .starts_with('y')
+    });
+    if config.verbosity.is_none() {
+        config.verbosity = Some(
+            ask("Search verbosity? (silent, scores, full or moves, leave empty for full)")
+                .parse()?,
+        );
     }
-    let botid = line.trim();
-    println!("Try resuming previous game?");
-    let mut line = String::new();
+    if config.strength.is_none() {
+        config.strength =
+            Some(ask("Strength? (beginner, club, master or max, leave empty for max)").parse()?);
+    }
+    let mut opponents = OpponentMemory::load(&config.data_dir)?;
+    let shutdown = Arc::new(AtomicBool::new(false));
     {
-        input.read_line(&mut line).unwrap();
+        let shutdown = shutdown.clone();
+        tokio::spawn(async move {
+            shutdown_signal().await;
+            println!(
+                "Shutting down once the current game is over, signal again to stop right away"
+            );
+            shutdown.store(true, Relaxed);
+            shutdown_signal().await;
+            std::process::exit(1);
+        });
     }
-    let resume = line.trim();
+    let opening_tree_file = config.data_dir.join(OPENING_TREE_FILE);
+    let opening_tree = if opening_tree_file.exists() {
+        let tree = BoardContinuation::from_bytes(&std::fs::read(opening_tree_file)?).await?;
+        println!(
+            "Loaded an opening tree of {} boards",
+            tree.total_continuation_boards()
+        );
+        Some(tree)
+    } else {
+        None
+    };
+    let opening_book_file = config.data_dir.join(OPENING_BOOK_FILE);
+    let opening_book = if opening_book_file.exists() {
+        let book = OpeningBook::from_bytes(&std::fs::read(opening_book_file)?)?;
+        println!("Loaded an opening book of {} positions", book.len());
+        Some(book)
+    } else {
+        None
+    };
+    let preloaded = Preloaded {
+        tree: opening_tree,
+        book: opening_book,
+        evaluator,
+    };
+    println!("Warming up the engine..");
+    Engine::new().0.warm_up(Some(Duration::from_secs(1))).await;
     let mut headers = HeaderMap::new();
     headers.insert(
         "Authorization",
@@ -265,11 +555,11 @@ async fn main() -> EmptyResult {
     let bots_best = best_rating_on_lichess(botid, &client)
         .await?
         .unwrap_or(1500);
-    let bots_max_target = bots_best + 400;
+    let bots_max_target = bots_best + config.challenge_rating_margin;
     println!("This bot will not challenge better rated bots than {bots_max_target}");
 
     let mut gameid = None;
-    if resume.to_lowercase().starts_with('y') {
+    if resume {
         println!("Searching for previous, unfinished games..");
         let currentlyplaying =
             lichess_api_call(client.get("https://lichess.org/api/account/playing")).await?;
@@ -284,9 +574,21 @@ async fn main() -> EmptyResult {
         }
     }
     loop {
+        if shutdown.load(Relaxed) && gameid.is_none() {
+            break;
+        }
         if let Some(gameid_str) = &gameid {
             println!("Starting to play game {gameid_str}");
-            let result = play_a_game(gameid_str.as_str(), botid, &client).await?;
+            let result = play_a_game(
+                gameid_str.as_str(),
+                botid,
+                &client,
+                &config,
+                &mut opponents,
+                &preloaded,
+                &shutdown,
+            )
+            .await?;
             // If we get a non-responsive opponent we ignore it from now on
             if let Some(problematicopponent) = result {
                 declining_bots.insert(problematicopponent);
@@ -366,6 +668,9 @@ async fn main() -> EmptyResult {
                         .bytes_stream();
                 let startwait = Instant::now();
                 'outer: while let Some(bytes) = events.next().await {
+                    if shutdown.load(Relaxed) {
+                        break 'outer;
+                    }
                     for eventupdate in String::from_utf8(Vec::from(bytes?.as_ref()))?.lines() {
                         if let Ok(event) = serde_json::from_str(eventupdate) {
                             let event: Value = event;
@@ -375,8 +680,15 @@ async fn main() -> EmptyResult {
                             } else if event["type"] == "challenge" {
                                 let event = &event["challenge"];
                                 let mut reason = None;
-                                if event["variant"]["key"] != "standard" {
-                                    reason = Some("standard");
+                                let variant = &event["variant"]["key"];
+                                if variant != "standard"
+                                    && !(config.chess960 && variant == "chess960")
+                                {
+                                    reason = Some(if config.chess960 {
+                                        "variant"
+                                    } else {
+                                        "standard"
+                                    });
                                 } else if event["rated"] != true {
                                     reason = Some("rated");
                                 }
@@ -420,4 +732,7 @@ async fn main() -> EmptyResult {
             }
         }
     }
+    opponents.save()?;
+    println!("Bye!");
+    Ok(())
 }
@@ -20,26 +20,36 @@
  *
  *  (C) Copyright 2022-3, Gabor Kecskemeti
  */
-use dbce::baserules::rawboard::is_mate;
+use dbce::baserules::board::PSBoard;
+use dbce::baserules::game::Game;
+use dbce::baserules::piece_color::PieceColor;
+use dbce::baserules::score::Score;
 use dbce::engine::gamestate::GameState;
+use dbce::engine::strength::Strength;
 use dbce::engine::Engine;
+use dbce::human_facing::coach::Coach;
+use dbce::human_facing::game::GameRecord;
 use dbce::human_facing::helper::calculate_move_for_console;
+use dbce::human_facing::moves::find_human_move;
+use dbce::human_facing::pgn::PgnExport;
 use lazy_static::lazy_static;
 use rand::random;
+use std::sync::Arc;
 use std::time::Duration;
 
 lazy_static! {
     static ref ENGINE_THINK_TIME: Duration = Duration::from_secs(5);
 }
 
-async fn make_machine_move(engine: &Engine, gamestate: &mut GameState) {
+async fn make_machine_move(engine: &Engine, gamestate: &mut GameState, game: &mut Game) {
     println!("It's my move now, let me think:");
-    let to_move = calculate_move_for_console(engine, gamestate, &ENGINE_THINK_TIME)
+    let to_move = calculate_move_for_console(engine, gamestate, &*ENGINE_THINK_TIME)
         .await
         .1
          .0
         .unwrap();
     gamestate.make_a_generated_move(&to_move).await;
+    game.make_move(&to_move).await.unwrap();
 }
 
 #[tokio::main]
@@ -50,38 +60,127 @@ async fn main() {
     {
         input.read_line(&mut line).unwrap();
     }
-    let (engine, mut gamestate) = if line.trim().to_lowercase().starts_with('y') {
+    let (mut engine, mut gamestate) = if line.trim().to_lowercase().starts_with('y') {
         println!("What is the FEN of the starting position?");
-        let mut line = String::new();
-        input.read_line(&mut line).unwrap();
-        Engine::from_fen(&line).await
+        let board = loop {
+            let mut line = String::new();
+            input.read_line(&mut line).unwrap();
+            match PSBoard::try_from_fen(&line) {
+                Ok(board) => match board.validate() {
+                    Ok(()) => break board,
+                    Err(error) => println!("Sorry, {error}. Please try again:"),
+                },
+                Err(error) => println!("Sorry, {error}. Please try again:"),
+            }
+        };
+        Engine::from_board(board)
     } else {
         Engine::new()
     };
+    engine.set_info_callback(Arc::new(|info| println!("{info}")));
+    println!("Search verbosity? (silent, scores, full or moves, leave empty for full)");
+    let mut line = String::new();
+    input.read_line(&mut line).unwrap();
+    engine.set_verbosity(line.parse().unwrap_or_default());
+    println!("Strength? (beginner, club, master or max, leave empty for max)");
+    let mut line = String::new();
+    input.read_line(&mut line).unwrap();
+    engine.set_strength(line.parse::<Strength>().unwrap_or_default());
+    println!("Search threads? (leave empty for 1)");
+    let mut line = String::new();
+    input.read_line(&mut line).unwrap();
+    engine.set_threads(line.trim().parse().unwrap_or(1));
+    println!("Coaching threshold in pawns? (leave empty to play without coaching)");
+    let mut line = String::new();
+    input.read_line(&mut line).unwrap();
+    let coach = line.trim().parse().ok().map(|pawns| Coach {
+        threshold: Score::from_pawns(pawns),
+        think_time: *ENGINE_THINK_TIME / 5,
+    });
+    let mut game = Game::new(gamestate.psboard().clone()).await;
     let machine_moves_first: bool = random();
     if machine_moves_first {
-        make_machine_move(&engine, &mut gamestate).await;
+        make_machine_move(&engine, &mut gamestate, &mut game).await;
     }
-    while !is_mate(gamestate.psboard().score) {
+    while !game.status().is_over() {
         println!("Current board: {}", gamestate.psboard());
+        let before_human_move = gamestate.continuation().board.clone();
+        // The engine keeps thinking about the expected reply while the human thinks
+        let ponder = engine.ponder(gamestate);
+        println!("What's your move?");
+        let mut line = String::new();
+        input.read_line(&mut line).unwrap();
+        let mut expected_hash = None;
+        gamestate = match ponder {
+            Ok(pondering) => {
+                let expected = *pondering.expected();
+                expected_hash = Some(
+                    before_human_move
+                        .make_move_noncached(&expected)
+                        .await
+                        .zobrist(),
+                );
+                pondering.stop().await
+            }
+            Err(state) => state,
+        };
         loop {
-            println!("What's your move?");
-            let mut line = String::new();
-            {
-                input.read_line(&mut line).unwrap();
-                let maybe_board_with_move = gamestate.make_a_human_move(line.trim()).await;
-                if let Err(an_error) = maybe_board_with_move {
-                    println!("Problem with your move: {an_error:?}");
-                } else {
+            let human_move = find_human_move(game.board(), line.trim());
+            let played = match human_move {
+                Ok(human_move) => game.make_move(&human_move).await.map(|()| human_move),
+                Err(an_error) => Err(an_error.into()),
+            };
+            match played {
+                Ok(human_move) => {
+                    gamestate.make_a_generated_move(&human_move).await;
                     break;
                 }
+                Err(an_error) => {
+                    println!("Problem with your move: {an_error:?}");
+                    println!("What's your move?");
+                    line.clear();
+                    input.read_line(&mut line).unwrap();
+                }
+            }
+        }
+        if expected_hash == Some(gamestate.psboard().zobrist()) {
+            println!("I expected this move, so I continue my earlier thoughts");
+        }
+        if let Some(coach) = &coach {
+            let advice = coach
+                .review(&engine, &before_human_move, gamestate.psboard())
+                .await;
+            if let Some(advice) = advice {
+                println!("{advice}");
             }
         }
         println!("Current board: {}", gamestate.psboard());
-        if !is_mate(gamestate.psboard().score) {
-            make_machine_move(&engine, &mut gamestate).await;
-        } else {
-            break;
+        if !game.status().is_over() {
+            make_machine_move(&engine, &mut gamestate, &mut game).await;
         }
     }
+    println!("Final board: {}", game.board());
+    println!("The game ended, {}", game.status());
+    let machine_color = if machine_moves_first {
+        game.start().who_moves
+    } else {
+        game.start().who_moves.invert()
+    };
+    let player = |color: PieceColor| {
+        if color == machine_color {
+            "DBCE"
+        } else {
+            "You"
+        }
+    };
+    let export = PgnExport {
+        tags: vec![
+            ("Event".into(), "Local game".into()),
+            ("White".into(), player(PieceColor::White).into()),
+            ("Black".into(), player(PieceColor::Black).into()),
+        ],
+        evals: Vec::new(),
+    };
+    let record = GameRecord::from(&game);
+    println!("The game in pgn:\n{}", record.to_pgn_with(&export));
 }
@@ -0,0 +1,80 @@
+/*
+ *  ========================================================================
+ *  DBCE chess bot, perft runner and move generator comparison
+ *  ========================================================================
+ *
+ *  This file is part of DBCE.
+ *
+ *  DBCE is free software: you can redistribute it and/or
+ *  modify it under the terms of the GNU General Public License as published
+ *  by the Free Software Foundation, either version 3 of the License, or (at
+ *  your option) any later version.
+ *
+ *  DBCE is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ *  General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License along
+ *  with DBCE.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ *  (C) Copyright 2022-3, Gabor Kecskemeti
+ */
+use dbce::baserules::board::PSBoard;
+use dbce::baserules::perft::{find_divergence, ExternalPerft};
+use dbce::util::EmptyResult;
+use std::env;
+use std::io;
+
+static STARTING_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+/// Understands the same `position`/`go perft` commands as other engines do, so a dbce build can
+/// also act as the reference of another build's comparison.
+async fn serve_perft_requests() -> EmptyResult {
+    let input = io::stdin();
+    let mut fen = String::from(STARTING_FEN);
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(());
+        }
+        let line = line.trim();
+        if let Some(new_fen) = line.strip_prefix("position fen ") {
+            fen = new_fen.to_string();
+        } else if line == "position startpos" {
+            fen = String::from(STARTING_FEN);
+        } else if let Some(depth) = line.strip_prefix("go perft ") {
+            let board = PSBoard::try_from_fen(&fen)?;
+            board.perft_divide(depth.trim().parse()?);
+        } else if line == "quit" {
+            return Ok(());
+        }
+    }
+}
+
+/*
+Without arguments, this reads perft requests from the standard input. To look for the first position
+where our move generation differs from a reference engine, run it as:
+perft compare <depth> "<fen>" <reference engine command> [reference engine arguments...]
+ */
+#[tokio::main]
+async fn main() -> EmptyResult {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.first().map(String::as_str) != Some("compare") {
+        return serve_perft_requests().await;
+    }
+    if args.len() < 4 {
+        return Err("Usage: perft compare <depth> <fen> <reference command> [args...]".into());
+    }
+    let depth = args[1].parse()?;
+    let board = PSBoard::try_from_fen(&args[2])?;
+    let mut reference = ExternalPerft {
+        command: args[3].clone(),
+        args: args[4..].to_vec(),
+    };
+    match find_divergence(&board, depth, &mut reference).await? {
+        Some(divergence) => println!("{divergence}"),
+        None => println!("Node counts match the reference at depth {depth}"),
+    }
+    Ok(())
+}
@@ -0,0 +1,68 @@
+/*
+ *  ========================================================================
+ *  DBCE chess bot, precomputing continuation trees
+ *  ========================================================================
+ *
+ *  This file is part of DBCE.
+ *
+ *  DBCE is free software: you can redistribute it and/or
+ *  modify it under the terms of the GNU General Public License as published
+ *  by the Free Software Foundation, either version 3 of the License, or (at
+ *  your option) any later version.
+ *
+ *  DBCE is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ *  General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License along
+ *  with DBCE.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ *  (C) Copyright 2022-3, Gabor Kecskemeti
+ */
+use dbce::baserules::board::PSBoard;
+use dbce::engine::continuation::BoardContinuation;
+use dbce::engine::gamestate::GameState;
+use dbce::engine::Engine;
+use dbce::util::EmptyResult;
+use std::env;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+/*
+Searches a position and saves the explored tree, e.g. for the lichess bot to load at startup.
+An existing tree file is searched further instead of starting from the position:
+tree <tree file> <seconds> [fen]
+ */
+#[tokio::main]
+async fn main() -> EmptyResult {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let (tree_file, seconds) = match args.as_slice() {
+        [tree_file, seconds, ..] if args.len() <= 3 => (tree_file, seconds.parse()?),
+        _ => return Err("Usage: tree <tree file> <seconds> [fen]".into()),
+    };
+    let tree = if Path::new(tree_file).exists() {
+        BoardContinuation::from_bytes(&std::fs::read(tree_file)?).await?
+    } else {
+        match args.get(2) {
+            Some(fen) => BoardContinuation::new(PSBoard::try_from_fen(fen)?),
+            None => BoardContinuation::default(),
+        }
+    };
+    let (mut engine, _) = Engine::new();
+    engine.set_info_callback(Arc::new(|info| println!("{info}")));
+    let mut state = GameState::from_tree(tree);
+    let (best_move, score, boards, depth) = engine
+        .best_move_for(&mut state, &Duration::from_secs(seconds))
+        .await;
+    if let Some(best_move) = best_move {
+        println!("{best_move} ({score}) after {boards} new boards, depth {depth}");
+    }
+    std::fs::write(tree_file, state.continuation().to_bytes())?;
+    println!(
+        "{} boards written to {tree_file}",
+        state.continuation().total_continuation_boards()
+    );
+    Ok(())
+}
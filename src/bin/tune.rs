@@ -0,0 +1,58 @@
+/*
+ *  ========================================================================
+ *  DBCE chess bot, tuning the evaluation with labelled positions
+ *  ========================================================================
+ *
+ *  This file is part of DBCE.
+ *
+ *  DBCE is free software: you can redistribute it and/or
+ *  modify it under the terms of the GNU General Public License as published
+ *  by the Free Software Foundation, either version 3 of the License, or (at
+ *  your option) any later version.
+ *
+ *  DBCE is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ *  General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License along
+ *  with DBCE.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ *  (C) Copyright 2022-3, Gabor Kecskemeti
+ */
+use dbce::baserules::evaluation::Imbalance;
+use dbce::human_facing::tuning::{read_labelled_positions, tune, DEFAULT_TUNING_ROUNDS};
+use dbce::util::EmptyResult;
+use std::env;
+use std::time::Instant;
+
+/*
+Tunes the weights of the terms of the default evaluation to the results of labelled positions:
+tune <positions file> <weights file> [rounds]
+ */
+#[tokio::main]
+async fn main() -> EmptyResult {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let (positions_file, weights_file) = match args.as_slice() {
+        [positions_file, weights_file, ..] if args.len() <= 3 => (positions_file, weights_file),
+        _ => return Err("Usage: tune <positions file> <weights file> [rounds]".into()),
+    };
+    let rounds = match args.get(2) {
+        Some(rounds) => rounds.parse()?,
+        None => DEFAULT_TUNING_ROUNDS,
+    };
+    let started = Instant::now();
+    let positions = read_labelled_positions(&std::fs::read_to_string(positions_file)?)?;
+    let tuning = tune(&positions, &Imbalance, rounds).await?;
+    println!(
+        "{} positions tuned in {:?} with the scaling {:.3}, mean squared error {:.6} -> {:.6}",
+        positions.len(),
+        started.elapsed(),
+        tuning.scaling,
+        tuning.initial_error,
+        tuning.error
+    );
+    std::fs::write(weights_file, tuning.weights.to_string())?;
+    print!("{}", tuning.weights);
+    Ok(())
+}
@@ -20,35 +20,230 @@
  *
  *  (C) Copyright 2022-3, Gabor Kecskemeti
  */
+pub mod alphabeta;
 pub mod continuation;
 pub mod gamestate;
+pub mod killers;
+pub mod line_analysis;
+pub mod mcts;
+pub mod ordering;
+pub mod ponder;
+pub mod prefetch;
+pub mod probe;
+pub mod pruning;
+pub mod quiescence;
+pub mod search;
+pub mod staged;
+pub mod strength;
+pub mod tablebase;
+pub mod timing;
+pub mod transposition;
 
 use crate::baserules::board::PSBoard;
 use crate::baserules::board_rep::PossibleMove;
+use crate::baserules::evaluation::{Evaluator, Imbalance};
+use crate::baserules::move_list::MoveList;
 use crate::baserules::piece_color::PieceColor;
-use std::cmp::Ordering;
+use crate::baserules::score::Score;
+use std::cell::Cell;
 use std::ptr;
+use std::str::FromStr;
 use std::sync::atomic::Ordering::Relaxed;
-use std::sync::atomic::{AtomicBool, AtomicU8};
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU8};
+use std::sync::{Arc, Mutex};
 
-use crate::baserules::rawboard::is_mate;
-use crate::engine::continuation::BoardContinuation;
+use crate::engine::alphabeta::{MoveVerdict, MultiPvEngine, PvLine, VerifyEngine};
+use crate::engine::continuation::{BoardContinuation, SIMILAR_SCORE_DIFFERENCE};
 use crate::engine::gamestate::GameState;
+use crate::engine::killers::KillerMoves;
+use crate::engine::pruning::{BestFirst, PrunePolicy};
+use crate::engine::quiescence::quiescence_score;
+use crate::engine::search::{AlphaBetaSearch, Search, SearchLimits};
+use crate::engine::strength::StrengthSettings;
+use crate::engine::tablebase::{Tablebase, MAX_TABLEBASE_PIECES};
+use crate::engine::timing::{BestMoveStability, GameClock, SearchTime, TimeAllocation};
+use crate::engine::transposition::TranspositionTable;
+use crate::util::IntResult;
+use crate::LIMITS;
 use async_scoped::TokioScope;
 use async_trait::async_trait;
+use futures_util::StreamExt;
 use global_counter::primitive::fast::FlushingCounterU32;
+use itertools::Itertools;
+use rand::rngs::StdRng;
+use rand::{random, SeedableRng};
+use tokio::select;
 use tokio::spawn;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
 
 use std::time::Duration;
 use tokio::task::yield_now;
 use tokio::time::{sleep, Instant};
 
+/// How often the watchdog of a search checks the stop token and the deadline
+const STOP_POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// The nodes the search loops of a thread visit between their own checks of the stop token
+const STOP_CHECK_NODES: u32 = 1024;
+
+thread_local! {
+    /// The nodes visited by the search loops of the thread since they last checked the stop token
+    static UNCHECKED_NODES: Cell<u32> = const { Cell::new(0) };
+}
+
+/// Turns the exploration off once the stop token is set, checked every `STOP_CHECK_NODES` nodes
+/// the search loops visit. The watchdog checks the token too, but it may not get to run while
+/// the searches keep the threads busy.
+pub(crate) fn poll_stop(stop: &AtomicBool, exploration_allowed: &AtomicBool) {
+    UNCHECKED_NODES.with(|nodes| {
+        let unchecked = nodes.get() + 1;
+        if unchecked < STOP_CHECK_NODES {
+            nodes.set(unchecked);
+        } else {
+            nodes.set(0);
+            if stop.load(Relaxed) {
+                exploration_allowed.store(false, Relaxed);
+            }
+        }
+    });
+}
+
+/// Positions with castling, en passant and promotions, so every kind of move is generated during
+/// the warm-up
+const WARM_UP_POSITIONS: [&str; 2] = [
+    "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+    "n1n5/PPPk4/8/8/8/8/4Kppp/5N1N b - - 0 1",
+];
+
+/// Receives the progress reports of the engine, they are dropped until one is set with
+/// `Engine::set_info_callback`
+pub type InfoCallback = Arc<dyn Fn(&str) + Send + Sync>;
+
+/// The progress of a search after a completed iteration
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchInfo {
+    pub depth: u8,
+    /// The deepest ply reached by the search, without the captures followed after it
+    pub seldepth: u8,
+    /// The number of boards created by the search
+    pub nodes: u32,
+    pub nps: u64,
+    pub time: Duration,
+    /// From white's perspective like all other scores
+    pub score: Score,
+    pub pv: Vec<PossibleMove>,
+    /// How often the best move changed in the iterations so far
+    pub stability: BestMoveStability,
+}
+
+/// How a root move fared in a search
+#[derive(Debug, Clone, PartialEq)]
+pub struct RootMoveStats {
+    pub the_move: PossibleMove,
+    /// The number of boards created for the move, including the board after it
+    pub nodes: u32,
+    /// From white's perspective, the static score of the board after the move if it has not got
+    /// a score from the search
+    pub score: Score,
+    /// The depth searched below the move, none if the search pruned it before it got a score
+    pub searched_depth: Option<u8>,
+}
+
+/// How much the engine reports about its searches, each level includes the reports of the
+/// levels before it
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum Verbosity {
+    Silent,
+    /// The score of each completed iteration and the chosen move
+    Scores,
+    /// The principal variations and the statistics of the iterations
+    #[default]
+    Full,
+    /// The evaluation of each explored root move
+    RootMoves,
+}
+
+impl FromStr for Verbosity {
+    type Err = String;
+
+    fn from_str(level: &str) -> Result<Self, Self::Err> {
+        match level.trim().to_lowercase().as_str() {
+            "silent" => Ok(Verbosity::Silent),
+            "scores" => Ok(Verbosity::Scores),
+            "" | "full" => Ok(Verbosity::Full),
+            "moves" => Ok(Verbosity::RootMoves),
+            _ => Err(format!("Unknown verbosity: {level}")),
+        }
+    }
+}
+
+/// The search algorithms the engine can choose its moves with
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum SearchAlgorithm {
+    /// Explores the continuation tree and averages the scores of the similar continuations
+    #[default]
+    Exploration,
+    /// Principal variation search with iterative deepening
+    Pvs,
+    /// Monte Carlo tree search
+    Mcts,
+}
+
+impl FromStr for SearchAlgorithm {
+    type Err = String;
+
+    fn from_str(algorithm: &str) -> Result<Self, Self::Err> {
+        match algorithm.trim().to_lowercase().as_str() {
+            "" | "exploration" => Ok(SearchAlgorithm::Exploration),
+            "pvs" => Ok(SearchAlgorithm::Pvs),
+            "mcts" => Ok(SearchAlgorithm::Mcts),
+            _ => Err(format!("Unknown search algorithm: {algorithm}")),
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct Engine {
     exploration_allowed: Arc<AtomicBool>,
     enable_parallel: Arc<AtomicBool>,
     thread_counter: Arc<AtomicU8>,
+    killers: Arc<KillerMoves>,
+    tablebase: Option<Arc<dyn Tablebase>>,
+    info: InfoCallback,
+    verbosity: Verbosity,
+    /// Set from the outside to stop the current search
+    stop: Arc<AtomicBool>,
+    /// Identifies the latest search, so the watchdogs of the earlier ones do not interfere with it
+    search_id: Arc<AtomicU32>,
+    tt: Arc<TranspositionTable>,
+    /// The adjusted scores calculated in earlier generations are no longer trusted, shared by
+    /// the clones of the engine
+    score_generation: Arc<AtomicU32>,
+    /// The time limits of the current search
+    time: Arc<SearchTime>,
+    /// The number of threads of the principal variation search
+    threads: usize,
+    /// Receives the progress of the searches started by `search_with_callback`
+    search_info: Option<UnboundedSender<SearchInfo>>,
+    /// Limits how well the engine plays
+    strength: StrengthSettings,
+    /// The number of boards the game's continuation tree is pruned to before each search
+    node_budget: u32,
+    /// The searches without a deadline, e.g. pondering, stop deepening once they created this many
+    /// boards
+    open_search_nodes: u32,
+    /// Chooses the boards dropped when the tree is over the node budget
+    prune_policy: Arc<dyn PrunePolicy>,
+    /// Makes the random choices of the moves played, shared by the clones of the engine
+    rng: Arc<Mutex<StdRng>>,
+    /// Mixed into the random choices made while searching below the root
+    seed: u64,
+    /// Chooses the moves of `best_move_within` and the searches built on it
+    algorithm: SearchAlgorithm,
+    /// White's score of the draws, shifted against the side the engine plays by its contempt
+    draw_score: Score,
+    /// Scores the boards the searches create
+    evaluator: Arc<dyn Evaluator>,
 }
 
 #[async_trait]
@@ -71,31 +266,37 @@ impl Explore for SeqEngine {
         while let Some(curr_move) = a.moves.pop() {
             let board_with_move = a
                 .start_board
-                .lookup_continuation_or_create(&curr_move, a.counter)
+                .lookup_continuation_or_create(&curr_move, a.depth.counter, self.0.evaluator())
                 .await;
+            if a.path.contains(&board_with_move.zobrist()) {
+                board_with_move.mark_repetition();
+            }
             yield_now().await;
+            poll_stop(&self.0.stop, &self.0.exploration_allowed);
             let explore_allowed = self.0.exploration_allowed.load(Relaxed);
-            let curr_score = if !is_mate(board_with_move.score)
+            let curr_score = if !board_with_move.score.is_mate()
                 && explore_allowed
-                && a.curr_depth < a.max_allowed_depth
+                && a.depth.curr_depth < a.depth.max_allowed_depth
             {
                 let (_, best_score) = self
                     .0
                     .best_move_for_internal(
                         board_with_move,
-                        a.curr_depth + 1,
-                        a.counter,
-                        a.maximum,
-                        a.max_allowed_depth,
+                        a.path,
+                        a.depth.curr_depth + 1,
+                        a.depth.counter,
+                        a.depth.maximum,
+                        a.depth.max_allowed_depth,
                     )
                     .await;
                 best_score
             } else {
-                let mut val_before = a.maximum.load(Relaxed);
+                let mut val_before = a.depth.maximum.load(Relaxed);
                 loop {
-                    let cur_max = a.maximum.fetch_max(a.curr_depth, Relaxed);
-                    if cur_max == a.curr_depth {
+                    let cur_max = a.depth.maximum.fetch_max(a.depth.curr_depth, Relaxed);
+                    if cur_max == a.depth.curr_depth {
                         let res = a
+                            .depth
                             .maximum
                             .compare_exchange(val_before, cur_max, Relaxed, Relaxed);
 
@@ -108,10 +309,14 @@ impl Explore for SeqEngine {
                         break;
                     }
                 }
-                board_with_move.score
+                if board_with_move.potential_draw {
+                    self.0.draw_score
+                } else {
+                    quiescence_score(board_with_move, self.0.evaluator()).await
+                }
             };
 
-            Engine::update_max_search(who, &mut a.max_search, curr_score);
+            Engine::update_max_search(who, &mut a.max_search, curr_score.backed_up());
         }
         ExplorationOutput {
             max_search: a.max_search,
@@ -130,24 +335,25 @@ impl Explore for ParEngine {
                     a.start_board.clone(),
                     self.0.clone(),
                     curr_move,
-                    a.counter,
-                    a.maximum,
-                    a.curr_depth,
-                    a.max_allowed_depth,
+                    a.path.clone(),
+                    a.depth,
                 ));
             }
         });
         let who = a.start_board.who_moves;
         for join in joins {
-            let (curr_score, curr_move, board_clone): (f32, PossibleMove, BoardContinuation) =
+            let (curr_score, curr_move, board_clone): (Score, PossibleMove, BoardContinuation) =
                 join.unwrap();
-            Engine::update_max_search(who, &mut a.max_search, curr_score);
-
-            let width = a.curr_depth as usize;
-            println!(
-                "{:width$} Evaluated move: {curr_move}, score: {}, adjusted: {}",
-                "", a.start_board.score, a.start_board.adjusted_score
-            );
+            Engine::update_max_search(who, &mut a.max_search, curr_score.backed_up());
+
+            let width = a.depth.curr_depth as usize;
+            self.0.report(Verbosity::RootMoves, || {
+                format!(
+                    "{:width$} Evaluated move: {curr_move}, score: {curr_score}, adjusted: {}",
+                    "",
+                    a.start_board.score()
+                )
+            });
 
             a.start_board.merge(board_clone);
         }
@@ -162,18 +368,26 @@ impl ParEngine {
         mut board_clone: BoardContinuation,
         engine_clone: Engine,
         curr_move: PossibleMove,
-        counter: &FlushingCounterU32,
-        maximum: &AtomicU8,
-        curr_depth: u8,
-        max_allowed_depth: u8,
-    ) -> (f32, PossibleMove, BoardContinuation) {
+        mut path: Vec<u64>,
+        depth: ExplorationDepth<'_>,
+    ) -> (Score, PossibleMove, BoardContinuation) {
+        let ExplorationDepth {
+            curr_depth,
+            max_allowed_depth,
+            counter,
+            maximum,
+        } = depth;
         engine_clone.thread_counter.fetch_add(1, Relaxed);
         let board_with_move = board_clone
-            .lookup_continuation_or_create(&curr_move, counter)
+            .lookup_continuation_or_create(&curr_move, counter, engine_clone.evaluator())
             .await;
+        if path.contains(&board_with_move.zobrist()) {
+            board_with_move.mark_repetition();
+        }
         let (_, curr_score) = engine_clone
             .best_move_for_internal(
                 board_with_move,
+                &mut path,
                 curr_depth + 1,
                 counter,
                 maximum,
@@ -186,39 +400,66 @@ impl ParEngine {
     }
 }
 
-pub struct ExplorationInput<'a> {
-    moves: Vec<PossibleMove>,
-    start_board: &'a mut BoardContinuation,
+/// The depth of the explored board and the limit of the exploration, with the counters the
+/// explorations below the root share
+#[derive(Clone, Copy)]
+pub struct ExplorationDepth<'a> {
     curr_depth: u8,
-    max_search: [f32; 4],
+    max_allowed_depth: u8,
+    /// Counts the boards created
     counter: &'a FlushingCounterU32,
+    /// The deepest depth reached
     maximum: &'a AtomicU8,
-    max_allowed_depth: u8,
+}
+
+pub struct ExplorationInput<'a> {
+    moves: MoveList,
+    start_board: &'a mut BoardContinuation,
+    /// The hashes of the positions before the children of the start board, the start board's too
+    path: &'a mut Vec<u64>,
+    depth: ExplorationDepth<'a>,
+    max_search: [Score; 4],
 }
 
 pub struct ExplorationOutput {
-    max_search: [f32; 4],
+    max_search: [Score; 4],
 }
 
-struct ExtEngine<'a>(Engine, &'a mut BoardContinuation);
+/// The averaging exploration of the tree, the path is the game's, leading to the root
+struct ExtEngine<'a>(Engine, &'a mut BoardContinuation, Vec<u64>);
 
 #[async_trait]
-impl<'a> DepthsBoardCountMaintenance<(Option<PossibleMove>, f32)> for ExtEngine<'a> {
+impl<'a> DepthsBoardCountMaintenance<(Option<PossibleMove>, Score)> for ExtEngine<'a> {
     async fn best_move_for(
         mut self,
         board_count: &FlushingCounterU32,
         depth: &AtomicU8,
-    ) -> (Option<PossibleMove>, f32) {
-        let mut best_move_and_score = (None, f32::NAN);
+    ) -> (Option<PossibleMove>, Score) {
+        let mut best_move_and_score = (None, Score::DRAW);
         let mut depth_allowed = 3;
-        while self.0.exploration_allowed.load(Relaxed) {
-            println!("before {:?}", Instant::now());
+        let started = Instant::now();
+        while self.0.exploration_allowed.load(Relaxed)
+            && depth_allowed < u8::MAX - 2
+            && (best_move_and_score.0.is_none() || self.0.strength.allows_depth(depth_allowed))
+            // Searches without a deadline, e.g. pondering, stop deepening at their own limit
+            && (self.0.time.deadline().is_some() || board_count.get() < self.0.open_search_nodes)
+        {
             best_move_and_score = self
                 .0
-                .best_move_for_internal(self.1, 0, board_count, depth, depth_allowed)
+                .best_move_for_internal(self.1, &mut self.2, 0, board_count, depth, depth_allowed)
                 .await;
+            if self.0.exploration_allowed.load(Relaxed) {
+                self.0.iteration_completed(best_move_and_score.0.as_ref());
+                self.0.report_iteration(
+                    self.1,
+                    depth_allowed,
+                    depth.load(Relaxed),
+                    best_move_and_score.1,
+                    board_count.get(),
+                    started,
+                );
+            }
             depth_allowed += 2;
-            println!("after {:?}", Instant::now());
         }
 
         best_move_and_score
@@ -230,15 +471,66 @@ impl Engine {
         Self::with_board_gen(PSBoard::default())
     }
 
-    async fn time_up(&self, duration: Duration) {
-        sleep(duration).await;
+    /// Turns the exploration off once the search is stopped or runs out of time. Exits without
+    /// doing anything when the search has finished or a newer search has been started.
+    async fn watchdog(&self, search: u32, started: Instant) {
+        loop {
+            sleep(STOP_POLL_INTERVAL).await;
+            if self.search_id.load(Relaxed) != search || !self.exploration_allowed.load(Relaxed) {
+                return;
+            }
+            if self.stop.load(Relaxed)
+                || self
+                    .time
+                    .deadline()
+                    .is_some_and(|deadline| started.elapsed() >= deadline)
+            {
+                self.exploration_allowed.store(false, Relaxed);
+                return;
+            }
+        }
+    }
+
+    /// Allows the exploration until the search is stopped or the optional time allocation is up
+    /// A stop requested before the search starts is kept, so the search stops right away.
+    fn start_search(&self, allocation: Option<TimeAllocation>) {
+        self.exploration_allowed.store(true, Relaxed);
+        self.time
+            .start(allocation.map(|allocation| self.strength.limit_time(allocation)));
+        let search = self.search_id.fetch_add(1, Relaxed) + 1;
+        let started = Instant::now();
+        let engine = self.clone();
+        spawn(async move { engine.watchdog(search, started).await });
+    }
+
+    /// Turns the exploration off and clears the stop request the search has just honoured
+    fn end_search(&self) {
         self.exploration_allowed.store(false, Relaxed);
+        self.stop.store(false, Relaxed);
+    }
+
+    /// The token stopping the current search when set, e.g. on the request of a user interface.
+    /// The search still returns the best move it has found so far. A stop requested between two
+    /// searches stops the next one, the token is cleared when a search ends.
+    pub fn stop_token(&self) -> Arc<AtomicBool> {
+        self.stop.clone()
+    }
+
+    /// Stops the current search, see `stop_token`
+    pub fn stop(&self) {
+        self.stop.store(true, Relaxed);
     }
 
     pub async fn from_fen(fen: &str) -> (Self, GameState) {
         Self::with_board_gen(PSBoard::from_fen(fen).await.expect("Incorrect fen input"))
     }
 
+    /// Starts from the board, e.g. a position given by the user and read with
+    /// `PSBoard::try_from_fen`
+    pub fn from_board(board: PSBoard) -> (Self, GameState) {
+        Self::with_board_gen(board)
+    }
+
     fn par_explore(&self) -> ParEngine {
         ParEngine(self.clone())
     }
@@ -248,11 +540,32 @@ impl Engine {
     }
 
     fn with_board_gen(initial_board: PSBoard) -> (Self, GameState) {
+        let seed = random();
         (
             Self {
                 enable_parallel: Arc::new(AtomicBool::new(true)),
                 exploration_allowed: Arc::new(AtomicBool::new(true)),
                 thread_counter: Arc::new(AtomicU8::new(0)),
+                killers: Arc::new(KillerMoves::default()),
+                tablebase: None,
+                info: Arc::new(|_| {}),
+                verbosity: Verbosity::default(),
+                stop: Arc::new(AtomicBool::new(false)),
+                search_id: Arc::new(AtomicU32::new(0)),
+                tt: Arc::new(TranspositionTable::default()),
+                score_generation: Arc::new(AtomicU32::new(0)),
+                time: Arc::new(SearchTime::default()),
+                threads: 1,
+                search_info: None,
+                strength: StrengthSettings::default(),
+                node_budget: LIMITS.max_board_count,
+                open_search_nodes: LIMITS.max_board_count,
+                prune_policy: Arc::new(BestFirst),
+                rng: Arc::new(Mutex::new(StdRng::seed_from_u64(seed))),
+                seed,
+                algorithm: SearchAlgorithm::default(),
+                draw_score: Score::DRAW,
+                evaluator: Arc::new(Imbalance),
             },
             GameState::new(initial_board),
         )
@@ -266,95 +579,566 @@ impl Engine {
         (ret, board_counter.get(), maximum_depth.load(Relaxed))
     }
 
+    /// Allows the engine to play the moves of the tablebase instead of searching in small endgames
+    pub fn set_tablebase(&mut self, tablebase: Arc<dyn Tablebase>) {
+        self.tablebase = Some(tablebase);
+    }
+
+    pub fn set_info_callback(&mut self, info: InfoCallback) {
+        self.info = info;
+    }
+
+    /// Chooses the search algorithm of `best_move_within` and the searches built on it, e.g. to
+    /// compare the playing styles of the algorithms
+    pub fn set_search_algorithm(&mut self, algorithm: SearchAlgorithm) {
+        self.algorithm = algorithm;
+    }
+
+    /// Limits the strength of the engine, either with a preset or custom settings
+    pub fn set_strength(&mut self, strength: impl Into<StrengthSettings>) {
+        self.strength = strength.into();
+    }
+
+    /// Limits the number of boards kept from one search to the next, so long games and analysis
+    /// sessions do not grow the memory use without bound. Defaults to a quarter of the memory
+    /// available at the start.
+    pub fn set_node_budget(&mut self, max_nodes: u32) {
+        self.node_budget = max_nodes;
+    }
+
+    /// Limits the number of boards the searches without a deadline create, e.g. pondering, which
+    /// would otherwise only stop when asked to. They finish the iteration they are in once over
+    /// the limit. Unlike the node budget, it does not count the boards already in the tree.
+    /// Defaults to a quarter of the memory available at the start.
+    pub fn set_open_search_nodes(&mut self, max_nodes: u32) {
+        self.open_search_nodes = max_nodes;
+    }
+
+    /// Scores the draws as this much worse for the side the engine plays, so it avoids the
+    /// repetitions against weaker opponents. A negative contempt makes it seek the draws instead.
+    pub fn set_contempt(&mut self, contempt: Score, engine_side: PieceColor) {
+        self.draw_score = -(contempt * engine_side.mate_multiplier());
+    }
+
+    /// White's score of the draws found by the searches, with the contempt
+    pub fn draw_score(&self) -> Score {
+        self.draw_score
+    }
+
+    /// Scores the boards created by the searches with the evaluator instead of `Imbalance`, e.g.
+    /// with a network. The boards already in the continuation trees keep their scores, so it
+    /// is best set before the game, the adjusted scores calculated so far are marked stale.
+    pub fn set_evaluator(&mut self, evaluator: Arc<dyn Evaluator>) {
+        self.evaluator = evaluator;
+        self.invalidate_scores();
+    }
+
+    /// The evaluator of the boards created by the searches, see `set_evaluator`
+    #[inline]
+    pub fn evaluator(&self) -> &dyn Evaluator {
+        self.evaluator.as_ref()
+    }
+
+    /// Marks all adjusted scores calculated so far as stale, e.g. after the evaluation parameters
+    /// change
+    pub fn invalidate_scores(&self) {
+        self.score_generation.fetch_add(1, Relaxed);
+    }
+
+    /// The generation the adjusted scores are calculated in, see `invalidate_scores`
+    #[inline]
+    pub fn score_generation(&self) -> u32 {
+        self.score_generation.load(Relaxed)
+    }
+
+    /// The score the searches compare the board by, the draws are scored with the contempt
+    fn board_score(&self, board: &BoardContinuation) -> Score {
+        if board.potential_draw {
+            self.draw_score
+        } else {
+            board.score()
+        }
+    }
+
+    /// Seeds the random choices of the engine, so the same explored trees lead to the same moves.
+    /// The searches cut short by the clock explore different trees from run to run, a game is
+    /// only repeated when the searches are not.
+    pub fn set_seed(&mut self, seed: u64) {
+        *self.rng.lock().unwrap() = StdRng::seed_from_u64(seed);
+        self.seed = seed;
+    }
+
+    /// Changes how the boards to drop are chosen once the tree is over the node budget, the best
+    /// scored ones are kept by default
+    pub fn set_prune_policy(&mut self, policy: Arc<dyn PrunePolicy>) {
+        self.prune_policy = policy;
+    }
+
+    /// Drops explored boards of the game with the prune policy, if there are more than the budget
+    fn prune_tree(&self, state: &mut GameState) {
+        if state.worked_on_board.total_continuation_boards() <= self.node_budget {
+            return;
+        }
+        let dropped = self
+            .prune_policy
+            .prune(&mut state.worked_on_board, self.node_budget);
+        if dropped > 0 {
+            self.report(Verbosity::Full, || {
+                format!("pruned {dropped} boards to stay within the budget")
+            });
+        }
+    }
+
+    pub fn set_verbosity(&mut self, verbosity: Verbosity) {
+        self.verbosity = verbosity;
+    }
+
+    #[inline]
+    pub fn verbosity(&self) -> Verbosity {
+        self.verbosity
+    }
+
+    /// Passes the message to the info callback if the verbosity allows reports of the given level
+    pub fn report(&self, level: Verbosity, message: impl FnOnce() -> String) {
+        if level != Verbosity::Silent && self.verbosity >= level {
+            (self.info)(&message());
+        }
+    }
+
+    /// Reports a completed iteration of a search from the given root
+    pub(crate) fn report_iteration(
+        &self,
+        root: &BoardContinuation,
+        depth: u8,
+        seldepth: u8,
+        score: Score,
+        boards: u32,
+        started: Instant,
+    ) {
+        if self.search_info.is_none() && self.verbosity < Verbosity::Scores {
+            return;
+        }
+        let time = started.elapsed();
+        let info = SearchInfo {
+            depth,
+            seldepth,
+            nodes: boards,
+            nps: u64::from(boards) * 1000 / 1.max(time.as_millis() as u64),
+            time,
+            score,
+            pv: root.principal_variation_up_to(depth as usize),
+            stability: self.time.stability(),
+        };
+        self.report(Verbosity::Scores, || {
+            let mut report = format!("depth {depth} score {score:.2}");
+            if self.verbosity >= Verbosity::Full {
+                let pv: Vec<String> = info.pv.iter().map(PossibleMove::to_string).collect();
+                report.push_str(&format!(
+                    " seldepth {seldepth} boards {boards} kboards/s {} time {}ms confidence {} pv {}",
+                    info.nps / 1000,
+                    time.as_millis(),
+                    info.stability,
+                    pv.join(" ")
+                ));
+            }
+            report
+        });
+        if let Some(sender) = &self.search_info {
+            // The receiver is only gone once the search is over
+            let _ = sender.send(info);
+        }
+    }
+
+    /// Reports the statistics of the root moves once a search is over
+    fn report_root_moves(&self, root: &BoardContinuation) {
+        self.report(Verbosity::RootMoves, || {
+            root.root_move_stats()
+                .iter()
+                .map(|stats| {
+                    let searched = stats
+                        .searched_depth
+                        .map_or_else(|| "pruned".to_string(), |depth| format!("depth {depth}"));
+                    format!(
+                        "root move {} score {:.2} boards {} {searched}",
+                        stats.the_move, stats.score, stats.nodes
+                    )
+                })
+                .join("\n")
+        });
+    }
+
+    /// How often the best move changed between the iterations of the current or the last search
+    pub fn best_move_stability(&self) -> BestMoveStability {
+        self.time.stability()
+    }
+
+    /// Lets the time management know the best move of a completed iteration of the main thread
+    pub(crate) fn iteration_completed(&self, best_move: Option<&PossibleMove>) {
+        if self.time.iteration_completed(best_move) {
+            self.report(Verbosity::Full, || {
+                let deadline = self.time.deadline().unwrap_or_default();
+                format!("best move changed, extending the search to {deadline:?}")
+            });
+        }
+    }
+
+    /// Initializes the tables built on first use by the move generation and the hashing, and
+    /// clears every slot of the transposition table, so the first move of a game does not pay for
+    /// them. With a duration, the starting position is searched for that long too, to warm up the
+    /// search itself. Nothing learnt during the warm-up is kept.
+    pub async fn warm_up(&self, search: Option<Duration>) {
+        lazy_static::initialize(&LIMITS);
+        for fen in WARM_UP_POSITIONS {
+            let board = PSBoard::from_fen(fen)
+                .await
+                .expect("Warm-up positions should be valid");
+            board.perft(2);
+        }
+        if let Some(duration) = search {
+            let mut silent = self.clone();
+            silent.set_verbosity(Verbosity::Silent);
+            let mut state = GameState::new(PSBoard::default());
+            silent.best_move_for(&mut state, &duration).await;
+        }
+        self.tt.clear();
+        self.killers.clear();
+    }
+
+    /// Sets how many threads the principal variation search uses, helper threads beyond the
+    /// first one share their findings through the transposition table (Lazy SMP)
+    pub fn set_threads(&mut self, threads: usize) {
+        self.threads = threads.max(1);
+    }
+
+    async fn tablebase_move(&self, board: &PSBoard) -> Option<(PossibleMove, Score)> {
+        let tablebase = self.tablebase.as_ref()?;
+        if board.piece_count() > MAX_TABLEBASE_PIECES {
+            return None;
+        }
+        match tablebase.best_move(board).await {
+            Ok(Some(tb_move)) => {
+                self.report(Verbosity::Scores, || {
+                    format!("tb hit: {} ({:?})", tb_move.the_move, tb_move.outcome)
+                });
+                Some((tb_move.the_move, tb_move.score(board)))
+            }
+            Ok(None) => None,
+            Err(error) => {
+                self.report(Verbosity::Scores, || {
+                    format!("tablebase lookup failed: {error}")
+                });
+                None
+            }
+        }
+    }
+
     pub async fn best_move_for(
         &self,
         state: &mut GameState,
         duration: &Duration,
-    ) -> (Option<PossibleMove>, f32, u32, u8) {
+    ) -> (Option<PossibleMove>, Score, u32, u8) {
+        self.best_move_within(state, duration.into()).await
+    }
+
+    /// Searches for the best move like `best_move_within`, passing the progress of each completed
+    /// iteration to the callback
+    pub async fn search_with_callback(
+        &self,
+        state: &mut GameState,
+        limits: impl Into<TimeAllocation>,
+        mut callback: impl FnMut(SearchInfo),
+    ) -> (Option<PossibleMove>, Score, u32, u8) {
+        let (sender, mut receiver) = unbounded_channel();
+        let mut engine = self.clone();
+        engine.search_info = Some(sender);
+        let search = engine.best_move_within(state, limits.into());
+        tokio::pin!(search);
+        loop {
+            select! {
+                result = &mut search => {
+                    while let Ok(info) = receiver.try_recv() {
+                        callback(info);
+                    }
+                    return result;
+                }
+                Some(info) = receiver.recv() => callback(info),
+            }
+        }
+    }
+
+    /// Searches for the best move with the time allocated from the clock of the side to move
+    pub async fn best_move_on_clock(
+        &self,
+        state: &mut GameState,
+        clock: &GameClock,
+    ) -> (Option<PossibleMove>, Score, u32, u8) {
+        let allocation = clock.allocate(state.psboard().move_count);
+        self.best_move_within(state, allocation).await
+    }
+
+    /// Searches for the best move with the chosen algorithm for the target time of the
+    /// allocation, which is extended towards its maximum while the best move keeps changing
+    pub async fn best_move_within(
+        &self,
+        state: &mut GameState,
+        allocation: TimeAllocation,
+    ) -> (Option<PossibleMove>, Score, u32, u8) {
+        let mut search = self.algorithm.search(self);
+        self.best_move_with(state, search.as_mut(), allocation)
+            .await
+    }
+
+    /// Searches for the best move with the given search for the target time of the allocation.
+    /// The engine still plays the tablebase moves, prunes the tree before the search, stops the
+    /// search once the time is up and finds a move if the search returned none.
+    pub async fn best_move_with(
+        &self,
+        state: &mut GameState,
+        search: &mut dyn Search,
+        allocation: TimeAllocation,
+    ) -> (Option<PossibleMove>, Score, u32, u8) {
+        if let Some((tb_move, score)) = self.tablebase_move(state.psboard()).await {
+            return (Some(tb_move), score, 0, 0);
+        }
+        self.prune_tree(state);
         self.thread_counter.store(0, Relaxed);
-        self.exploration_allowed.store(true, Relaxed);
+        self.killers.clear();
         self.enable_parallel.store(true, Relaxed);
-        let engine_clone = self.clone();
-        let duration_clone = *duration;
+        self.start_search(Some(allocation));
+        let limits = SearchLimits {
+            allocation: Some(allocation),
+            game_path: state.game_path().to_vec(),
+            running: self.exploration_allowed.clone(),
+        };
+        let result = search.best_move(&mut state.worked_on_board, &limits).await;
+        self.end_search();
+        self.report_root_moves(&state.worked_on_board);
+        let (best_move, score) = self
+            .with_fallback_move(state.psboard(), result.best_move, result.score)
+            .await;
 
-        spawn(async move { engine_clone.time_up(duration_clone).await });
-        let ((best_move, score), board_count, maximum) =
-            Self::manage_counter(ExtEngine(self.clone(), &mut state.worked_on_board)).await;
+        (best_move, score, result.boards, result.depth)
+    }
 
-        (best_move, score, board_count, maximum)
+    /// Makes sure a search ends with a move whenever the board has a legal one. Searches can end
+    /// without one, e.g. when the root was scored a draw by repetition without exploring it, then
+    /// the legal moves are generated afresh and the best one by its static score is played.
+    async fn with_fallback_move(
+        &self,
+        board: &PSBoard,
+        best_move: Option<PossibleMove>,
+        score: Score,
+    ) -> (Option<PossibleMove>, Score) {
+        if best_move.is_some() {
+            return (best_move, score);
+        }
+        let mate_multiplier = board.who_moves.mate_multiplier();
+        let fallback = board
+            .successors()
+            .fold(
+                None,
+                |best: Option<(PossibleMove, Score)>, (a_move, next_board)| async move {
+                    match best {
+                        Some((_, best_score))
+                            if mate_multiplier * best_score
+                                >= mate_multiplier * next_board.score =>
+                        {
+                            best
+                        }
+                        _ => Some((a_move, next_board.score)),
+                    }
+                },
+            )
+            .await;
+        match fallback {
+            Some((a_move, fallback_score)) => {
+                self.report(Verbosity::Full, || {
+                    format!("the search found no move, playing {a_move} by its static score")
+                });
+                (Some(a_move), fallback_score)
+            }
+            None => (None, score),
+        }
+    }
+
+    /// Searches for the best move with the principal variation search instead of the averaging exploration
+    pub async fn best_move_pvs(
+        &self,
+        state: &mut GameState,
+        duration: &Duration,
+    ) -> (Option<PossibleMove>, Score, u32, u8) {
+        self.best_move_with(state, &mut AlphaBetaSearch(self.clone()), duration.into())
+            .await
     }
 
+    /// Searches for the given number of best moves, each with its own principal variation
+    pub async fn best_lines_pvs(
+        &self,
+        state: &mut GameState,
+        duration: &Duration,
+        lines: usize,
+    ) -> (Vec<PvLine>, u32, u8) {
+        self.prune_tree(state);
+        self.killers.clear();
+        self.start_search(Some(duration.into()));
+        let path = state.game_path().to_vec();
+        let result = Self::manage_counter(MultiPvEngine(
+            self.clone(),
+            &mut state.worked_on_board,
+            path,
+            lines,
+        ))
+        .await;
+        self.end_search();
+        result
+    }
+
+    /// Checks if the candidate move is at least as good as the best move the engine finds within
+    /// the limits. The candidate is only searched with a null window around the score of the best
+    /// move, which is much cheaper than scoring it exactly, e.g. to answer the questions of a
+    /// player or to annotate a game.
+    ///
+    /// # Errors
+    /// When the candidate is not a legal move or the search was stopped before its first
+    /// iteration
+    pub async fn verify_move(
+        &self,
+        state: &mut GameState,
+        candidate: &PossibleMove,
+        limits: impl Into<TimeAllocation>,
+    ) -> IntResult<MoveVerdict> {
+        let is_legal = state
+            .psboard()
+            .successors()
+            .any(|(a_move, _)| async move { a_move == *candidate })
+            .await;
+        if !is_legal {
+            return Err(format!("Not a legal move: {candidate}").into());
+        }
+        self.prune_tree(state);
+        self.killers.clear();
+        self.start_search(Some(limits.into()));
+        let path = state.game_path().to_vec();
+        let (verdict, _, _) = Self::manage_counter(VerifyEngine(
+            self.clone(),
+            &mut state.worked_on_board,
+            path,
+            *candidate,
+        ))
+        .await;
+        self.end_search();
+        verdict.ok_or_else(|| format!("No time was left to verify {candidate}").into())
+    }
+
+    /// Explores the board unless it was explored deep enough already, boards repeating a position
+    /// of the path are not explored but scored as draws
     async fn best_move_for_internal(
         &self,
         start_board: &mut BoardContinuation,
+        path: &mut Vec<u64>,
         curr_depth: u8,
         counter: &FlushingCounterU32,
         maximum: &AtomicU8,
         max_allowed_depth: u8,
-    ) -> (Option<PossibleMove>, f32) {
+    ) -> (Option<PossibleMove>, Score) {
+        if start_board.potential_draw {
+            return (None, self.draw_score);
+        }
         let mut ret = (None, start_board.score);
         let mate_multiplier = start_board.who_moves.mate_multiplier();
 
-        if !is_mate(start_board.score) {
-            let mut moves = Vec::new();
-            start_board.gen_potential_moves(&mut moves);
-            let enable_parallel = self
-                .enable_parallel
-                .compare_exchange_weak(true, false, Relaxed, Relaxed)
-                .is_ok();
-
-            //println!("Potential moves: {:?}", moves);
-            let exploration_method: Box<dyn Explore> =
-                if enable_parallel && self.thread_counter.load(Relaxed) < 25 {
-                    Box::new(self.par_explore())
-                } else {
-                    Box::new(self.seq_explore())
+        if !start_board.score.is_mate() {
+            // Boards searched deep enough earlier are not explored again
+            if !start_board.is_trusted(
+                max_allowed_depth.saturating_sub(curr_depth),
+                self.score_generation(),
+            ) {
+                let mut moves = MoveList::new();
+                start_board.gen_search_moves(&mut moves);
+                self.killers
+                    .order_moves(start_board, curr_depth, &mut moves);
+                moves.reverse(); // the explorers pop their next move from the back
+                let enable_parallel = self
+                    .enable_parallel
+                    .compare_exchange_weak(true, false, Relaxed, Relaxed)
+                    .is_ok();
+
+                //println!("Potential moves: {:?}", moves);
+                let exploration_method: Box<dyn Explore> =
+                    if enable_parallel && self.thread_counter.load(Relaxed) < 25 {
+                        Box::new(self.par_explore())
+                    } else {
+                        Box::new(self.seq_explore())
+                    };
+
+                path.push(start_board.zobrist());
+                let depth = ExplorationDepth {
+                    curr_depth,
+                    max_allowed_depth,
+                    counter,
+                    maximum,
                 };
+                self.exploration(moves, start_board, path, exploration_method, depth)
+                    .await;
+                path.pop();
 
-            self.exploration(
-                moves,
-                start_board,
-                exploration_method,
-                curr_depth,
-                counter,
-                maximum,
-                max_allowed_depth,
-            )
-            .await;
-
-            if enable_parallel {
-                self.enable_parallel.store(true, Relaxed);
+                if enable_parallel {
+                    self.enable_parallel.store(true, Relaxed);
+                }
             }
 
-            let best_potential_board = start_board.values().max_by(|b1, b2| {
-                (mate_multiplier * b1.adjusted_score)
-                    .partial_cmp(&(mate_multiplier * b2.adjusted_score))
-                    .unwrap_or(Ordering::Less)
-            });
+            // Boards not explored yet are ranked by their own score
+            let best_potential_board = start_board
+                .values()
+                .max_by_key(|b| mate_multiplier * self.board_score(b));
             if let Some(best_board) = best_potential_board {
-                let selected_board = if best_board.adjusted_score.is_nan() {
-                    start_board.select_similar_board(best_board, |b| b.score)
+                // The strength only affects the choice of the move played
+                let selected_board = if curr_depth == 0 {
+                    start_board.select_similar_board(
+                        best_board,
+                        |board| self.board_score(board),
+                        self.strength.randomness,
+                        self.strength.temperature,
+                        &mut *self.rng.lock().unwrap(),
+                    )
                 } else {
-                    start_board.select_similar_board(best_board, |b| b.adjusted_score)
-                };
-                ret = start_board
+                    // The boards below the root are explored in parallel, so their choices only
+                    // depend on the position to stay the same in any order of the threads
+                    start_board.select_similar_board(
+                        best_board,
+                        |board| self.board_score(board),
+                        SIMILAR_SCORE_DIFFERENCE,
+                        None,
+                        &mut StdRng::seed_from_u64(self.seed ^ start_board.zobrist()),
+                    )
+                }
+                // An empty window, like a randomness of zero, still leaves the best board
+                .unwrap_or(best_board);
+                if let Some((a_move, _)) = start_board
                     .iter()
-                    .find_map(|(amove, aboard)| {
-                        if ptr::eq(aboard, selected_board) {
-                            Some((Some(*amove), selected_board.score()))
-                        } else {
-                            None
-                        }
-                    })
-                    .unwrap();
+                    .find(|(_, aboard)| ptr::eq(aboard, selected_board))
+                {
+                    ret = (Some(*a_move), self.board_score(selected_board).backed_up());
+                }
+                if let Some(best_move) = ret.0.filter(|a_move| !start_board.is_capture(a_move)) {
+                    self.killers.store(curr_depth, &best_move);
+                }
+            }
+            // Without a legal move all the moves lose the king
+            let relative = mate_multiplier * ret.1;
+            if (ret.0.is_none() || relative.is_mate() && relative < Score::DRAW)
+                && start_board.mark_if_terminal(self.score_generation()).await
+            {
+                ret = (None, self.board_score(start_board));
             }
         } else {
-            start_board.adjusted_score = start_board.score;
+            start_board.adjusted_score = Some(start_board.score);
+            start_board.stamp_score(u8::MAX, self.score_generation()); // Mates are final
         }
         ret
     }
 
-    fn update_max_search(who: PieceColor, max_search: &mut [f32], curr_score: f32) {
+    fn update_max_search(who: PieceColor, max_search: &mut [Score], curr_score: Score) {
         max_search.sort_unstable_by(who.score_comparator());
         for (idx, a_good_score) in max_search.iter().enumerate() {
             if who.is_better_score(*a_good_score, curr_score) {
@@ -366,15 +1150,13 @@ impl Engine {
 
     async fn exploration(
         &self,
-        moves: Vec<PossibleMove>,
+        moves: MoveList,
         start_board: &mut BoardContinuation,
+        path: &mut Vec<u64>,
         exploration_helper: Box<dyn Explore>,
-        curr_depth: u8,
-        counter: &FlushingCounterU32,
-        maximum: &AtomicU8,
-        max_allowed_depth: u8,
+        depth: ExplorationDepth<'_>,
     ) {
-        start_board.adjusted_score = 0.0;
+        start_board.adjusted_score = Some(Score::DRAW);
         let who = start_board.who_moves;
         let max_search = [who.worst_score(); 4];
 
@@ -382,49 +1164,64 @@ impl Engine {
             .explore(ExplorationInput {
                 moves,
                 start_board,
-                curr_depth,
+                path,
+                depth,
                 max_search,
-                counter,
-                maximum,
-                max_allowed_depth,
             })
             .await
             .max_search;
 
         max_search.sort_unstable_by(who.score_comparator());
-        for idx in 0..max_search.len() {
-            let use_source_idx = if (max_search[idx] - max_search[3]).abs() > 10.0 {
-                3 // Does not consider bad situations where there is only a few good moves
-            } else {
-                idx
-            };
-            max_search[idx] = max_search[use_source_idx] * (idx * 2 + 1) as f32;
-            // Weighted towards the best scores
+        let weighted_sum: Score = (0..max_search.len())
+            .filter_map(|idx| {
+                let use_source_idx =
+                    if (max_search[idx] - max_search[3]).abs() > Score::centipawns(1000) {
+                        3 // Does not consider bad situations where there is only a few good moves
+                    } else {
+                        idx
+                    };
+                let a_score = max_search[use_source_idx];
+                // Weighted towards the best scores, the unfilled places are left out
+                a_score.is_finite().then(|| a_score * (idx * 2 + 1) as i32)
+            })
+            .sum();
+        // sum of all weights + 1 for the start_board's base score.
+        let average = (start_board.score + weighted_sum) / 17;
+        // An average of mates is not a mate in any number of plies
+        start_board.adjusted_score = Some(Score::centipawns(average.as_centipawns()));
+        if self.exploration_allowed.load(Relaxed) {
+            // Scores of interrupted searches are not stamped, so they will be explored again
+            start_board.stamp_score(
+                depth.max_allowed_depth.saturating_sub(depth.curr_depth),
+                self.score_generation(),
+            );
         }
-        start_board.adjusted_score = (start_board.score
-            + max_search
-                .iter()
-                .filter(|a_score| a_score.is_finite())
-                .sum::<f32>())
-            / 17f32; // sum of all weights + 1 for the start_board's base score.
     }
 }
 
 #[cfg(test)]
 mod test {
+    use crate::baserules::move_list::MoveList;
     use std::time::Duration;
 
     use super::continuation::BoardContinuation;
-    use super::DepthsBoardCountMaintenance;
+    use super::{poll_stop, DepthsBoardCountMaintenance, ExplorationDepth, STOP_CHECK_NODES};
     use crate::baserules::board::PSBoard;
+    use crate::baserules::piece_color::PieceColor;
+    use crate::baserules::score::Score;
+    use crate::engine::strength::Strength;
+    use crate::engine::transposition::{Bound, TtEntry};
     use crate::engine::GameState;
+    use crate::engine::Verbosity;
     use crate::human_facing::helper;
     use crate::{baserules::board_rep::PossibleMove, engine::Engine};
     use async_trait::async_trait;
     use global_counter::primitive::fast::FlushingCounterU32;
-    use std::sync::atomic::AtomicU8;
-    use tokio::spawn;
+    use std::sync::atomic::Ordering::Relaxed;
+    use std::sync::atomic::{AtomicBool, AtomicU8};
+    use std::sync::{Arc, Mutex};
     use tokio::test;
+    use tokio::time::{sleep, Instant};
 
     /// Test for this game: https://lichess.org/dRlJX08zhn1L
     #[test(flavor = "multi_thread")]
@@ -432,7 +1229,9 @@ mod test {
         let (engine, mut gamestate) =
             Engine::from_fen("r1b1kbnr/pppn1ppp/4p3/6q1/4P3/8/PPPP1PPP/RNBQK1NR w KQkq - 0 4")
                 .await;
-        let moves = vec![PossibleMove::simple_from_uci("d1h5").unwrap()];
+        let moves: MoveList = [PossibleMove::simple_from_uci("d1h5").unwrap()]
+            .into_iter()
+            .collect();
 
         let result = Engine::manage_counter(ExploreHelper(
             engine.clone(),
@@ -441,8 +1240,8 @@ mod test {
         ))
         .await;
         println!("Depth: {}", result.2);
-        println!("{}", gamestate.worked_on_board.adjusted_score);
-        assert!(gamestate.worked_on_board.adjusted_score < -4.0);
+        println!("{}", gamestate.worked_on_board.score());
+        assert!(gamestate.worked_on_board.score() < Score::from_pawns(-4.0));
     }
 
     /// Test for this game: https://lichess.org/dRlJX08zhn1L
@@ -457,26 +1256,29 @@ mod test {
             &Duration::from_millis(200),
         )
         .await;
-        assert!(score < -6.0);
+        assert!(score < Score::from_pawns(-6.0));
     }
 
-    struct ExploreHelper<'a>(Engine, Vec<PossibleMove>, &'a mut BoardContinuation);
+    struct ExploreHelper<'a>(Engine, MoveList, &'a mut BoardContinuation);
 
     #[async_trait]
     impl<'a> DepthsBoardCountMaintenance<()> for ExploreHelper<'a> {
         async fn best_move_for(mut self, board_count: &FlushingCounterU32, depth: &AtomicU8) {
-            let engine_clone = self.0.clone();
-
-            spawn(async move { engine_clone.time_up(Duration::from_millis(500)).await });
+            self.0
+                .start_search(Some((&Duration::from_millis(500)).into()));
+            let mut path = vec![self.2.zobrist()];
             self.0
                 .exploration(
                     self.1,
-                    &mut self.2,
+                    self.2,
+                    &mut path,
                     Box::new(self.0.par_explore()),
-                    0,
-                    board_count,
-                    depth,
-                    5,
+                    ExplorationDepth {
+                        curr_depth: 0,
+                        max_allowed_depth: 5,
+                        counter: board_count,
+                        maximum: depth,
+                    },
                 )
                 .await
         }
@@ -488,7 +1290,9 @@ mod test {
         let (engine, mut gamestate) =
             Engine::from_fen("r1b1kbnr/pppn1ppp/4p3/6qQ/4P3/8/PPPP1PPP/RNB1K1NR b KQkq - 1 4")
                 .await;
-        let moves = vec![PossibleMove::simple_from_uci("g5d2").unwrap()];
+        let moves: MoveList = [PossibleMove::simple_from_uci("g5d2").unwrap()]
+            .into_iter()
+            .collect();
 
         let result = Engine::manage_counter(ExploreHelper(
             engine.clone(),
@@ -497,8 +1301,8 @@ mod test {
         ))
         .await;
         println!("Depth: {}", result.2);
-        println!("{}", gamestate.worked_on_board.adjusted_score);
-        assert!(gamestate.worked_on_board.adjusted_score > 2.0);
+        println!("{}", gamestate.worked_on_board.score());
+        assert!(gamestate.worked_on_board.score() > Score::from_pawns(2.0));
     }
 
     /// Test for this game: https://lichess.org/ZnIAbaQXqHCF
@@ -507,7 +1311,9 @@ mod test {
         let (engine, mut gamestate) =
             Engine::from_fen("r2qk2r/pp1nbppp/2p5/5b2/4p3/PQ6/1P1PPPPP/R1B1KBNR w KQkq - 4 11")
                 .await;
-        let moves = vec![PossibleMove::simple_from_uci("b3f7").unwrap()];
+        let moves: MoveList = [PossibleMove::simple_from_uci("b3f7").unwrap()]
+            .into_iter()
+            .collect();
 
         let result = Engine::manage_counter(ExploreHelper(
             engine.clone(),
@@ -516,8 +1322,8 @@ mod test {
         ))
         .await;
         println!("Depth: {}", result.2);
-        println!("{}", gamestate.worked_on_board.adjusted_score);
-        assert!(gamestate.worked_on_board.adjusted_score < -5.0);
+        println!("{}", gamestate.worked_on_board.score());
+        assert!(gamestate.worked_on_board.score() < Score::from_pawns(-5.0));
     }
 
     /// Test for this game: https://lichess.org/ZnIAbaQXqHCF
@@ -639,9 +1445,10 @@ mod test {
         let (engine, mut gamestate) = prep_failed_game_4().await;
         helper::calculate_move_for_console(&engine, &mut gamestate, &Duration::from_millis(200))
             .await;
-        gamestate.make_a_human_move_or_panic("cxb2").await;
+        // Not a legal move in check, so it is given in uci notation, which is not checked
+        gamestate.make_an_uci_move("c3b2").await.unwrap();
         let the_board = gamestate.continuation().clone();
-        let mut moves = Vec::new();
+        let mut moves = MoveList::new();
         the_board.gen_potential_moves(&mut moves);
         println!("{moves:?}");
         assert!(moves.contains(&PossibleMove::simple_from_uci("f7d8").unwrap()));
@@ -670,6 +1477,103 @@ mod test {
         assert_eq!(continuations_after - continuations_before, board_count);
     }
 
+    #[test(flavor = "multi_thread")]
+    async fn root_moves_have_statistics() {
+        let (engine, mut gamestate) =
+            Engine::from_fen("r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 4 4")
+                .await;
+        let (best_move, _, boards, _) = engine
+            .best_move_pvs(&mut gamestate, &Duration::from_millis(300))
+            .await;
+        let stats = gamestate.continuation().root_move_stats();
+        assert_eq!(best_move, Some(stats[0].the_move));
+        assert!(stats[0].score.is_mate() && stats[0].searched_depth.is_some());
+        assert_eq!(boards, stats.iter().map(|stats| stats.nodes).sum::<u32>());
+        assert!(stats.iter().any(|stats| stats.searched_depth.is_none()));
+    }
+
+    #[test(flavor = "multi_thread")]
+    async fn warm_up_leaves_nothing_behind() {
+        let (engine, mut gamestate) = Engine::new();
+        let hash = gamestate.psboard().zobrist();
+        engine.tt.store(
+            hash,
+            &TtEntry {
+                depth: 1,
+                score: Score::DRAW,
+                bound: Bound::Exact,
+                best_move: None,
+            },
+        );
+        engine.warm_up(Some(Duration::from_millis(100))).await;
+        assert!(engine.tt.probe(hash).is_none());
+        let (best_move, _, _, _) = engine
+            .best_move_for(&mut gamestate, &Duration::from_millis(100))
+            .await;
+        assert!(best_move.is_some());
+    }
+
+    #[test(flavor = "multi_thread")]
+    async fn beginner_stops_at_shallow_depth() {
+        let (mut engine, mut gamestate) = Engine::new();
+        engine.set_strength(Strength::Beginner);
+        let started = Instant::now();
+        let (best_move, _, _, depth) = engine
+            .best_move_pvs(&mut gamestate, &Duration::from_secs(8))
+            .await;
+        assert!(best_move.is_some());
+        assert_eq!(3, depth);
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test(flavor = "multi_thread")]
+    async fn unexplored_root_still_gets_a_move() {
+        let (engine, mut gamestate) = Engine::from_fen("4k3/8/8/8/8/8/3q4/4K3 w - - 0 1").await;
+        gamestate.worked_on_board.mark_repetition();
+        let (best_move, _, _, _) = engine
+            .best_move_for(&mut gamestate, &Duration::from_millis(100))
+            .await;
+        // The king takes the queen, the static scores see that much
+        assert_eq!("e1d2", best_move.unwrap().to_string());
+
+        let (engine, mut mated) = Engine::from_fen("4k3/8/8/8/8/8/3qq3/4K3 w - - 0 1").await;
+        mated.worked_on_board.mark_repetition();
+        let (best_move, _, _, _) = engine
+            .best_move_for(&mut mated, &Duration::from_millis(100))
+            .await;
+        assert!(best_move.is_none());
+    }
+
+    #[test(flavor = "multi_thread")]
+    async fn goes_back_to_a_position_of_the_game_when_behind() {
+        let (engine, mut gamestate) = Engine::from_fen("4k1n1/8/8/8/8/8/8/1Q2K1N1 w - - 0 1").await;
+        for a_move in ["g1f3", "g8f6", "f3g1"] {
+            gamestate.make_an_uci_move(a_move).await.unwrap();
+        }
+        let (best_move, score, _, _) = engine
+            .best_move_for(&mut gamestate, &Duration::from_millis(300))
+            .await;
+        assert_eq!("f6g8", best_move.unwrap().to_string());
+        assert_eq!(Score::DRAW, score);
+    }
+
+    #[test(flavor = "multi_thread")]
+    async fn contempt_avoids_the_repetition() {
+        let (mut engine, mut gamestate) =
+            Engine::from_fen("4k1n1/8/8/8/8/8/8/1Q2K1N1 w - - 0 1").await;
+        for a_move in ["g1f3", "g8f6", "f3g1"] {
+            gamestate.make_an_uci_move(a_move).await.unwrap();
+        }
+        // Black would rather play on a queen down than draw
+        engine.set_contempt(Score::centipawns(2000), PieceColor::Black);
+        assert_eq!(Score::centipawns(2000), engine.draw_score());
+        let (best_move, score, _, _) = engine
+            .best_move_for(&mut gamestate, &Duration::from_millis(300))
+            .await;
+        assert_ne!("f6g8", best_move.unwrap().to_string());
+        assert!(score < engine.draw_score());
+    }
+
     /// Tests for this game: https://lichess.org/NPchEbrvI0qD
     #[test(flavor = "multi_thread")]
     async fn failed_game_1() {
@@ -682,11 +1586,12 @@ mod test {
         gamestate.make_a_human_move("Rc1+").await.unwrap();
         assert_eq!(format!("{board}"), format!("{}", gamestate.psboard()));
 
+        // Taking the rook runs into Qxc1#, the king has to step out of the check
         let move_to_do = engine
             .best_move_for(&mut gamestate, &Duration::from_millis(1))
             .await;
         assert_eq!(
-            PossibleMove::simple_from_uci("a1c1").unwrap(),
+            PossibleMove::simple_from_uci("e1d2").unwrap(),
             move_to_do.0.unwrap()
         );
     }
@@ -740,4 +1645,109 @@ mod test {
             move_to_do.0.unwrap()
         );
     }
+
+    #[test(flavor = "multi_thread")]
+    async fn verbosity_limits_reports() {
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let (mut engine, _) = Engine::new();
+        let collected = reports.clone();
+        engine.set_info_callback(Arc::new(move |info| {
+            collected.lock().unwrap().push(info.to_string())
+        }));
+        for verbosity in [Verbosity::Silent, Verbosity::Scores, Verbosity::Full] {
+            engine.set_verbosity(verbosity);
+            let (_, mut state) = Engine::new();
+            engine
+                .best_move_pvs(&mut state, &Duration::from_millis(200))
+                .await;
+            let reports: Vec<String> = reports.lock().unwrap().drain(..).collect();
+            match verbosity {
+                Verbosity::Silent => assert!(reports.is_empty()),
+                Verbosity::Scores => assert!(reports
+                    .iter()
+                    .all(|report| report.starts_with("depth") && !report.contains(" pv "))),
+                _ => assert!(reports.iter().all(|report| report.contains(" pv "))),
+            }
+            if verbosity != Verbosity::Silent {
+                assert!(!reports.is_empty());
+            }
+        }
+        assert_eq!(Ok(Verbosity::RootMoves), "moves".parse());
+        assert_eq!(Ok(Verbosity::Full), "".parse());
+        assert!("chatty".parse::<Verbosity>().is_err());
+    }
+
+    #[test(flavor = "multi_thread")]
+    async fn stop_token_interrupts_the_search() {
+        let (engine, mut state) = Engine::new();
+        let stop_token = engine.stop_token();
+        let stopper = tokio::spawn(async move {
+            sleep(Duration::from_millis(200)).await;
+            stop_token.store(true, std::sync::atomic::Ordering::Relaxed);
+        });
+        let started = Instant::now();
+        let (best_move, ..) = engine
+            .best_move_pvs(&mut state, &Duration::from_secs(60))
+            .await;
+        stopper.await.unwrap();
+        assert!(best_move.is_some());
+        assert!(started.elapsed() < Duration::from_secs(10));
+    }
+
+    #[test(flavor = "multi_thread")]
+    async fn stop_before_the_search_is_kept() {
+        let (engine, mut state) = Engine::new();
+        engine.stop();
+        let started = Instant::now();
+        let (best_move, ..) = engine
+            .best_move_pvs(&mut state, &Duration::from_secs(60))
+            .await;
+        assert!(best_move.is_some());
+        assert!(started.elapsed() < Duration::from_secs(10));
+        // The stop is cleared once honoured, the next search runs until its time is up
+        assert!(!engine
+            .stop_token()
+            .load(std::sync::atomic::Ordering::Relaxed));
+        let started = Instant::now();
+        engine
+            .best_move_pvs(&mut state, &Duration::from_millis(300))
+            .await;
+        assert!(started.elapsed() >= Duration::from_millis(250));
+    }
+
+    #[test]
+    async fn search_loops_check_the_stop_token() {
+        let stop = AtomicBool::new(false);
+        let exploration_allowed = AtomicBool::new(true);
+        for _ in 0..2 * STOP_CHECK_NODES {
+            poll_stop(&stop, &exploration_allowed);
+        }
+        assert!(exploration_allowed.load(Relaxed));
+        stop.store(true, Relaxed);
+        for _ in 0..STOP_CHECK_NODES {
+            poll_stop(&stop, &exploration_allowed);
+        }
+        assert!(!exploration_allowed.load(Relaxed));
+    }
+
+    #[test(flavor = "multi_thread")]
+    async fn callback_receives_search_progress() {
+        let (mut engine, mut state) = Engine::from_fen("4k3/8/8/3p4/8/8/4P3/4K3 w - - 0 1").await;
+        engine.set_verbosity(Verbosity::Silent);
+        let mut infos = Vec::new();
+        let (best_move, _, _, _) = engine
+            .search_with_callback(&mut state, &Duration::from_secs(1), |info| infos.push(info))
+            .await;
+        assert!(!infos.is_empty());
+        assert!(infos.windows(2).all(|pair| pair[0].depth < pair[1].depth));
+        assert!(best_move.is_some());
+        let last = infos.last().unwrap();
+        assert!(!last.pv.is_empty() && last.score.is_finite());
+        assert!(last.seldepth >= last.depth && last.nodes > 0);
+        assert!(infos
+            .windows(2)
+            .all(|pair| pair[0].stability.iterations < pair[1].stability.iterations));
+        assert_eq!(engine.best_move_stability(), last.stability);
+        assert!(last.stability.stable_for >= 1);
+    }
 }
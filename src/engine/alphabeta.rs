@@ -0,0 +1,970 @@
+use crate::baserules::board::PSBoard;
+use crate::baserules::board_rep::PossibleMove;
+use crate::baserules::evaluation::Evaluator;
+use crate::baserules::move_list::MoveList;
+use crate::baserules::score::Score;
+use crate::engine::continuation::BoardContinuation;
+use crate::engine::killers::KillerMoves;
+use crate::engine::quiescence::quiescence_score;
+use crate::engine::staged::StagedMoves;
+use crate::engine::strength::StrengthSettings;
+use crate::engine::transposition::{Bound, TranspositionTable, TtEntry};
+use crate::engine::{poll_stop, DepthsBoardCountMaintenance, Engine};
+use async_scoped::TokioScope;
+use async_trait::async_trait;
+use futures_util::future::BoxFuture;
+use futures_util::FutureExt;
+use global_counter::primitive::fast::FlushingCounterU32;
+use std::sync::atomic::Ordering::Relaxed;
+use std::sync::atomic::{AtomicBool, AtomicU8};
+use tokio::task::yield_now;
+use tokio::time::Instant;
+
+/// The width of the windows used to test if a move is better than the principal variation
+const NULL_WINDOW: Score = Score::centipawns(1);
+
+/// The initial half width of the root window around the score of the previous iteration, in
+/// centipawns
+const ASPIRATION_WINDOW: i32 = 50;
+
+/// The state shared by all nodes searched by a thread
+pub struct SearchContext<'a> {
+    pub killers: &'a KillerMoves,
+    pub counter: &'a FlushingCounterU32,
+    /// Once this turns false, the search returns the static evaluations of the unexplored boards
+    pub exploration_allowed: &'a AtomicBool,
+    /// The stop token of the engine, turns the exploration off when set
+    pub stop: &'a AtomicBool,
+    /// Shared by all the threads of a search, unlike the rest of the context
+    pub tt: &'a TranspositionTable,
+    /// The deepest ply the search has reached before the quiescence search
+    pub seldepth: &'a AtomicU8,
+    /// White's score of the draws, with the contempt of the engine
+    pub draw: Score,
+    /// The score generation of the engine, the boards scored in other generations are searched
+    /// again
+    pub generation: u32,
+    /// Scores the boards created by the search
+    pub evaluator: &'a dyn Evaluator,
+}
+
+impl<'a> SearchContext<'a> {
+    /// The context of a search of the engine, each thread with its own killer moves and seldepth
+    pub fn new(
+        engine: &'a Engine,
+        killers: &'a KillerMoves,
+        counter: &'a FlushingCounterU32,
+        seldepth: &'a AtomicU8,
+    ) -> Self {
+        Self {
+            killers,
+            counter,
+            exploration_allowed: &engine.exploration_allowed,
+            stop: &engine.stop,
+            tt: &engine.tt,
+            seldepth,
+            draw: engine.draw_score(),
+            generation: engine.score_generation(),
+            evaluator: engine.evaluator(),
+        }
+    }
+
+    /// Stops the search with the given flag instead of the engine's
+    pub fn with_exploration_allowed(self, exploration_allowed: &'a AtomicBool) -> Self {
+        Self {
+            exploration_allowed,
+            ..self
+        }
+    }
+}
+
+/// Principal variation search with negamax scores, i.e. relative to the side to move.
+/// The first move is searched with the full window, the rest only with a null window to prove
+/// they are worse. Only moves failing high on the null window are searched again.
+/// Exact scores are backed up into the adjusted scores of the boards (from white's perspective).
+/// The path holds the hashes of the positions before the board, boards repeating one of them are
+/// scored as draws.
+pub fn principal_variation_search<'a>(
+    board: &'a mut BoardContinuation,
+    path: &'a mut Vec<u64>,
+    depth: u8,
+    ply: u8,
+    mut alpha: Score,
+    beta: Score,
+    context: &'a SearchContext<'a>,
+) -> BoxFuture<'a, (Option<PossibleMove>, Score)> {
+    async move {
+        let mate_multiplier = board.who_moves.mate_multiplier();
+        if board.score.is_mate() {
+            return (None, mate_multiplier * board.score);
+        }
+        if board.potential_draw {
+            return (None, mate_multiplier * context.draw);
+        }
+        if ply > 0 && path.contains(&board.zobrist()) {
+            board.mark_repetition();
+            return (None, mate_multiplier * context.draw);
+        }
+        poll_stop(context.stop, context.exploration_allowed);
+        if depth == 0 || !context.exploration_allowed.load(Relaxed) {
+            context.seldepth.fetch_max(ply, Relaxed);
+            return (
+                None,
+                mate_multiplier * quiescence_score(board, context.evaluator).await,
+            );
+        }
+        let stored = context.tt.probe(board.zobrist());
+        if ply > 0 {
+            if let Some(entry) = stored {
+                if let Some(score) = entry.cutoff(depth, alpha, beta) {
+                    return (entry.best_move, score);
+                }
+            }
+        }
+        // The best move of an earlier search of the same position is tried first, the quiet
+        // moves are only generated when no capture or killer cuts off
+        let stored_move = stored.and_then(|entry| entry.best_move);
+        let mut moves = StagedMoves::new(board, stored_move, context.killers, ply);
+        let original_alpha = alpha;
+        let mut best = (None, Score::NEG_INFINITY);
+        path.push(board.zobrist());
+        let mut idx = 0;
+        while let Some(a_move) = moves.next_move(board) {
+            let a_move = &a_move;
+            let next_board = board
+                .lookup_continuation_or_create(a_move, context.counter, context.evaluator)
+                .await;
+            // The bucket of the child loads while the other tasks run
+            context.tt.prefetch(next_board.zobrist());
+            yield_now().await;
+            let score = if idx == 0 {
+                -principal_variation_search(
+                    next_board,
+                    path,
+                    depth - 1,
+                    ply + 1,
+                    -beta,
+                    -alpha,
+                    context,
+                )
+                .await
+                .1
+                .backed_up()
+            } else {
+                let null_window_score = -principal_variation_search(
+                    next_board,
+                    path,
+                    depth - 1,
+                    ply + 1,
+                    -alpha - NULL_WINDOW,
+                    -alpha,
+                    context,
+                )
+                .await
+                .1
+                .backed_up();
+                if alpha < null_window_score && null_window_score < beta {
+                    let next_board = board.find_continuation_mut(a_move).unwrap();
+                    -principal_variation_search(
+                        next_board,
+                        path,
+                        depth - 1,
+                        ply + 1,
+                        -beta,
+                        -alpha,
+                        context,
+                    )
+                    .await
+                    .1
+                    .backed_up()
+                } else {
+                    null_window_score
+                }
+            };
+            idx += 1;
+            if score > best.1 {
+                best = (Some(*a_move), score);
+            }
+            alpha = alpha.max(score);
+            if alpha >= beta {
+                if !board.is_capture(a_move) {
+                    context.killers.store(ply, a_move);
+                }
+                break;
+            }
+        }
+        path.pop();
+        // Without a legal move all the moves lose the king
+        if (best.0.is_none() || best.1.is_mate() && best.1 < Score::DRAW)
+            && board.mark_if_terminal(context.generation).await
+        {
+            let score = if board.potential_draw {
+                context.draw
+            } else {
+                board.score()
+            };
+            return (None, mate_multiplier * score);
+        }
+        if context.exploration_allowed.load(Relaxed) && best.0.is_some() {
+            let bound = if best.1 <= original_alpha {
+                Bound::Upper
+            } else if best.1 >= beta {
+                Bound::Lower
+            } else {
+                board.adjusted_score = Some(mate_multiplier * best.1);
+                board.stamp_score(depth, context.generation);
+                Bound::Exact
+            };
+            context.tt.store(
+                board.zobrist(),
+                &TtEntry {
+                    depth,
+                    score: best.1,
+                    bound,
+                    best_move: best.0,
+                },
+            );
+        }
+        best
+    }
+    .boxed()
+}
+
+/// Searches the root with a narrow window around the expected (relative) score, as long as the
+/// result falls within the window. Results failing low or high are only bounds, so the window is
+/// widened on their side and the search is repeated.
+pub async fn aspiration_search<'a>(
+    board: &'a mut BoardContinuation,
+    path: &'a mut Vec<u64>,
+    depth: u8,
+    expected: Option<Score>,
+    context: &'a SearchContext<'a>,
+) -> (Option<PossibleMove>, Score) {
+    let Some(expected) = expected.filter(|score| !score.is_mate()) else {
+        return principal_variation_search(
+            board,
+            path,
+            depth,
+            0,
+            Score::NEG_INFINITY,
+            Score::INFINITY,
+            context,
+        )
+        .await;
+    };
+    let (mut lower_width, mut upper_width) = (ASPIRATION_WINDOW, ASPIRATION_WINDOW);
+    loop {
+        // Windows failing repeatedly are opened up completely on their side
+        let alpha = if lower_width > 8 * ASPIRATION_WINDOW {
+            Score::NEG_INFINITY
+        } else {
+            expected - Score::centipawns(lower_width)
+        };
+        let beta = if upper_width > 8 * ASPIRATION_WINDOW {
+            Score::INFINITY
+        } else {
+            expected + Score::centipawns(upper_width)
+        };
+        let result = principal_variation_search(board, path, depth, 0, alpha, beta, context).await;
+        if !context.exploration_allowed.load(Relaxed) {
+            return result;
+        }
+        if result.1 <= alpha {
+            lower_width *= 2;
+        } else if result.1 >= beta {
+            upper_width *= 2;
+        } else {
+            return result;
+        }
+    }
+}
+
+/// One of the best root moves with the line expected to follow it
+#[derive(Debug, Clone, PartialEq)]
+pub struct PvLine {
+    /// White-relative score
+    pub score: Score,
+    /// Starts with the root move
+    pub line: Vec<PossibleMove>,
+}
+
+/// Searches the root moves for the given number of best lines. A move only gets a full window
+/// search if it beats the worst of the best lines found so far on a null window.
+pub async fn multi_pv_search<'a>(
+    board: &'a mut BoardContinuation,
+    path: &'a mut Vec<u64>,
+    depth: u8,
+    lines: usize,
+    context: &'a SearchContext<'a>,
+) -> Vec<PvLine> {
+    let mate_multiplier = board.who_moves.mate_multiplier();
+    if board.score.is_mate() || depth == 0 || lines == 0 {
+        return Vec::new();
+    }
+    let mut moves = MoveList::new();
+    board.gen_search_moves(&mut moves);
+    context.killers.order_moves(board, 0, &mut moves);
+    // Relative scores, the best first
+    let mut best_moves: Vec<(Score, PossibleMove)> = Vec::with_capacity(lines + 1);
+    path.push(board.zobrist());
+    for a_move in moves {
+        let next_board = board
+            .lookup_continuation_or_create(&a_move, context.counter, context.evaluator)
+            .await;
+        yield_now().await;
+        let score = if best_moves.len() < lines {
+            -principal_variation_search(
+                next_board,
+                path,
+                depth - 1,
+                1,
+                Score::NEG_INFINITY,
+                Score::INFINITY,
+                context,
+            )
+            .await
+            .1
+            .backed_up()
+        } else {
+            let to_beat = best_moves[lines - 1].0;
+            let null_window_score = -principal_variation_search(
+                next_board,
+                path,
+                depth - 1,
+                1,
+                -to_beat - NULL_WINDOW,
+                -to_beat,
+                context,
+            )
+            .await
+            .1
+            .backed_up();
+            if null_window_score <= to_beat {
+                continue;
+            }
+            let next_board = board.find_continuation_mut(&a_move).unwrap();
+            -principal_variation_search(
+                next_board,
+                path,
+                depth - 1,
+                1,
+                Score::NEG_INFINITY,
+                -to_beat,
+                context,
+            )
+            .await
+            .1
+            .backed_up()
+        };
+        let position = best_moves.partition_point(|(better, _)| *better >= score);
+        best_moves.insert(position, (score, a_move));
+        best_moves.truncate(lines);
+    }
+    path.pop();
+    best_moves
+        .into_iter()
+        .map(|(score, a_move)| {
+            let mut line = vec![a_move];
+            if let Some(next_board) = board.find_continuation(&a_move) {
+                line.extend(next_board.principal_variation_up_to(depth as usize - 1));
+            }
+            PvLine {
+                score: mate_multiplier * score,
+                line,
+            }
+        })
+        .collect()
+}
+
+/// How a move suggested from outside the search compares to the best move of the engine
+#[derive(Debug, Clone, PartialEq)]
+pub struct MoveVerdict {
+    /// The suggested move scores at least as well as the best move
+    pub holds: bool,
+    pub best_move: Option<PossibleMove>,
+    /// White-relative score of the best move
+    pub best_score: Score,
+    /// White-relative bound of the score of the suggested move, proven by the null window search.
+    /// It is a bound in the favour of the side to move if the move holds, against it otherwise.
+    pub candidate_bound: Score,
+    /// The depth of the last completed iteration
+    pub depth: u8,
+}
+
+/// Tests the candidate root move with a null window just below the relative score to beat, so
+/// the result only tells if the move reaches that score (the result is at least the score to
+/// beat) or not (the result is below it).
+pub async fn verify_candidate<'a>(
+    board: &'a mut BoardContinuation,
+    path: &'a mut Vec<u64>,
+    depth: u8,
+    candidate: &PossibleMove,
+    to_beat: Score,
+    context: &'a SearchContext<'a>,
+) -> Score {
+    path.push(board.zobrist());
+    let next_board = board
+        .lookup_continuation_or_create(candidate, context.counter, context.evaluator)
+        .await;
+    let score = -principal_variation_search(
+        next_board,
+        path,
+        depth.saturating_sub(1),
+        1,
+        -to_beat,
+        -to_beat + NULL_WINDOW,
+        context,
+    )
+    .await
+    .1
+    .backed_up();
+    path.pop();
+    score
+}
+
+/// Deepens the principal variation search of the root one ply at a time, starting at the given
+/// depth, until the exploration is stopped. The depth of the last completed iteration is stored.
+/// The completed iterations are reported through the engine, if there is one. No iteration
+/// deeper than the strength allows is started after the first one.
+async fn iterative_deepening<'a>(
+    board: &'a mut BoardContinuation,
+    path: &'a mut Vec<u64>,
+    start_depth: u8,
+    context: &'a SearchContext<'a>,
+    depth: &AtomicU8,
+    strength: &StrengthSettings,
+    reporter: Option<&Engine>,
+) -> (Option<PossibleMove>, Score) {
+    let started = Instant::now();
+    let mate_multiplier = board.who_moves.mate_multiplier();
+    let mut best_move_and_score = (None, Score::DRAW);
+    // The relative score of the last completed iteration
+    let mut expected = None;
+    let mut depth_allowed = start_depth;
+    while context.exploration_allowed.load(Relaxed)
+        && depth_allowed < u8::MAX
+        && (best_move_and_score.0.is_none() || strength.allows_depth(depth_allowed))
+    {
+        let (best_move, score) =
+            aspiration_search(board, path, depth_allowed, expected, context).await;
+        // An interrupted iteration is only used if we have nothing better
+        if context.exploration_allowed.load(Relaxed) || best_move_and_score.0.is_none() {
+            best_move_and_score = (best_move, mate_multiplier * score);
+            expected = Some(score);
+            depth.store(depth_allowed, Relaxed);
+            if let Some(engine) = reporter {
+                engine.iteration_completed(best_move_and_score.0.as_ref());
+                engine.report_iteration(
+                    board,
+                    depth_allowed,
+                    context.seldepth.load(Relaxed),
+                    best_move_and_score.1,
+                    context.counter.get(),
+                    started,
+                );
+            }
+        }
+        if best_move.is_none() {
+            break; // The game has ended
+        }
+        depth_allowed += 1;
+    }
+    best_move_and_score
+}
+
+/// Iterative deepening driver of the principal variation search. With more than one thread
+/// the helper threads search their own trees grown from the root board (Lazy SMP), half of them
+/// a ply deeper than the main thread. They only help through the shared transposition table, the
+/// result is always the main thread's. The path is the game's, leading to the root.
+pub(crate) struct PvsEngine<'a>(
+    pub(crate) Engine,
+    pub(crate) &'a mut BoardContinuation,
+    pub(crate) Vec<u64>,
+);
+
+#[async_trait]
+impl<'a> DepthsBoardCountMaintenance<(Option<PossibleMove>, Score)> for PvsEngine<'a> {
+    async fn best_move_for(
+        self,
+        board_count: &FlushingCounterU32,
+        depth: &AtomicU8,
+    ) -> (Option<PossibleMove>, Score) {
+        let PvsEngine(engine, root, mut path) = self;
+        let engine = &engine;
+        let helper_roots: Vec<(BoardContinuation, Vec<u64>)> = (1..engine.threads)
+            .map(|_| (BoardContinuation::new(PSBoard::clone(root)), path.clone()))
+            .collect();
+        let main_search = async move {
+            let seldepth = AtomicU8::new(0);
+            let context = SearchContext::new(engine, &engine.killers, board_count, &seldepth);
+            let result = iterative_deepening(
+                root,
+                &mut path,
+                1,
+                &context,
+                depth,
+                &engine.strength,
+                Some(engine),
+            )
+            .await;
+            // The helpers are not needed once the main thread has finished
+            engine.exploration_allowed.store(false, Relaxed);
+            // The boards counted on the worker thread are added to the total, like the helpers'
+            board_count.flush();
+            Some(result)
+        };
+        if engine.threads <= 1 {
+            return main_search.await.unwrap();
+        }
+        let (_, results) = TokioScope::scope_and_block(|scope| {
+            scope.spawn(main_search);
+            for (idx, (mut root, mut path)) in helper_roots.into_iter().enumerate() {
+                scope.spawn(async move {
+                    let killers = KillerMoves::default();
+                    let seldepth = AtomicU8::new(0);
+                    let context = SearchContext::new(engine, &killers, board_count, &seldepth);
+                    let helper_depth = AtomicU8::new(0);
+                    let start_depth = 1 + (idx % 2) as u8;
+                    iterative_deepening(
+                        &mut root,
+                        &mut path,
+                        start_depth,
+                        &context,
+                        &helper_depth,
+                        &engine.strength,
+                        None,
+                    )
+                    .await;
+                    board_count.flush();
+                    None
+                });
+            }
+        });
+        results
+            .into_iter()
+            .find_map(|result| result.ok().flatten())
+            .unwrap_or((None, Score::DRAW))
+    }
+}
+
+/// Iterative deepening driver of the multi principal variation search
+pub(crate) struct MultiPvEngine<'a>(
+    pub(crate) Engine,
+    pub(crate) &'a mut BoardContinuation,
+    pub(crate) Vec<u64>,
+    pub(crate) usize,
+);
+
+#[async_trait]
+impl<'a> DepthsBoardCountMaintenance<Vec<PvLine>> for MultiPvEngine<'a> {
+    async fn best_move_for(
+        mut self,
+        board_count: &FlushingCounterU32,
+        depth: &AtomicU8,
+    ) -> Vec<PvLine> {
+        let seldepth = AtomicU8::new(0);
+        let context = SearchContext::new(&self.0, &self.0.killers, board_count, &seldepth);
+        let mut best_lines = Vec::new();
+        let mut depth_allowed = 1;
+        while self.0.exploration_allowed.load(Relaxed)
+            && depth_allowed < u8::MAX
+            && (best_lines.is_empty() || self.0.strength.allows_depth(depth_allowed))
+        {
+            let lines = multi_pv_search(self.1, &mut self.2, depth_allowed, self.3, &context).await;
+            if self.0.exploration_allowed.load(Relaxed) || best_lines.is_empty() {
+                depth.store(depth_allowed, Relaxed);
+                best_lines = lines;
+            }
+            if best_lines.is_empty() {
+                break; // The game has ended
+            }
+            depth_allowed += 1;
+        }
+        best_lines
+    }
+}
+
+/// Iterative deepening driver checking a candidate root move against the best move of each
+/// iteration. The best move is searched as usual, then the candidate only with a null window,
+/// which mostly hits the transposition table entries left by the first search.
+pub(crate) struct VerifyEngine<'a>(
+    pub(crate) Engine,
+    pub(crate) &'a mut BoardContinuation,
+    pub(crate) Vec<u64>,
+    pub(crate) PossibleMove,
+);
+
+#[async_trait]
+impl<'a> DepthsBoardCountMaintenance<Option<MoveVerdict>> for VerifyEngine<'a> {
+    async fn best_move_for(
+        mut self,
+        board_count: &FlushingCounterU32,
+        depth: &AtomicU8,
+    ) -> Option<MoveVerdict> {
+        let seldepth = AtomicU8::new(0);
+        let context = SearchContext::new(&self.0, &self.0.killers, board_count, &seldepth);
+        let mate_multiplier = self.1.who_moves.mate_multiplier();
+        let mut verdict = None;
+        let mut expected = None;
+        let mut depth_allowed = 1;
+        while self.0.exploration_allowed.load(Relaxed)
+            && depth_allowed < u8::MAX
+            && (verdict.is_none() || self.0.strength.allows_depth(depth_allowed))
+        {
+            let (best_move, best_score) =
+                aspiration_search(self.1, &mut self.2, depth_allowed, expected, &context).await;
+            let candidate_score = if best_move == Some(self.3) {
+                best_score
+            } else {
+                verify_candidate(
+                    self.1,
+                    &mut self.2,
+                    depth_allowed,
+                    &self.3,
+                    best_score,
+                    &context,
+                )
+                .await
+            };
+            if self.0.exploration_allowed.load(Relaxed) || verdict.is_none() {
+                depth.store(depth_allowed, Relaxed);
+                expected = Some(best_score);
+                verdict = Some(MoveVerdict {
+                    holds: candidate_score >= best_score,
+                    best_move,
+                    best_score: mate_multiplier * best_score,
+                    candidate_bound: mate_multiplier * candidate_score,
+                    depth: depth_allowed,
+                });
+            }
+            if best_move.is_none() {
+                break; // Nothing to compare the candidate to
+            }
+            depth_allowed += 1;
+        }
+        verdict
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::baserules::board::PSBoard;
+    use crate::baserules::board_rep::PossibleMove;
+    use crate::baserules::evaluation::Imbalance;
+    use crate::baserules::move_list::MoveList;
+    use crate::baserules::piece_color::PieceColor::White;
+    use crate::baserules::score::Score;
+    use crate::engine::alphabeta::{
+        aspiration_search, multi_pv_search, principal_variation_search, SearchContext,
+    };
+    use crate::engine::continuation::BoardContinuation;
+    use crate::engine::killers::KillerMoves;
+    use crate::engine::quiescence::quiescence_score;
+    use crate::engine::Engine;
+    use futures_util::future::BoxFuture;
+    use futures_util::FutureExt;
+    use global_counter::primitive::fast::FlushingCounterU32;
+    use std::sync::atomic::AtomicU8;
+    use std::time::Duration;
+    use tokio::test;
+
+    /// What the search contexts of the tests borrow, with a new engine's defaults
+    struct TestSearch {
+        engine: Engine,
+        killers: KillerMoves,
+        counter: FlushingCounterU32,
+        seldepth: AtomicU8,
+    }
+
+    impl TestSearch {
+        fn new() -> Self {
+            Self {
+                engine: Engine::new().0,
+                killers: KillerMoves::default(),
+                counter: FlushingCounterU32::new(0),
+                seldepth: AtomicU8::new(0),
+            }
+        }
+
+        fn context(&self) -> SearchContext<'_> {
+            SearchContext::new(&self.engine, &self.killers, &self.counter, &self.seldepth)
+        }
+    }
+
+    /// Plain negamax without pruning, the principal variation search must agree with it
+    fn negamax(board: &PSBoard, depth: u8) -> BoxFuture<'_, Score> {
+        async move {
+            let mate_multiplier = board.who_moves.mate_multiplier();
+            if board.score.is_mate() {
+                return mate_multiplier * board.score;
+            }
+            if depth == 0 {
+                return mate_multiplier * quiescence_score(board, &Imbalance).await;
+            }
+            let mut moves = MoveList::new();
+            board.gen_potential_moves(&mut moves);
+            let mut best = Score::NEG_INFINITY;
+            for a_move in &moves {
+                let next_board = board.make_move_noncached(a_move).await;
+                best = best.max(-negamax(&next_board, depth - 1).await.backed_up());
+            }
+            best
+        }
+        .boxed()
+    }
+
+    #[test]
+    async fn agrees_with_negamax() {
+        let fen = "r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 4 4";
+        let board = PSBoard::from_fen(fen).await.unwrap();
+        let expected = negamax(&board, 2).await;
+        let search = TestSearch::new();
+        let context = search.context();
+        let mut continuation = BoardContinuation::new(board);
+        let (best_move, score) = principal_variation_search(
+            &mut continuation,
+            &mut Vec::new(),
+            2,
+            0,
+            Score::NEG_INFINITY,
+            Score::INFINITY,
+            &context,
+        )
+        .await;
+        assert_eq!(expected, score);
+        // Scholar's mate
+        assert_eq!("h5f7", best_move.unwrap().to_string());
+        assert_eq!(Some(score), continuation.adjusted_score);
+        assert!(continuation.is_trusted(2, 0));
+    }
+
+    #[test(flavor = "multi_thread")]
+    async fn iterative_deepening_within_deadline() {
+        let (engine, mut state) =
+            Engine::from_fen("r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 4 4")
+                .await;
+        let (best_move, score, _, depth) = engine
+            .best_move_pvs(&mut state, &Duration::from_millis(500))
+            .await;
+        assert_eq!("h5f7", best_move.unwrap().to_string());
+        assert!(score.is_mate());
+        assert!(depth >= 1);
+    }
+
+    #[test(flavor = "multi_thread")]
+    async fn lazy_smp_threads_find_the_mate() {
+        let (mut engine, mut state) =
+            Engine::from_fen("r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 4 4")
+                .await;
+        engine.set_threads(4);
+        let (best_move, score, boards, depth) = engine
+            .best_move_pvs(&mut state, &Duration::from_millis(500))
+            .await;
+        assert_eq!("h5f7", best_move.unwrap().to_string());
+        assert!(score.is_mate());
+        assert!(boards > 0);
+        assert!(depth >= 1);
+    }
+
+    #[test(flavor = "multi_thread")]
+    async fn candidates_are_verified_against_the_best_move() {
+        let (engine, mut state) =
+            Engine::from_fen("r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 4 4")
+                .await;
+        let mut moves = MoveList::new();
+        state.psboard().gen_potential_moves(&mut moves);
+        let find = |uci: &str| {
+            *moves
+                .iter()
+                .find(|a_move| a_move.to_string() == uci)
+                .unwrap()
+        };
+        let verdict = engine
+            .verify_move(&mut state, &find("h5f7"), &Duration::from_millis(300))
+            .await
+            .unwrap();
+        assert!(verdict.holds);
+        assert!(verdict.best_score.is_mate());
+        assert!(verdict.depth >= 1);
+
+        let verdict = engine
+            .verify_move(&mut state, &find("a2a3"), &Duration::from_millis(300))
+            .await
+            .unwrap();
+        assert!(!verdict.holds);
+        assert_eq!("h5f7", verdict.best_move.unwrap().to_string());
+        assert!(verdict.candidate_bound < verdict.best_score);
+
+        // The pawn has left e2 already
+        let mut start_moves = MoveList::new();
+        PSBoard::default().gen_potential_moves(&mut start_moves);
+        let gone = start_moves
+            .into_iter()
+            .find(|a_move| a_move.to_string() == "e2e4")
+            .unwrap();
+        assert!(engine
+            .verify_move(&mut state, &gone, &Duration::from_millis(300))
+            .await
+            .is_err());
+    }
+
+    #[test]
+    async fn aspiration_recovers_from_wrong_guess() {
+        let fen = "r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5Q2/PPPP1PPP/RNB1K1NR w KQkq - 4 4";
+        let board = PSBoard::from_fen(fen).await.unwrap();
+        let expected = negamax(&board, 2).await;
+        let search = TestSearch::new();
+        let context = search.context();
+        let off = Score::centipawns(300);
+        for guess in [expected, expected - off, expected + off] {
+            let mut continuation = BoardContinuation::new(PSBoard::from_fen(fen).await.unwrap());
+            let (_, score) =
+                aspiration_search(&mut continuation, &mut Vec::new(), 2, Some(guess), &context)
+                    .await;
+            assert_eq!(expected, score);
+        }
+    }
+
+    #[test(flavor = "multi_thread")]
+    async fn engine_reports_multiple_lines() {
+        let (engine, mut state) = Engine::new();
+        let (lines, _, depth) = engine
+            .best_lines_pvs(&mut state, &Duration::from_millis(300), 4)
+            .await;
+        assert_eq!(4, lines.len());
+        assert!(depth >= 1);
+        assert!(lines.windows(2).all(|pair| pair[0].score >= pair[1].score));
+    }
+
+    #[test]
+    async fn multi_pv_lists_best_moves_in_order() {
+        let fen = "r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 4 4";
+        let board = PSBoard::from_fen(fen).await.unwrap();
+        let mut expected = Vec::new();
+        let mut moves = MoveList::new();
+        board.gen_potential_moves(&mut moves);
+        for a_move in &moves {
+            let next_board = board.make_move_noncached(a_move).await;
+            expected.push(-negamax(&next_board, 1).await.backed_up());
+        }
+        expected.sort_by(|a, b| b.cmp(a));
+
+        let search = TestSearch::new();
+        let context = search.context();
+        let mut continuation = BoardContinuation::new(board);
+        let lines = multi_pv_search(&mut continuation, &mut Vec::new(), 2, 3, &context).await;
+        assert_eq!(3, lines.len());
+        assert_eq!("h5f7", lines[0].line[0].to_string());
+        // white moves, so the best lines have the highest scores
+        assert_eq!(
+            expected[..3].to_vec(),
+            lines.iter().map(|l| l.score).collect::<Vec<_>>()
+        );
+        assert_eq!(2, lines[1].line.len());
+    }
+
+    #[test]
+    async fn multi_pv_scores_agree_with_negamax() {
+        let fen = "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3";
+        let board = PSBoard::from_fen(fen).await.unwrap();
+        let mut expected = Vec::new();
+        let mut moves = MoveList::new();
+        board.gen_potential_moves(&mut moves);
+        for a_move in &moves {
+            let next_board = board.make_move_noncached(a_move).await;
+            expected.push((*a_move, -negamax(&next_board, 2).await.backed_up()));
+        }
+
+        let search = TestSearch::new();
+        let context = search.context();
+        let mut continuation = BoardContinuation::new(board);
+        // Fewer lines than moves, so the later moves have to beat the null window first
+        let lines = multi_pv_search(&mut continuation, &mut Vec::new(), 3, 5, &context).await;
+        assert_eq!(5, lines.len());
+        // white moves, so the white-relative scores are the negamax ones
+        for line in &lines {
+            let (_, score) = expected
+                .iter()
+                .find(|(a_move, _)| *a_move == line.line[0])
+                .unwrap();
+            assert_eq!(*score, line.score, "{}", line.line[0]);
+        }
+        let mut best_scores: Vec<Score> = expected.iter().map(|(_, score)| *score).collect();
+        best_scores.sort_by(|a, b| b.cmp(a));
+        assert_eq!(
+            best_scores[..5].to_vec(),
+            lines.iter().map(|l| l.score).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    async fn repeating_a_position_of_the_path_is_a_draw() {
+        // Black is a queen down, but can take back its knight move
+        let mut board = PSBoard::from_fen("4k1n1/8/8/8/8/8/8/1Q2K1N1 w - - 0 1")
+            .await
+            .unwrap();
+        let mut path = Vec::new();
+        for a_move in ["g1f3", "g8f6", "f3g1"] {
+            path.push(board.zobrist());
+            board = board
+                .make_move_noncached(&PossibleMove::simple_from_uci(a_move).unwrap())
+                .await;
+        }
+        let search = TestSearch::new();
+        let context = search.context();
+        let mut continuation = BoardContinuation::new(board);
+        let (best_move, score) = principal_variation_search(
+            &mut continuation,
+            &mut path,
+            2,
+            0,
+            Score::NEG_INFINITY,
+            Score::INFINITY,
+            &context,
+        )
+        .await;
+        let back = PossibleMove::simple_from_uci("f6g8").unwrap();
+        assert_eq!(Some(back), best_move);
+        assert_eq!(Score::DRAW, score);
+        assert!(
+            continuation
+                .find_continuation(&back)
+                .unwrap()
+                .potential_draw
+        );
+        assert_eq!(3, path.len());
+    }
+
+    #[test]
+    async fn stalemates_are_draws_unlike_checkmates() {
+        let search = TestSearch::new();
+        let context = search.context();
+        let search = |fen: &'static str, depth| {
+            let context = &context;
+            async move {
+                let board = PSBoard::from_fen(fen).await.unwrap();
+                let mut continuation = BoardContinuation::new(board);
+                let result = principal_variation_search(
+                    &mut continuation,
+                    &mut Vec::new(),
+                    depth,
+                    0,
+                    Score::NEG_INFINITY,
+                    Score::INFINITY,
+                    context,
+                )
+                .await;
+                (result, continuation)
+            }
+        };
+        let (stalemate, stalemated) = search("k7/2Q5/1K6/8/8/8/8/8 b - - 0 1", 2).await;
+        assert_eq!((None, Score::DRAW), stalemate);
+        assert!(stalemated.potential_draw);
+        let (checkmate, _) = search("k7/1Q6/1K6/8/8/8/8/8 b - - 0 1", 2).await;
+        assert_eq!((None, -Score::mate(White, 2)), checkmate);
+        // Qc7 would stalemate the king
+        let (best_move, score) = search("k7/8/1K6/8/8/8/8/2Q5 w - - 0 1", 3).await.0;
+        assert_eq!(PossibleMove::simple_from_uci("c1c8").ok(), best_move);
+        assert_eq!(Score::mate(White, 3), score);
+    }
+}
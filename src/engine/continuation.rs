@@ -1,19 +1,120 @@
 use crate::baserules::board::PSBoard;
-use crate::baserules::board_rep::PossibleMove;
-use rand::{thread_rng, Rng};
+use crate::baserules::board_rep::{PackedMove, PossibleMove};
+use crate::baserules::evaluation::Evaluator;
+use crate::baserules::move_list::MoveList;
+use crate::baserules::score::Score;
+use crate::engine::RootMoveStats;
+use crate::util::{FileFormat, IntResult};
+use futures_util::future::BoxFuture;
+use futures_util::FutureExt;
+use rand::distributions::WeightedIndex;
+use rand::prelude::Distribution;
+use rand::Rng;
+use serde_json::{json, Value};
 
-use generational_arena::Arena;
+use generational_arena::{Arena, Index};
 use global_counter::primitive::fast::FlushingCounterU32;
 use itertools::Itertools;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::mem::size_of;
 use std::{ops::Deref, sync::Arc};
 
+/// Boards scored closer than this to the best one are chosen from randomly
+pub const SIMILAR_SCORE_DIFFERENCE: Score = Score::centipawns(5);
+
+/// The files written by `BoardContinuation::to_bytes`
+const TREE_FORMAT: FileFormat = FileFormat {
+    name: "a continuation tree",
+    magic: b"DBCETREE",
+    version: 1,
+};
+/// The trees written before the versions continue with the length of their fen, which is never
+/// this short
+const SHORTEST_FEN: u16 = 16;
+/// Flags, score, searched depth and the number of continuations
+const NODE_HEADER_SIZE: usize = 1 + 4 + 1 + 2;
+const HAS_SCORE_FLAG: u8 = 1;
+const POTENTIAL_DRAW_FLAG: u8 = 2;
+
 #[derive(Clone)]
 pub struct BoardContinuation {
     pub board: Arc<PSBoard>,
-    /// The overall expected score of this board after considering the continuations
-    pub adjusted_score: f32,
+    /// The overall expected score of this board after considering the continuations, if they
+    /// have been considered
+    pub adjusted_score: Option<Score>,
+    /// The position already occurred earlier on the game path, so it is scored as a draw
+    pub potential_draw: bool,
+    /// The number of plies searched below this board when its adjusted score was calculated
+    pub searched_depth: u8,
+    /// The score generation of the engine the adjusted score was calculated in, the scores of
+    /// earlier generations are no longer trusted
+    pub generation: u32,
     /// If we have calculated a few positions ahead from this board, we store these positions here
-    continuation: Arena<(PossibleMove, Self)>,
+    continuation: Continuations,
+}
+
+/// The boards following a board in the order they were explored, indexed by the move leading to
+/// them so they are found without scanning through all of them. The index keeps the moves packed,
+/// hashing and comparing them as 16 bit numbers.
+#[derive(Clone, Default)]
+struct Continuations {
+    boards: Arena<(PossibleMove, BoardContinuation)>,
+    index: HashMap<PackedMove, Index>,
+}
+
+impl Continuations {
+    /// Replaces the board stored for the move earlier, if there is one
+    fn insert(&mut self, the_move: PossibleMove, board: BoardContinuation) {
+        let index = self.boards.insert((the_move, board));
+        if let Some(replaced) = self.index.insert(PackedMove::from(&the_move), index) {
+            self.boards.remove(replaced);
+        }
+    }
+
+    #[inline]
+    fn contains(&self, the_move: &PossibleMove) -> bool {
+        self.index.contains_key(&PackedMove::from(the_move))
+    }
+
+    fn get(&self, the_move: &PossibleMove) -> Option<&BoardContinuation> {
+        let index = *self.index.get(&PackedMove::from(the_move))?;
+        self.boards.get(index).map(|(_, board)| board)
+    }
+
+    fn get_mut(&mut self, the_move: &PossibleMove) -> Option<&mut BoardContinuation> {
+        let index = *self.index.get(&PackedMove::from(the_move))?;
+        self.boards.get_mut(index).map(|(_, board)| board)
+    }
+
+    fn remove(&mut self, the_move: &PossibleMove) -> Option<BoardContinuation> {
+        let index = self.index.remove(&PackedMove::from(the_move))?;
+        self.boards.remove(index).map(|(_, board)| board)
+    }
+
+    fn iter(&self) -> impl Iterator<Item = &(PossibleMove, BoardContinuation)> {
+        self.boards.iter().map(|(_, entry)| entry)
+    }
+
+    fn values_mut(&mut self) -> impl Iterator<Item = &mut BoardContinuation> {
+        self.boards.iter_mut().map(|(_, (_, board))| board)
+    }
+
+    fn drain(&mut self) -> impl Iterator<Item = (PossibleMove, BoardContinuation)> + '_ {
+        self.index.clear();
+        self.boards.drain().map(|(_, entry)| entry)
+    }
+
+    fn clear(&mut self) {
+        self.index.clear();
+        self.boards.clear();
+    }
+
+    #[inline]
+    fn len(&self) -> usize {
+        self.boards.len()
+    }
 }
 
 impl Default for BoardContinuation {
@@ -53,11 +154,16 @@ where
 }
 
 impl BoardContinuation {
+    /// Boards drawn by the fifty-move rule start out as draws, like the repeated positions
     pub fn new(board: PSBoard) -> Self {
+        let fifty_move_draw = board.is_fifty_move_draw();
         Self {
             board: Arc::new(board),
-            adjusted_score: f32::NAN,
-            continuation: Arena::new(),
+            adjusted_score: fifty_move_draw.then_some(Score::DRAW),
+            potential_draw: fifty_move_draw,
+            searched_depth: 0,
+            generation: 0,
+            continuation: Continuations::default(),
         }
     }
 
@@ -70,118 +176,434 @@ impl BoardContinuation {
         }
     }
 
+    /// Re-roots the tree at the board after our move and the opponent's reply, keeping whatever
+    /// has been explored below it. Everything else is dropped with the old root, so the memory of
+    /// the lines not played is freed.
+    pub async fn advance_root(self, our_move: &PossibleMove, their_move: &PossibleMove) -> Self {
+        self.make_cached_move(our_move)
+            .await
+            .make_cached_move(their_move)
+            .await
+    }
+
+    /// The continuation after the move, the board is created and scored with the evaluator if
+    /// it is not in the tree yet
     pub async fn lookup_continuation_or_create<'a>(
         &'a mut self,
         amove: &PossibleMove,
         counter: &FlushingCounterU32,
+        evaluator: &dyn Evaluator,
     ) -> &'a mut Self {
         if self.continuation_exists(amove) {
             self.find_continuation_mut(amove).unwrap()
         } else {
             counter.inc();
-            let psb = self.make_move_noncached(amove).await;
+            let psb = self.make_move_with(amove, evaluator).await;
             self.insert_psboard(amove, psb);
             self.find_continuation_mut(amove).unwrap()
         }
     }
 
     pub fn continuation_exists(&self, the_move: &PossibleMove) -> bool {
-        self.keys().any(|possible_move| possible_move == the_move)
+        self.continuation.contains(the_move)
     }
 
     pub fn insert_psboard(&mut self, the_move: &PossibleMove, board: PSBoard) {
-        self.continuation.insert((*the_move, Self::new(board)));
+        self.continuation.insert(*the_move, Self::new(board));
     }
 
     pub fn find_continuation_remove(&mut self, the_move: &PossibleMove) -> Option<Self> {
-        let index_opt =
-            self.continuation.iter().find_map(
-                |(index, (amove, _))| {
-                    if amove == the_move {
-                        Some(index)
-                    } else {
-                        None
-                    }
-                },
-            );
-        index_opt.map(|index| self.continuation.remove(index).unwrap().1)
+        self.continuation.remove(the_move)
     }
 
     pub fn find_continuation(&self, the_move: &PossibleMove) -> Option<&Self> {
-        self.iter().find_map(|(possible_move, continuation)| {
-            if possible_move == the_move {
-                Some(continuation)
-            } else {
-                None
-            }
-        })
+        self.continuation.get(the_move)
     }
 
     pub fn find_continuation_mut(&mut self, the_move: &PossibleMove) -> Option<&mut Self> {
-        self.continuation
-            .iter_mut()
-            .find_map(|(_, (possible_move, continuation))| {
-                if possible_move == the_move {
-                    Some(continuation)
-                } else {
-                    None
-                }
-            })
+        self.continuation.get_mut(the_move)
+    }
+
+    /// Follows the moves from this board, if all of them have been explored
+    pub fn lookup_line(&self, moves: &[PossibleMove]) -> Option<&Self> {
+        moves
+            .iter()
+            .try_fold(self, |board, a_move| board.find_continuation(a_move))
+    }
+
+    pub fn lookup_line_mut(&mut self, moves: &[PossibleMove]) -> Option<&mut Self> {
+        moves
+            .iter()
+            .try_fold(self, |board, a_move| board.find_continuation_mut(a_move))
     }
 
     pub fn values(&self) -> impl Iterator<Item = &Self> {
-        self.continuation
+        self.iter().map(|(_, continutation)| continutation)
+    }
+
+    pub fn values_mut(&mut self) -> impl Iterator<Item = &mut Self> {
+        self.continuation.values_mut()
+    }
+
+    /// Drops the continuations rejected by the predicate, with the boards explored after them
+    pub fn retain_continuations(&mut self, mut keep: impl FnMut(&PossibleMove, &Self) -> bool) {
+        let dropped: Vec<PossibleMove> = self
             .iter()
-            .map(|(_, (_, continutation))| continutation)
+            .filter(|(a_move, next_board)| !keep(a_move, next_board))
+            .map(|(a_move, _)| *a_move)
+            .collect();
+        for a_move in dropped {
+            self.continuation.remove(&a_move);
+        }
     }
 
     pub fn keys(&self) -> impl Iterator<Item = &PossibleMove> {
-        self.continuation
-            .iter()
-            .map(|(_, (posssible_move, _))| posssible_move)
+        self.iter().map(|(posssible_move, _)| posssible_move)
+    }
+
+    /// Tells if the adjusted score was calculated in the given (current) generation with at least the given search depth
+    pub fn is_trusted(&self, required_depth: u8, generation: u32) -> bool {
+        self.adjusted_score.is_some()
+            && self.generation == generation
+            && self.searched_depth >= required_depth
+    }
+
+    /// Records that the adjusted score was just calculated in the generation with the given search depth
+    pub fn stamp_score(&mut self, searched_depth: u8, generation: u32) {
+        self.searched_depth = searched_depth;
+        self.generation = generation;
+    }
+
+    /// Tells if the adjusted score of this board is preferable to the other's: newer generations
+    /// win, then the deeper searches
+    fn better_scored_than(&self, other: &Self) -> bool {
+        self.adjusted_score.is_some()
+            && (other.adjusted_score.is_none()
+                || (self.generation, self.searched_depth)
+                    > (other.generation, other.searched_depth))
     }
 
     pub fn merge(&mut self, mut to_merge: Self) {
+        if to_merge.better_scored_than(self) {
+            self.adjusted_score = to_merge.adjusted_score;
+            self.searched_depth = to_merge.searched_depth;
+            self.generation = to_merge.generation;
+        }
         to_merge
             .continuation
             .drain()
-            .for_each(|(_, (amove, sub_continuation))| {
-                if let Some(found_in_self) = self.find_continuation_mut(&amove) {
-                    found_in_self.merge(sub_continuation);
-                } else {
-                    self.continuation.insert((amove, sub_continuation));
-                }
+            .for_each(|(amove, sub_continuation)| {
+                self.merge_continuation(amove, sub_continuation);
             });
     }
 
+    /// Merges the board into the continuation of the move, which is added if it was not explored
+    pub fn merge_continuation(&mut self, the_move: PossibleMove, to_merge: Self) {
+        if let Some(found_in_self) = self.find_continuation_mut(&the_move) {
+            found_in_self.merge(to_merge);
+        } else {
+            self.continuation.insert(the_move, to_merge);
+        }
+    }
+
+    /// Flags the cached positions that already occurred on the way to them, either in the game
+    /// or higher up in the tree. Their stale analysis is dropped and they are scored as draws.
+    pub fn mark_repetitions(&mut self, game_path: &mut Vec<u64>) {
+        game_path.push(self.zobrist());
+        for next_board in self.continuation.values_mut() {
+            if game_path.contains(&next_board.zobrist()) {
+                next_board.mark_repetition();
+            } else {
+                next_board.mark_repetitions(game_path);
+            }
+        }
+        game_path.pop();
+    }
+
+    /// Scores the board as a draw for good, as its position already occurred on the way to it.
+    /// Repeating it once is enough, the side repeating it could repeat it again.
+    pub fn mark_repetition(&mut self) {
+        self.mark_draw();
+    }
+
+    /// Scores the board as a draw for good, its continuations are not needed any more
+    pub fn mark_draw(&mut self) {
+        self.potential_draw = true;
+        self.adjusted_score = Some(Score::DRAW);
+        self.continuation.clear();
+    }
+
+    /// Scores the board for good if its side to move has no legal move: checkmated when in check,
+    /// a draw by stalemate otherwise. The pseudo-legal moves of both lose the king, so it is only
+    /// worth asking when all the moves seem to lose. Returns whether the board was terminal.
+    /// Mates are stamped with the given score generation.
+    pub async fn mark_if_terminal(&mut self, generation: u32) -> bool {
+        if self.has_legal_move() {
+            return false;
+        }
+        if self.is_in_check() {
+            // Any move lets the king be captured on the next ply
+            self.adjusted_score = Some(Score::mate(self.who_moves.invert(), 2));
+            self.stamp_score(u8::MAX, generation);
+        } else {
+            self.mark_draw();
+        }
+        true
+    }
+
     pub fn iter(&self) -> impl Iterator<Item = &(PossibleMove, Self)> {
-        self.continuation.iter().map(|(_, tuple)| tuple)
+        self.continuation.iter()
     }
 
     pub fn similar_quality_moves<'a, F>(
         &'a self,
         best_board: &'a Self,
         score_query: F,
+        within: Score,
     ) -> impl Iterator<Item = &'a Self>
     where
-        F: Fn(&Self) -> f32,
+        F: Fn(&Self) -> Score,
     {
         let bb_score = score_query(best_board);
 
-        self.values().filter(move |other| {
-            let other_score = score_query(other);
-            (other_score.max(bb_score) - other_score.min(bb_score)) < 0.05
-        })
+        self.values()
+            .filter(move |other| (score_query(other) - bb_score).abs() < within)
     }
 
-    pub fn select_similar_board<'a, F>(&'a self, best_board: &'a Self, score_query: F) -> &'a Self
+    /// Picks one of the boards scored within the given window of the best one. Without a
+    /// temperature all of them are equally likely, otherwise they are weighted by the softmax
+    /// of their scores: a board scored a temperature worse than the best one is e times less
+    /// likely to be picked. Lower temperatures play sharper, higher ones more varied moves.
+    /// The choice is made with the given random generator, so a seeded one repeats it.
+    /// Returns none when no board is scored within the window, e.g. when there are no
+    /// continuations or the best board given is not one of them.
+    pub fn select_similar_board<'a, F>(
+        &'a self,
+        best_board: &'a Self,
+        score_query: F,
+        within: Score,
+        temperature: Option<Score>,
+        rng: &mut impl Rng,
+    ) -> Option<&'a Self>
     where
-        F: Fn(&Self) -> f32,
+        F: Fn(&Self) -> Score,
     {
-        let choices = self.similar_quality_moves(best_board, &score_query).count();
-        self.similar_quality_moves(best_board, &score_query)
-            .nth(thread_rng().gen_range(0..choices))
-            .unwrap()
+        let choices: Vec<&Self> = self
+            .similar_quality_moves(best_board, &score_query, within)
+            .collect();
+        if choices.is_empty() {
+            return None;
+        }
+        let chosen = match temperature {
+            None => rng.gen_range(0..choices.len()),
+            Some(temperature) => {
+                let bb_score = score_query(best_board);
+                let temperature = f64::from(temperature.as_centipawns().max(1));
+                let weights = choices.iter().map(|board| {
+                    let loss = (score_query(board) - bb_score).abs().as_centipawns();
+                    (-f64::from(loss) / temperature).exp()
+                });
+                WeightedIndex::new(weights).map_or(0, |distribution| distribution.sample(rng))
+            }
+        };
+        choices.get(chosen).copied()
+    }
+
+    /// Recomputes the adjusted scores of the boards with continuations bottom-up with minimax:
+    /// each of them gets the score of its best continuation for its side to move. The leaves
+    /// keep their own scores, adjusted or static, boards repeating a position stay draws.
+    /// Useful after expanding or merging trees outside of the searches. The scores are stamped
+    /// with the given score generation. Returns the score of this board.
+    pub fn backup_scores(&mut self, generation: u32) -> Score {
+        if self.potential_draw || self.continuation.len() == 0 {
+            return self.score();
+        }
+        let mate_multiplier = self.who_moves.mate_multiplier();
+        let mut best = None;
+        let mut shallowest = u8::MAX;
+        for next_board in self.continuation.values_mut() {
+            let score = next_board.backup_scores(generation).backed_up();
+            if best.is_none_or(|best| mate_multiplier * score > mate_multiplier * best) {
+                best = Some(score);
+            }
+            let depth = next_board
+                .adjusted_score
+                .map_or(0, |_| next_board.searched_depth);
+            shallowest = shallowest.min(depth);
+        }
+        self.adjusted_score = best;
+        self.stamp_score(shallowest.saturating_add(1), generation);
+        self.score()
+    }
+
+    /// Follows the best scored continuations for the side to move at each step, until the
+    /// explored part of the tree ends. Unexplored boards count with their own score.
+    pub fn principal_variation(&self) -> Vec<PossibleMove> {
+        self.principal_variation_up_to(usize::MAX)
+    }
+
+    /// The start of the `principal_variation`, at most the given number of moves
+    pub fn principal_variation_up_to(&self, max_length: usize) -> Vec<PossibleMove> {
+        let mut line = Vec::new();
+        let mut current = self;
+        while line.len() < max_length {
+            let mate_multiplier = current.who_moves.mate_multiplier();
+            let Some((a_move, next_board)) = current
+                .iter()
+                .max_by_key(|(_, next_board)| mate_multiplier * next_board.score())
+            else {
+                break;
+            };
+            line.push(*a_move);
+            current = next_board;
+        }
+        line
+    }
+
+    /// The statistics of the moves searched from this board, the best ones for the side to move
+    /// first
+    pub fn root_move_stats(&self) -> Vec<RootMoveStats> {
+        let mate_multiplier = self.who_moves.mate_multiplier();
+        let mut stats: Vec<RootMoveStats> = self
+            .iter()
+            .map(|(a_move, next_board)| RootMoveStats {
+                the_move: *a_move,
+                nodes: 1 + next_board.total_continuation_boards(),
+                score: next_board.score(),
+                searched_depth: next_board.adjusted_score.map(|_| next_board.searched_depth),
+            })
+            .collect();
+        stats.sort_by_key(|stats| Reverse(mate_multiplier * stats.score));
+        stats
+    }
+
+    /// Drops the least promising boards until at most the given number of boards remain below
+    /// this one. The continuations are kept best first for the side to move, but the boards
+    /// right after this one are kept before anything deeper, so their scores stay known as long
+    /// as the budget allows. Returns the number of boards dropped.
+    pub fn prune_to(&mut self, max_nodes: u32) -> u32 {
+        self.prune_by(max_nodes, &|board, next_board| {
+            board.who_moves.mate_multiplier() * next_board.score()
+        })
+    }
+
+    /// Drops boards like `prune_to`, but the continuations of each board are ranked by the given
+    /// function of the board and the continuation instead of their scores, the highest first
+    pub fn prune_by<K: Ord>(&mut self, max_nodes: u32, rank: &impl Fn(&Self, &Self) -> K) -> u32 {
+        let before = self.total_continuation_boards();
+        if before <= max_nodes {
+            return 0;
+        }
+        let mut ranked: Vec<PossibleMove> = self
+            .iter()
+            .sorted_by_key(|(_, next_board)| Reverse(rank(self, next_board)))
+            .map(|(a_move, _)| *a_move)
+            .collect();
+        let kept = ranked.len().min(max_nodes as usize);
+        for dropped in ranked.drain(kept..) {
+            self.continuation.remove(&dropped);
+        }
+        let mut budget = max_nodes - kept as u32;
+        for a_move in ranked {
+            let next_board = self.continuation.get_mut(&a_move).unwrap();
+            next_board.prune_by(budget, rank);
+            budget -= next_board.total_continuation_boards();
+        }
+        before - self.total_continuation_boards()
+    }
+
+    /// An estimate of the memory taken by this board and the ones explored after it, in bytes
+    pub fn memory_usage(&self) -> usize {
+        let per_board = size_of::<Self>()
+            + size_of::<PSBoard>()
+            + size_of::<(PackedMove, Index)>()
+            + size_of::<(PossibleMove, Self)>();
+        (1 + self.total_continuation_boards() as usize) * per_board
+    }
+
+    /// A compact binary form of the tree: the fen of this board, then the boards in preorder,
+    /// each with its score, its searched depth and the packed moves leading to its continuations.
+    /// Only the moves are stored, the boards are replayed from them when the tree is read.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let fen = self.to_fen();
+        let mut bytes = TREE_FORMAT.header();
+        bytes.extend_from_slice(&(fen.len() as u16).to_le_bytes());
+        bytes.extend_from_slice(fen.as_bytes());
+        self.write_node(&mut bytes);
+        bytes
+    }
+
+    fn write_node(&self, bytes: &mut Vec<u8>) {
+        let mut flags = 0;
+        if self.adjusted_score.is_some() {
+            flags |= HAS_SCORE_FLAG;
+        }
+        if self.potential_draw {
+            flags |= POTENTIAL_DRAW_FLAG;
+        }
+        bytes.push(flags);
+        let score = self.adjusted_score.unwrap_or(Score::DRAW);
+        bytes.extend_from_slice(&score.to_bits().to_le_bytes());
+        bytes.push(self.searched_depth);
+        bytes.extend_from_slice(&(self.continuation.len() as u16).to_le_bytes());
+        for (a_move, next_board) in self.iter() {
+            bytes.extend_from_slice(&PackedMove::from(a_move).0.to_le_bytes());
+            next_board.write_node(bytes);
+        }
+    }
+
+    /// Reads a tree written by `to_bytes` of any version, its scores count as calculated in the
+    /// first generation, the one of the engines whose scores were never invalidated
+    ///
+    /// # Errors
+    /// When the bytes are not a tree, they were written by a newer version, or one of the moves
+    /// of the tree is not possible on its board
+    pub async fn from_bytes(bytes: &[u8]) -> IntResult<Self> {
+        let (_, mut reader) = TREE_FORMAT.read(bytes, |rest| {
+            rest.first_chunk::<2>()
+                .is_some_and(|length| u16::from_le_bytes(*length) >= SHORTEST_FEN)
+        })?;
+        // The trees of version 0 only lack the version, so they need no migration
+        let fen_length = u16::from_le_bytes(take_bytes(&mut reader, 2)?.try_into()?);
+        let fen = std::str::from_utf8(take_bytes(&mut reader, fen_length as usize)?)?;
+        let mut root = Self::new(PSBoard::from_fen(fen).await?);
+        root.read_node(&mut reader).await?;
+        if !reader.is_empty() {
+            return Err("Unexpected bytes after the continuation tree".into());
+        }
+        Ok(root)
+    }
+
+    /// The errors are plain strings, so the future can be sent between threads
+    fn read_node<'a, 'b: 'a>(
+        &'a mut self,
+        reader: &'a mut &'b [u8],
+    ) -> BoxFuture<'a, Result<(), String>> {
+        async move {
+            let header = take_bytes(reader, NODE_HEADER_SIZE).map_err(|error| error.to_string())?;
+            if header[0] & HAS_SCORE_FLAG != 0 {
+                let bits = u32::from_le_bytes([header[1], header[2], header[3], header[4]]);
+                self.adjusted_score = Some(Score::from_bits(bits));
+            }
+            self.potential_draw = header[0] & POTENTIAL_DRAW_FLAG != 0;
+            self.searched_depth = header[5];
+            let continuations = u16::from_le_bytes([header[6], header[7]]);
+            let mut possible_moves = MoveList::new();
+            self.gen_potential_moves(&mut possible_moves);
+            for _ in 0..continuations {
+                let packed = take_bytes(reader, 2).map_err(|error| error.to_string())?;
+                let a_move: PossibleMove =
+                    PackedMove(u16::from_le_bytes([packed[0], packed[1]])).into();
+                if !possible_moves.contains(&a_move) {
+                    return Err(format!("{a_move} is not possible on {}", self.to_fen()));
+                }
+                let mut next_board = Self::new(self.make_move_noncached(&a_move).await);
+                next_board.read_node(reader).await?;
+                self.continuation.insert(a_move, next_board);
+            }
+            Ok(())
+        }
+        .boxed()
     }
 
     pub fn total_continuation_boards(&self) -> u32 {
@@ -210,7 +632,9 @@ impl BoardContinuation {
                     "{prefix}{:depth$}{a_move} ({}/{}) - {} \n{}",
                     "",
                     its_board.score,
-                    its_board.adjusted_score,
+                    its_board
+                        .adjusted_score
+                        .map_or_else(|| "-".to_string(), |score| score.to_string()),
                     its_board.to_fen(),
                     its_board.internal_visualise(prefix, next_depth)
                 )
@@ -218,28 +642,110 @@ impl BoardContinuation {
             .join("")
     }
 
-    pub fn score(&self) -> f32 {
-        if self.adjusted_score.is_nan() {
-            self.score
-        } else {
+    /// The explored tree in Graphviz dot format, down to the given number of plies below this
+    /// board. The edges are labelled with the moves, the boards with their static and adjusted
+    /// scores and the depth searched below them. The root board is labelled with its fen too.
+    pub fn to_dot(&self, max_depth: usize) -> String {
+        let mut dot = String::from("digraph explored {\n    node [shape=box];\n");
+        let _ = writeln!(
+            dot,
+            "    n0 [label=\"{}\\n{}\"];",
+            self.to_fen(),
+            self.dot_label()
+        );
+        let mut next_id = 1;
+        self.write_dot_edges(0, max_depth, &mut next_id, &mut dot);
+        dot.push_str("}\n");
+        dot
+    }
+
+    fn dot_label(&self) -> String {
+        format!(
+            "{}/{} d{}{}",
+            self.score,
             self.adjusted_score
+                .map_or_else(|| "-".to_string(), |score| score.to_string()),
+            self.searched_depth,
+            if self.potential_draw { " draw" } else { "" }
+        )
+    }
+
+    fn write_dot_edges(&self, id: usize, depth_left: usize, next_id: &mut usize, dot: &mut String) {
+        if depth_left == 0 {
+            return;
+        }
+        for (a_move, its_board) in self.iter() {
+            let child_id = *next_id;
+            *next_id += 1;
+            let _ = writeln!(
+                dot,
+                "    n{child_id} [label=\"{}\"];\n    n{id} -> n{child_id} [label=\"{a_move}\"];",
+                its_board.dot_label()
+            );
+            its_board.write_dot_edges(child_id, depth_left - 1, next_id, dot);
         }
     }
+
+    /// The explored tree as nested json objects of `{move, score, depth, children}`, the move
+    /// of the root is null and its fen is added. Scores are white's, either `{"cp": centipawns}`
+    /// or `{"mate": moves}` with a negative number of moves when black mates.
+    pub fn to_json(&self) -> Value {
+        let mut root = self.json_node(None);
+        root["fen"] = json!(self.to_fen());
+        root
+    }
+
+    fn json_node(&self, a_move: Option<&PossibleMove>) -> Value {
+        let score = self.score();
+        json!({
+            "move": a_move.map(|a_move| a_move.to_string()),
+            "score": match score.mate_in() {
+                Some(moves) => json!({ "mate": moves }),
+                None => json!({ "cp": score.as_centipawns() }),
+            },
+            "depth": self.searched_depth,
+            "children": self
+                .iter()
+                .map(|(a_move, its_board)| its_board.json_node(Some(a_move)))
+                .collect::<Vec<_>>(),
+        })
+    }
+
+    pub fn score(&self) -> Score {
+        self.adjusted_score.unwrap_or(self.score)
+    }
+}
+
+/// Splits the given number of bytes off the front of the reader
+fn take_bytes<'a>(reader: &mut &'a [u8], count: usize) -> IntResult<&'a [u8]> {
+    let (taken, rest) = reader
+        .split_at_checked(count)
+        .ok_or("The continuation tree ends unexpectedly")?;
+    *reader = rest;
+    Ok(taken)
 }
 
 #[cfg(test)]
 mod test {
     use crate::baserules::board::PSBoard;
     use crate::baserules::board_rep::PossibleMove;
-    use crate::engine::continuation::BoardContinuation;
-    use generational_arena::Arena;
+    use crate::baserules::piece_color::PieceColor::Black;
+    use crate::baserules::score::Score;
+    use crate::engine::continuation::{BoardContinuation, Continuations};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use serde_json::json;
+    use std::ptr;
     use std::sync::Arc;
 
     async fn create_simple_cont() -> BoardContinuation {
         let mut first = BoardContinuation {
             board: Arc::new(PSBoard::default()),
-            adjusted_score: f32::NAN,
-            continuation: Arena::new(),
+            adjusted_score: None,
+            potential_draw: false,
+            searched_depth: 0,
+            generation: 0,
+            continuation: Continuations::default(),
         };
         let e2e4 = PossibleMove::simple_from_uci("e2e4").unwrap();
         first.insert_psboard(&e2e4, PSBoard::default().make_move_noncached(&e2e4).await);
@@ -259,4 +765,347 @@ mod test {
         acont.merge(bcont);
         assert_eq!(acont.total_continuation_boards(), btotal);
     }
+
+    #[tokio::test]
+    async fn merge_keeps_deeper_score() {
+        let mut shallow = create_simple_cont().await;
+        shallow.adjusted_score = Some(Score::centipawns(100));
+        shallow.stamp_score(2, 0);
+        let mut deep = create_simple_cont().await;
+        deep.adjusted_score = Some(Score::centipawns(-100));
+        deep.stamp_score(4, 0);
+        shallow.merge(deep);
+        assert_eq!(Some(Score::centipawns(-100)), shallow.adjusted_score);
+        assert!(shallow.is_trusted(4, 0));
+        assert!(!shallow.is_trusted(5, 0));
+
+        // The scores of an earlier generation are replaced even by shallower ones
+        let mut newer = create_simple_cont().await;
+        assert!(!shallow.is_trusted(1, 1));
+        newer.adjusted_score = Some(Score::centipawns(50));
+        newer.stamp_score(1, 1);
+        shallow.merge(newer);
+        assert_eq!(Some(Score::centipawns(50)), shallow.adjusted_score);
+    }
+
+    #[tokio::test]
+    async fn best_line_is_followed() {
+        let e2e4 = PossibleMove::simple_from_uci("e2e4").unwrap();
+        let d2d4 = PossibleMove::simple_from_uci("d2d4").unwrap();
+        let e7e5 = PossibleMove::simple_from_uci("e7e5").unwrap();
+        let c7c5 = PossibleMove::simple_from_uci("c7c5").unwrap();
+        let mut root = create_simple_cont().await;
+        root.insert_psboard(&d2d4, PSBoard::default().make_move_noncached(&d2d4).await);
+        root.find_continuation_mut(&d2d4).unwrap().adjusted_score = Some(Score::centipawns(10));
+        let after_e2e4 = root.find_continuation_mut(&e2e4).unwrap();
+        after_e2e4.adjusted_score = Some(Score::centipawns(30));
+        for (a_move, score) in [(e7e5, 30), (c7c5, 50)] {
+            let next_board = after_e2e4.make_move_noncached(&a_move).await;
+            after_e2e4.insert_psboard(&a_move, next_board);
+            after_e2e4
+                .find_continuation_mut(&a_move)
+                .unwrap()
+                .adjusted_score = Some(Score::centipawns(score));
+        }
+        assert_eq!(vec![e2e4, e7e5], root.principal_variation());
+        assert_eq!(vec![e2e4], root.principal_variation_up_to(1));
+    }
+
+    #[tokio::test]
+    async fn lines_are_looked_up() {
+        let e2e4 = PossibleMove::simple_from_uci("e2e4").unwrap();
+        let e7e5 = PossibleMove::simple_from_uci("e7e5").unwrap();
+        let mut root = create_simple_cont().await;
+        let after_e2e4 = root.find_continuation_mut(&e2e4).unwrap();
+        let next_board = after_e2e4.make_move_noncached(&e7e5).await;
+        after_e2e4.insert_psboard(&e7e5, next_board);
+
+        root.lookup_line_mut(&[e2e4, e7e5]).unwrap().adjusted_score = Some(Score::DRAW);
+        let line_end = root.lookup_line(&[e2e4, e7e5]).unwrap();
+        assert_eq!(Some(Score::DRAW), line_end.adjusted_score);
+        assert!(ptr::eq(&root, root.lookup_line(&[]).unwrap()));
+        assert!(root.lookup_line(&[e7e5]).is_none());
+        assert!(root.lookup_line(&[e2e4, e7e5, e2e4]).is_none());
+    }
+
+    #[tokio::test]
+    async fn continuations_stay_indexed() {
+        let e2e4 = PossibleMove::simple_from_uci("e2e4").unwrap();
+        let d2d4 = PossibleMove::simple_from_uci("d2d4").unwrap();
+        let mut root = create_simple_cont().await;
+        root.insert_psboard(&d2d4, PSBoard::default().make_move_noncached(&d2d4).await);
+        // Storing a move again replaces its board
+        root.insert_psboard(&e2e4, PSBoard::default().make_move_noncached(&e2e4).await);
+        assert_eq!(2, root.total_continuation_boards());
+        assert_eq!(vec![d2d4, e2e4], root.keys().copied().collect::<Vec<_>>());
+
+        assert!(root.find_continuation_remove(&d2d4).is_some());
+        assert!(!root.continuation_exists(&d2d4));
+        assert!(root.find_continuation_remove(&d2d4).is_none());
+        assert!(root.find_continuation(&e2e4).is_some());
+    }
+
+    #[tokio::test]
+    async fn pruning_keeps_the_best_boards() {
+        let moves = |ucis: &[&str]| -> Vec<PossibleMove> {
+            ucis.iter()
+                .map(|uci| PossibleMove::simple_from_uci(uci).unwrap())
+                .collect()
+        };
+        let mut root = create_simple_cont().await;
+        for (line, score) in [
+            (moves(&["e2e4"]), 30),
+            (moves(&["d2d4"]), 10),
+            (moves(&["g1f3"]), -20),
+            (moves(&["e2e4", "e7e5"]), 30),
+            (moves(&["e2e4", "c7c5"]), 50),
+            (moves(&["d2d4", "d7d5"]), 10),
+        ] {
+            let (last, start) = line.split_last().unwrap();
+            let parent = root.lookup_line_mut(start).unwrap();
+            if !parent.continuation_exists(last) {
+                let next_board = parent.make_move_noncached(last).await;
+                parent.insert_psboard(last, next_board);
+            }
+            parent.find_continuation_mut(last).unwrap().adjusted_score =
+                Some(Score::centipawns(score));
+        }
+        let usage = root.memory_usage();
+        assert_eq!(0, root.prune_to(6));
+
+        assert_eq!(2, root.prune_to(4));
+        assert!(root.memory_usage() < usage);
+        assert_eq!(3, root.keys().count());
+        // Black keeps the reply better for black
+        assert!(root.lookup_line(&moves(&["e2e4", "e7e5"])).is_some());
+        assert!(root.lookup_line(&moves(&["e2e4", "c7c5"])).is_none());
+
+        assert_eq!(2, root.prune_to(2));
+        assert_eq!(
+            moves(&["e2e4", "d2d4"]),
+            root.keys().copied().collect::<Vec<_>>()
+        );
+    }
+
+    #[tokio::test]
+    async fn tree_survives_the_disk() {
+        let e2e4 = PossibleMove::simple_from_uci("e2e4").unwrap();
+        let e7e5 = PossibleMove::simple_from_uci("e7e5").unwrap();
+        let mut root = create_simple_cont().await;
+        let after_e2e4 = root.find_continuation_mut(&e2e4).unwrap();
+        after_e2e4.adjusted_score = Some(Score::mate(Black, 3));
+        after_e2e4.stamp_score(4, 0);
+        let next_board = after_e2e4.make_move_noncached(&e7e5).await;
+        after_e2e4.insert_psboard(&e7e5, next_board);
+        after_e2e4
+            .find_continuation_mut(&e7e5)
+            .unwrap()
+            .mark_repetition();
+
+        let bytes = root.to_bytes();
+        let read = BoardContinuation::from_bytes(&bytes).await.unwrap();
+        assert_eq!(root.to_fen(), read.to_fen());
+        assert_eq!(None, read.adjusted_score);
+        assert_eq!(2, read.total_continuation_boards());
+        let read_e2e4 = read.find_continuation(&e2e4).unwrap();
+        assert_eq!(Some(Score::mate(Black, 3)), read_e2e4.adjusted_score);
+        assert!(read_e2e4.is_trusted(4, 0));
+        let read_e7e5 = read.lookup_line(&[e2e4, e7e5]).unwrap();
+        assert!(read_e7e5.potential_draw);
+        assert_eq!(root.lookup_line(&[e2e4, e7e5]).unwrap().raw, read_e7e5.raw);
+
+        assert!(BoardContinuation::from_bytes(&bytes[..bytes.len() - 1])
+            .await
+            .is_err());
+        assert!(BoardContinuation::from_bytes(b"DBCEBOOK").await.is_err());
+
+        // The trees written before the versions lack the version after the magic
+        let legacy = [&bytes[..8], &bytes[10..]].concat();
+        let read = BoardContinuation::from_bytes(&legacy).await.unwrap();
+        assert_eq!(2, read.total_continuation_boards());
+        let mut newer = bytes.clone();
+        newer[8] = 2;
+        let Err(error) = BoardContinuation::from_bytes(&newer).await else {
+            panic!("A tree of a newer version was read");
+        };
+        assert_eq!(
+            "Cannot read a continuation tree of format version 2, only up to 1",
+            error.to_string()
+        );
+    }
+
+    #[tokio::test]
+    async fn dot_export_follows_the_tree() {
+        let mut cont = create_simple_cont().await;
+        let e2e4 = PossibleMove::simple_from_uci("e2e4").unwrap();
+        let e7e5 = PossibleMove::simple_from_uci("e7e5").unwrap();
+        let inner_cont = cont.find_continuation_mut(&e2e4).unwrap();
+        let new_board = inner_cont.make_move_noncached(&e7e5).await;
+        inner_cont.insert_psboard(&e7e5, new_board);
+
+        let dot = cont.to_dot(usize::MAX);
+        assert!(dot.starts_with("digraph explored {"));
+        assert!(dot.contains(&PSBoard::default().to_fen()));
+        assert!(dot.contains("n0 -> n1 [label=\"e2e4\"];"));
+        assert!(dot.contains("n1 -> n2 [label=\"e7e5\"];"));
+        assert!(dot.trim_end().ends_with('}'));
+
+        let shallow = cont.to_dot(1);
+        assert!(shallow.contains("n0 -> n1"));
+        assert!(!shallow.contains("n2"));
+    }
+
+    #[tokio::test]
+    async fn json_export_nests_the_children() {
+        let mut cont = create_simple_cont().await;
+        cont.adjusted_score = Some(Score::mate(Black, 3));
+        let json = cont.to_json();
+        assert!(json["move"].is_null());
+        assert_eq!(PSBoard::default().to_fen(), json["fen"]);
+        assert_eq!(
+            json!(Score::mate(Black, 3).mate_in()),
+            json["score"]["mate"]
+        );
+        let children = json["children"].as_array().unwrap();
+        assert_eq!(1, children.len());
+        assert_eq!("e2e4", children[0]["move"]);
+        assert!(children[0]["score"]["cp"].is_i64());
+        assert!(children[0]["children"].as_array().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn low_temperature_prefers_the_best_board() {
+        let mut cont = create_simple_cont().await;
+        for uci in ["d2d4", "g1f3"] {
+            let a_move = PossibleMove::simple_from_uci(uci).unwrap();
+            let next_board = cont.make_move_noncached(&a_move).await;
+            cont.insert_psboard(&a_move, next_board);
+        }
+        for (index, board) in cont.continuation.values_mut().enumerate() {
+            board.adjusted_score = Some(Score::centipawns(100 - 50 * index as i32));
+        }
+        let best = cont.values().max_by_key(|board| board.score()).unwrap();
+        let all_of_them = Score::centipawns(500);
+        let mut rng = StdRng::seed_from_u64(0xdbce);
+        for _ in 0..20 {
+            let sharp = cont.select_similar_board(
+                best,
+                BoardContinuation::score,
+                all_of_them,
+                Some(Score::centipawns(1)),
+                &mut rng,
+            );
+            assert!(ptr::eq(best, sharp.unwrap()));
+        }
+        let pick_many = |seed| -> Vec<Score> {
+            let mut rng = StdRng::seed_from_u64(seed);
+            (0..200)
+                .map(|_| {
+                    cont.select_similar_board(
+                        best,
+                        BoardContinuation::score,
+                        all_of_them,
+                        Some(Score::centipawns(100)),
+                        &mut rng,
+                    )
+                    .unwrap()
+                    .score()
+                })
+                .collect()
+        };
+        let picked = pick_many(1);
+        assert_eq!(picked, pick_many(1));
+        assert!(picked.contains(&Score::centipawns(0)));
+        let best_picks = picked
+            .iter()
+            .filter(|&&score| score == best.score())
+            .count();
+        assert!(best_picks > picked.len() / 3);
+
+        // Nothing is within an empty window, and there is nothing to choose from a leaf
+        let nothing = Score::centipawns(0);
+        assert!(cont
+            .select_similar_board(best, BoardContinuation::score, nothing, None, &mut rng)
+            .is_none());
+        assert!(best
+            .select_similar_board(best, BoardContinuation::score, all_of_them, None, &mut rng)
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn scores_are_backed_up_with_minimax() {
+        let moves = |ucis: &[&str]| -> Vec<PossibleMove> {
+            ucis.iter()
+                .map(|uci| PossibleMove::simple_from_uci(uci).unwrap())
+                .collect()
+        };
+        let mut root = create_simple_cont().await;
+        for (line, score) in [
+            (moves(&["d2d4"]), Score::centipawns(10)),
+            (moves(&["e2e4", "e7e5"]), Score::centipawns(30)),
+            (moves(&["e2e4", "c7c5"]), Score::centipawns(-20)),
+            (moves(&["g2g4"]), Score::centipawns(-50)),
+            (moves(&["g2g4", "e7e5"]), Score::centipawns(-50)),
+            (moves(&["g2g4", "e7e5", "f2f3"]), Score::centipawns(-50)),
+            (
+                moves(&["g2g4", "e7e5", "f2f3", "d8h4"]),
+                Score::mate(Black, 0),
+            ),
+        ] {
+            let (last, start) = line.split_last().unwrap();
+            let parent = root.lookup_line_mut(start).unwrap();
+            let next_board = parent.make_move_noncached(last).await;
+            parent.insert_psboard(last, next_board);
+            let inserted = parent.find_continuation_mut(last).unwrap();
+            inserted.adjusted_score = Some(score);
+            inserted.stamp_score(2, 0);
+        }
+
+        assert_eq!(Score::centipawns(10), root.backup_scores(0));
+        let e2e4 = root.lookup_line(&moves(&["e2e4"])).unwrap();
+        // Black prefers the reply better for black
+        assert_eq!(Some(Score::centipawns(-20)), e2e4.adjusted_score);
+        assert_eq!(3, e2e4.searched_depth);
+        // The mate is found through the only continuations explored
+        let g2g4 = root.lookup_line(&moves(&["g2g4"])).unwrap();
+        assert_eq!(
+            Some(Score::mate(Black, 0).backed_up().backed_up().backed_up()),
+            g2g4.adjusted_score
+        );
+        assert_eq!(Some(Score::centipawns(10)), root.adjusted_score);
+    }
+
+    #[tokio::test]
+    async fn root_advances_two_plies() {
+        let e2e4 = PossibleMove::simple_from_uci("e2e4").unwrap();
+        let e7e5 = PossibleMove::simple_from_uci("e7e5").unwrap();
+        let c7c5 = PossibleMove::simple_from_uci("c7c5").unwrap();
+        let g1f3 = PossibleMove::simple_from_uci("g1f3").unwrap();
+        let mut root = create_simple_cont().await;
+        let after_e2e4 = root.find_continuation_mut(&e2e4).unwrap();
+        for a_move in [e7e5, c7c5] {
+            let next_board = after_e2e4.make_move_noncached(&a_move).await;
+            after_e2e4.insert_psboard(&a_move, next_board);
+        }
+        let after_e7e5 = root.lookup_line_mut(&[e2e4, e7e5]).unwrap();
+        let next_board = after_e7e5.make_move_noncached(&g1f3).await;
+        after_e7e5.insert_psboard(&g1f3, next_board);
+        after_e7e5.adjusted_score = Some(Score::centipawns(20));
+
+        let advanced = root.clone().advance_root(&e2e4, &e7e5).await;
+        assert_eq!(Some(Score::centipawns(20)), advanced.adjusted_score);
+        assert_eq!(1, advanced.total_continuation_boards());
+        assert!(advanced.continuation_exists(&g1f3));
+
+        // The reply was not explored, so the new root starts from scratch
+        let d7d5 = PossibleMove::simple_from_uci("d7d5").unwrap();
+        let expected = PSBoard::default()
+            .make_move_noncached(&e2e4)
+            .await
+            .make_move_noncached(&d7d5)
+            .await;
+        let unexplored = root.advance_root(&e2e4, &d7d5).await;
+        assert_eq!(0, unexplored.total_continuation_boards());
+        assert_eq!(expected.zobrist(), unexplored.zobrist());
+    }
 }
@@ -1,4 +1,4 @@
-use crate::baserules::board::PSBoard;
+use crate::baserules::board::{PSBoard, FIFTY_MOVE_RULE};
 use crate::baserules::board_rep::PossibleMove;
 use crate::engine::continuation::BoardContinuation;
 use crate::human_facing::moves::{make_a_human_move, make_an_uci_move, BoardParseResult};
@@ -41,8 +41,17 @@ impl ReplacementGenerator for HumanMove {
     }
 }
 
+/// The reasons a player may claim a draw instead of playing on
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DrawClaim {
+    ThreefoldRepetition,
+    FiftyMoveRule,
+}
+
 pub struct GameState {
     pub(crate) worked_on_board: BoardContinuation,
+    /// The hashes of the positions played before the current one since the last irreversible move
+    game_path: Vec<u64>,
 }
 
 impl GameState {
@@ -84,9 +93,39 @@ impl GameState {
             .unwrap();
     }
 
+    /// The hashes of the positions played before the current one since the last irreversible
+    /// move, the searches start their own paths from these
+    #[inline]
+    pub(crate) fn game_path(&self) -> &[u64] {
+        &self.game_path
+    }
+
+    /// Tells if the player to move could claim a draw in the current position
+    pub fn claimable_draw(&self) -> Option<DrawClaim> {
+        let current_hash = self.worked_on_board.zobrist();
+        let earlier_occurrences = self
+            .game_path
+            .iter()
+            .filter(|hash| **hash == current_hash)
+            .count();
+        if earlier_occurrences >= 2 {
+            Some(DrawClaim::ThreefoldRepetition)
+        } else if self.worked_on_board.halfmove_clock() >= FIFTY_MOVE_RULE {
+            Some(DrawClaim::FiftyMoveRule)
+        } else {
+            None
+        }
+    }
+
     pub fn new(board: PSBoard) -> GameState {
+        Self::from_tree(BoardContinuation::new(board))
+    }
+
+    /// Starts the game from an already explored tree, e.g. one read from the disk
+    pub fn from_tree(tree: BoardContinuation) -> GameState {
         GameState {
-            worked_on_board: BoardContinuation::new(board),
+            worked_on_board: tree,
+            game_path: Vec::new(),
         }
     }
 
@@ -95,9 +134,16 @@ impl GameState {
         generator: impl ReplacementGenerator,
     ) -> EmptyResult {
         let old_board = mem::take(&mut self.worked_on_board);
+        let old_hash = old_board.zobrist();
         let potential_result_board = generator.produce_replacment(old_board).await;
         match potential_result_board {
-            Ok(result_board) => {
+            Ok(mut result_board) => {
+                if result_board.half_moves_since_pawn == 0 {
+                    self.game_path.clear();
+                } else {
+                    self.game_path.push(old_hash);
+                }
+                result_board.mark_repetitions(&mut self.game_path);
                 self.worked_on_board = result_board;
                 Ok(())
             }
@@ -112,7 +158,9 @@ impl GameState {
 #[cfg(test)]
 mod test {
     use crate::baserules::board::PSBoard;
-    use crate::engine::gamestate::GameState;
+    use crate::baserules::board_rep::PossibleMove;
+    use crate::baserules::score::Score;
+    use crate::engine::gamestate::{DrawClaim, GameState};
 
     #[tokio::test]
     pub async fn move_error_should_return_to_state_before() {
@@ -125,4 +173,49 @@ mod test {
             kasparov_immortal.worked_on_board.to_fen()
         );
     }
+
+    #[tokio::test]
+    pub async fn cached_repetition_is_scored_as_draw() {
+        let mut gamestate = GameState::new(PSBoard::default());
+        let knight_dance = ["g1f3", "g8f6", "f3g1", "f6g8"]
+            .map(|a_move| PossibleMove::simple_from_uci(a_move).unwrap());
+        let mut cached = &mut gamestate.worked_on_board;
+        for a_move in &knight_dance {
+            let next_board = cached.make_move_noncached(a_move).await;
+            cached.insert_psboard(a_move, next_board);
+            cached = cached.find_continuation_mut(a_move).unwrap();
+            cached.adjusted_score = Some(Score::centipawns(300));
+        }
+        for a_move in &knight_dance[..3] {
+            gamestate.make_a_generated_move(a_move).await;
+        }
+        let back_at_start = gamestate
+            .worked_on_board
+            .find_continuation(&knight_dance[3])
+            .unwrap();
+        assert!(back_at_start.potential_draw);
+        assert_eq!(Some(Score::DRAW), back_at_start.adjusted_score);
+        assert!(!gamestate.worked_on_board.potential_draw);
+    }
+
+    #[tokio::test]
+    pub async fn detects_claimable_draws() {
+        let mut gamestate = GameState::new(PSBoard::default());
+        for a_move in ["Nf3", "Nf6", "Ng1", "Ng8", "Nf3", "Nf6", "Ng1"] {
+            gamestate.make_a_human_move_or_panic(a_move).await;
+            assert_eq!(None, gamestate.claimable_draw());
+        }
+        gamestate.make_a_human_move_or_panic("Ng8").await;
+        assert_eq!(
+            Some(DrawClaim::ThreefoldRepetition),
+            gamestate.claimable_draw()
+        );
+
+        let fifty_moves = GameState::new(
+            PSBoard::from_fen("8/8/4k3/8/8/4K3/8/7R w - - 100 80")
+                .await
+                .unwrap(),
+        );
+        assert_eq!(Some(DrawClaim::FiftyMoveRule), fifty_moves.claimable_draw());
+    }
 }
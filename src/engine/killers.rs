@@ -0,0 +1,114 @@
+use crate::baserules::board::PSBoard;
+use crate::baserules::board_rep::{PackedMove, PossibleMove};
+use std::cmp::Reverse;
+use std::sync::atomic::AtomicU16;
+use std::sync::atomic::Ordering::Relaxed;
+
+/// The deepest ply we remember killer moves for
+pub const MAX_PLIES: usize = 64;
+
+/// An a1a1 move never occurs, so its packed form marks the empty slots
+const NO_MOVE: u16 = 0;
+
+/// The per ply search stack of the quiet moves that were found best by earlier explorations of
+/// sibling boards. These are likely good in the other boards of the same ply as well.
+/// The slots are atomic, so the parallel explorations can share them.
+pub struct KillerMoves {
+    plies: [[AtomicU16; 2]; MAX_PLIES],
+}
+
+impl Default for KillerMoves {
+    fn default() -> Self {
+        Self {
+            plies: std::array::from_fn(|_| [AtomicU16::new(NO_MOVE), AtomicU16::new(NO_MOVE)]),
+        }
+    }
+}
+
+impl KillerMoves {
+    /// Remembers a quiet move as the newest killer of the ply, the older killer is kept in the second slot
+    pub fn store(&self, ply: u8, a_move: &PossibleMove) {
+        if let Some([first, second]) = self.plies.get(ply as usize) {
+            let packed = PackedMove::from(a_move).0;
+            let previous = first.swap(packed, Relaxed);
+            if previous != packed {
+                second.store(previous, Relaxed);
+            }
+        }
+    }
+
+    /// The position of the move amongst the killers of the ply, if it is one of them
+    pub fn killer_rank(&self, ply: u8, a_move: &PossibleMove) -> Option<usize> {
+        let packed = PackedMove::from(a_move).0;
+        self.plies
+            .get(ply as usize)?
+            .iter()
+            .position(|slot| slot.load(Relaxed) == packed)
+    }
+
+    /// The killers of the ply, the newest first
+    pub fn killers(&self, ply: u8) -> impl Iterator<Item = PossibleMove> + '_ {
+        self.plies
+            .get(ply as usize)
+            .into_iter()
+            .flatten()
+            .map(|slot| slot.load(Relaxed))
+            .filter(|packed| *packed != NO_MOVE)
+            .map(|packed| PackedMove(packed).into())
+    }
+
+    /// Orders the captures first in MVV-LVA order, then the killers of the ply and then the other moves
+    pub fn order_moves(&self, board: &PSBoard, ply: u8, moves: &mut [PossibleMove]) {
+        moves.sort_by_cached_key(|a_move| {
+            (
+                Reverse(board.mvv_lva(a_move)),
+                self.killer_rank(ply, a_move).unwrap_or(usize::MAX),
+            )
+        });
+    }
+
+    pub fn clear(&self) {
+        self.plies
+            .iter()
+            .flatten()
+            .for_each(|slot| slot.store(NO_MOVE, Relaxed));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::baserules::board::PSBoard;
+    use crate::baserules::board_rep::PossibleMove;
+    use crate::baserules::move_list::MoveList;
+    use crate::engine::killers::KillerMoves;
+    use tokio::test;
+
+    #[test]
+    async fn killers_follow_captures() {
+        let board = PSBoard::from_fen("4k3/8/8/3p4/4P3/8/8/4K1N1 w - - 0 1")
+            .await
+            .unwrap();
+        let killers = KillerMoves::default();
+        let knight_move = PossibleMove::simple_from_uci("g1f3").unwrap();
+        let king_move = PossibleMove::simple_from_uci("e1d2").unwrap();
+        killers.store(3, &knight_move);
+        killers.store(3, &king_move);
+        assert_eq!(Some(0), killers.killer_rank(3, &king_move));
+        assert_eq!(Some(1), killers.killer_rank(3, &knight_move));
+        assert_eq!(None, killers.killer_rank(2, &knight_move));
+
+        let mut moves = MoveList::new();
+        board.gen_potential_moves(&mut moves);
+        killers.order_moves(&board, 3, &mut moves);
+        assert_eq!(
+            vec!["e4d5", "e1d2", "g1f3"],
+            moves[..3]
+                .iter()
+                .map(PossibleMove::to_string)
+                .collect::<Vec<_>>()
+        );
+
+        killers.clear();
+        assert_eq!(None, killers.killer_rank(3, &king_move));
+    }
+}
@@ -0,0 +1,145 @@
+use crate::baserules::board::PSBoard;
+use crate::baserules::board_rep::PossibleMove;
+use crate::baserules::score::Score;
+use crate::engine::alphabeta::{principal_variation_search, SearchContext};
+use crate::engine::continuation::BoardContinuation;
+use crate::engine::killers::KillerMoves;
+use crate::engine::Engine;
+use crate::util::IntResult;
+use async_scoped::TokioScope;
+use futures_util::future::ready;
+use futures_util::StreamExt;
+use global_counter::primitive::fast::FlushingCounterU32;
+use std::pin::pin;
+use std::sync::atomic::{AtomicBool, AtomicU8};
+
+impl Engine {
+    /// Analyses every position along the line from the start board to the given depth, e.g. to
+    /// check a published variation. The positions are searched in parallel, each with a principal
+    /// variation search of its own sharing only the transposition table, so a long line takes
+    /// about as long as its slowest position. The searches are merged into one tree following the
+    /// line, where each board of the line holds the score of its own search.
+    ///
+    /// # Errors
+    /// When a move of the line is not legal in the position it is played in
+    pub async fn analyse_line(
+        &self,
+        start: PSBoard,
+        line: &[PossibleMove],
+        depth: u8,
+    ) -> IntResult<BoardContinuation> {
+        let mut boards = vec![start];
+        for (ply, a_move) in line.iter().enumerate() {
+            let board = &boards[ply];
+            let Some((_, next_board)) = pin!(board
+                .successors()
+                .filter(|(legal_move, _)| ready(legal_move == a_move)))
+            .next()
+            .await
+            else {
+                return Err(format!("Illegal move {a_move} on ply {} of the line", ply + 1).into());
+            };
+            boards.push(next_board);
+        }
+        let paths: Vec<Vec<u64>> = (0..boards.len())
+            .map(|ply| boards[..ply].iter().map(PSBoard::zobrist).collect())
+            .collect();
+        let counter = FlushingCounterU32::new(0);
+        let (_, searched) = TokioScope::scope_and_block(|scope| {
+            for (ply, (board, path)) in boards.into_iter().zip(paths).enumerate() {
+                let counter = &counter;
+                scope.spawn(async move {
+                    let mut root = BoardContinuation::new(board);
+                    let mut path = path;
+                    let killers = KillerMoves::default();
+                    let exploration_allowed = AtomicBool::new(true);
+                    let seldepth = AtomicU8::new(0);
+                    let context = SearchContext::new(self, &killers, counter, &seldepth)
+                        .with_exploration_allowed(&exploration_allowed);
+                    principal_variation_search(
+                        &mut root,
+                        &mut path,
+                        depth,
+                        0,
+                        Score::NEG_INFINITY,
+                        Score::INFINITY,
+                        &context,
+                    )
+                    .await;
+                    (ply, root)
+                });
+            }
+        });
+        let mut searched = searched
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|error| format!("The analysis of a position failed: {error}"))?;
+        // The searches finish in any order
+        searched.sort_unstable_by_key(|(ply, _)| *ply);
+        // The searches are hung below each other from the end of the line
+        let (_, mut merged) = searched.pop().expect("The start board is always searched");
+        for ((_, mut board), a_move) in searched.into_iter().zip(line).rev() {
+            board.merge_continuation(*a_move, merged);
+            merged = board;
+        }
+        Ok(merged)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::baserules::board::PSBoard;
+    use crate::baserules::board_rep::PossibleMove;
+    use crate::baserules::move_list::MoveList;
+    use crate::engine::Engine;
+    use tokio::test;
+
+    /// The moves of the line from the starting position
+    async fn parse_line(uci_moves: &str) -> Vec<PossibleMove> {
+        let mut board = PSBoard::default();
+        let mut line = Vec::new();
+        for uci in uci_moves.split_ascii_whitespace() {
+            let mut moves = MoveList::new();
+            board.gen_potential_moves(&mut moves);
+            let a_move = *moves
+                .iter()
+                .find(|a_move| a_move.to_string() == uci)
+                .unwrap();
+            board = board.make_move_noncached(&a_move).await;
+            line.push(a_move);
+        }
+        line
+    }
+
+    #[test(flavor = "multi_thread")]
+    async fn every_position_of_the_line_is_analysed() {
+        let (engine, _) = Engine::new();
+        let line = parse_line("e2e4 e7e5 d1h5 b8c6 f1c4 g8f6 h5f7").await;
+        let analysed = engine
+            .analyse_line(PSBoard::default(), &line, 2)
+            .await
+            .unwrap();
+        for ply in 0..line.len() {
+            let board = analysed.lookup_line(&line[..ply]).unwrap();
+            assert!(
+                board.is_trusted(2, engine.score_generation()),
+                "ply {ply} was not searched"
+            );
+        }
+        // Black is mated at the end of the line, which the search before it finds
+        let before_mate = analysed.lookup_line(&line[..line.len() - 1]).unwrap();
+        assert!(before_mate.score().is_mate());
+    }
+
+    #[test(flavor = "multi_thread")]
+    async fn illegal_moves_are_rejected() {
+        let (engine, _) = Engine::new();
+        let line = parse_line("e2e4 e7e5").await;
+        // The second move is played twice, when it is white's turn again
+        let wrong = [line[0], line[1], line[1]];
+        assert!(engine
+            .analyse_line(PSBoard::default(), &wrong, 1)
+            .await
+            .is_err());
+    }
+}
@@ -0,0 +1,272 @@
+use crate::baserules::board_rep::PossibleMove;
+use crate::baserules::evaluation::Evaluator;
+use crate::baserules::move_list::MoveList;
+use crate::baserules::score::Score;
+use crate::engine::continuation::BoardContinuation;
+use crate::engine::quiescence::quiescence_score;
+use crate::engine::strength::StrengthSettings;
+use crate::engine::{poll_stop, DepthsBoardCountMaintenance, Engine};
+use async_trait::async_trait;
+use futures_util::future::BoxFuture;
+use futures_util::FutureExt;
+use global_counter::primitive::fast::FlushingCounterU32;
+use rustc_hash::FxHashMap;
+use std::sync::atomic::AtomicU8;
+use std::sync::atomic::Ordering::Relaxed;
+use tokio::task::yield_now;
+use tokio::time::Instant;
+
+/// Weighs the exploration of the rarely visited moves against the exploitation of the best ones
+/// in the UCT formula
+const EXPLORATION: f32 = 1.4;
+
+/// The score advantage in centipawns making a side ten times more likely to win than to lose
+const WIN_ODDS_SCALE: f32 = 400.0;
+
+/// The number of playouts between two progress reports
+const REPORT_INTERVAL: u32 = 1000;
+
+/// White's expected result with the score, between 0 for a loss and 1 for a win
+pub fn win_probability(score: Score) -> f32 {
+    if score.is_mate() {
+        return if score > Score::DRAW { 1.0 } else { 0.0 };
+    }
+    1.0 / (1.0 + 10f32.powf(-(score.as_centipawns() as f32) / WIN_ODDS_SCALE))
+}
+
+/// The score giving white the expected result, the inverse of `win_probability` apart from the
+/// mates, which are only reported as large advantages
+pub fn probability_score(probability: f32) -> Score {
+    let probability = probability.clamp(1e-6, 1.0 - 1e-6);
+    Score::centipawns((WIN_ODDS_SCALE * (probability / (1.0 - probability)).log10()).round() as i32)
+}
+
+/// The playouts that went through a position, positions reached on different paths share them
+#[derive(Debug, Default, Copy, Clone)]
+struct NodeStats {
+    visits: u32,
+    /// The sum of white's results of the playouts
+    total_value: f32,
+}
+
+impl NodeStats {
+    fn mean(&self) -> f32 {
+        self.total_value / self.visits as f32
+    }
+
+    /// The upper confidence bound of the result of the side choosing the board, unvisited boards
+    /// are chosen first
+    fn uct(&self, parent_visits: u32, mate_multiplier: i32) -> f32 {
+        if self.visits == 0 {
+            return f32::INFINITY;
+        }
+        let mean = if mate_multiplier > 0 {
+            self.mean()
+        } else {
+            1.0 - self.mean()
+        };
+        mean + EXPLORATION * ((parent_visits as f32).ln() / self.visits as f32).sqrt()
+    }
+}
+
+/// The state of a Monte Carlo tree search, the visits are only kept for one search
+struct MctsSearch<'a> {
+    stats: FxHashMap<u64, NodeStats>,
+    counter: &'a FlushingCounterU32,
+    strength: &'a StrengthSettings,
+    /// White's expected result of the draws, with the contempt of the engine
+    draw: f32,
+    /// The deepest ply a playout has reached before the quiescence search
+    seldepth: u8,
+    /// The score generation of the engine the terminal boards are stamped with
+    generation: u32,
+    /// Scores the boards created by the playouts
+    evaluator: &'a dyn Evaluator,
+}
+
+impl<'a> MctsSearch<'a> {
+    /// One playout from the board: the children with the best upper confidence bounds are
+    /// followed until a board visited the first time, which is evaluated with the quiescence
+    /// search instead of playing random moves to the end of the game. The boards are expanded on
+    /// their second visit. The result is backed up into the visits of the boards on the way and
+    /// their adjusted scores.
+    fn playout<'b>(
+        &'b mut self,
+        board: &'b mut BoardContinuation,
+        path: &'b mut Vec<u64>,
+        ply: u8,
+    ) -> BoxFuture<'b, f32>
+    where
+        'a: 'b,
+    {
+        async move {
+            let hash = board.zobrist();
+            let node = self.stats.get(&hash).copied().unwrap_or_default();
+            let value = if board.score.is_mate() {
+                win_probability(board.score)
+            } else if board.potential_draw || (ply > 0 && path.contains(&hash)) {
+                self.draw
+            } else if ply > 0 && (node.visits == 0 || !self.strength.allows_depth(ply + 1)) {
+                self.seldepth = self.seldepth.max(ply);
+                win_probability(quiescence_score(board, self.evaluator).await)
+            } else {
+                self.expand_and_select(board, path, ply, node.visits).await
+            };
+            let node = self.stats.entry(hash).or_default();
+            node.visits += 1;
+            node.total_value += value;
+            // The checkmates marked on the expansion keep their scores
+            if node.visits > 1 && !board.score().is_mate() && !board.potential_draw {
+                board.adjusted_score = Some(probability_score(node.mean()));
+            }
+            value
+        }
+        .boxed()
+    }
+
+    async fn expand_and_select(
+        &mut self,
+        board: &mut BoardContinuation,
+        path: &mut Vec<u64>,
+        ply: u8,
+        visits: u32,
+    ) -> f32 {
+        // Without a legal move all the moves lose the king, found out on the first expansion
+        if visits <= 1 && board.mark_if_terminal(self.generation).await {
+            return if board.potential_draw {
+                self.draw
+            } else {
+                win_probability(board.score())
+            };
+        }
+        let mate_multiplier = board.who_moves.mate_multiplier();
+        let mut moves = MoveList::new();
+        board.gen_search_moves(&mut moves);
+        board.order_moves(&mut moves);
+        let mut selected = None;
+        let mut best_bound = f32::NEG_INFINITY;
+        for a_move in &moves {
+            let next_board = board
+                .lookup_continuation_or_create(a_move, self.counter, self.evaluator)
+                .await;
+            let bound = self
+                .stats
+                .get(&next_board.zobrist())
+                .copied()
+                .unwrap_or_default()
+                .uct(visits.max(1), mate_multiplier);
+            if bound > best_bound {
+                best_bound = bound;
+                selected = Some(*a_move);
+            }
+        }
+        let Some(a_move) = selected else {
+            return win_probability(board.score());
+        };
+        path.push(board.zobrist());
+        let next_board = board.find_continuation_mut(&a_move).unwrap();
+        let value = self.playout(next_board, path, ply + 1).await;
+        path.pop();
+        value
+    }
+
+    /// The root move with the most playouts, with white's score expected after it
+    fn most_visited(&self, root: &BoardContinuation) -> (Option<PossibleMove>, Score) {
+        root.iter()
+            .filter_map(|(a_move, next_board)| {
+                let stats = self.stats.get(&next_board.zobrist())?;
+                Some((a_move, next_board, stats.visits))
+            })
+            .max_by_key(|(_, _, visits)| *visits)
+            .map_or((None, Score::DRAW), |(a_move, next_board, _)| {
+                (Some(*a_move), next_board.score())
+            })
+    }
+}
+
+/// Monte Carlo tree search on the continuation tree with UCT selection, an alternative to the
+/// averaging exploration and the principal variation search for experimenting with the style
+/// of the engine. It plays the move visited most by the playouts once the time is up.
+pub(crate) struct MctsEngine<'a>(
+    pub(crate) Engine,
+    pub(crate) &'a mut BoardContinuation,
+    pub(crate) Vec<u64>,
+);
+
+#[async_trait]
+impl<'a> DepthsBoardCountMaintenance<(Option<PossibleMove>, Score)> for MctsEngine<'a> {
+    async fn best_move_for(
+        self,
+        board_count: &FlushingCounterU32,
+        depth: &AtomicU8,
+    ) -> (Option<PossibleMove>, Score) {
+        let MctsEngine(engine, root, mut path) = self;
+        let started = Instant::now();
+        let mut search = MctsSearch {
+            stats: FxHashMap::default(),
+            counter: board_count,
+            strength: &engine.strength,
+            draw: win_probability(engine.draw_score()),
+            seldepth: 0,
+            generation: engine.score_generation(),
+            evaluator: engine.evaluator(),
+        };
+        let mut playouts = 0;
+        while engine.exploration_allowed.load(Relaxed) {
+            search.playout(root, &mut path, 0).await;
+            poll_stop(&engine.stop, &engine.exploration_allowed);
+            playouts += 1;
+            if playouts % REPORT_INTERVAL == 0 {
+                let (best_move, score) = search.most_visited(root);
+                depth.store(search.seldepth, Relaxed);
+                engine.iteration_completed(best_move.as_ref());
+                engine.report_iteration(
+                    root,
+                    search.seldepth,
+                    search.seldepth,
+                    score,
+                    board_count.get(),
+                    started,
+                );
+            }
+            yield_now().await;
+        }
+        depth.store(search.seldepth, Relaxed);
+        search.most_visited(root)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::baserules::piece_color::PieceColor::{Black, White};
+    use crate::baserules::score::Score;
+    use crate::engine::mcts::{probability_score, win_probability};
+    use crate::engine::{Engine, SearchAlgorithm};
+    use std::time::Duration;
+    use tokio::test;
+
+    #[test]
+    async fn scores_convert_to_probabilities_and_back() {
+        assert_eq!(0.5, win_probability(Score::DRAW));
+        assert_eq!(1.0, win_probability(Score::mate(White, 3)));
+        assert_eq!(0.0, win_probability(Score::mate(Black, 3)));
+        let advantage = Score::centipawns(150);
+        assert!(win_probability(advantage) > 0.5);
+        assert_eq!(advantage, probability_score(win_probability(advantage)));
+        assert_eq!(-advantage, probability_score(win_probability(-advantage)));
+    }
+
+    #[test(flavor = "multi_thread")]
+    async fn playouts_find_the_mate_in_one() {
+        let (mut engine, mut state) =
+            Engine::from_fen("r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 4 4")
+                .await;
+        engine.set_search_algorithm(SearchAlgorithm::Mcts);
+        let (best_move, score, boards, _) = engine
+            .best_move_for(&mut state, &Duration::from_millis(500))
+            .await;
+        assert_eq!("h5f7", best_move.unwrap().to_string());
+        assert!(score > Score::centipawns(500));
+        assert!(boards > 0);
+    }
+}
@@ -0,0 +1,77 @@
+use crate::baserules::board::PSBoard;
+use crate::baserules::board_rep::PossibleMove;
+use crate::baserules::piece_kind::PieceKind;
+use crate::baserules::piece_kind::PieceKind::*;
+use std::cmp::Reverse;
+
+/// The rank of the pieces when ordering captures, only their relative order matters
+#[inline]
+fn ordering_rank(kind: PieceKind) -> u8 {
+    match kind {
+        Pawn => 1,
+        Knight => 2,
+        Bishop => 3,
+        Rook => 4,
+        Queen => 5,
+        King => 6,
+    }
+}
+
+impl PSBoard {
+    /// Tells which kind of piece the move takes, including pawns taken en passant. Castlings
+    /// take nothing, even when the Chess960 king lands on its own rook or stays in place.
+    #[inline]
+    pub fn captured_kind(&self, a_move: &PossibleMove) -> Option<PieceKind> {
+        if a_move.rook.is_some() {
+            return None;
+        }
+        let attacker = self[a_move.the_move.from]?;
+        if let Some(victim) = self[a_move.the_move.to] {
+            (victim.color != attacker.color).then_some(victim.kind)
+        } else if attacker.kind == Pawn && self.ep == Some(a_move.the_move.to) {
+            Some(Pawn)
+        } else {
+            None
+        }
+    }
+
+    #[inline]
+    pub fn is_capture(&self, a_move: &PossibleMove) -> bool {
+        self.captured_kind(a_move).is_some()
+    }
+
+    /// Most valuable victim - least valuable attacker score of a move, quiet moves have none.
+    /// Larger values are expected to be better captures.
+    pub fn mvv_lva(&self, a_move: &PossibleMove) -> Option<u8> {
+        let victim = self.captured_kind(a_move)?;
+        let attacker = self[a_move.the_move.from]?.kind;
+        Some(ordering_rank(victim) * 8 + 7 - ordering_rank(attacker))
+    }
+
+    /// Puts the captures to the front of the moves in MVV-LVA order, e.g. pawn takes queen comes
+    /// first while queen takes pawn is the last of the captures. Quiet moves keep their order.
+    pub fn order_moves(&self, moves: &mut [PossibleMove]) {
+        moves.sort_by_cached_key(|a_move| Reverse(self.mvv_lva(a_move)));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::baserules::board::PSBoard;
+    use crate::baserules::board_rep::PossibleMove;
+    use crate::baserules::move_list::MoveList;
+    use tokio::test;
+
+    #[test]
+    async fn pawn_takes_queen_first() {
+        let board = PSBoard::from_fen("4k3/8/3p4/2q1p3/3PQ3/8/8/4K3 w - - 0 1")
+            .await
+            .unwrap();
+        let mut moves = MoveList::new();
+        board.gen_potential_moves(&mut moves);
+        board.order_moves(&mut moves);
+        let first_moves: Vec<String> = moves.iter().take(3).map(PossibleMove::to_string).collect();
+        assert_eq!(vec!["d4c5", "d4e5", "e4e5"], first_moves);
+        assert!(moves[3..].iter().all(|a_move| !board.is_capture(a_move)));
+    }
+}
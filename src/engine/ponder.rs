@@ -0,0 +1,124 @@
+use crate::baserules::board_rep::PossibleMove;
+use crate::engine::gamestate::GameState;
+use crate::engine::{Engine, ExtEngine, Verbosity};
+use global_counter::primitive::fast::FlushingCounterU32;
+use std::sync::atomic::Ordering::Relaxed;
+use tokio::spawn;
+use tokio::task::JoinHandle;
+
+/// A search running on the opponent's time in the subtree of their expected reply.
+/// Whatever is explored stays in the continuation tree, so when the opponent plays the expected
+/// move the next search continues from it, while any other move drops the pondered subtree.
+pub struct Ponder {
+    engine: Engine,
+    expected: PossibleMove,
+    search: JoinHandle<GameState>,
+}
+
+impl Ponder {
+    /// The reply of the opponent that is being pondered on
+    #[inline]
+    pub fn expected(&self) -> &PossibleMove {
+        &self.expected
+    }
+
+    /// Stops the search and gives back the game, ready for the opponent's actual move
+    pub async fn stop(self) -> GameState {
+        self.engine.stop();
+        let state = self.search.await.expect("Pondering should not panic");
+        // The pondering may have used up the node budget before the stop, which must not stop
+        // the next search then
+        self.engine.stop.store(false, Relaxed);
+        state
+    }
+}
+
+impl Engine {
+    /// Starts searching the position after the reply the engine expects from the opponent. The
+    /// search runs until stopped, without a deadline. Gives the game back when there is no
+    /// expected reply, e.g. when the game has ended or was not searched yet.
+    #[allow(clippy::result_large_err)]
+    pub fn ponder(&self, mut state: GameState) -> Result<Ponder, GameState> {
+        let Some(expected) = state.continuation().principal_variation_up_to(1).pop() else {
+            return Err(state);
+        };
+        self.killers.clear();
+        self.enable_parallel.store(true, Relaxed);
+        self.start_search(None);
+        let engine = self.clone();
+        let search = spawn(async move {
+            let counter = FlushingCounterU32::new(0);
+            let mut path = state.game_path().to_vec();
+            path.push(state.psboard().zobrist());
+            let reply = state
+                .worked_on_board
+                .lookup_continuation_or_create(&expected, &counter, engine.evaluator())
+                .await;
+            let (_, boards, depth) =
+                Engine::manage_counter(ExtEngine(engine.clone(), reply, path)).await;
+            engine.end_search();
+            engine.report(Verbosity::Full, || {
+                format!("pondered on {expected}: {boards} boards, depth {depth}")
+            });
+            state
+        });
+        Ok(Ponder {
+            engine: self.clone(),
+            expected,
+            search,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::engine::Engine;
+    use std::time::Duration;
+    use tokio::test;
+    use tokio::time::sleep;
+
+    #[test(flavor = "multi_thread")]
+    async fn pondered_reply_keeps_its_subtree() {
+        let (engine, mut state) = Engine::new();
+        let (our_move, ..) = engine
+            .best_move_for(&mut state, &Duration::from_millis(300))
+            .await;
+        state.make_a_generated_move(&our_move.unwrap()).await;
+
+        let ponder = engine.ponder(state).ok().unwrap();
+        let expected = *ponder.expected();
+        sleep(Duration::from_millis(300)).await;
+        let mut state = ponder.stop().await;
+        state.make_a_generated_move(&expected).await;
+        assert!(state.continuation().values().count() > 0);
+        assert!(state
+            .continuation()
+            .values()
+            .any(|child| child.values().count() > 0));
+    }
+
+    #[test(flavor = "multi_thread")]
+    async fn pondering_stops_at_its_node_limit() {
+        // The iterations are cheap in the pawn endgame, so the limit is reached between them
+        let (mut engine, mut state) = Engine::from_fen("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1").await;
+        engine.set_open_search_nodes(2_000);
+        engine
+            .best_move_for(&mut state, &Duration::from_millis(100))
+            .await;
+        let ponder = engine.ponder(state).ok().unwrap();
+        for _ in 0..200 {
+            if ponder.search.is_finished() {
+                break;
+            }
+            sleep(Duration::from_millis(50)).await;
+        }
+        assert!(ponder.search.is_finished());
+        ponder.stop().await;
+    }
+
+    #[test]
+    async fn nothing_to_ponder_without_search() {
+        let (engine, state) = Engine::new();
+        assert!(engine.ponder(state).is_err());
+    }
+}
@@ -0,0 +1,183 @@
+use crate::baserules::evaluation::Evaluator;
+use crate::baserules::move_list::MoveList;
+use crate::engine::continuation::BoardContinuation;
+use crate::engine::gamestate::GameState;
+use crate::engine::{Engine, Verbosity};
+use futures_util::future::BoxFuture;
+use futures_util::FutureExt;
+use global_counter::primitive::fast::FlushingCounterU32;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering::Relaxed;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::spawn;
+use tokio::task::{yield_now, JoinHandle};
+use tokio::time::{sleep, Instant};
+
+/// The number of the opponent's replies expanded, the most likely ones by their scores
+const PREFETCHED_REPLIES: usize = 3;
+
+/// The deepest ply below the replies the prefetch expands the tree to
+const PREFETCH_DEPTH: u8 = 3;
+
+/// The time the prefetch works before it sleeps to keep within its share of the CPU
+const WORK_SLICE: Duration = Duration::from_millis(10);
+
+/// Expands the subtrees of the opponent's most likely replies while waiting for their move,
+/// without searching them. Unlike pondering it does not take over the engine, and it only uses
+/// a share of the CPU, so the boards are already in the continuation tree when the opponent's
+/// move is made on it.
+pub struct Prefetch {
+    running: Arc<AtomicBool>,
+    task: JoinHandle<GameState>,
+}
+
+impl Prefetch {
+    /// Stops the expansion and gives back the game, ready for the opponent's actual move
+    pub async fn stop(self) -> GameState {
+        self.running.store(false, Relaxed);
+        self.task.await.expect("Prefetching should not panic")
+    }
+}
+
+/// Keeps the work within the share of the CPU by sleeping after each slice of work
+struct Throttle {
+    cpu_percent: u8,
+    working_since: Instant,
+    running: Arc<AtomicBool>,
+}
+
+impl Throttle {
+    /// Gives way to the other tasks, returns false once the prefetch has to stop
+    async fn pause(&mut self) -> bool {
+        let worked = self.working_since.elapsed();
+        if worked >= WORK_SLICE {
+            let percent = u32::from(self.cpu_percent.clamp(1, 100));
+            sleep(worked * (100 - percent) / percent).await;
+            self.working_since = Instant::now();
+        } else {
+            yield_now().await;
+        }
+        self.running.load(Relaxed)
+    }
+}
+
+/// Creates the missing boards below the board down to the depth, scored with the evaluator
+fn expand<'a>(
+    board: &'a mut BoardContinuation,
+    depth: u8,
+    counter: &'a FlushingCounterU32,
+    throttle: &'a mut Throttle,
+    evaluator: &'a dyn Evaluator,
+) -> BoxFuture<'a, ()> {
+    async move {
+        if depth == 0 || board.score.is_mate() {
+            return;
+        }
+        let mut moves = MoveList::new();
+        board.gen_potential_moves(&mut moves);
+        for a_move in &moves {
+            if !throttle.pause().await {
+                return;
+            }
+            let next_board = board
+                .lookup_continuation_or_create(a_move, counter, evaluator)
+                .await;
+            expand(next_board, depth - 1, counter, throttle, evaluator).await;
+        }
+    }
+    .boxed()
+}
+
+impl Engine {
+    /// Starts expanding the opponent's most likely replies on at most the given percentage of a
+    /// CPU. The replies are the best ones for the opponent by the scores of the last search, or
+    /// the first generated ones when the position was not searched yet. The expansion stops
+    /// once the tree reaches the node budget.
+    pub fn prefetch(&self, mut state: GameState, cpu_percent: u8) -> Prefetch {
+        let running = Arc::new(AtomicBool::new(true));
+        let mut throttle = Throttle {
+            cpu_percent,
+            working_since: Instant::now(),
+            running: running.clone(),
+        };
+        let engine = self.clone();
+        let task = spawn(async move {
+            let counter = FlushingCounterU32::new(state.continuation().total_continuation_boards());
+            let root = &mut state.worked_on_board;
+            let mut moves = MoveList::new();
+            root.gen_potential_moves(&mut moves);
+            for a_move in &moves {
+                root.lookup_continuation_or_create(a_move, &counter, engine.evaluator())
+                    .await;
+            }
+            let mate_multiplier = root.who_moves.mate_multiplier();
+            moves.sort_by_cached_key(|a_move| {
+                -mate_multiplier * root.find_continuation(a_move).unwrap().score()
+            });
+            moves.truncate(PREFETCHED_REPLIES);
+            'deepening: for depth in 1..=PREFETCH_DEPTH {
+                for a_move in &moves {
+                    if counter.get() >= engine.node_budget {
+                        break 'deepening;
+                    }
+                    let reply = root.find_continuation_mut(a_move).unwrap();
+                    expand(reply, depth, &counter, &mut throttle, engine.evaluator()).await;
+                    if !throttle.running.load(Relaxed) {
+                        break 'deepening;
+                    }
+                }
+            }
+            engine.report(Verbosity::Full, || {
+                format!(
+                    "prefetched {}: {} boards",
+                    moves
+                        .iter()
+                        .map(ToString::to_string)
+                        .collect::<Vec<_>>()
+                        .join(" "),
+                    counter.get()
+                )
+            });
+            state
+        });
+        Prefetch { running, task }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::engine::Engine;
+    use std::cmp::Reverse;
+    use std::time::Duration;
+    use tokio::test;
+    use tokio::time::sleep;
+
+    #[test(flavor = "multi_thread")]
+    async fn likely_replies_are_expanded() {
+        let (engine, mut state) = Engine::new();
+        let (our_move, ..) = engine
+            .best_move_for(&mut state, &Duration::from_millis(200))
+            .await;
+        state.make_a_generated_move(&our_move.unwrap()).await;
+        let before = state.continuation().total_continuation_boards();
+
+        let prefetch = engine.prefetch(state, 50);
+        sleep(Duration::from_millis(200)).await;
+        let mut state = prefetch.stop().await;
+        assert!(state.continuation().total_continuation_boards() > before);
+
+        let mut replies: Vec<_> = state.continuation().keys().copied().collect();
+        replies.sort_by_key(|reply| {
+            Reverse(
+                state
+                    .continuation()
+                    .find_continuation(reply)
+                    .unwrap()
+                    .total_continuation_boards(),
+            )
+        });
+        state.make_a_generated_move(&replies[0]).await;
+        assert!(state.continuation().values().count() > 0);
+    }
+}
@@ -0,0 +1,160 @@
+use crate::baserules::board::PSBoard;
+use crate::baserules::move_list::MoveList;
+use crate::baserules::score::Score;
+use crate::engine::killers::KillerMoves;
+use crate::engine::quiescence::quiescence_score;
+use crate::engine::Engine;
+use futures_util::future::BoxFuture;
+use futures_util::FutureExt;
+
+impl Engine {
+    /// Scores the board with a plain alpha-beta search of the given depth followed by the
+    /// quiescence search. No continuation tree, transposition table or time limit is involved,
+    /// so it suits screening many positions, e.g. puzzle candidates or adjudications.
+    /// The result is from white's perspective like all other scores.
+    pub async fn quick_eval(&self, board: &PSBoard, depth: u8) -> Score {
+        let killers = KillerMoves::default();
+        let mut board = board.clone();
+        board.rescore(self.evaluator());
+        board.who_moves.mate_multiplier()
+            * alpha_beta(
+                &board,
+                depth,
+                0,
+                Score::NEG_INFINITY,
+                Score::INFINITY,
+                &killers,
+                self,
+            )
+            .await
+    }
+}
+
+/// Fail-soft negamax alpha-beta search, scores are relative to the side to move. The boards are
+/// scored with the evaluator of the engine and the stalemates with its draw score.
+fn alpha_beta<'a>(
+    board: &'a PSBoard,
+    depth: u8,
+    ply: u8,
+    mut alpha: Score,
+    beta: Score,
+    killers: &'a KillerMoves,
+    engine: &'a Engine,
+) -> BoxFuture<'a, Score> {
+    async move {
+        if board.score.is_mate() {
+            return board.who_moves.mate_multiplier() * board.score;
+        }
+        // Without a legal move all the moves lose the king, which is only a loss in check
+        if !board.has_legal_move() {
+            let score = if board.is_in_check() {
+                Score::mate(board.who_moves.invert(), 2)
+            } else {
+                engine.draw_score
+            };
+            return board.who_moves.mate_multiplier() * score;
+        }
+        if depth == 0 {
+            return board.who_moves.mate_multiplier()
+                * quiescence_score(board, engine.evaluator()).await;
+        }
+        let mut moves = MoveList::new();
+        board.gen_potential_moves(&mut moves);
+        killers.order_moves(board, ply, &mut moves);
+        let mut best = Score::NEG_INFINITY;
+        for a_move in &moves {
+            let next_board = board.make_move_with(a_move, engine.evaluator()).await;
+            let score = -alpha_beta(
+                &next_board,
+                depth - 1,
+                ply + 1,
+                -beta,
+                -alpha,
+                killers,
+                engine,
+            )
+            .await
+            .backed_up();
+            best = best.max(score);
+            alpha = alpha.max(score);
+            if alpha >= beta {
+                if !board.is_capture(a_move) {
+                    killers.store(ply, a_move);
+                }
+                break;
+            }
+        }
+        best
+    }
+    .boxed()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::baserules::board::PSBoard;
+    use crate::baserules::evaluation::{Evaluator, MaterialCount};
+    use crate::baserules::piece_color::PieceColor::White;
+    use crate::baserules::score::Score;
+    use crate::engine::Engine;
+    use std::sync::Arc;
+    use tokio::test;
+
+    #[test]
+    async fn finds_material_and_mates() {
+        let (engine, _) = Engine::new();
+        let start = PSBoard::default();
+        assert!(engine.quick_eval(&start, 2).await.abs() < Score::centipawns(100));
+
+        // The knight forks the king and the queen, which only the search sees
+        let fork = PSBoard::from_fen("q3k3/8/8/3N4/8/8/8/6K1 w - - 0 1")
+            .await
+            .unwrap();
+        assert_eq!(fork.score, engine.quick_eval(&fork, 0).await);
+        // A lone knight without pawns is worth less than its flat value
+        assert!(engine.quick_eval(&fork, 2).await > Score::centipawns(150));
+
+        // Back rank mate for white in one, the king is captured on the third ply
+        let mate = PSBoard::from_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1")
+            .await
+            .unwrap();
+        let score = engine.quick_eval(&mate, 3).await;
+        assert_eq!(Score::mate(White, 3), score);
+        assert_eq!(Some(1), score.mate_in());
+    }
+
+    #[test]
+    async fn stalemates_are_draws() {
+        let (engine, _) = Engine::new();
+        let stalemate = PSBoard::from_fen("k7/2Q5/1K6/8/8/8/8/8 b - - 0 1")
+            .await
+            .unwrap();
+        assert_eq!(Score::DRAW, engine.quick_eval(&stalemate, 2).await);
+        let checkmate = PSBoard::from_fen("k7/1Q6/1K6/8/8/8/8/8 b - - 0 1")
+            .await
+            .unwrap();
+        assert_eq!(
+            Score::mate(White, 2),
+            engine.quick_eval(&checkmate, 2).await
+        );
+        // Qc7 would stalemate the king, only Qc8 mates
+        let mate = PSBoard::from_fen("k7/8/1K6/8/8/8/8/2Q5 w - - 0 1")
+            .await
+            .unwrap();
+        assert_eq!(Score::mate(White, 3), engine.quick_eval(&mate, 3).await);
+    }
+
+    #[test]
+    async fn boards_are_scored_with_the_engines_evaluator() {
+        let (mut engine, _) = Engine::new();
+        engine.set_evaluator(Arc::new(MaterialCount));
+        let (default, _) = Engine::new();
+        let fork = PSBoard::from_fen("q3k3/8/8/3N4/8/8/8/6K1 w - - 0 1")
+            .await
+            .unwrap();
+        assert_eq!(MaterialCount.eval(&fork), engine.quick_eval(&fork, 0).await);
+        assert_eq!(fork.score, default.quick_eval(&fork, 0).await);
+        assert_ne!(fork.score, MaterialCount.eval(&fork));
+        // The knight takes the queen after the fork
+        assert_eq!(Score::centipawns(300), engine.quick_eval(&fork, 2).await);
+    }
+}
@@ -0,0 +1,165 @@
+use crate::baserules::score::Score;
+use crate::engine::continuation::BoardContinuation;
+
+/// Decides which boards of the game's continuation tree are dropped when the tree has grown over
+/// the node budget of the engine, see `Engine::set_prune_policy`
+pub trait PrunePolicy: Send + Sync {
+    /// Drops boards below the root until at most the given number of boards remain there.
+    /// Returns the number of boards dropped.
+    fn prune(&self, root: &mut BoardContinuation, max_nodes: u32) -> u32;
+}
+
+/// Keeps the best scored continuations for the side to move, the boards right after each kept
+/// board first. This is the policy of the engine unless another one is set.
+pub struct BestFirst;
+
+impl PrunePolicy for BestFirst {
+    fn prune(&self, root: &mut BoardContinuation, max_nodes: u32) -> u32 {
+        root.prune_to(max_nodes)
+    }
+}
+
+/// Keeps the boards closest to the root: the tree is cut below the deepest level that still fits
+/// entirely, so the next searches start from fully known shallow levels
+pub struct ShallowestFirst;
+
+impl PrunePolicy for ShallowestFirst {
+    fn prune(&self, root: &mut BoardContinuation, max_nodes: u32) -> u32 {
+        let before = root.total_continuation_boards();
+        let mut level_sizes = Vec::new();
+        count_levels(root, 0, &mut level_sizes);
+        let mut kept = 0;
+        let kept_levels = level_sizes
+            .iter()
+            .take_while(|&&size| {
+                kept += size;
+                kept <= max_nodes
+            })
+            .count();
+        if kept_levels == 0 {
+            // Not even the moves of the root fit, only the best of them are kept
+            return root.prune_to(max_nodes);
+        }
+        cut_below(root, kept_levels);
+        before - root.total_continuation_boards()
+    }
+}
+
+/// Drops the continuations scored more than the margin worse than the best one for the side to
+/// move, then the least promising ones if the tree is still over the budget
+pub struct ScoreMargin(pub Score);
+
+impl PrunePolicy for ScoreMargin {
+    fn prune(&self, root: &mut BoardContinuation, max_nodes: u32) -> u32 {
+        let before = root.total_continuation_boards();
+        drop_outside_margin(root, self.0);
+        root.prune_to(max_nodes);
+        before - root.total_continuation_boards()
+    }
+}
+
+/// Keeps the lines the searches spent the most effort on, measured by the number of boards
+/// explored below them
+pub struct MostExplored;
+
+impl PrunePolicy for MostExplored {
+    fn prune(&self, root: &mut BoardContinuation, max_nodes: u32) -> u32 {
+        root.prune_by(max_nodes, &|_, next_board| {
+            next_board.total_continuation_boards()
+        })
+    }
+}
+
+fn count_levels(board: &BoardContinuation, depth: usize, level_sizes: &mut Vec<u32>) {
+    for next_board in board.values() {
+        if level_sizes.len() == depth {
+            level_sizes.push(0);
+        }
+        level_sizes[depth] += 1;
+        count_levels(next_board, depth + 1, level_sizes);
+    }
+}
+
+fn cut_below(board: &mut BoardContinuation, levels_kept: usize) {
+    if levels_kept == 0 {
+        board.retain_continuations(|_, _| false);
+    } else {
+        for next_board in board.values_mut() {
+            cut_below(next_board, levels_kept - 1);
+        }
+    }
+}
+
+fn drop_outside_margin(board: &mut BoardContinuation, margin: Score) {
+    let mate_multiplier = board.who_moves.mate_multiplier();
+    let Some(best) = board
+        .values()
+        .map(|next_board| mate_multiplier * next_board.score())
+        .max()
+    else {
+        return;
+    };
+    board.retain_continuations(|_, next_board| {
+        best - mate_multiplier * next_board.score() <= margin
+    });
+    for next_board in board.values_mut() {
+        drop_outside_margin(next_board, margin);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::baserules::board::PSBoard;
+    use crate::baserules::board_rep::PossibleMove;
+    use crate::baserules::score::Score;
+    use crate::engine::continuation::BoardContinuation;
+    use crate::engine::pruning::{MostExplored, PrunePolicy, ScoreMargin, ShallowestFirst};
+    use futures_util::StreamExt;
+    use tokio::test;
+
+    /// All moves of the starting position, with all replies to 1. e4 below them
+    async fn explored_tree() -> BoardContinuation {
+        let mut root = BoardContinuation::new(PSBoard::default());
+        let successors: Vec<_> = PSBoard::default().successors().collect().await;
+        for (a_move, next_board) in successors {
+            root.insert_psboard(&a_move, next_board);
+        }
+        let e2e4 = root
+            .find_continuation_mut(&PossibleMove::simple_from_uci("e2e4").unwrap())
+            .unwrap();
+        let replies: Vec<_> = e2e4.successors().collect().await;
+        for (a_move, next_board) in replies {
+            e2e4.insert_psboard(&a_move, next_board);
+        }
+        root
+    }
+
+    #[test]
+    async fn shallow_levels_are_kept_whole() {
+        let mut root = explored_tree().await;
+        assert_eq!(40, root.total_continuation_boards());
+        assert_eq!(20, ShallowestFirst.prune(&mut root, 39));
+        assert_eq!(20, root.total_continuation_boards());
+        assert_eq!(10, ShallowestFirst.prune(&mut root, 10));
+        assert_eq!(10, root.total_continuation_boards());
+    }
+
+    #[test]
+    async fn only_moves_within_the_margin_are_kept() {
+        let mut root = explored_tree().await;
+        let e2e4 = PossibleMove::simple_from_uci("e2e4").unwrap();
+        // The other moves and the replies only differ by the squares of their pieces
+        root.find_continuation_mut(&e2e4).unwrap().adjusted_score = Some(Score::centipawns(500));
+        assert_eq!(19, ScoreMargin(Score::centipawns(100)).prune(&mut root, 40));
+        assert_eq!(vec![e2e4], root.keys().copied().collect::<Vec<_>>());
+        assert_eq!(21, root.total_continuation_boards());
+    }
+
+    #[test]
+    async fn most_explored_lines_are_kept() {
+        let mut root = explored_tree().await;
+        assert_eq!(35, MostExplored.prune(&mut root, 5));
+        assert_eq!(5, root.keys().count());
+        assert!(root.continuation_exists(&PossibleMove::simple_from_uci("e2e4").unwrap()));
+    }
+}
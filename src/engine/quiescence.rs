@@ -0,0 +1,91 @@
+use crate::baserules::board::PSBoard;
+use crate::baserules::evaluation::Evaluator;
+use crate::baserules::move_list::MoveList;
+use crate::baserules::score::Score;
+
+/// The maximum number of captures followed after the main search depth is exhausted
+pub const QUIESCENCE_DEPTH: u8 = 8;
+
+/// Evaluates the board only after the capture sequences possible on it are played out, so the
+/// leaves of the search are not scored in the middle of an exchange.
+/// The captures are scored with the evaluator, the board itself keeps its score.
+/// The result is from white's perspective like all other scores.
+pub async fn quiescence_score(board: &PSBoard, evaluator: &dyn Evaluator) -> Score {
+    // The captures are made and taken back on a single copy of the board
+    let mut board = board.clone();
+    board.who_moves.mate_multiplier()
+        * negamax_captures(
+            &mut board,
+            Score::NEG_INFINITY,
+            Score::INFINITY,
+            QUIESCENCE_DEPTH,
+            evaluator,
+        )
+}
+
+/// Captures only alpha-beta search, scores are relative to the side to move
+fn negamax_captures(
+    board: &mut PSBoard,
+    mut alpha: Score,
+    beta: Score,
+    depth: u8,
+    evaluator: &dyn Evaluator,
+) -> Score {
+    let stand_pat = board.who_moves.mate_multiplier() * board.score;
+    if board.score.is_mate() || depth == 0 || stand_pat >= beta {
+        return stand_pat;
+    }
+    alpha = alpha.max(stand_pat);
+    let mut moves = MoveList::new();
+    board.gen_potential_moves(&mut moves);
+    moves.retain(|a_move| board.is_capture(a_move));
+    board.order_moves(&mut moves);
+    let mut best = stand_pat;
+    for capture in &moves {
+        let undo = board.make_move_in_place_with(capture, evaluator);
+        let score = -negamax_captures(board, -beta, -alpha, depth - 1, evaluator).backed_up();
+        board.unmake_move(undo);
+        if score > best {
+            best = score;
+            if score >= beta {
+                break;
+            }
+            alpha = alpha.max(score);
+        }
+    }
+    best
+}
+
+#[cfg(test)]
+mod test {
+    use crate::baserules::board::PSBoard;
+    use crate::baserules::evaluation::Imbalance;
+    use crate::baserules::score::Score;
+    use crate::engine::quiescence::quiescence_score;
+    use tokio::test;
+
+    #[test]
+    async fn quiet_position_keeps_static_score() {
+        let board = PSBoard::default();
+        assert_eq!(board.score, quiescence_score(&board, &Imbalance).await);
+    }
+
+    #[test]
+    async fn defended_pawn_is_not_won() {
+        // The queen can take the pawn on d5, but loses itself to the e6 pawn
+        let board = PSBoard::from_fen("4k3/8/4p3/3p4/8/8/3Q4/4K3 w - - 0 1")
+            .await
+            .unwrap();
+        let static_score = board.score;
+        assert_eq!(static_score, quiescence_score(&board, &Imbalance).await);
+
+        // Once the queen has taken, the recapture is seen too
+        let after_capture = PSBoard::from_fen("4k3/8/4p3/3Q4/8/8/8/4K3 b - - 0 1")
+            .await
+            .unwrap();
+        assert!(
+            quiescence_score(&after_capture, &Imbalance).await
+                < after_capture.score - Score::centipawns(800)
+        );
+    }
+}
@@ -0,0 +1,190 @@
+use crate::baserules::board_rep::PossibleMove;
+use crate::baserules::score::Score;
+use crate::engine::alphabeta::PvsEngine;
+use crate::engine::continuation::BoardContinuation;
+use crate::engine::mcts::MctsEngine;
+use crate::engine::timing::TimeAllocation;
+use crate::engine::{Engine, ExtEngine, SearchAlgorithm};
+use async_trait::async_trait;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+
+/// What a search may spend on the root and what it has to know about the game leading to it
+#[derive(Debug, Clone)]
+pub struct SearchLimits {
+    /// The time allocated to the search, the engine stops the search once it is up
+    pub allocation: Option<TimeAllocation>,
+    /// The hashes of the positions played before the root, boards repeating them are draws
+    pub game_path: Vec<u64>,
+    /// Turns false once the search has to return its best move
+    pub running: Arc<AtomicBool>,
+}
+
+/// The move a search has chosen
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchResult {
+    pub best_move: Option<PossibleMove>,
+    /// From white's perspective like all other scores
+    pub score: Score,
+    /// The number of boards the search created
+    pub boards: u32,
+    /// The deepest ply the search reached
+    pub depth: u8,
+}
+
+/// A way of choosing the move of the root of the continuation tree. The engine takes care of
+/// everything around the search, e.g. the time management, the pruning of the tree and the
+/// tablebases, see `Engine::best_move_with`.
+#[async_trait]
+pub trait Search: Send {
+    async fn best_move(
+        &mut self,
+        root: &mut BoardContinuation,
+        limits: &SearchLimits,
+    ) -> SearchResult;
+}
+
+/// The averaging exploration of the continuation tree
+pub struct ExplorationSearch(pub Engine);
+
+/// The principal variation search, a kind of alpha-beta search
+pub struct AlphaBetaSearch(pub Engine);
+
+/// The Monte Carlo tree search
+pub struct MonteCarloSearch(pub Engine);
+
+#[async_trait]
+impl Search for ExplorationSearch {
+    async fn best_move(
+        &mut self,
+        root: &mut BoardContinuation,
+        limits: &SearchLimits,
+    ) -> SearchResult {
+        let ((best_move, score), boards, depth) =
+            Engine::manage_counter(ExtEngine(self.0.clone(), root, limits.game_path.clone())).await;
+        SearchResult {
+            best_move,
+            score,
+            boards,
+            depth,
+        }
+    }
+}
+
+#[async_trait]
+impl Search for AlphaBetaSearch {
+    async fn best_move(
+        &mut self,
+        root: &mut BoardContinuation,
+        limits: &SearchLimits,
+    ) -> SearchResult {
+        let ((best_move, score), boards, depth) =
+            Engine::manage_counter(PvsEngine(self.0.clone(), root, limits.game_path.clone())).await;
+        SearchResult {
+            best_move,
+            score,
+            boards,
+            depth,
+        }
+    }
+}
+
+#[async_trait]
+impl Search for MonteCarloSearch {
+    async fn best_move(
+        &mut self,
+        root: &mut BoardContinuation,
+        limits: &SearchLimits,
+    ) -> SearchResult {
+        let ((best_move, score), boards, depth) =
+            Engine::manage_counter(MctsEngine(self.0.clone(), root, limits.game_path.clone()))
+                .await;
+        SearchResult {
+            best_move,
+            score,
+            boards,
+            depth,
+        }
+    }
+}
+
+impl SearchAlgorithm {
+    /// The search of the algorithm, running on the engine
+    pub fn search(self, engine: &Engine) -> Box<dyn Search> {
+        let engine = engine.clone();
+        match self {
+            SearchAlgorithm::Exploration => Box::new(ExplorationSearch(engine)),
+            SearchAlgorithm::Pvs => Box::new(AlphaBetaSearch(engine)),
+            SearchAlgorithm::Mcts => Box::new(MonteCarloSearch(engine)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::baserules::move_list::MoveList;
+    use crate::baserules::score::Score;
+    use crate::engine::continuation::BoardContinuation;
+    use crate::engine::search::{Search, SearchLimits, SearchResult};
+    use crate::engine::timing::TimeAllocation;
+    use crate::engine::{Engine, SearchAlgorithm};
+    use async_trait::async_trait;
+    use std::sync::atomic::Ordering::Relaxed;
+    use std::time::Duration;
+    use tokio::test;
+
+    /// Plays the last move generated, once the engine stops it
+    struct LastMove {
+        searched_after: usize,
+    }
+
+    #[async_trait]
+    impl Search for LastMove {
+        async fn best_move(
+            &mut self,
+            root: &mut BoardContinuation,
+            limits: &SearchLimits,
+        ) -> SearchResult {
+            while limits.running.load(Relaxed) {
+                tokio::task::yield_now().await;
+            }
+            self.searched_after = limits.game_path.len();
+            let mut moves = MoveList::new();
+            root.gen_potential_moves(&mut moves);
+            SearchResult {
+                best_move: moves.pop(),
+                score: Score::DRAW,
+                boards: 0,
+                depth: 1,
+            }
+        }
+    }
+
+    #[test(flavor = "multi_thread")]
+    async fn searches_are_swapped() {
+        let (engine, mut state) = Engine::new();
+        state.make_a_human_move_or_panic("Nf3").await;
+        let mut search = LastMove { searched_after: 0 };
+        let allocation = TimeAllocation::from(&Duration::from_millis(50));
+        let (best_move, score, _, _) = engine
+            .best_move_with(&mut state, &mut search, allocation)
+            .await;
+        let mut moves = MoveList::new();
+        state.psboard().gen_potential_moves(&mut moves);
+        assert_eq!(moves.pop(), best_move);
+        assert_eq!(Score::DRAW, score);
+        assert_eq!(1, search.searched_after);
+
+        for algorithm in [
+            SearchAlgorithm::Exploration,
+            SearchAlgorithm::Pvs,
+            SearchAlgorithm::Mcts,
+        ] {
+            let mut search = algorithm.search(&engine);
+            let (best_move, _, _, _) = engine
+                .best_move_with(&mut state, search.as_mut(), allocation)
+                .await;
+            assert!(best_move.is_some(), "{algorithm:?} found no move");
+        }
+    }
+}
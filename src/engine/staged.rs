@@ -0,0 +1,219 @@
+use crate::baserules::board::PSBoard;
+use crate::baserules::board_rep::PossibleMove;
+use crate::baserules::move_list::MoveList;
+use crate::engine::killers::KillerMoves;
+use std::cmp::Reverse;
+
+/// The phases of the staged move generation, in the order their moves are tried
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+enum Stage {
+    TtMove,
+    Captures,
+    Killers,
+    Quiets,
+    Evasions,
+    Done,
+}
+
+/// Generates the moves of a board in stages: the best move of an earlier search of the position,
+/// the captures in MVV-LVA order, the killers of the ply and then the rest of the quiet moves.
+/// A stage is only generated when the moves of the earlier ones are used up, so a search cutting
+/// off on a capture never generates the quiet moves. In check all the evasions come in one stage.
+/// The board is passed to each `next_move` instead of kept, so the search can extend the
+/// continuations of the board between its moves.
+pub struct StagedMoves {
+    stage: Stage,
+    tt_move: Option<PossibleMove>,
+    killers: [Option<PossibleMove>; 2],
+    moves: MoveList,
+    next: usize,
+}
+
+impl StagedMoves {
+    pub fn new(
+        board: &PSBoard,
+        tt_move: Option<PossibleMove>,
+        killers: &KillerMoves,
+        ply: u8,
+    ) -> Self {
+        let mut ply_killers = [None; 2];
+        for (slot, killer) in ply_killers.iter_mut().zip(killers.killers(ply)) {
+            *slot = Some(killer);
+        }
+        Self {
+            stage: if board.is_in_check() {
+                Stage::Evasions
+            } else {
+                Stage::TtMove
+            },
+            tt_move,
+            killers: ply_killers,
+            moves: MoveList::new(),
+            next: 0,
+        }
+    }
+
+    /// The next move to try on the board, the stages are generated as they are reached
+    pub fn next_move(&mut self, board: &PSBoard) -> Option<PossibleMove> {
+        loop {
+            if let Some(a_move) = self.moves.get(self.next) {
+                self.next += 1;
+                return Some(*a_move);
+            }
+            self.moves.clear();
+            self.next = 0;
+            match self.stage {
+                Stage::TtMove => {
+                    self.stage = Stage::Captures;
+                    // The stored move may come from another position with the same hash bucket
+                    if let Some(tt_move) = self.tt_move.filter(|a_move| generates(board, a_move)) {
+                        self.moves.push(tt_move);
+                    }
+                }
+                Stage::Captures => {
+                    self.stage = Stage::Killers;
+                    board.gen_captures(&mut self.moves);
+                    self.moves.retain(|a_move| Some(*a_move) != self.tt_move);
+                    self.moves
+                        .sort_by_cached_key(|a_move| Reverse(board.mvv_lva(a_move)));
+                }
+                Stage::Killers => {
+                    self.stage = Stage::Quiets;
+                    for killer in self.killers.into_iter().flatten() {
+                        if Some(killer) != self.tt_move
+                            && !board.is_capture(&killer)
+                            && generates(board, &killer)
+                        {
+                            self.moves.push(killer);
+                        }
+                    }
+                }
+                Stage::Quiets => {
+                    self.stage = Stage::Done;
+                    board.gen_potential_moves(&mut self.moves);
+                    let tried = [self.tt_move, self.killers[0], self.killers[1]];
+                    self.moves.retain(|a_move| {
+                        !board.is_capture(a_move) && !tried.contains(&Some(*a_move))
+                    });
+                }
+                Stage::Evasions => {
+                    self.stage = Stage::Done;
+                    board.gen_evasions(&mut self.moves);
+                    let (tt_move, killers) = (self.tt_move, self.killers);
+                    self.moves.sort_by_cached_key(|a_move| {
+                        (
+                            Some(*a_move) != tt_move,
+                            Reverse(board.mvv_lva(a_move)),
+                            killers.iter().position(|killer| *killer == Some(*a_move)),
+                        )
+                    });
+                }
+                Stage::Done => return None,
+            }
+        }
+    }
+
+    /// The moves of the board in the order of the stages
+    pub fn iter(mut self, board: &PSBoard) -> impl Iterator<Item = PossibleMove> + '_ {
+        std::iter::from_fn(move || self.next_move(board))
+    }
+}
+
+/// Tells if the piece on the starting square of the move would generate it on the board
+fn generates(board: &PSBoard, a_move: &PossibleMove) -> bool {
+    let Some(piece) = board[a_move.the_move.from].filter(|piece| piece.color == board.who_moves)
+    else {
+        return false;
+    };
+    let mut moves = MoveList::new();
+    piece
+        .kind
+        .gen_moves(board, a_move.the_move.from, &mut moves);
+    moves.contains(a_move)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::baserules::board::PSBoard;
+    use crate::baserules::board_rep::PossibleMove;
+    use crate::baserules::move_list::MoveList;
+    use crate::engine::killers::KillerMoves;
+    use crate::engine::staged::StagedMoves;
+    use std::collections::HashSet;
+    use tokio::test;
+
+    #[test]
+    async fn stages_come_in_order() {
+        let board = PSBoard::from_fen("4k3/8/3p4/2q1p3/3PQ3/8/8/4K1N1 w - - 0 1")
+            .await
+            .unwrap();
+        let killers = KillerMoves::default();
+        let killer = PossibleMove::simple_from_uci("g1f3").unwrap();
+        killers.store(3, &killer);
+        let tt_move = PossibleMove::simple_from_uci("e1d2").unwrap();
+        let staged: Vec<String> = StagedMoves::new(&board, Some(tt_move), &killers, 3)
+            .iter(&board)
+            .map(|a_move| a_move.to_string())
+            .collect();
+        assert_eq!(
+            vec!["e1d2", "d4c5", "d4e5", "e4e5", "g1f3"],
+            staged[..5].to_vec()
+        );
+        let mut moves = MoveList::new();
+        board.gen_potential_moves(&mut moves);
+        assert_eq!(moves.len(), staged.len());
+        let expected: HashSet<String> = moves.iter().map(ToString::to_string).collect();
+        assert_eq!(expected, staged.into_iter().collect());
+    }
+
+    #[test]
+    async fn moves_of_other_positions_are_skipped() {
+        let board = PSBoard::default();
+        let killers = KillerMoves::default();
+        killers.store(0, &PossibleMove::simple_from_uci("d1h5").unwrap());
+        let foreign = PossibleMove::simple_from_uci("e4e5").unwrap();
+        let staged: Vec<PossibleMove> = StagedMoves::new(&board, Some(foreign), &killers, 0)
+            .iter(&board)
+            .collect();
+        assert_eq!(20, staged.len());
+        assert!(!staged.contains(&foreign));
+    }
+
+    #[test]
+    async fn only_the_evasions_in_check() {
+        let board = PSBoard::from_fen("4k3/8/8/8/8/8/3q4/4K3 w - - 0 1")
+            .await
+            .unwrap();
+        let staged: Vec<String> = StagedMoves::new(&board, None, &KillerMoves::default(), 0)
+            .iter(&board)
+            .map(|a_move| a_move.to_string())
+            .collect();
+        assert_eq!("e1d2", staged[0]);
+        let mut evasions = MoveList::new();
+        board.gen_evasions(&mut evasions);
+        assert_eq!(evasions.len(), staged.len());
+    }
+    #[test]
+    async fn chess960_castling_onto_the_rook_is_quiet() {
+        // The king on f1 castles short to g1, where its rook stands
+        let board = PSBoard::chess960(3);
+        let castling = board.find_uci_move("f1g1").unwrap();
+        assert!(castling.rook.is_some());
+        assert!(!board.is_capture(&castling));
+        let staged: Vec<PossibleMove> = StagedMoves::new(&board, None, &KillerMoves::default(), 0)
+            .iter(&board)
+            .collect();
+        assert!(staged.contains(&castling));
+        let mut moves = MoveList::new();
+        board.gen_potential_moves(&mut moves);
+        assert_eq!(moves.len(), staged.len());
+        // The king on g1 stays where it is
+        let board = PSBoard::try_from_fen("4k3/8/8/8/8/8/8/6KR w H - 0 1").unwrap();
+        let castling = board.find_uci_move("g1h1").unwrap();
+        assert!(castling.rook.is_some());
+        let staged: Vec<PossibleMove> = StagedMoves::new(&board, None, &KillerMoves::default(), 0)
+            .iter(&board)
+            .collect();
+        assert!(staged.contains(&castling));
+    }
+}
@@ -0,0 +1,137 @@
+use crate::baserules::score::Score;
+use crate::engine::continuation::SIMILAR_SCORE_DIFFERENCE;
+use crate::engine::timing::TimeAllocation;
+use std::str::FromStr;
+
+/// Named presets of how well the engine plays, for opponents who do not want to face it at full
+/// strength
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum Strength {
+    Beginner,
+    Club,
+    Master,
+    /// No limits, the engine plays as well as it can
+    #[default]
+    Max,
+}
+
+impl FromStr for Strength {
+    type Err = String;
+
+    fn from_str(level: &str) -> Result<Self, Self::Err> {
+        match level.trim().to_lowercase().as_str() {
+            "beginner" => Ok(Strength::Beginner),
+            "club" => Ok(Strength::Club),
+            "master" => Ok(Strength::Master),
+            "" | "max" => Ok(Strength::Max),
+            _ => Err(format!("Unknown strength: {level}")),
+        }
+    }
+}
+
+/// The limits a strength preset puts on the searches
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct StrengthSettings {
+    /// The deepest iteration started, none for no limit. The first iteration always runs.
+    pub max_depth: Option<u8>,
+    /// The root moves scored at most this much worse than the best one are played just as likely
+    pub randomness: Score,
+    /// Weights the choice between these moves towards the better ones, the smaller it is the
+    /// more. Without it, the moves are chosen uniformly.
+    pub temperature: Option<Score>,
+    /// The share of the allocated thinking time used, between 0 and 1
+    pub time_use: f32,
+}
+
+impl Strength {
+    pub const fn settings(self) -> StrengthSettings {
+        match self {
+            Strength::Beginner => StrengthSettings {
+                max_depth: Some(3),
+                randomness: Score::centipawns(150),
+                temperature: Some(Score::centipawns(60)),
+                time_use: 0.25,
+            },
+            Strength::Club => StrengthSettings {
+                max_depth: Some(5),
+                randomness: Score::centipawns(50),
+                temperature: Some(Score::centipawns(20)),
+                time_use: 0.5,
+            },
+            Strength::Master => StrengthSettings {
+                max_depth: None,
+                randomness: Score::centipawns(15),
+                temperature: None,
+                time_use: 1.0,
+            },
+            Strength::Max => StrengthSettings {
+                max_depth: None,
+                randomness: SIMILAR_SCORE_DIFFERENCE,
+                temperature: None,
+                time_use: 1.0,
+            },
+        }
+    }
+}
+
+impl From<Strength> for StrengthSettings {
+    fn from(strength: Strength) -> Self {
+        strength.settings()
+    }
+}
+
+impl Default for StrengthSettings {
+    fn default() -> Self {
+        Strength::default().settings()
+    }
+}
+
+impl StrengthSettings {
+    #[inline]
+    pub fn allows_depth(&self, depth: u8) -> bool {
+        self.max_depth.is_none_or(|max_depth| depth <= max_depth)
+    }
+
+    /// Shortens the time allocation to the share of it used
+    pub fn limit_time(&self, allocation: TimeAllocation) -> TimeAllocation {
+        TimeAllocation {
+            target: allocation.target.mul_f32(self.time_use),
+            maximum: allocation.maximum.mul_f32(self.time_use),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::engine::strength::Strength;
+    use crate::engine::timing::TimeAllocation;
+    use std::time::Duration;
+    use tokio::test;
+
+    #[test]
+    async fn presets_are_parsed() {
+        assert_eq!(Ok(Strength::Beginner), " Beginner\n".parse());
+        assert_eq!(Ok(Strength::Club), "club".parse());
+        assert_eq!(Ok(Strength::Max), "".parse());
+        assert!("grandmaster".parse::<Strength>().is_err());
+    }
+
+    #[test]
+    async fn weaker_presets_think_less() {
+        let allocation = TimeAllocation {
+            target: Duration::from_secs(4),
+            maximum: Duration::from_secs(8),
+        };
+        let beginner = Strength::Beginner.settings();
+        assert_eq!(
+            Duration::from_secs(1),
+            beginner.limit_time(allocation).target
+        );
+        assert!(beginner.allows_depth(3));
+        assert!(!beginner.allows_depth(5));
+        let max = Strength::Max.settings();
+        assert_eq!(allocation.maximum, max.limit_time(allocation).maximum);
+        assert!(max.allows_depth(u8::MAX));
+        assert!(beginner.randomness > max.randomness);
+    }
+}
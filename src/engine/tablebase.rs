@@ -0,0 +1,143 @@
+use crate::baserules::board::PSBoard;
+use crate::baserules::board_rep::PossibleMove;
+use crate::baserules::move_list::MoveList;
+use crate::baserules::score::Score;
+use crate::util::IntResult;
+use async_trait::async_trait;
+use reqwest::Client;
+use serde_json::Value;
+
+/// Positions with at most this many pieces (kings included) are looked up instead of searched
+pub const MAX_TABLEBASE_PIECES: usize = 5;
+
+/// The score reported for tablebase wins, it is below the resignation threshold as the
+/// opponent still has to find the best defence
+pub const TABLEBASE_WIN: Score = Score::centipawns(10_000);
+
+/// The outcome of a position for the side to move with best play, according to the tablebase
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TablebaseOutcome {
+    Win,
+    Draw,
+    Loss,
+}
+
+/// The DTZ optimal move of a position
+#[derive(Debug, Clone, PartialEq)]
+pub struct TablebaseMove {
+    pub the_move: PossibleMove,
+    pub outcome: TablebaseOutcome,
+}
+
+impl TablebaseMove {
+    /// The white-relative score of the position the move is played in
+    pub fn score(&self, board: &PSBoard) -> Score {
+        board.who_moves.mate_multiplier()
+            * match self.outcome {
+                TablebaseOutcome::Win => TABLEBASE_WIN,
+                TablebaseOutcome::Draw => Score::DRAW,
+                TablebaseOutcome::Loss => -TABLEBASE_WIN,
+            }
+    }
+}
+
+#[async_trait]
+pub trait Tablebase: Send + Sync {
+    /// Looks up the DTZ optimal move, `None` if the position is not covered
+    async fn best_move(&self, board: &PSBoard) -> IntResult<Option<TablebaseMove>>;
+}
+
+impl PSBoard {
+    pub fn piece_count(&self) -> usize {
+        self.raw.occupied().count_ones() as usize
+    }
+
+    /// Finds the move with the given uci form amongst the moves of the board
+    pub fn find_uci_move(&self, uci: &str) -> Option<PossibleMove> {
+        let mut moves = MoveList::new();
+        self.gen_potential_moves(&mut moves);
+        moves.into_iter().find(|a_move| self.uci(a_move) == uci)
+    }
+}
+
+/// Queries the online tablebase of lichess, so no local tablebase files are needed
+pub struct LichessTablebase {
+    client: Client,
+}
+
+impl LichessTablebase {
+    pub fn new(client: Client) -> Self {
+        Self { client }
+    }
+}
+
+#[async_trait]
+impl Tablebase for LichessTablebase {
+    async fn best_move(&self, board: &PSBoard) -> IntResult<Option<TablebaseMove>> {
+        if board.piece_count() > MAX_TABLEBASE_PIECES {
+            return Ok(None);
+        }
+        let response: Value = self
+            .client
+            .get("https://tablebase.lichess.ovh/standard")
+            .query(&[("fen", board.to_fen())])
+            .send()
+            .await?
+            .json()
+            .await?;
+        let outcome = match response["category"].as_str() {
+            Some("win") => TablebaseOutcome::Win,
+            Some("loss") => TablebaseOutcome::Loss,
+            Some("draw" | "cursed-win" | "blessed-loss") => TablebaseOutcome::Draw,
+            _ => return Ok(None),
+        };
+        // The moves are listed best first
+        let best_uci = response["moves"][0]["uci"].as_str();
+        Ok(best_uci
+            .and_then(|uci| board.find_uci_move(uci))
+            .map(|the_move| TablebaseMove { the_move, outcome }))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::baserules::board::PSBoard;
+    use crate::baserules::board_rep::PossibleMove;
+    use crate::engine::tablebase::{Tablebase, TablebaseMove, TablebaseOutcome, TABLEBASE_WIN};
+    use crate::engine::Engine;
+    use crate::util::IntResult;
+    use async_trait::async_trait;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use tokio::test;
+
+    struct FixedTablebase;
+
+    #[async_trait]
+    impl Tablebase for FixedTablebase {
+        async fn best_move(&self, board: &PSBoard) -> IntResult<Option<TablebaseMove>> {
+            Ok(board.find_uci_move("a1a8").map(|the_move| TablebaseMove {
+                the_move,
+                outcome: TablebaseOutcome::Win,
+            }))
+        }
+    }
+
+    #[test]
+    async fn tablebase_move_skips_search() {
+        let (mut engine, mut state) = Engine::from_fen("7k/8/6K1/8/8/8/8/R7 w - - 0 1").await;
+        let infos = Arc::new(Mutex::new(Vec::new()));
+        let infos_clone = infos.clone();
+        engine.set_tablebase(Arc::new(FixedTablebase));
+        engine.set_info_callback(Arc::new(move |info: &str| {
+            infos_clone.lock().unwrap().push(info.to_string())
+        }));
+        let (best_move, score, board_count, _) = engine
+            .best_move_for(&mut state, &Duration::from_secs(60))
+            .await;
+        assert_eq!(PossibleMove::simple_from_uci("a1a8").ok(), best_move);
+        assert_eq!(TABLEBASE_WIN, score);
+        assert_eq!(0, board_count);
+        assert!(infos.lock().unwrap()[0].starts_with("tb hit"));
+    }
+}
@@ -0,0 +1,284 @@
+use crate::baserules::board_rep::{PackedMove, PossibleMove};
+use crate::baserules::piece_color::PieceColor;
+use std::fmt::{Display, Formatter};
+use std::sync::atomic::AtomicU32;
+use std::sync::atomic::Ordering::Relaxed;
+use std::time::Duration;
+
+/// Kept in reserve on every move for the network and the move transfer
+pub const MOVE_OVERHEAD: Duration = Duration::from_millis(50);
+/// The number of moves a game is expected to last when planning without moves to go
+const EXPECTED_GAME_LENGTH: u16 = 60;
+/// The fewest moves the remaining time is planned for without moves to go
+const MIN_MOVES_TO_GO: u16 = 20;
+/// How many times its target a search may run when its best move keeps changing
+const PANIC_MULTIPLIER: u32 = 3;
+/// Marks the absence of a best move or a deadline in the atomics of `SearchTime`
+const NONE: u32 = u32::MAX;
+
+/// The clock of the side to move with the time control of the game
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct GameClock {
+    pub remaining: Duration,
+    pub increment: Duration,
+    /// The number of moves to be made until the next time control, if there is one
+    pub moves_to_go: Option<u32>,
+}
+
+/// How long to think about a move
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct TimeAllocation {
+    /// The time spent on a move when the search is stable
+    pub target: Duration,
+    /// The time the search may be extended to when the best move keeps changing
+    pub maximum: Duration,
+}
+
+impl GameClock {
+    /// The clock of the given side from the times of a uci `go` command or a lichess game
+    /// state, all of them in milliseconds
+    pub fn from_uci(
+        color: PieceColor,
+        wtime: u64,
+        btime: u64,
+        winc: u64,
+        binc: u64,
+        moves_to_go: Option<u32>,
+    ) -> Self {
+        let (time, inc) = match color {
+            PieceColor::White => (wtime, winc),
+            PieceColor::Black => (btime, binc),
+        };
+        GameClock {
+            remaining: Duration::from_millis(time),
+            increment: Duration::from_millis(inc),
+            moves_to_go,
+        }
+    }
+
+    /// Decides how long to think about the move with the given move number. The remaining time
+    /// is spread over the moves to go, or over the expected rest of the game, and most of the
+    /// increment is spent right away.
+    pub fn allocate(&self, move_count: u16) -> TimeAllocation {
+        let usable = self.remaining.saturating_sub(MOVE_OVERHEAD);
+        let moves_to_go = self.moves_to_go.unwrap_or_else(|| {
+            u32::from(
+                EXPECTED_GAME_LENGTH
+                    .saturating_sub(move_count)
+                    .max(MIN_MOVES_TO_GO),
+            )
+        });
+        let target = (usable / moves_to_go.max(1) + self.increment * 3 / 4)
+            .min(usable)
+            .max(Duration::from_millis(1));
+        let maximum = (target * PANIC_MULTIPLIER).min(usable / 3).max(target);
+        TimeAllocation { target, maximum }
+    }
+}
+
+/// How often the best move changed between the completed iterations of a search
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct BestMoveStability {
+    pub iterations: u32,
+    /// The iterations ending with a different best move than the one before
+    pub changes: u32,
+    /// The number of the last iterations ending with the final best move
+    pub stable_for: u32,
+}
+
+impl BestMoveStability {
+    /// The share of the iterations after the first that kept the best move, between 0 and 1.
+    /// A single iteration tells nothing about the stability, so it is 0 then.
+    pub fn confidence(&self) -> f32 {
+        if self.iterations < 2 {
+            0.0
+        } else {
+            1.0 - self.changes as f32 / (self.iterations - 1) as f32
+        }
+    }
+}
+
+impl Display for BestMoveStability {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:.0}% (best move kept for the last {} of {} iterations)",
+            100.0 * self.confidence(),
+            self.stable_for,
+            self.iterations
+        )
+    }
+}
+
+/// A fixed time for the move without any extension
+impl From<&Duration> for TimeAllocation {
+    fn from(duration: &Duration) -> Self {
+        TimeAllocation {
+            target: *duration,
+            maximum: *duration,
+        }
+    }
+}
+
+/// The time limits of the current search, shared by the search and its watchdog. The times are
+/// milliseconds from the start of the search.
+pub(crate) struct SearchTime {
+    deadline: AtomicU32,
+    maximum: AtomicU32,
+    /// The deadline is moved by this much times the number of changes so far on each change of
+    /// the best move, so the more unstable the search is, the longer it may think
+    extension: AtomicU32,
+    /// The packed best move of the last completed iteration
+    best_move: AtomicU32,
+    iterations: AtomicU32,
+    changes: AtomicU32,
+    stable_for: AtomicU32,
+}
+
+impl Default for SearchTime {
+    fn default() -> Self {
+        SearchTime {
+            deadline: AtomicU32::new(NONE),
+            maximum: AtomicU32::new(NONE),
+            extension: AtomicU32::new(0),
+            best_move: AtomicU32::new(NONE),
+            iterations: AtomicU32::new(0),
+            changes: AtomicU32::new(0),
+            stable_for: AtomicU32::new(0),
+        }
+    }
+}
+
+fn to_millis(duration: Duration) -> u32 {
+    duration.as_millis().min(u128::from(NONE - 1)) as u32
+}
+
+impl SearchTime {
+    pub(crate) fn start(&self, allocation: Option<TimeAllocation>) {
+        let (deadline, maximum) = allocation.map_or((NONE, NONE), |allocation| {
+            (to_millis(allocation.target), to_millis(allocation.maximum))
+        });
+        self.deadline.store(deadline, Relaxed);
+        self.maximum.store(maximum, Relaxed);
+        self.extension.store(deadline / 2, Relaxed);
+        self.best_move.store(NONE, Relaxed);
+        self.iterations.store(0, Relaxed);
+        self.changes.store(0, Relaxed);
+        self.stable_for.store(0, Relaxed);
+    }
+
+    /// The stability of the best move in the current or the last search
+    pub(crate) fn stability(&self) -> BestMoveStability {
+        BestMoveStability {
+            iterations: self.iterations.load(Relaxed),
+            changes: self.changes.load(Relaxed),
+            stable_for: self.stable_for.load(Relaxed),
+        }
+    }
+
+    /// The time after the start of the search when it has to stop, if it has such a limit
+    pub(crate) fn deadline(&self) -> Option<Duration> {
+        let deadline = self.deadline.load(Relaxed);
+        (deadline != NONE).then(|| Duration::from_millis(u64::from(deadline)))
+    }
+
+    /// Counts the iteration in the stability of the best move, and extends the deadline towards
+    /// the maximum when the best move differs from the one of the previous iteration (panic
+    /// extension), telling whether it did so
+    pub(crate) fn iteration_completed(&self, best_move: Option<&PossibleMove>) -> bool {
+        let packed = best_move.map_or(NONE, |a_move| u32::from(PackedMove::from(a_move).0));
+        let previous = self.best_move.swap(packed, Relaxed);
+        self.iterations.fetch_add(1, Relaxed);
+        if previous != NONE && previous != packed {
+            self.stable_for.store(1, Relaxed);
+        } else {
+            self.stable_for.fetch_add(1, Relaxed);
+            return false;
+        }
+        let changes = self.changes.fetch_add(1, Relaxed) + 1;
+        let deadline = self.deadline.load(Relaxed);
+        let maximum = self.maximum.load(Relaxed);
+        if deadline >= maximum {
+            return false;
+        }
+        let extended = deadline
+            .saturating_add(self.extension.load(Relaxed).saturating_mul(changes))
+            .min(maximum);
+        self.deadline.store(extended, Relaxed);
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::baserules::board_rep::PossibleMove;
+    use crate::baserules::piece_color::PieceColor::{Black, White};
+    use crate::engine::timing::{BestMoveStability, GameClock, SearchTime, TimeAllocation};
+    use crate::engine::Engine;
+    use std::time::{Duration, Instant};
+    use tokio::test;
+
+    #[test]
+    async fn allocates_from_the_clock() {
+        let clock = GameClock::from_uci(Black, 1_000, 60_050, 0, 2_000, None);
+        assert_eq!(Duration::from_millis(60_050), clock.remaining);
+        let allocation = clock.allocate(10);
+        assert_eq!(
+            Duration::from_millis(60_000 / 50 + 1_500),
+            allocation.target
+        );
+        assert_eq!(allocation.target * 3, allocation.maximum);
+
+        // The last move before the time control may use all the time but the overhead
+        let last_move = GameClock::from_uci(White, 10_050, 0, 0, 0, Some(1)).allocate(40);
+        assert_eq!(Duration::from_secs(10), last_move.target);
+        assert_eq!(last_move.target, last_move.maximum);
+
+        let flagging = GameClock::from_uci(White, 10, 0, 0, 0, None).allocate(40);
+        assert_eq!(Duration::from_millis(1), flagging.target);
+    }
+
+    #[test]
+    async fn extends_when_the_best_move_changes() {
+        let time = SearchTime::default();
+        time.start(Some(TimeAllocation {
+            target: Duration::from_millis(100),
+            maximum: Duration::from_millis(180),
+        }));
+        let e4 = PossibleMove::simple_from_uci("e2e4").unwrap();
+        let d4 = PossibleMove::simple_from_uci("d2d4").unwrap();
+        assert!(!time.iteration_completed(Some(&e4)));
+        assert!(!time.iteration_completed(Some(&e4)));
+        assert!(time.iteration_completed(Some(&d4)));
+        assert_eq!(Some(Duration::from_millis(150)), time.deadline());
+        assert!(time.iteration_completed(Some(&e4)));
+        assert_eq!(Some(Duration::from_millis(180)), time.deadline());
+        assert!(!time.iteration_completed(Some(&d4)));
+        assert_eq!(
+            BestMoveStability {
+                iterations: 5,
+                changes: 3,
+                stable_for: 1
+            },
+            time.stability()
+        );
+        assert_eq!(0.25, time.stability().confidence());
+
+        time.start(Some((&Duration::from_millis(100)).into()));
+        time.iteration_completed(Some(&e4));
+        assert!(!time.iteration_completed(Some(&d4)));
+        time.start(None);
+        assert_eq!(None, time.deadline());
+    }
+
+    #[test(flavor = "multi_thread")]
+    async fn searches_on_the_clock() {
+        let (engine, mut state) = Engine::new();
+        let clock = GameClock::from_uci(White, 5_050, 5_050, 0, 0, None);
+        let allocation = clock.allocate(state.psboard().move_count);
+        let started = Instant::now();
+        let (best_move, _, _, _) = engine.best_move_on_clock(&mut state, &clock).await;
+        assert!(best_move.is_some());
+        assert!(started.elapsed() < allocation.maximum + Duration::from_millis(500));
+    }
+}
@@ -0,0 +1,261 @@
+use crate::baserules::board_rep::{PackedMove, PossibleMove};
+use crate::baserules::score::Score;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering::Relaxed;
+
+/// The number of entries of the table unless set otherwise, 16 MB of memory
+pub const DEFAULT_TT_ENTRIES: usize = 1 << 20;
+
+/// Marks the used slots, so an all zero slot is never mistaken for a stored entry
+const OCCUPIED: u64 = 1 << 58;
+
+/// Tells how the stored score relates to the real score of the position
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Bound {
+    Exact,
+    /// The search failed high, the real score is at least this much
+    Lower,
+    /// The search failed low, the real score is at most this much
+    Upper,
+}
+
+/// The result of searching a position, the score is relative to the side to move
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TtEntry {
+    pub depth: u8,
+    pub score: Score,
+    pub bound: Bound,
+    pub best_move: Option<PossibleMove>,
+}
+
+impl TtEntry {
+    /// The score that can be used instead of searching the position to the given depth with the
+    /// given window, if the entry is deep enough and its bound decides the window
+    pub fn cutoff(&self, depth: u8, alpha: Score, beta: Score) -> Option<Score> {
+        if self.depth < depth {
+            return None;
+        }
+        match self.bound {
+            Bound::Exact => Some(self.score),
+            Bound::Lower if self.score >= beta => Some(self.score),
+            Bound::Upper if self.score <= alpha => Some(self.score),
+            _ => None,
+        }
+    }
+
+    /// From the lowest bits: 32 bits of score, 8 bits of depth, 2 bits of bound, 16 bits of the
+    /// packed move and the occupied flag. The a1a1 move never occurs, so it stands for no move.
+    fn pack(&self) -> u64 {
+        let bound = match self.bound {
+            Bound::Exact => 0,
+            Bound::Lower => 1,
+            Bound::Upper => 2,
+        };
+        let packed_move = self
+            .best_move
+            .as_ref()
+            .map_or(0, |a_move| PackedMove::from(a_move).0);
+        u64::from(self.score.to_bits())
+            | u64::from(self.depth) << 32
+            | bound << 40
+            | u64::from(packed_move) << 42
+            | OCCUPIED
+    }
+
+    fn unpack(data: u64) -> Option<Self> {
+        if data & OCCUPIED == 0 {
+            return None;
+        }
+        let packed_move = (data >> 42) as u16;
+        Some(Self {
+            depth: (data >> 32) as u8,
+            score: Score::from_bits(data as u32),
+            bound: match (data >> 40) & 0b11 {
+                0 => Bound::Exact,
+                1 => Bound::Lower,
+                _ => Bound::Upper,
+            },
+            best_move: (packed_move != 0).then(|| PackedMove(packed_move).into()),
+        })
+    }
+}
+
+/// The entries sharing the index of a hash, one cache line of memory
+const BUCKET_SIZE: usize = 4;
+
+/// A stored key xor-ed with the data and the data itself, 16 bytes
+type Slot = [AtomicU64; 2];
+
+/// The slots of a bucket are aligned to a cache line, so a probe reads a single line
+#[repr(align(64))]
+struct Bucket([Slot; BUCKET_SIZE]);
+
+impl Default for Bucket {
+    fn default() -> Self {
+        Bucket(std::array::from_fn(|_| {
+            [AtomicU64::new(0), AtomicU64::new(0)]
+        }))
+    }
+}
+
+/// Remembers the search results of the positions by their zobrist hashes, so transpositions are
+/// not searched again. The slots can be read and written by several search threads at the same
+/// time without locking: the key is stored xor-ed with the data, so a slot written by two threads
+/// at once does not match either hash and is ignored.
+/// A hash can be stored in any slot of its bucket, a new position replaces the shallowest one.
+pub struct TranspositionTable {
+    buckets: Vec<Bucket>,
+}
+
+impl Default for TranspositionTable {
+    fn default() -> Self {
+        Self::new(DEFAULT_TT_ENTRIES)
+    }
+}
+
+impl TranspositionTable {
+    /// Creates a table with at least the given number of entries, rounded up to a power of two
+    /// number of buckets
+    pub fn new(entries: usize) -> Self {
+        let buckets = entries.div_ceil(BUCKET_SIZE).max(1).next_power_of_two();
+        Self {
+            buckets: (0..buckets).map(|_| Bucket::default()).collect(),
+        }
+    }
+
+    #[inline]
+    fn bucket(&self, hash: u64) -> &Bucket {
+        &self.buckets[(hash as usize) & (self.buckets.len() - 1)]
+    }
+
+    /// Starts loading the bucket of the hash into the cache, so a probe soon after it does not
+    /// wait for the memory. Does nothing on architectures without a prefetch instruction.
+    #[inline]
+    pub fn prefetch(&self, hash: u64) {
+        #[cfg(target_arch = "x86_64")]
+        {
+            use std::arch::x86_64::{_mm_prefetch, _MM_HINT_T0};
+            let bucket: *const Bucket = self.bucket(hash);
+            // SAFETY: sse is part of every x86_64 target and a prefetch never faults
+            unsafe { _mm_prefetch::<_MM_HINT_T0>(bucket.cast()) };
+        }
+        #[cfg(not(target_arch = "x86_64"))]
+        let _ = hash;
+    }
+
+    #[inline]
+    fn read(slot: &Slot, hash: u64) -> Option<TtEntry> {
+        let [key, data] = slot;
+        let data = data.load(Relaxed);
+        if key.load(Relaxed) ^ data == hash {
+            TtEntry::unpack(data)
+        } else {
+            None
+        }
+    }
+
+    pub fn probe(&self, hash: u64) -> Option<TtEntry> {
+        self.bucket(hash)
+            .0
+            .iter()
+            .find_map(|slot| Self::read(slot, hash))
+    }
+
+    /// Stores the entry, unless the bucket holds a deeper search of the same position
+    pub fn store(&self, hash: u64, entry: &TtEntry) {
+        let slots = &self.bucket(hash).0;
+        let same_position = slots
+            .iter()
+            .find_map(|slot| Self::read(slot, hash).map(|stored| (slot, stored)));
+        let slot = match same_position {
+            Some((_, stored)) if stored.depth > entry.depth => return,
+            Some((slot, _)) => slot,
+            // Empty slots have no depth, so they are the first to be replaced
+            None => slots
+                .iter()
+                .min_by_key(|[_, data]| TtEntry::unpack(data.load(Relaxed)).map(|e| e.depth))
+                .unwrap(),
+        };
+        let [key, data] = slot;
+        let packed = entry.pack();
+        key.store(hash ^ packed, Relaxed);
+        data.store(packed, Relaxed);
+    }
+
+    pub fn clear(&self) {
+        self.buckets
+            .iter()
+            .flat_map(|bucket| bucket.0.iter().flatten())
+            .for_each(|slot| slot.store(0, Relaxed));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::baserules::board_rep::PossibleMove;
+    use crate::baserules::score::Score;
+    use crate::engine::transposition::{Bound, Bucket, TranspositionTable, TtEntry};
+    use std::mem::size_of;
+    use tokio::test;
+
+    #[test]
+    async fn stores_and_replaces_entries() {
+        let table = TranspositionTable::new(16);
+        let entry = TtEntry {
+            depth: 3,
+            score: Score::centipawns(-125),
+            bound: Bound::Lower,
+            best_move: Some(PossibleMove::simple_from_uci("e7e8q").unwrap()),
+        };
+        table.store(42, &entry);
+        assert_eq!(Some(entry), table.probe(42));
+        // Same bucket, different position
+        assert_eq!(None, table.probe(42 + 16));
+
+        let shallower = TtEntry {
+            depth: 2,
+            best_move: None,
+            ..entry
+        };
+        table.store(42, &shallower);
+        assert_eq!(Some(entry), table.probe(42));
+        table.store(42 + 16, &shallower);
+        assert_eq!(Some(shallower), table.probe(42 + 16));
+        assert_eq!(Some(entry), table.probe(42));
+
+        // Once the bucket is full, the shallowest position gives way
+        let deeper = TtEntry { depth: 5, ..entry };
+        table.store(42 + 32, &shallower);
+        table.store(42 + 48, &deeper);
+        table.prefetch(42 + 64);
+        table.store(42 + 64, &TtEntry { depth: 4, ..entry });
+        assert_eq!(None, table.probe(42 + 16));
+        for hash in [42, 42 + 32, 42 + 48, 42 + 64] {
+            assert!(table.probe(hash).is_some());
+        }
+
+        table.clear();
+        assert_eq!(None, table.probe(42));
+        assert_eq!(64, size_of::<Bucket>());
+    }
+
+    #[test]
+    async fn bounds_decide_cutoffs() {
+        let cp = Score::centipawns;
+        let entry = TtEntry {
+            depth: 4,
+            score: Score::centipawns(100),
+            bound: Bound::Lower,
+            best_move: None,
+        };
+        assert_eq!(Some(entry.score), entry.cutoff(4, cp(0), cp(50)));
+        assert_eq!(None, entry.cutoff(4, cp(0), cp(200)));
+        assert_eq!(None, entry.cutoff(5, cp(0), cp(50)));
+        let upper = TtEntry {
+            bound: Bound::Upper,
+            ..entry
+        };
+        assert_eq!(Some(entry.score), upper.cutoff(2, cp(150), cp(300)));
+        assert_eq!(None, upper.cutoff(2, cp(0), cp(300)));
+    }
+}
@@ -20,6 +20,16 @@
  *
  *  (C) Copyright 2022-3, Gabor Kecskemeti
  */
+pub mod analysis;
+pub mod animation;
+pub mod book;
+pub mod coach;
+pub mod config;
 pub mod conversion;
+pub mod game;
 pub mod helper;
 pub mod moves;
+pub mod opponents;
+pub mod pgn;
+pub mod render;
+pub mod tuning;
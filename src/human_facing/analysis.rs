@@ -0,0 +1,382 @@
+/*
+ *  ========================================================================
+ *  DBCE chess bot, analysis of played games
+ *  ========================================================================
+ *
+ *  This file is part of DBCE.
+ *
+ *  DBCE is free software: you can redistribute it and/or
+ *  modify it under the terms of the GNU General Public License as published
+ *  by the Free Software Foundation, either version 3 of the License, or (at
+ *  your option) any later version.
+ *
+ *  DBCE is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ *  General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License along
+ *  with DBCE.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ *  (C) Copyright 2022-3, Gabor Kecskemeti
+ */
+use crate::baserules::board::PSBoard;
+use crate::baserules::board_rep::PossibleMove;
+use crate::baserules::piece_color::PieceColor;
+use crate::baserules::score::Score;
+use crate::engine::gamestate::GameState;
+use crate::engine::Engine;
+use futures_util::StreamExt;
+use serde_json::{json, Value};
+use std::collections::BTreeSet;
+use std::fmt::Write;
+use std::time::{Duration, Instant};
+
+/// Scores beyond this are drawn at the edge of the evaluation chart
+pub const CHART_SCORE_LIMIT: Score = Score::centipawns(1000);
+
+/// The depth the moves are searched to when looking for the only moves of the positions
+pub const ONLY_MOVE_DEPTH: u8 = 3;
+
+/// The moves leaving the mover worse off than this are counted as losing
+pub const LOSING_MARGIN: Score = Score::centipawns(200);
+
+/// The engine's opinion of a position
+#[derive(Debug, Clone, PartialEq)]
+pub struct PositionEvaluation {
+    /// White-relative score
+    pub score: Score,
+    pub best_move: Option<PossibleMove>,
+    pub depth: u8,
+    /// The time the engine spent on the position
+    pub time_used: Duration,
+}
+
+/// How a played move changed the evaluation of the game
+#[derive(Debug, Clone, PartialEq)]
+pub struct MoveEvaluation {
+    pub the_move: PossibleMove,
+    pub mover: PieceColor,
+    pub before: PositionEvaluation,
+    pub after: PositionEvaluation,
+    /// All the other moves of the position would have lost, see `only_move`
+    pub only_move: bool,
+}
+
+/// The per move evaluation series of a game
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct GameAnalysis {
+    pub moves: Vec<MoveEvaluation>,
+}
+
+pub async fn evaluate_position(
+    engine: &Engine,
+    board: &PSBoard,
+    think_time: &Duration,
+) -> PositionEvaluation {
+    let mut state = GameState::new(board.clone());
+    let start = Instant::now();
+    let (best_move, score, _, depth) = engine.best_move_pvs(&mut state, think_time).await;
+    PositionEvaluation {
+        score,
+        best_move,
+        depth,
+        time_used: start.elapsed(),
+    }
+}
+
+/// Lists the starting position and the positions after each move
+async fn game_positions(start: &PSBoard, moves: &[PossibleMove]) -> Vec<PSBoard> {
+    let mut boards = vec![start.clone()];
+    for a_move in moves {
+        let board = boards.last().unwrap().make_move_noncached(a_move).await;
+        boards.push(board);
+    }
+    boards
+}
+
+/// Evaluates every position of the game, spending the given time on each
+pub async fn analyse_game(
+    engine: &Engine,
+    start: &PSBoard,
+    moves: &[PossibleMove],
+    think_time: &Duration,
+) -> GameAnalysis {
+    let boards = game_positions(start, moves).await;
+    let mut evaluations = Vec::with_capacity(boards.len());
+    for board in &boards {
+        evaluations.push(evaluate_position(engine, board, think_time).await);
+    }
+    GameAnalysis::from_evaluations(&boards, moves, &evaluations)
+}
+
+/// Finds the only move of the board which does not lose, i.e. leaves the mover better off than
+/// the negative of the margin after a search of the given depth. There is none if the board has
+/// a single legal move, as that is not worth pointing out, or if all or several of its moves hold.
+/// The search of the moves stops as soon as a second one holds.
+pub async fn only_move(
+    engine: &Engine,
+    board: &PSBoard,
+    depth: u8,
+    margin: Score,
+) -> Option<PossibleMove> {
+    let mate_multiplier = board.who_moves.mate_multiplier();
+    let mut successors: Vec<(PossibleMove, PSBoard)> = board.successors().collect().await;
+    if successors.len() < 2 {
+        return None;
+    }
+    // The likely good moves first, so a second holding move is found early
+    let mut moves: Vec<PossibleMove> = successors.iter().map(|(a_move, _)| *a_move).collect();
+    board.order_moves(&mut moves);
+    successors.sort_by_key(|(a_move, _)| moves.iter().position(|ordered| ordered == a_move));
+    let mut holding = None;
+    for (a_move, next_board) in &successors {
+        let score = engine
+            .quick_eval(next_board, depth.saturating_sub(1))
+            .await
+            .backed_up();
+        if mate_multiplier * score >= -margin {
+            if holding.is_some() {
+                return None;
+            }
+            holding = Some(*a_move);
+        }
+    }
+    holding
+}
+
+/// Marks the moves of the analysis which were the only moves of their positions
+pub async fn mark_only_moves(
+    engine: &Engine,
+    start: &PSBoard,
+    analysis: &mut GameAnalysis,
+    depth: u8,
+    margin: Score,
+) {
+    let moves: Vec<PossibleMove> = analysis
+        .moves
+        .iter()
+        .map(|evaluation| evaluation.the_move)
+        .collect();
+    let boards = game_positions(start, &moves).await;
+    for (evaluation, board) in analysis.moves.iter_mut().zip(&boards) {
+        evaluation.only_move =
+            only_move(engine, board, depth, margin).await == Some(evaluation.the_move);
+    }
+}
+
+/// The settings of the two phase analysis
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct AnalysisPasses {
+    /// The time spent on each position in the first pass
+    pub quick_time: Duration,
+    /// The time spent on the positions around the suspicious moves in the second pass
+    pub deep_time: Duration,
+    /// Moves losing more than this for the side making them are analysed again
+    pub suspicion_threshold: Score,
+}
+
+impl Default for AnalysisPasses {
+    fn default() -> Self {
+        Self {
+            quick_time: Duration::from_millis(50),
+            deep_time: Duration::from_secs(2),
+            suspicion_threshold: Score::centipawns(100),
+        }
+    }
+}
+
+/// Analyses the whole game quickly first, then spends more time only on the positions around
+/// the moves that look like mistakes after the first pass
+pub async fn analyse_game_in_passes(
+    engine: &Engine,
+    start: &PSBoard,
+    moves: &[PossibleMove],
+    passes: &AnalysisPasses,
+) -> GameAnalysis {
+    let boards = game_positions(start, moves).await;
+    let mut evaluations = Vec::with_capacity(boards.len());
+    for board in &boards {
+        evaluations.push(evaluate_position(engine, board, &passes.quick_time).await);
+    }
+    let quick_analysis = GameAnalysis::from_evaluations(&boards, moves, &evaluations);
+    let suspicious_positions: BTreeSet<usize> = quick_analysis
+        .moves
+        .iter()
+        .enumerate()
+        .filter(|(_, evaluation)| evaluation.is_suspicious(passes.suspicion_threshold))
+        .flat_map(|(ply, _)| [ply, ply + 1])
+        .collect();
+    for idx in suspicious_positions {
+        evaluations[idx] = evaluate_position(engine, &boards[idx], &passes.deep_time).await;
+    }
+    GameAnalysis::from_evaluations(&boards, moves, &evaluations)
+}
+
+impl MoveEvaluation {
+    /// How much the move worsened the evaluation for the side making it
+    pub fn score_loss(&self) -> Score {
+        self.mover.mate_multiplier() * (self.before.score - self.after.score)
+    }
+
+    /// The move is not the one the engine preferred and it lost more than the threshold
+    pub fn is_suspicious(&self, threshold: Score) -> bool {
+        self.before.best_move != Some(self.the_move) && self.score_loss() > threshold
+    }
+}
+
+impl GameAnalysis {
+    fn from_evaluations(
+        boards: &[PSBoard],
+        moves: &[PossibleMove],
+        evaluations: &[PositionEvaluation],
+    ) -> Self {
+        GameAnalysis {
+            moves: moves
+                .iter()
+                .zip(boards)
+                .zip(evaluations.windows(2))
+                .map(|((the_move, board), pair)| MoveEvaluation {
+                    the_move: *the_move,
+                    mover: board.who_moves,
+                    before: pair[0].clone(),
+                    after: pair[1].clone(),
+                    only_move: false,
+                })
+                .collect(),
+        }
+    }
+
+    /// The white-relative scores of the starting position and the positions after each move
+    pub fn score_series(&self) -> Vec<Score> {
+        self.moves
+            .first()
+            .map(|first| first.before.score)
+            .into_iter()
+            .chain(self.moves.iter().map(|evaluation| evaluation.after.score))
+            .collect()
+    }
+
+    /// The analysis as structured data, one entry per move, the scores in pawns
+    pub fn to_json(&self) -> Value {
+        Value::Array(
+            self.moves
+                .iter()
+                .enumerate()
+                .map(|(ply, evaluation)| {
+                    json!({
+                        "ply": ply + 1,
+                        "move": evaluation.the_move.to_string(),
+                        "score": evaluation.after.score.as_pawns(),
+                        "best": evaluation.before.best_move.map(|m| m.to_string()),
+                        "bestScore": evaluation.before.score.as_pawns(),
+                        "depth": evaluation.after.depth,
+                        "onlyMove": evaluation.only_move,
+                        "timeMs": evaluation.after.time_used.as_millis() as u64,
+                    })
+                })
+                .collect(),
+        )
+    }
+
+    /// Draws the evaluation series as a simple line chart, white's advantage pointing upwards
+    pub fn to_svg_chart(&self, width: u32, height: u32) -> String {
+        let scores = self.score_series();
+        let step = width as f32 / (scores.len().max(2) - 1) as f32;
+        let middle = height as f32 / 2.0;
+        let points = scores
+            .into_iter()
+            .enumerate()
+            .map(|(idx, score)| {
+                let clamped = score.clamp(-CHART_SCORE_LIMIT, CHART_SCORE_LIMIT);
+                let y = middle - clamped.as_pawns() / CHART_SCORE_LIMIT.as_pawns() * middle;
+                format!("{:.1},{y:.1}", idx as f32 * step)
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+        let mut svg = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{width}\" height=\"{height}\">\n"
+        );
+        writeln!(
+            svg,
+            "<rect width=\"{width}\" height=\"{height}\" fill=\"#404040\"/>\n<line x1=\"0\" y1=\"{middle}\" x2=\"{width}\" y2=\"{middle}\" stroke=\"#909090\"/>\n<polyline points=\"{points}\" fill=\"none\" stroke=\"#f0f0f0\" stroke-width=\"2\"/>"
+        )
+        .unwrap();
+        svg.push_str("</svg>\n");
+        svg
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::baserules::board::PSBoard;
+    use crate::baserules::board_rep::PossibleMove;
+    use crate::baserules::score::Score;
+    use crate::engine::Engine;
+    use crate::human_facing::analysis::{
+        analyse_game, analyse_game_in_passes, mark_only_moves, only_move, AnalysisPasses,
+        LOSING_MARGIN,
+    };
+    use std::time::Duration;
+    use tokio::test;
+
+    #[test(flavor = "multi_thread")]
+    async fn hanging_queen_swings_evaluation() {
+        let moves = ["e2e4", "e7e5", "d1h5", "g7g6", "h5e5"]
+            .map(|a_move| PossibleMove::simple_from_uci(a_move).unwrap());
+        let (engine, _) = Engine::new();
+        let analysis = analyse_game(
+            &engine,
+            &PSBoard::default(),
+            &moves,
+            &Duration::from_millis(100),
+        )
+        .await;
+        let series = analysis.score_series();
+        assert_eq!(6, series.len());
+        assert!(series[5] > series[3] + Score::centipawns(50));
+
+        let json = analysis.to_json();
+        assert_eq!(5, json.as_array().unwrap().len());
+        assert_eq!("h5e5", json[4]["move"]);
+        assert!(analysis.to_svg_chart(300, 100).contains("<polyline"));
+    }
+
+    #[test(flavor = "multi_thread")]
+    async fn only_mistakes_are_analysed_deeply() {
+        let moves = ["e2e4", "e7e5", "d1h5", "g7g6", "h5e5"]
+            .map(|a_move| PossibleMove::simple_from_uci(a_move).unwrap());
+        let (engine, _) = Engine::new();
+        let passes = AnalysisPasses {
+            quick_time: Duration::from_millis(20),
+            deep_time: Duration::from_millis(300),
+            suspicion_threshold: Score::centipawns(50),
+        };
+        let analysis = analyse_game_in_passes(&engine, &PSBoard::default(), &moves, &passes).await;
+        let blunder = &analysis.moves[3];
+        assert!(blunder.is_suspicious(passes.suspicion_threshold));
+        assert!(blunder.after.time_used >= passes.deep_time);
+        assert!(analysis.moves[0].before.time_used < passes.deep_time);
+    }
+
+    #[test(flavor = "multi_thread")]
+    async fn only_moves_are_found() {
+        let (engine, _) = Engine::new();
+        // The king could escape the check, but only taking the rook keeps white's own rook
+        let board = PSBoard::from_fen("6k1/5ppp/8/8/8/7P/5PP1/R2r2K1 w - - 0 1")
+            .await
+            .unwrap();
+        let the_move = only_move(&engine, &board, 3, LOSING_MARGIN).await;
+        assert_eq!("a1d1", the_move.unwrap().to_string());
+        assert_eq!(
+            None,
+            only_move(&engine, &PSBoard::default(), 2, LOSING_MARGIN).await
+        );
+
+        let moves = [PossibleMove::simple_from_uci("a1d1").unwrap()];
+        let mut analysis = analyse_game(&engine, &board, &moves, &Duration::from_millis(50)).await;
+        mark_only_moves(&engine, &board, &mut analysis, 3, LOSING_MARGIN).await;
+        assert!(analysis.moves[0].only_move);
+        assert_eq!(Some(true), analysis.to_json()[0]["onlyMove"].as_bool());
+    }
+}
@@ -0,0 +1,506 @@
+/*
+ *  ========================================================================
+ *  DBCE chess bot, animated gif export of games
+ *  ========================================================================
+ *
+ *  This file is part of DBCE.
+ *
+ *  DBCE is free software: you can redistribute it and/or
+ *  modify it under the terms of the GNU General Public License as published
+ *  by the Free Software Foundation, either version 3 of the License, or (at
+ *  your option) any later version.
+ *
+ *  DBCE is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ *  General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License along
+ *  with DBCE.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ *  (C) Copyright 2022-3, Gabor Kecskemeti
+ */
+use crate::baserules::board::PSBoard;
+use crate::baserules::board_diff::BoardDiff;
+use crate::baserules::board_rep::PossibleMove;
+use crate::baserules::piece_color::PieceColor::White;
+use crate::baserules::piece_kind::PieceKind;
+use crate::baserules::piece_kind::PieceKind::*;
+use crate::baserules::positions::AbsoluteBoardPos;
+use crate::human_facing::render::{Highlight, RenderOptions};
+use enum_map::{enum_map, EnumMap};
+use lazy_static::lazy_static;
+use std::collections::{HashMap, HashSet};
+
+/// The colours used in the frames, the pixels of a frame index into this table
+static PALETTE: [[u8; 3]; 8] = [
+    [0xf0, 0xd9, 0xb5], // light square
+    [0xb5, 0x88, 0x63], // dark square
+    [0xcd, 0xd2, 0x6a], // light square of the last move
+    [0xaa, 0xa2, 0x3a], // dark square of the last move
+    [0xe0, 0x60, 0x60], // square of the checked king
+    [0xff, 0xff, 0xff], // white pieces
+    [0x20, 0x20, 0x20], // black pieces
+    [0x00, 0x00, 0x00], // piece outlines
+];
+const WHITE_PIECE: u8 = 5;
+const BLACK_PIECE: u8 = 6;
+const OUTLINE: u8 = 7;
+
+/// The pieces are drawn on a grid of this size, scaled up to the square size
+const SPRITE_SIZE: usize = 12;
+
+lazy_static! {
+    static ref SPRITES: EnumMap<PieceKind, [&'static str; SPRITE_SIZE]> = enum_map! {
+        Pawn => [
+            "............",
+            "............",
+            ".....##.....",
+            "....####....",
+            "....####....",
+            ".....##.....",
+            "....####....",
+            ".....##.....",
+            "....####....",
+            "...######...",
+            "..########..",
+            "............",
+        ],
+        Knight => [
+            "............",
+            ".....#.#....",
+            "....#####...",
+            "...######...",
+            "..####.###..",
+            "..###..###..",
+            "......####..",
+            ".....####...",
+            "....#####...",
+            "...######...",
+            "..########..",
+            "............",
+        ],
+        Bishop => [
+            "............",
+            ".....##.....",
+            "....#.##....",
+            "....##.#....",
+            "....####....",
+            ".....##.....",
+            "....####....",
+            ".....##.....",
+            "....####....",
+            "...######...",
+            "..########..",
+            "............",
+        ],
+        Rook => [
+            "............",
+            "..##.##.##..",
+            "..########..",
+            "...######...",
+            "....####....",
+            "....####....",
+            "....####....",
+            "....####....",
+            "...######...",
+            "..########..",
+            "..########..",
+            "............",
+        ],
+        Queen => [
+            "............",
+            ".#...##...#.",
+            ".##..##..##.",
+            ".###.##.###.",
+            "..########..",
+            "..########..",
+            "...######...",
+            "....####....",
+            "...######...",
+            "..########..",
+            "..########..",
+            "............",
+        ],
+        King => [
+            ".....##.....",
+            "....####....",
+            ".....##.....",
+            "...######...",
+            "..########..",
+            "..########..",
+            "...######...",
+            "....####....",
+            "...######...",
+            "..########..",
+            "..########..",
+            "............",
+        ],
+    };
+}
+
+/// A single raster image of a board, its pixels are indexes into the palette of the gif
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Frame {
+    pub width: u16,
+    pub height: u16,
+    pub pixels: Vec<u8>,
+}
+
+/// Draws the boards as raster images. The coordinates of the render options are not drawn.
+pub struct RasterRenderer {
+    /// The width and height of a square in pixels, it is rounded down to a multiple of 12
+    pub square_size: u16,
+}
+
+impl Default for RasterRenderer {
+    fn default() -> Self {
+        Self { square_size: 48 }
+    }
+}
+
+fn sprite_pixel(kind: PieceKind, row: isize, col: isize) -> bool {
+    (0..SPRITE_SIZE as isize).contains(&row)
+        && (0..SPRITE_SIZE as isize).contains(&col)
+        && SPRITES[kind][row as usize].as_bytes()[col as usize] == b'#'
+}
+
+impl RasterRenderer {
+    pub fn render(&self, board: &PSBoard, options: &RenderOptions) -> Frame {
+        let width = 8 * self.sprite_scale() * SPRITE_SIZE;
+        let mut frame = Frame {
+            width: width as u16,
+            height: width as u16,
+            pixels: vec![0; width * width],
+        };
+        let in_check = options.highlight_check && board.is_in_check();
+        for (idx, pos) in options.squares().enumerate() {
+            self.paint_square(&mut frame, board, options, in_check, idx, pos);
+        }
+        frame
+    }
+
+    /// Redraws only the squares that may look different since the frame was rendered with the
+    /// previous options: the changed squares, the last move highlights and the kings
+    pub fn update(
+        &self,
+        frame: &mut Frame,
+        board: &PSBoard,
+        previous: &RenderOptions,
+        options: &RenderOptions,
+        diff: &BoardDiff,
+    ) {
+        let in_check = options.highlight_check && board.is_in_check();
+        let highlighted = [previous.last_move, options.last_move]
+            .into_iter()
+            .flatten()
+            .flat_map(|a_move| [a_move.from, a_move.to]);
+        let kings = options
+            .squares()
+            .filter(|pos| board[*pos].is_some_and(|piece| piece.kind == King));
+        let to_paint: HashSet<AbsoluteBoardPos> = diff
+            .changed_squares()
+            .chain(highlighted)
+            .chain(kings)
+            .collect();
+        for (idx, pos) in options.squares().enumerate() {
+            if to_paint.contains(&pos) {
+                self.paint_square(frame, board, options, in_check, idx, pos);
+            }
+        }
+    }
+
+    #[inline]
+    fn sprite_scale(&self) -> usize {
+        (self.square_size as usize / SPRITE_SIZE).max(1)
+    }
+
+    fn paint_square(
+        &self,
+        frame: &mut Frame,
+        board: &PSBoard,
+        options: &RenderOptions,
+        in_check: bool,
+        idx: usize,
+        pos: AbsoluteBoardPos,
+    ) {
+        let scale = self.sprite_scale();
+        let size = scale * SPRITE_SIZE;
+        let width = frame.width as usize;
+        let (left, top) = ((idx % 8) * size, (idx / 8) * size);
+        let light = usize::from((pos.0 + pos.1) % 2 == 1);
+        let background = match options.highlight(board, in_check, pos) {
+            Highlight::Check => 4,
+            Highlight::LastMove => 3 - light as u8,
+            Highlight::None => 1 - light as u8,
+        };
+        for y in 0..size {
+            for x in 0..size {
+                let (row, col) = ((y / scale) as isize, (x / scale) as isize);
+                let colour = board[pos].map_or(background, |piece| {
+                    if !sprite_pixel(piece.kind, row, col) {
+                        background
+                    } else if [(-1, 0), (1, 0), (0, -1), (0, 1)]
+                        .iter()
+                        .all(|(dr, dc)| sprite_pixel(piece.kind, row + dr, col + dc))
+                    {
+                        if piece.color == White {
+                            WHITE_PIECE
+                        } else {
+                            BLACK_PIECE
+                        }
+                    } else {
+                        OUTLINE
+                    }
+                });
+                frame.pixels[(top + y) * width + left + x] = colour;
+            }
+        }
+    }
+}
+
+/// Renders the board before the first move and after each of the moves, highlighting the move just made
+pub async fn record_game(
+    start: &PSBoard,
+    moves: &[PossibleMove],
+    renderer: &RasterRenderer,
+    options: &RenderOptions,
+) -> Vec<Frame> {
+    let mut frames = vec![renderer.render(start, options)];
+    let mut previous: Option<PSBoard> = None;
+    let mut previous_options = *options;
+    for a_move in moves {
+        let board = previous
+            .as_ref()
+            .unwrap_or(start)
+            .make_move_noncached(a_move)
+            .await;
+        let options = RenderOptions {
+            last_move: Some(a_move.the_move),
+            ..*options
+        };
+        let mut frame = frames.last().unwrap().clone();
+        let diff = previous.as_ref().unwrap_or(start).diff(&board);
+        renderer.update(&mut frame, &board, &previous_options, &options, &diff);
+        frames.push(frame);
+        previous = Some(board);
+        previous_options = options;
+    }
+    frames
+}
+
+/// The smallest code size gif allows for an 8 colour palette
+const MIN_CODE_SIZE: u8 = 3;
+const MAX_CODE: u16 = 4095;
+
+/// Collects the variable length lzw codes into bytes, least significant bits first
+struct BitWriter {
+    bytes: Vec<u8>,
+    buffer: u32,
+    used_bits: u8,
+}
+
+impl BitWriter {
+    fn write(&mut self, code: u16, code_size: u8) {
+        self.buffer |= u32::from(code) << self.used_bits;
+        self.used_bits += code_size;
+        while self.used_bits >= 8 {
+            self.bytes.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.used_bits -= 8;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.used_bits > 0 {
+            self.bytes.push(self.buffer as u8);
+        }
+        self.bytes
+    }
+}
+
+fn lzw_compress(pixels: &[u8]) -> Vec<u8> {
+    let clear_code = 1u16 << MIN_CODE_SIZE;
+    let end_code = clear_code + 1;
+    let mut writer = BitWriter {
+        bytes: Vec::new(),
+        buffer: 0,
+        used_bits: 0,
+    };
+    let mut dictionary: HashMap<(u16, u8), u16> = HashMap::new();
+    let mut code_size = MIN_CODE_SIZE + 1;
+    let mut next_code = end_code + 1;
+    writer.write(clear_code, code_size);
+    let Some((first, rest)) = pixels.split_first() else {
+        writer.write(end_code, code_size);
+        return writer.finish();
+    };
+    let mut prefix = u16::from(*first);
+    for pixel in rest {
+        if let Some(code) = dictionary.get(&(prefix, *pixel)) {
+            prefix = *code;
+            continue;
+        }
+        writer.write(prefix, code_size);
+        if next_code <= MAX_CODE {
+            dictionary.insert((prefix, *pixel), next_code);
+            next_code += 1;
+            // The decoder only learns the new code after reading the next one
+            if next_code > (1 << code_size) && code_size < 12 {
+                code_size += 1;
+            }
+        } else {
+            writer.write(clear_code, code_size);
+            dictionary.clear();
+            code_size = MIN_CODE_SIZE + 1;
+            next_code = end_code + 1;
+        }
+        prefix = u16::from(*pixel);
+    }
+    writer.write(prefix, code_size);
+    writer.write(end_code, code_size);
+    writer.finish()
+}
+
+/// Encodes the frames as a looping animated gif, showing each frame for the given hundredths of a second
+pub fn encode_gif(frames: &[Frame], delay_centis: u16) -> Vec<u8> {
+    let (width, height) = frames
+        .first()
+        .map_or((0, 0), |frame| (frame.width, frame.height));
+    let mut gif = b"GIF89a".to_vec();
+    gif.extend(width.to_le_bytes());
+    gif.extend(height.to_le_bytes());
+    // global colour table of 2^(2+1) entries, 8 bits per primary colour
+    gif.extend([0xf2, 0, 0]);
+    gif.extend(PALETTE.iter().flatten());
+    // the netscape extension makes the animation loop forever
+    gif.extend([0x21, 0xff, 0x0b]);
+    gif.extend(b"NETSCAPE2.0");
+    gif.extend([3, 1, 0, 0, 0]);
+    for frame in frames {
+        gif.extend([0x21, 0xf9, 4, 0]);
+        gif.extend(delay_centis.to_le_bytes());
+        gif.extend([0, 0]);
+        gif.push(0x2c);
+        gif.extend([0, 0, 0, 0]);
+        gif.extend(frame.width.to_le_bytes());
+        gif.extend(frame.height.to_le_bytes());
+        gif.push(0);
+        gif.push(MIN_CODE_SIZE);
+        for block in lzw_compress(&frame.pixels).chunks(255) {
+            gif.push(block.len() as u8);
+            gif.extend(block);
+        }
+        gif.push(0);
+    }
+    gif.push(0x3b);
+    gif
+}
+
+#[cfg(test)]
+mod test {
+    use crate::baserules::board::PSBoard;
+    use crate::baserules::board_rep::PossibleMove;
+    use crate::human_facing::animation::{
+        encode_gif, lzw_compress, record_game, RasterRenderer, MIN_CODE_SIZE,
+    };
+    use crate::human_facing::render::RenderOptions;
+    use std::collections::HashMap;
+    use tokio::test;
+
+    /// A straightforward lzw decoder, following the gif specification
+    fn lzw_decompress(data: &[u8]) -> Vec<u8> {
+        let clear_code = 1u16 << MIN_CODE_SIZE;
+        let end_code = clear_code + 1;
+        let mut table: HashMap<u16, Vec<u8>> = HashMap::new();
+        let mut code_size = MIN_CODE_SIZE + 1;
+        let (mut buffer, mut bits, mut pos) = (0u32, 0u8, 0);
+        let mut previous: Option<Vec<u8>> = None;
+        let mut output = Vec::new();
+        loop {
+            while bits < code_size {
+                buffer |= u32::from(data[pos]) << bits;
+                pos += 1;
+                bits += 8;
+            }
+            let code = (buffer & ((1 << code_size) - 1)) as u16;
+            buffer >>= code_size;
+            bits -= code_size;
+            if code == clear_code {
+                table = (0..clear_code).map(|c| (c, vec![c as u8])).collect();
+                code_size = MIN_CODE_SIZE + 1;
+                previous = None;
+                continue;
+            }
+            if code == end_code {
+                return output;
+            }
+            let entry = match (table.get(&code), &previous) {
+                (Some(entry), _) => entry.clone(),
+                (None, Some(prev)) => [prev.clone(), vec![prev[0]]].concat(),
+                (None, None) => panic!("Unknown first code {code}"),
+            };
+            output.extend(&entry);
+            if let Some(prev) = previous {
+                let next_code = table.len() as u16 + 2;
+                table.insert(next_code, [prev, vec![entry[0]]].concat());
+                if next_code + 1 == (1 << code_size) && code_size < 12 {
+                    code_size += 1;
+                }
+            }
+            previous = Some(entry);
+        }
+    }
+
+    #[test]
+    async fn lzw_round_trip() {
+        // pseudo random enough to fill the dictionary several times
+        let pixels: Vec<u8> = (0..50000u32)
+            .map(|i| (i.wrapping_mul(2654435761) >> 29) as u8)
+            .collect();
+        assert_eq!(pixels, lzw_decompress(&lzw_compress(&pixels)));
+    }
+
+    #[test]
+    async fn animated_game() {
+        let moves = ["e2e4", "e7e5"].map(|a_move| PossibleMove::simple_from_uci(a_move).unwrap());
+        let renderer = RasterRenderer { square_size: 24 };
+        let frames = record_game(
+            &PSBoard::default(),
+            &moves,
+            &renderer,
+            &RenderOptions::default(),
+        )
+        .await;
+        assert_eq!(3, frames.len());
+        assert_eq!(192, frames[0].width);
+        assert_ne!(frames[0].pixels, frames[1].pixels);
+        let gif = encode_gif(&frames, 100);
+        assert!(gif.starts_with(b"GIF89a"));
+        assert_eq!(Some(&0x3b), gif.last());
+    }
+
+    #[test]
+    async fn incremental_frames_match_full_renders() {
+        // The last move gives check, so the king highlight has to be updated too
+        let moves =
+            ["e2e4", "f7f6", "d1h5"].map(|a_move| PossibleMove::simple_from_uci(a_move).unwrap());
+        let renderer = RasterRenderer { square_size: 12 };
+        let frames = record_game(
+            &PSBoard::default(),
+            &moves,
+            &renderer,
+            &RenderOptions::default(),
+        )
+        .await;
+        let mut board = PSBoard::default();
+        for (a_move, frame) in moves.iter().zip(&frames[1..]) {
+            board = board.make_move_noncached(a_move).await;
+            let options = RenderOptions {
+                last_move: Some(a_move.the_move),
+                ..RenderOptions::default()
+            };
+            assert_eq!(&renderer.render(&board, &options), frame);
+        }
+    }
+}
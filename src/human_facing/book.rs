@@ -0,0 +1,382 @@
+/*
+ *  ========================================================================
+ *  DBCE chess bot, opening book built from pgn files
+ *  ========================================================================
+ *
+ *  This file is part of DBCE.
+ *
+ *  DBCE is free software: you can redistribute it and/or
+ *  modify it under the terms of the GNU General Public License as published
+ *  by the Free Software Foundation, either version 3 of the License, or (at
+ *  your option) any later version.
+ *
+ *  DBCE is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ *  General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License along
+ *  with DBCE.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ *  (C) Copyright 2022-3, Gabor Kecskemeti
+ */
+use crate::baserules::board::PSBoard;
+use crate::baserules::board_rep::{PackedMove, PossibleMove};
+use crate::baserules::move_list::MoveList;
+use crate::baserules::piece_color::PieceColor;
+use crate::human_facing::game::{GameRecord, GameResult};
+use crate::human_facing::pgn::{pgn_move_tokens, PgnReader};
+use crate::util::{FileFormat, IntResult};
+use async_scoped::TokioScope;
+use rustc_hash::{FxHashMap, FxHashSet, FxHasher};
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::io::BufRead;
+use tokio::sync::mpsc;
+
+/// The number of plies of each game recorded in the book unless set otherwise
+pub const DEFAULT_BOOK_PLIES: usize = 20;
+
+/// The files written by `OpeningBook::to_bytes`
+const BOOK_FORMAT: FileFormat = FileFormat {
+    name: "an opening book",
+    magic: b"DBCEBOOK",
+    version: 1,
+};
+/// Hash, packed move and four counters
+const RECORD_SIZE: usize = 8 + 2 + 4 * 4;
+/// The games read ahead for each worker while it is busy with its earlier games
+const GAMES_IN_FLIGHT: usize = 64;
+
+/// How the games continuing with a move ended
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct BookMoveStats {
+    pub games: u32,
+    pub white_wins: u32,
+    pub draws: u32,
+    pub black_wins: u32,
+}
+
+impl BookMoveStats {
+    fn record(&mut self, result: GameResult) {
+        self.games += 1;
+        match result {
+            GameResult::WhiteWon => self.white_wins += 1,
+            GameResult::BlackWon => self.black_wins += 1,
+            GameResult::Draw => self.draws += 1,
+            GameResult::Unfinished => {}
+        }
+    }
+
+    pub fn merge(&mut self, other: &BookMoveStats) {
+        self.games += other.games;
+        self.white_wins += other.white_wins;
+        self.draws += other.draws;
+        self.black_wins += other.black_wins;
+    }
+
+    /// The share of the points the given side scored in the finished games, half for the draws
+    pub fn score_for(&self, color: PieceColor) -> f32 {
+        let finished = self.white_wins + self.draws + self.black_wins;
+        if finished == 0 {
+            return 0.5;
+        }
+        let wins = match color {
+            PieceColor::White => self.white_wins,
+            PieceColor::Black => self.black_wins,
+        };
+        (wins as f32 + self.draws as f32 / 2.0) / finished as f32
+    }
+}
+
+/// The moves played in the positions of a collection of games, the positions are identified by
+/// their zobrist hashes, so transposing move orders share their statistics
+#[derive(Debug, Default, PartialEq)]
+pub struct OpeningBook {
+    positions: FxHashMap<u64, FxHashMap<PackedMove, BookMoveStats>>,
+}
+
+/// The outcome of building a book from a pgn file
+#[derive(Debug, Default)]
+pub struct BookBuild {
+    pub book: OpeningBook,
+    pub games: usize,
+    /// Games with the same moves as an earlier one, these are only counted once
+    pub duplicates: usize,
+    /// Games that could not be read, e.g. because of an impossible move
+    pub invalid: usize,
+}
+
+impl OpeningBook {
+    /// Records the first plies of the game
+    pub async fn add_game(&mut self, game: &GameRecord, max_plies: usize) {
+        let mut board: Option<PSBoard> = None;
+        for a_move in game.moves.iter().take(max_plies) {
+            let current = board.as_ref().unwrap_or(&game.start);
+            self.positions
+                .entry(current.zobrist())
+                .or_default()
+                .entry(PackedMove::from(a_move))
+                .or_default()
+                .record(game.result);
+            board = Some(current.make_move_noncached(a_move).await);
+        }
+    }
+
+    /// Adds the statistics of the other book to this one
+    pub fn merge(&mut self, other: OpeningBook) {
+        for (hash, moves) in other.positions {
+            let position = self.positions.entry(hash).or_default();
+            for (a_move, stats) in moves {
+                position.entry(a_move).or_default().merge(&stats);
+            }
+        }
+    }
+
+    /// The book moves of the position, the most played first
+    pub fn moves(&self, board: &PSBoard) -> Vec<(PossibleMove, BookMoveStats)> {
+        let mut moves: Vec<(PossibleMove, BookMoveStats)> = self
+            .positions
+            .get(&board.zobrist())
+            .map(|moves| {
+                moves
+                    .iter()
+                    .map(|(a_move, stats)| ((*a_move).into(), *stats))
+                    .collect()
+            })
+            .unwrap_or_default();
+        moves.sort_by(|(m1, s1), (m2, s2)| {
+            s2.games
+                .cmp(&s1.games)
+                .then_with(|| m1.to_string().cmp(&m2.to_string()))
+        });
+        moves
+    }
+
+    /// The legal book move scoring the best for the side to move, among the ones played in at
+    /// least `min_games` games, the more played one on a tie
+    pub fn best_move(&self, board: &PSBoard, min_games: u32) -> Option<PossibleMove> {
+        let moves = self.positions.get(&board.zobrist())?;
+        let mut legal = MoveList::default();
+        board.gen_legal_moves(&mut legal);
+        let color = board.who_moves;
+        moves
+            .iter()
+            .filter(|(_, stats)| stats.games >= min_games)
+            // A different position with the same hash may have moves impossible here
+            .filter_map(|(packed, stats)| {
+                legal
+                    .iter()
+                    .find(|a_move| PackedMove::from(*a_move) == *packed)
+                    .map(|a_move| (*a_move, stats))
+            })
+            .max_by(|(m1, s1), (m2, s2)| {
+                s1.score_for(color)
+                    .total_cmp(&s2.score_for(color))
+                    .then(s1.games.cmp(&s2.games))
+                    .then_with(|| m2.to_string().cmp(&m1.to_string()))
+            })
+            .map(|(a_move, _)| a_move)
+    }
+
+    /// The number of positions in the book
+    pub fn len(&self) -> usize {
+        self.positions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.positions.is_empty()
+    }
+
+    /// A compact binary form of the book, with fixed size records sorted by the position hashes
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut records: Vec<(u64, u16, &BookMoveStats)> = self
+            .positions
+            .iter()
+            .flat_map(|(hash, moves)| moves.iter().map(|(a_move, stats)| (*hash, a_move.0, stats)))
+            .collect();
+        records.sort_unstable_by_key(|(hash, a_move, _)| (*hash, *a_move));
+        let mut bytes = BOOK_FORMAT.header();
+        bytes.reserve(records.len() * RECORD_SIZE);
+        for (hash, a_move, stats) in records {
+            bytes.extend_from_slice(&hash.to_le_bytes());
+            bytes.extend_from_slice(&a_move.to_le_bytes());
+            for counter in [stats.games, stats.white_wins, stats.draws, stats.black_wins] {
+                bytes.extend_from_slice(&counter.to_le_bytes());
+            }
+        }
+        bytes
+    }
+
+    /// Reads a book written by `to_bytes` of any version
+    ///
+    /// # Errors
+    /// When the bytes are not a book or they were written by a newer version
+    pub fn from_bytes(bytes: &[u8]) -> IntResult<OpeningBook> {
+        // The books written before the versions only had whole records after the magic, and
+        // they need no migration
+        let (_, records) = BOOK_FORMAT.read(bytes, |rest| rest.len() % RECORD_SIZE == 0)?;
+        if records.len() % RECORD_SIZE != 0 {
+            return Err("Not an opening book".into());
+        }
+        let mut book = OpeningBook::default();
+        for record in records.chunks_exact(RECORD_SIZE) {
+            let hash = u64::from_le_bytes(record[0..8].try_into()?);
+            let a_move = PackedMove(u16::from_le_bytes(record[8..10].try_into()?));
+            let counter = |idx: usize| -> IntResult<u32> {
+                Ok(u32::from_le_bytes(
+                    record[10 + 4 * idx..14 + 4 * idx].try_into()?,
+                ))
+            };
+            let stats = BookMoveStats {
+                games: counter(0)?,
+                white_wins: counter(1)?,
+                draws: counter(2)?,
+                black_wins: counter(3)?,
+            };
+            book.positions
+                .entry(hash)
+                .or_default()
+                .insert(a_move, stats);
+        }
+        Ok(book)
+    }
+}
+
+/// Builds a book from the games of the pgn on several workers, see `build_book_from`
+pub fn build_book(pgn: &str, workers: usize, max_plies: usize) -> BookBuild {
+    build_book_from(PgnReader::new(pgn.as_bytes()), workers, max_plies)
+        .expect("Reading from memory does not fail")
+}
+
+/// Builds a book from the games of the pgn file on several workers while the file is read, so
+/// files larger than the memory can be used too. The games are sharded by the hash of their
+/// moves, so the duplicates of a game end up on the same worker, which skips them. The partial
+/// books of the workers are merged at the end.
+///
+/// # Errors
+/// When the file can not be read
+pub fn build_book_from<R: BufRead + Send>(
+    games: PgnReader<R>,
+    workers: usize,
+    max_plies: usize,
+) -> io::Result<BookBuild> {
+    let workers = workers.max(1);
+    let (senders, receivers): (Vec<_>, Vec<_>) = (0..workers)
+        .map(|_| mpsc::channel::<(u64, String)>(GAMES_IN_FLIGHT))
+        .unzip();
+    let (_, partial_builds) = TokioScope::scope_and_block(|scope| {
+        scope.spawn(async move {
+            for game in games {
+                let game = game?.text;
+                // The same moves are the same game, whatever its tags say
+                let movetext: Vec<&str> = game
+                    .lines()
+                    .filter(|line| !line.trim_start().starts_with('['))
+                    .collect();
+                let mut hasher = FxHasher::default();
+                pgn_move_tokens(&movetext.join("\n")).hash(&mut hasher);
+                let key = hasher.finish();
+                // The workers only stop when the senders are dropped
+                senders[(key % workers as u64) as usize]
+                    .send((key, game))
+                    .await
+                    .ok();
+            }
+            Ok(BookBuild::default())
+        });
+        for mut shard in receivers {
+            scope.spawn(async move {
+                let mut build = BookBuild::default();
+                let mut seen = FxHashSet::default();
+                while let Some((key, game)) = shard.recv().await {
+                    build.games += 1;
+                    if !seen.insert(key) {
+                        build.duplicates += 1;
+                        continue;
+                    }
+                    let parsed = GameRecord::from_pgn(&game).await.ok();
+                    match parsed {
+                        Some(game) => build.book.add_game(&game, max_plies).await,
+                        None => build.invalid += 1,
+                    }
+                }
+                Ok(build)
+            });
+        }
+    });
+    partial_builds.into_iter().flatten().try_fold(
+        BookBuild::default(),
+        |mut all, partial: io::Result<BookBuild>| {
+            let partial = partial?;
+            all.book.merge(partial.book);
+            all.games += partial.games;
+            all.duplicates += partial.duplicates;
+            all.invalid += partial.invalid;
+            Ok(all)
+        },
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use crate::baserules::board::PSBoard;
+    use crate::baserules::piece_color::PieceColor::White;
+    use crate::human_facing::book::{build_book, OpeningBook, DEFAULT_BOOK_PLIES};
+    use tokio::test;
+
+    const GAMES: &str = r#"[Event "First"]
+[Result "1-0"]
+
+1. e4 e5 2. Nf3 Nc6 3. Bb5 1-0
+
+[Result "1/2-1/2"]
+
+1. Nf3 e5 2. e4 Nc6 3. Bb5 a6 1/2-1/2
+
+[Event "Second"]
+[Result "1-0"]
+
+1. e4 e5 2. Nf3 Nc6 3. Bb5 1-0
+
+[Result "0-1"]
+
+1. d4 d5 2. Kd3 0-1
+"#;
+
+    #[test(flavor = "multi_thread")]
+    async fn builds_book_in_parallel() {
+        for workers in [1, 3] {
+            let build = build_book(GAMES, workers, DEFAULT_BOOK_PLIES);
+            assert_eq!(4, build.games);
+            assert_eq!(1, build.duplicates);
+            assert_eq!(1, build.invalid);
+            let moves = build.book.moves(&PSBoard::default());
+            assert_eq!(2, moves.len());
+            assert_eq!("e2e4", moves[0].0.to_string());
+            assert_eq!(1, moves[0].1.games);
+            assert_eq!(1.0, moves[0].1.score_for(White));
+            assert_eq!(0.5, moves[1].1.score_for(White));
+            let best = build.book.best_move(&PSBoard::default(), 1);
+            assert_eq!("e2e4", best.unwrap().to_string());
+            assert_eq!(None, build.book.best_move(&PSBoard::default(), 2));
+
+            // The two move orders transpose into the same position
+            let transposed = PSBoard::from_fen(
+                "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3",
+            )
+            .await
+            .unwrap();
+            let moves = build.book.moves(&transposed);
+            assert_eq!(1, moves.len());
+            assert_eq!("f1b5", moves[0].0.to_string());
+            assert_eq!(2, moves[0].1.games);
+            assert_eq!(1, moves[0].1.draws);
+            let bytes = build.book.to_bytes();
+            assert_eq!(build.book, OpeningBook::from_bytes(&bytes).unwrap());
+            let legacy = [&bytes[..8], &bytes[10..]].concat();
+            assert_eq!(build.book, OpeningBook::from_bytes(&legacy).unwrap());
+        }
+        assert!(OpeningBook::from_bytes(b"not a book").is_err());
+    }
+}
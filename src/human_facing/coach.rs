@@ -0,0 +1,132 @@
+/*
+ *  ========================================================================
+ *  DBCE chess bot, coaching the human player
+ *  ========================================================================
+ *
+ *  This file is part of DBCE.
+ *
+ *  DBCE is free software: you can redistribute it and/or
+ *  modify it under the terms of the GNU General Public License as published
+ *  by the Free Software Foundation, either version 3 of the License, or (at
+ *  your option) any later version.
+ *
+ *  DBCE is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ *  General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License along
+ *  with DBCE.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ *  (C) Copyright 2022-3, Gabor Kecskemeti
+ */
+use crate::baserules::board::PSBoard;
+use crate::baserules::board_rep::PossibleMove;
+use crate::baserules::score::Score;
+use crate::engine::gamestate::GameState;
+use crate::engine::Engine;
+use crate::human_facing::analysis::evaluate_position;
+use itertools::Itertools;
+use std::fmt::{Display, Formatter};
+use std::time::Duration;
+
+/// The number of moves shown from the better line
+pub const COACHING_LINE_LENGTH: usize = 4;
+
+/// Compares the moves of the human player to the engine's choice
+pub struct Coach {
+    /// The coach only speaks up if the move lost more pawns than this
+    pub threshold: Score,
+    /// The time the engine spends on each of the positions before and after the move
+    pub think_time: Duration,
+}
+
+/// The coach's comment on a move that was worse than the engine's choice
+#[derive(Debug, Clone, PartialEq)]
+pub struct CoachAdvice {
+    /// How many pawns the move lost compared to the best one
+    pub score_loss: Score,
+    /// The better line, starting with the move the engine would have played
+    pub better_line: Vec<PossibleMove>,
+}
+
+impl Display for CoachAdvice {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Your move lost {:.1} pawns compared to {}, e.g.: {}",
+            self.score_loss.as_pawns(),
+            self.better_line[0],
+            self.better_line.iter().join(" ")
+        )
+    }
+}
+
+impl Coach {
+    /// Evaluates the positions before and after the player's move, gives advice if the move lost
+    /// more than the threshold compared to the engine's choice
+    pub async fn review(
+        &self,
+        engine: &Engine,
+        before: &PSBoard,
+        after: &PSBoard,
+    ) -> Option<CoachAdvice> {
+        let mut state = GameState::new(before.clone());
+        let (best_move, best_score, _, _) =
+            engine.best_move_pvs(&mut state, &self.think_time).await;
+        let best_move = best_move?;
+        let best_board = before.make_move_noncached(&best_move).await;
+        if best_board.zobrist() == after.zobrist() {
+            return None;
+        }
+        let played = evaluate_position(engine, after, &self.think_time).await;
+        let score_loss = before.who_moves.mate_multiplier() * (best_score - played.score);
+        if score_loss <= self.threshold {
+            return None;
+        }
+        let mut better_line = vec![best_move];
+        if let Some(continuation) = state.continuation().find_continuation(&best_move) {
+            better_line.extend(continuation.principal_variation_up_to(COACHING_LINE_LENGTH - 1));
+        }
+        Some(CoachAdvice {
+            score_loss,
+            better_line,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::baserules::board::PSBoard;
+    use crate::baserules::board_rep::PossibleMove;
+    use crate::baserules::score::Score;
+    use crate::engine::Engine;
+    use crate::human_facing::coach::Coach;
+    use std::time::Duration;
+    use tokio::test;
+
+    #[test(flavor = "multi_thread")]
+    async fn hanging_the_queen_is_pointed_out() {
+        let before =
+            PSBoard::from_fen("rnbqkbnr/ppp2ppp/8/3pp3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 3")
+                .await
+                .unwrap();
+        let blunder = before
+            .make_move_noncached(&PossibleMove::simple_from_uci("d1g4").unwrap())
+            .await;
+        let (engine, _) = Engine::new();
+        let coach = Coach {
+            threshold: Score::centipawns(200),
+            think_time: Duration::from_millis(200),
+        };
+        let advice = coach.review(&engine, &before, &blunder).await.unwrap();
+        assert!(advice.score_loss > Score::centipawns(200));
+        assert_ne!("d1g4", advice.better_line[0].to_string());
+        assert!(advice.to_string().starts_with("Your move lost"));
+
+        let fine_move = before
+            .make_move_noncached(&PossibleMove::simple_from_uci("g1f3").unwrap())
+            .await;
+        assert_eq!(None, coach.review(&engine, &before, &fine_move).await);
+    }
+}
@@ -0,0 +1,479 @@
+/*
+ *  ========================================================================
+ *  DBCE chess bot, configuration file of the bot
+ *  ========================================================================
+ *
+ *  This file is part of DBCE.
+ *
+ *  DBCE is free software: you can redistribute it and/or
+ *  modify it under the terms of the GNU General Public License as published
+ *  by the Free Software Foundation, either version 3 of the License, or (at
+ *  your option) any later version.
+ *
+ *  DBCE is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ *  General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License along
+ *  with DBCE.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ *  (C) Copyright 2022-3, Gabor Kecskemeti
+ */
+use crate::baserules::score::Score;
+use crate::engine::strength::{Strength, StrengthSettings};
+use crate::engine::{SearchAlgorithm, Verbosity};
+use crate::human_facing::opponents::DEFAULT_DATA_DIR;
+use crate::util::IntResult;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// The configuration read at startup when no other file is given on the command line
+pub const DEFAULT_CONFIG_FILE: &str = "dbce.toml";
+
+/// Bots rated higher than our best rating by more than this are not challenged, unless set otherwise
+pub const DEFAULT_CHALLENGE_RATING_MARGIN: u64 = 400;
+
+/// The version of the configuration's settings, optionally given as `version` before the
+/// sections. The configurations without it are read as the current version.
+const CONFIG_VERSION: u64 = 1;
+
+/// What the bot does with the game it is playing when asked to shut down
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum ShutdownPolicy {
+    /// Plays the game to its end before stopping
+    #[default]
+    Finish,
+    /// Aborts the game if it has just started, resigns it otherwise
+    Resign,
+}
+
+impl FromStr for ShutdownPolicy {
+    type Err = String;
+
+    fn from_str(policy: &str) -> Result<Self, Self::Err> {
+        match policy.trim().to_lowercase().as_str() {
+            "finish" => Ok(ShutdownPolicy::Finish),
+            "resign" => Ok(ShutdownPolicy::Resign),
+            _ => Err(format!("Unknown shutdown policy: {policy}")),
+        }
+    }
+}
+
+/// The settings of the lichess bot and its engine, read from a toml file like this:
+/// ```toml
+/// [lichess]
+/// token = "lip_..."
+/// bot_id = "dbce"
+/// resume = true
+/// challenge_rating_margin = 400
+/// on_shutdown = "finish"
+///
+/// [engine]
+/// verbosity = "scores"
+/// strength = "club"
+/// algorithm = "exploration"
+/// threads = 4
+/// randomness = 50
+/// temperature = 20
+/// contempt = 15
+/// node_budget = 1_000_000
+/// tablebase = true
+/// chess960 = false
+/// nnue = "dbce_data/network.nnue"
+/// weights = "dbce_data/weights.toml"
+///
+/// [files]
+/// data_dir = "dbce_data"
+/// log_file = "dbce_data/engine.log"
+/// ```
+/// The settings not given are asked for on the console if they have no default.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BotConfig {
+    pub token: Option<String>,
+    pub bot_id: Option<String>,
+    /// Continue the unfinished game of the bot at startup
+    pub resume: Option<bool>,
+    pub challenge_rating_margin: u64,
+    pub on_shutdown: ShutdownPolicy,
+    pub verbosity: Option<Verbosity>,
+    pub strength: Option<Strength>,
+    pub algorithm: SearchAlgorithm,
+    /// The threads of the principal variation search, the ones beyond the first search with
+    /// Lazy SMP
+    pub threads: usize,
+    /// Overrides the window of the strength preset for the moves chosen from, in centipawns
+    pub randomness: Option<Score>,
+    /// Overrides the temperature of the strength preset for the move choice, in centipawns
+    pub temperature: Option<Score>,
+    /// How much worse than even the bot scores the draws against lower rated opponents, a
+    /// negative contempt makes it seek the draws instead
+    pub contempt: Score,
+    /// The boards kept from one search to the next, the engine's own default if not given
+    pub node_budget: Option<u32>,
+    /// The percentage of a CPU used to expand the likely replies while the opponent thinks, no
+    /// expansion if not given
+    pub prefetch_cpu: Option<u8>,
+    /// Play the moves of the lichess tablebase in small endgames
+    pub tablebase: bool,
+    /// Accept the challenges to Chess960 games besides the standard ones
+    pub chess960: bool,
+    /// The network file to evaluate the boards with, needs a build with the nnue feature
+    pub nnue: Option<PathBuf>,
+    /// The weights written by the tuning, the terms of the evaluation are scaled by them
+    pub weights: Option<PathBuf>,
+    /// Where the bot keeps what it learns and the trees it starts from
+    pub data_dir: PathBuf,
+    /// The engine's reports are appended to this file too, not only printed on the console
+    pub log_file: Option<PathBuf>,
+}
+
+impl Default for BotConfig {
+    fn default() -> Self {
+        Self {
+            token: None,
+            bot_id: None,
+            resume: None,
+            challenge_rating_margin: DEFAULT_CHALLENGE_RATING_MARGIN,
+            on_shutdown: ShutdownPolicy::default(),
+            verbosity: None,
+            strength: None,
+            algorithm: SearchAlgorithm::default(),
+            threads: 1,
+            randomness: None,
+            temperature: None,
+            contempt: Score::DRAW,
+            node_budget: None,
+            prefetch_cpu: None,
+            tablebase: true,
+            chess960: false,
+            nnue: None,
+            weights: None,
+            data_dir: PathBuf::from(DEFAULT_DATA_DIR),
+            log_file: None,
+        }
+    }
+}
+
+/// The kinds of toml values the configuration uses
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum ConfigValue {
+    Text(String),
+    Integer(i64),
+    Boolean(bool),
+}
+
+impl ConfigValue {
+    /// Reads the value at the start of the text, only a comment may follow it
+    fn parse(text: &str) -> Result<Self, String> {
+        if let Some(quoted) = text.strip_prefix('"') {
+            let mut value = String::new();
+            let mut chars = quoted.chars();
+            loop {
+                match chars.next() {
+                    None => return Err("the text is not closed with \"".into()),
+                    Some('"') => break,
+                    Some('\\') => match chars.next() {
+                        Some('"') => value.push('"'),
+                        Some('\\') => value.push('\\'),
+                        Some('n') => value.push('\n'),
+                        Some('t') => value.push('\t'),
+                        other => return Err(format!("unknown escape \\{}", other.unwrap_or(' '))),
+                    },
+                    Some(other) => value.push(other),
+                }
+            }
+            let rest = chars.as_str().trim();
+            if !rest.is_empty() && !rest.starts_with('#') {
+                return Err(format!("unexpected {rest} after the text"));
+            }
+            return Ok(ConfigValue::Text(value));
+        }
+        let value = text.split('#').next().unwrap_or_default().trim();
+        match value {
+            "true" => Ok(ConfigValue::Boolean(true)),
+            "false" => Ok(ConfigValue::Boolean(false)),
+            _ => value
+                .replace('_', "")
+                .parse()
+                .map(ConfigValue::Integer)
+                .map_err(|_| format!("{value} is not a text in quotes, a number, true or false")),
+        }
+    }
+
+    fn text(self) -> Result<String, String> {
+        match self {
+            ConfigValue::Text(text) => Ok(text),
+            other => Err(format!("expected a text in quotes instead of {other:?}")),
+        }
+    }
+
+    fn integer(self) -> Result<i64, String> {
+        match self {
+            ConfigValue::Integer(value) => Ok(value),
+            other => Err(format!("expected a number instead of {other:?}")),
+        }
+    }
+
+    /// The number of the settings that cannot be negative, e.g. counts and limits
+    fn unsigned(self) -> Result<u64, String> {
+        let value = self.integer()?;
+        u64::try_from(value).map_err(|_| format!("{value} cannot be negative"))
+    }
+
+    /// The centipawns of the settings that cannot be negative unlike the contempt
+    fn unsigned_centipawns(self) -> Result<Score, String> {
+        let value = self.unsigned()?;
+        Ok(Score::centipawns(
+            i32::try_from(value).map_err(|_| format!("{value} is too large"))?,
+        ))
+    }
+
+    fn centipawns(self) -> Result<Score, String> {
+        let value = self.integer()?;
+        Ok(Score::centipawns(
+            i32::try_from(value).map_err(|_| format!("{value} is too large"))?,
+        ))
+    }
+
+    fn boolean(self) -> Result<bool, String> {
+        match self {
+            ConfigValue::Boolean(value) => Ok(value),
+            other => Err(format!("expected true or false instead of {other:?}")),
+        }
+    }
+}
+
+impl BotConfig {
+    /// The strength preset with the overrides of the configuration
+    pub fn strength_settings(&self) -> StrengthSettings {
+        let preset = self.strength.unwrap_or_default().settings();
+        StrengthSettings {
+            randomness: self.randomness.unwrap_or(preset.randomness),
+            temperature: self.temperature.or(preset.temperature),
+            ..preset
+        }
+    }
+
+    /// Reads the configuration file
+    ///
+    /// # Errors
+    /// When the file cannot be read or it is not a valid configuration
+    pub fn load(file: &Path) -> IntResult<Self> {
+        let text = fs::read_to_string(file)
+            .map_err(|error| format!("Cannot read {}: {error}", file.display()))?;
+        Ok(text
+            .parse()
+            .map_err(|error| format!("{}: {error}", file.display()))?)
+    }
+
+    fn set(&mut self, section: &str, key: &str, value: ConfigValue) -> Result<(), String> {
+        match (section, key) {
+            ("lichess", "token") => self.token = Some(value.text()?),
+            ("lichess", "bot_id") => self.bot_id = Some(value.text()?),
+            ("lichess", "resume") => self.resume = Some(value.boolean()?),
+            ("lichess", "challenge_rating_margin") => {
+                self.challenge_rating_margin = value.unsigned()?
+            }
+            ("lichess", "on_shutdown") => self.on_shutdown = value.text()?.parse()?,
+            ("engine", "verbosity") => self.verbosity = Some(value.text()?.parse()?),
+            ("engine", "strength") => self.strength = Some(value.text()?.parse()?),
+            ("engine", "algorithm") => self.algorithm = value.text()?.parse()?,
+            ("engine", "threads") => {
+                let threads = value.unsigned()?;
+                if threads == 0 {
+                    return Err("at least 1 thread is needed".into());
+                }
+                self.threads =
+                    usize::try_from(threads).map_err(|_| format!("{threads} is too large"))?;
+            }
+            ("engine", "randomness") => self.randomness = Some(value.unsigned_centipawns()?),
+            ("engine", "temperature") => self.temperature = Some(value.unsigned_centipawns()?),
+            ("engine", "contempt") => self.contempt = value.centipawns()?,
+            ("engine", "node_budget") => {
+                let budget = value.unsigned()?;
+                self.node_budget =
+                    Some(u32::try_from(budget).map_err(|_| format!("{budget} is too large"))?);
+            }
+            ("engine", "prefetch_cpu") => {
+                let percent = value.unsigned()?;
+                if !(1..=100).contains(&percent) {
+                    return Err(format!("{percent} is not a percentage between 1 and 100"));
+                }
+                self.prefetch_cpu = Some(percent as u8);
+            }
+            ("engine", "tablebase") => self.tablebase = value.boolean()?,
+            ("engine", "chess960") => self.chess960 = value.boolean()?,
+            ("engine", "nnue") => self.nnue = Some(PathBuf::from(value.text()?)),
+            ("engine", "weights") => self.weights = Some(PathBuf::from(value.text()?)),
+            ("files", "data_dir") => self.data_dir = PathBuf::from(value.text()?),
+            ("files", "log_file") => self.log_file = Some(PathBuf::from(value.text()?)),
+            ("", "version") => {
+                let version = value.unsigned()?;
+                if version > CONFIG_VERSION {
+                    return Err(format!(
+                        "version {version} is newer than the supported {CONFIG_VERSION}"
+                    ));
+                }
+            }
+            ("", _) => return Err(format!("{key} is not in a section")),
+            _ => return Err(format!("unknown setting {key} in [{section}]")),
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for BotConfig {
+    type Err = String;
+
+    /// Reads the subset of toml used by the configuration: sections, comments and settings with
+    /// texts, numbers or booleans
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut config = BotConfig::default();
+        let mut section = String::new();
+        for (idx, line) in text.lines().enumerate() {
+            let at_line = |error: String| format!("line {}: {error}", idx + 1);
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some(header) = line.strip_prefix('[') {
+                let (name, rest) = header
+                    .split_once(']')
+                    .ok_or_else(|| at_line("the section name is not closed with ]".into()))?;
+                if !matches!(name.trim(), "lichess" | "engine" | "files") {
+                    return Err(at_line(format!("unknown section [{name}]")));
+                }
+                if !rest.trim().is_empty() && !rest.trim().starts_with('#') {
+                    return Err(at_line(format!("unexpected {rest} after the section")));
+                }
+                section = name.trim().to_owned();
+                continue;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| at_line("expected a setting like key = value".into()))?;
+            let value = ConfigValue::parse(value.trim()).map_err(at_line)?;
+            config.set(&section, key.trim(), value).map_err(at_line)?;
+        }
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::baserules::score::Score;
+    use crate::engine::strength::Strength;
+    use crate::engine::{SearchAlgorithm, Verbosity};
+    use crate::human_facing::config::{BotConfig, ShutdownPolicy, DEFAULT_CHALLENGE_RATING_MARGIN};
+    use std::path::PathBuf;
+    use tokio::test;
+
+    #[test]
+    async fn settings_are_read() {
+        let config: BotConfig = r#"
+            # Our bot
+            version = 1
+
+            [lichess]
+            token = "lip_\"secret\"" # not a real one
+            bot_id = "dbce"
+            on_shutdown = "resign"
+
+            [engine]
+            strength = "club"
+            algorithm = "mcts"
+            threads = 4
+            node_budget = 1_000_000
+            prefetch_cpu = 25
+            temperature = 35
+            contempt = 15
+            tablebase = false
+            chess960 = true
+            nnue = "nets/dbce.nnue"
+            weights = "nets/weights.toml"
+
+            [files]
+            data_dir = "/var/lib/dbce"
+            log_file = "/var/log/dbce.log"
+        "#
+        .parse()
+        .unwrap();
+        assert_eq!(Some("lip_\"secret\"".to_owned()), config.token);
+        assert_eq!(Some("dbce".to_owned()), config.bot_id);
+        assert_eq!(None, config.resume);
+        assert_eq!(ShutdownPolicy::Resign, config.on_shutdown);
+        assert_eq!(
+            DEFAULT_CHALLENGE_RATING_MARGIN,
+            config.challenge_rating_margin
+        );
+        assert_eq!(None, config.verbosity);
+        assert_eq!(Some(Strength::Club), config.strength);
+        assert_eq!(SearchAlgorithm::Mcts, config.algorithm);
+        assert_eq!(4, config.threads);
+        assert_eq!(Some(1_000_000), config.node_budget);
+        assert_eq!(Some(25), config.prefetch_cpu);
+        let settings = config.strength_settings();
+        assert_eq!(Strength::Club.settings().randomness, settings.randomness);
+        assert_eq!(Some(Score::centipawns(35)), settings.temperature);
+        assert_eq!(Score::centipawns(15), config.contempt);
+        assert!(!config.tablebase);
+        assert!(config.chess960);
+        assert_eq!(Some(PathBuf::from("nets/dbce.nnue")), config.nnue);
+        assert_eq!(Some(PathBuf::from("nets/weights.toml")), config.weights);
+        assert_eq!(PathBuf::from("/var/lib/dbce"), config.data_dir);
+        assert_eq!(Some(PathBuf::from("/var/log/dbce.log")), config.log_file);
+        assert_eq!(
+            Ok(Score::centipawns(-20)),
+            "[engine]\ncontempt = -20 # seeks the draws"
+                .parse::<BotConfig>()
+                .map(|config| config.contempt)
+        );
+        assert_eq!(
+            Ok(Some(Verbosity::Silent)),
+            "[engine]\nverbosity = \"silent\""
+                .parse::<BotConfig>()
+                .map(|config| config.verbosity)
+        );
+    }
+
+    #[test]
+    async fn mistakes_are_pointed_out() {
+        let error = |text: &str| text.parse::<BotConfig>().unwrap_err();
+        assert_eq!("line 1: token is not in a section", error("token = \"x\""));
+        assert_eq!(
+            "line 1: version 2 is newer than the supported 1",
+            error("version = 2")
+        );
+        assert_eq!("line 2: unknown section [bot]", error("\n[bot]"));
+        assert_eq!(
+            "line 2: unknown setting depth in [engine]",
+            error("[engine]\ndepth = 3")
+        );
+        assert_eq!(
+            "line 2: expected true or false instead of Integer(1)",
+            error("[engine]\ntablebase = 1")
+        );
+        assert_eq!(
+            "line 2: at least 1 thread is needed",
+            error("[engine]\nthreads = 0")
+        );
+        assert_eq!(
+            "line 2: -2 cannot be negative",
+            error("[engine]\nthreads = -2")
+        );
+        assert_eq!(
+            "line 2: -5 cannot be negative",
+            error("[engine]\nrandomness = -5")
+        );
+        assert_eq!(
+            "line 2: Unknown strength: grandmaster",
+            error("[engine]\nstrength = \"grandmaster\"")
+        );
+        assert_eq!(
+            "line 2: the text is not closed with \"",
+            error("[lichess]\ntoken = \"lip")
+        );
+    }
+}
@@ -21,15 +21,21 @@
  *  (C) Copyright 2022-3, Gabor Kecskemeti
  */
 use crate::baserules::board::PSBoard;
-use crate::baserules::castling::Castling;
+use crate::baserules::castling::{Castling, CastlingFiles};
+use crate::baserules::evaluation::{evaluate, Imbalance};
 use crate::baserules::move_gen::{CASTLE_ALLOWED, CASTLE_FORBIDDEN};
 use crate::baserules::piece_color::PieceColor;
 use crate::baserules::piece_color::PieceColor::*;
 use crate::baserules::piece_kind::PieceKind::*;
 use crate::baserules::piece_state::PieceState;
+use crate::baserules::positions::AbsoluteBoardPos;
 use crate::baserules::rawboard::RawBoard;
+use crate::baserules::score::Score;
+use crate::baserules::zobrist;
+use crate::human_facing::render::{AsciiRenderer, BoardRenderer, RenderOptions};
 use crate::util::{IntResult, TryWithPanic};
 use enumset::EnumSet;
+use std::error::Error;
 use std::fmt::{Display, Formatter};
 
 impl PieceState {
@@ -74,107 +80,487 @@ impl PieceState {
     }
 }
 
+impl Display for RawBoard {
+    /// This allows a simple text display of the raw board on your console, drawn by the
+    /// `AsciiRenderer` with the default options. Good for debugging purposes
+    ///
+    /// # Example:
+    /// ```
+    /// use dbce::baserules::rawboard::RawBoard;
+    /// let formatted = format!("{}", RawBoard::default());
+    /// let lines: Vec<_> = formatted.lines().collect();
+    /// assert_eq!("   a  b  c  d  e  f  g  h ", lines[0]);
+    /// assert_eq!("8  r  n  b  q  k  b  n  r  8", lines[1]);
+    /// assert_eq!("4  .  .  .  .  .  .  .  .  4", lines[5]);
+    /// ```
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            AsciiRenderer.render_raw(self, &RenderOptions::default())
+        )
+    }
+}
+
 impl Display for PSBoard {
-    /// This allows a simple text display of the board on your console. Good for debugging purposes
+    /// This allows a simple text display of the board on your console, drawn by the
+    /// `AsciiRenderer` with the default options. Good for debugging purposes
     ///
     /// # Example:
     /// ```
     /// use dbce::baserules::board::PSBoard;
-    /// let immortal_game = PSBoard::from_fen("r1bk3r/p2pBpNp/n4n2/1p1NP2P/6P1/3P4/P1P1K3/q5b1 b - - 0 1").unwrap();
-    /// let formatted = format!("{immortal_game}");
-    /// assert_eq!("Black to move, move 1\n  abcdefgh\n8 ♜-♝♚---♜ 8\n7 ♟--♟♗♟♘♟ 7\n6 ♞----♞-- 6\n5 -♟-♘♙--♙ 5\n4 ------♙- 4\n3 ---♙---- 3\n2 ♙-♙-♔--- 2\n1 ♛-----♝- 1\n  abcdefgh\n",formatted);
+    /// let scholars_mate = PSBoard::try_from_fen("r1bqkb1r/pppp1Qpp/2n2n2/4p3/2B1P3/8/PPPP1PPP/RNB1K1NR b KQkq - 0 4").unwrap();
+    /// let formatted = format!("{scholars_mate}");
+    /// let lines: Vec<_> = formatted.lines().collect();
+    /// assert_eq!("Black to move, move 4", lines[0]);
+    /// assert_eq!("8  r  .  b  q <k> b  .  r  8", lines[2]);
     /// ```
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
             "{:?} to move, move {}\n{}",
-            self.who_moves, self.move_count, self.raw
+            self.who_moves,
+            self.move_count,
+            AsciiRenderer.render(self, &RenderOptions::default())
         )
     }
 }
 
-impl PSBoard {
-    /// Allows initialising a particular position from fen
-    /// <https://en.wikipedia.org/wiki/Forsyth%E2%80%93Edwards_Notation>
-    /// # Panics
-    /// When the input string is an incorrect fen
-    pub async fn from_fen(fen: &str) -> IntResult<Self> {
-        let mut raw = RawBoard::empty();
-        let mut next_move = None;
-        let mut castling = EnumSet::empty();
-        let mut ep = None;
-        let mut half: Option<u16> = None;
-        let mut full: Option<u16> = None;
-        for (idx, fen_part) in fen.split_whitespace().enumerate() {
-            match idx {
-                0 => raw = RawBoard::from_fen_prefix(fen_part)?,
-
-                1 => {
-                    let deciphered_who_moves: PieceColor =
-                        fen_part.chars().next().unwrap().transform();
-                    next_move = Some(deciphered_who_moves);
-                }
+/// What is wrong with a fen that could not be read
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FenError {
+    /// Not all the six fields of the fen are given, or there are more
+    WrongFieldCount(usize),
+    /// A character of the piece placement that is neither a piece nor the count of empty squares
+    BadPiece(char),
+    /// The piece placement does not have eight ranks
+    WrongRankCount(usize),
+    /// A rank of the piece placement does not have eight squares
+    WrongRankLength { rank: u8, squares: u8 },
+    /// The side to move is neither "w" nor "b"
+    BadSideToMove(String),
+    /// The castling rights are neither "-" nor made of "KQkq" or the files of the rooks
+    BadCastling(String),
+    /// The en passant square is neither "-" nor a square behind a pawn that has just moved two
+    BadEnPassant(String),
+    /// The half move clock or the move number is not a number
+    BadCounter(String),
+}
 
-                2 => {
-                    castling = EnumSet::new();
-                    for castle_right in fen_part.chars().filter(|c| *c != '-') {
-                        let additional_castling: Castling = castle_right.transform();
-                        castling |= additional_castling;
-                    }
-                }
+impl Display for FenError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FenError::WrongFieldCount(fields) => {
+                write!(f, "the fen should have 6 fields instead of {fields}")
+            }
+            FenError::BadPiece(piece) => write!(f, "unknown piece in the fen: {piece}"),
+            FenError::WrongRankCount(ranks) => {
+                write!(f, "the fen should have 8 ranks instead of {ranks}")
+            }
+            FenError::WrongRankLength { rank, squares } => {
+                write!(
+                    f,
+                    "rank {rank} of the fen has {squares} squares instead of 8"
+                )
+            }
+            FenError::BadSideToMove(side) => write!(f, "unknown side to move in the fen: {side}"),
+            FenError::BadCastling(castling) => {
+                write!(f, "invalid castling rights in the fen: {castling}")
+            }
+            FenError::BadEnPassant(ep) => write!(f, "invalid en passant square in the fen: {ep}"),
+            FenError::BadCounter(counter) => {
+                write!(f, "invalid move counter in the fen: {counter}")
+            }
+        }
+    }
+}
 
-                3 => {
-                    if !fen_part.starts_with('-') {
-                        ep = Some(fen_part.transform());
-                    }
-                }
+impl Error for FenError {}
 
-                4 => half = Some(fen_part.parse().unwrap()),
+/// The pieces of the first field of the fen, the ranks from the eighth to the first
+fn parse_placement(placement: &str) -> Result<RawBoard, FenError> {
+    let ranks: Vec<&str> = placement.split('/').collect();
+    if ranks.len() != 8 {
+        return Err(FenError::WrongRankCount(ranks.len()));
+    }
+    let mut raw = RawBoard::empty();
+    for (row, rank) in (0..8u8).rev().zip(ranks) {
+        let mut col = 0u8;
+        for piece_info in rank.chars() {
+            if let Some(empty) = piece_info
+                .to_digit(10)
+                .filter(|empty| (1..=8).contains(empty))
+            {
+                col += empty as u8;
+            } else {
+                let piece =
+                    PieceState::try_from(piece_info).map_err(|_| FenError::BadPiece(piece_info))?;
+                if col < 8 {
+                    raw.set_loc((row, col).transform(), &Some(piece));
+                }
+                col += 1;
+            }
+        }
+        if col != 8 {
+            return Err(FenError::WrongRankLength {
+                rank: row + 1,
+                squares: col,
+            });
+        }
+    }
+    Ok(raw)
+}
 
-                5 => full = Some(fen_part.parse().unwrap()),
+/// The file of the outermost rook on the given side of the king, which the castling rights of
+/// X-FEN mean with KQkq
+fn outermost_rook(raw: &RawBoard, color: PieceColor, king: u8, king_side: bool) -> Option<u8> {
+    let rook = Some(PieceState { kind: Rook, color });
+    let row = color.piece_row();
+    if king_side {
+        (king + 1..8).rev().find(|col| raw[(row, *col)] == rook)
+    } else {
+        (0..king).find(|col| raw[(row, *col)] == rook)
+    }
+}
 
-                _ => panic!("Too many fields in the FEN"),
-            }
+/// The castling rights of the fen with the files they castle from. Besides KQkq, the rights may
+/// name the files of the rooks as Shredder-FEN and X-FEN do. KQkq castle with the outermost rook
+/// of the side, or with the rook of the corner when there is none, so the rights of standard
+/// chess are read the same even when the pieces have left their squares. The files are the same
+/// for both sides, as the Chess960 positions start with mirrored pieces.
+fn parse_castling(
+    field: &str,
+    raw: &RawBoard,
+) -> Result<(EnumSet<Castling>, CastlingFiles), FenError> {
+    let mut castling = EnumSet::empty();
+    if field == "-" {
+        return Ok((castling, CastlingFiles::STANDARD));
+    }
+    let bad_castling = || FenError::BadCastling(field.into());
+    let (mut king_file, mut rook_files) = (None, [None; 2]);
+    for right in field.chars() {
+        let color = if right.is_ascii_uppercase() {
+            White
+        } else {
+            Black
+        };
+        let king_piece = Some(PieceState { kind: King, color });
+        let king = (0..8)
+            .find(|col| raw[(color.piece_row(), *col)] == king_piece)
+            .unwrap_or(CastlingFiles::STANDARD.king);
+        let rook = match right.to_ascii_lowercase() {
+            'k' => outermost_rook(raw, color, king, true).unwrap_or(7),
+            'q' => outermost_rook(raw, color, king, false).unwrap_or(0),
+            file @ 'a'..='h' => file as u8 - b'a',
+            _ => return Err(bad_castling()),
+        };
+        let king_side = rook > king;
+        if rook == king
+            || *king_file.get_or_insert(king) != king
+            || *rook_files[usize::from(king_side)].get_or_insert(rook) != rook
+        {
+            return Err(bad_castling());
         }
-        Ok(PSBoard {
-            score: raw.score().await,
+        castling |= if king_side {
+            color.king_side_castling()
+        } else {
+            color.queen_side_castling()
+        };
+    }
+    let standard = CastlingFiles::STANDARD;
+    let mut files = CastlingFiles {
+        king: king_file.unwrap_or(standard.king),
+        king_side_rook: rook_files[1].unwrap_or(standard.king_side_rook),
+        queen_side_rook: rook_files[0].unwrap_or(standard.queen_side_rook),
+        chess960: false,
+    };
+    files.chess960 = files != standard;
+    Ok((castling, files))
+}
+
+impl PSBoard {
+    /// Allows initialising a particular position from fen
+    /// <https://en.wikipedia.org/wiki/Forsyth%E2%80%93Edwards_Notation>
+    /// # Errors
+    /// When the input string is an incorrect fen, see `try_from_fen`
+    pub async fn from_fen(fen: &str) -> IntResult<Self> {
+        Ok(Self::try_from_fen(fen)?)
+    }
+
+    /// Reads the position of the fen, telling what is wrong with the fen instead of panicking.
+    /// This is what the fens coming from the users should be read with. The castling rights may
+    /// also be given by the files of the rooks, as in Shredder-FEN and X-FEN, the boards with
+    /// the king or the rooks castling from other files than in standard chess play Chess960.
+    ///
+    /// # Example
+    /// ```
+    /// use dbce::baserules::board::PSBoard;
+    /// use dbce::human_facing::conversion::FenError;
+    /// let fen = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+    /// assert_eq!(fen, PSBoard::try_from_fen(fen).unwrap().to_fen());
+    /// let castling = PSBoard::try_from_fen("4k3/8/8/8/8/8/8/4K3 w X - 0 1");
+    /// assert_eq!(Some(FenError::BadCastling("X".into())), castling.err());
+    /// ```
+    pub fn try_from_fen(fen: &str) -> Result<Self, FenError> {
+        let fields: Vec<&str> = fen.split_whitespace().collect();
+        let [placement, side, castling_field, ep_field, half, full] = fields[..] else {
+            return Err(FenError::WrongFieldCount(fields.len()));
+        };
+        let raw = parse_placement(placement)?;
+        let who_moves = match side {
+            "w" => White,
+            "b" => Black,
+            _ => return Err(FenError::BadSideToMove(side.into())),
+        };
+        let (castling, castling_files) = parse_castling(castling_field, &raw)?;
+        let ep = if ep_field == "-" {
+            None
+        } else {
+            // The square passed by the pawn of the opponent, on the third or the sixth rank
+            let ep_row = if who_moves == White { 5 } else { 2 };
+            let square = (ep_field.len() == 2)
+                .then(|| AbsoluteBoardPos::try_from(ep_field).ok())
+                .flatten()
+                .filter(|square| square.0 == ep_row);
+            Some(square.ok_or_else(|| FenError::BadEnPassant(ep_field.into()))?)
+        };
+        let counter = |field: &str| {
+            field
+                .parse()
+                .map_err(|_| FenError::BadCounter(field.into()))
+        };
+        let half_moves_since_pawn = counter(half)?;
+        let move_count = counter(full)?;
+        let mut board = PSBoard {
+            score: Score::DRAW,
+            material: raw.material(),
+            kings: raw.find_kings(),
+            in_check: false,
+            zobrist: zobrist::full_key(&raw, who_moves, castling, ep),
             raw,
-            who_moves: next_move.unwrap_or_else(|| panic!("Unspecified whose turn it is!")),
+            who_moves,
             king_move_gen: if castling.is_empty() {
                 &CASTLE_FORBIDDEN
             } else {
                 &CASTLE_ALLOWED
             },
             castling,
+            castling_files,
             ep,
-            move_count: full.unwrap_or_else(|| panic!("Unspecified move count")),
-            half_moves_since_pawn: half.unwrap_or_else(|| panic!("Unspecified half move count")),
-        })
+            move_count,
+            half_moves_since_pawn,
+            #[cfg(feature = "nnue")]
+            accumulator: None,
+        };
+        board.in_check = board.king_attacked();
+        board.score = evaluate(&board, &Imbalance);
+        Ok(board)
     }
 
     /// Allows exporting a `PSBoard` to fen for external analysis
-    /// <https://en.wikipedia.org/wiki/Forsyth%E2%80%93Edwards_Notation>
-    /// # Panics
-    /// When `PieceState` is failing to produce a proper output
+    /// <https://en.wikipedia.org/wiki/Forsyth%E2%80%93Edwards_Notation>. The castling rights
+    /// always come in the KQkq order, so the same position always gives the same fen, and
+    /// `try_from_fen` reads back every field of it, the en passant square and the counters too.
+    /// The Chess960 rights not castling with the outermost rook are written with the file of the
+    /// rook, as X-FEN has them.
+    ///
+    /// # Example
+    /// ```
+    /// use dbce::baserules::board::PSBoard;
+    /// let fen = "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w Kq f6 0 3";
+    /// assert_eq!(fen, PSBoard::try_from_fen(fen).unwrap().to_fen());
+    /// ```
     pub fn to_fen(&self) -> String {
-        let mut ret = self.raw.to_fen_prefix();
-        ret.push(' ');
-        ret.push(self.who_moves.fen_color());
-        ret.push(' ');
-        if self.castling.is_empty() {
-            ret.push('-');
-        } else {
-            let mut castling: Vec<char> = self
-                .castling
-                .iter()
-                .map(|a_castling| a_castling.fen_char())
-                .collect();
-            castling.sort();
-            ret.push_str(castling.into_iter().collect::<String>().as_str())
+        self.fen_with_castling(false)
+    }
+
+    /// The fen of the position with the castling rights named by the files of the rooks, as
+    /// Shredder-FEN has them, for the GUIs expecting it. `try_from_fen` reads it back as well.
+    ///
+    /// # Example
+    /// ```
+    /// use dbce::baserules::board::PSBoard;
+    /// let shredder = PSBoard::default().to_shredder_fen();
+    /// assert_eq!("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w HAha - 0 1", shredder);
+    /// assert_eq!(PSBoard::default().to_fen(), PSBoard::try_from_fen(&shredder).unwrap().to_fen());
+    /// ```
+    pub fn to_shredder_fen(&self) -> String {
+        self.fen_with_castling(true)
+    }
+
+    fn fen_with_castling(&self, shredder: bool) -> String {
+        let files = self.castling_files;
+        let castling: String = self
+            .castling
+            .iter()
+            .map(|a_castling| {
+                let rook = files.rook(a_castling);
+                let outermost = outermost_rook(
+                    &self.raw,
+                    a_castling.color(),
+                    files.king,
+                    a_castling.is_king_side(),
+                );
+                let standard = CastlingFiles {
+                    chess960: files.chess960,
+                    ..CastlingFiles::STANDARD
+                };
+                if !shredder && (files == standard || outermost == Some(rook)) {
+                    a_castling.fen_char()
+                } else if a_castling.color() == White {
+                    (b'A' + rook) as char
+                } else {
+                    (b'a' + rook) as char
+                }
+            })
+            .collect();
+        format!(
+            "{} {} {} {} {} {}",
+            self.raw.to_fen_prefix(),
+            self.who_moves.fen_color(),
+            if castling.is_empty() { "-" } else { &castling },
+            self.ep.map_or("-".into(), |ep| ep.to_string()),
+            self.half_moves_since_pawn,
+            self.move_count
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::baserules::board::PSBoard;
+    use crate::baserules::move_list::MoveList;
+    use crate::human_facing::conversion::FenError;
+
+    #[test]
+    fn malformed_fens_are_explained() {
+        let cases = [
+            ("8/8/8/8/8/8/8/8 w - -", FenError::WrongFieldCount(4)),
+            ("4k3/8/8/8/8/8/8/4X3 w - - 0 1", FenError::BadPiece('X')),
+            ("4k3/8/8/8/8/8/4K3 w - - 0 1", FenError::WrongRankCount(7)),
+            ("4k3/8/8/8/8/9/8/4K3 w - - 0 1", FenError::BadPiece('9')),
+            (
+                "4k3/8/8/8/8/8/8/4K4 w - - 0 1",
+                FenError::WrongRankLength {
+                    rank: 1,
+                    squares: 9,
+                },
+            ),
+            (
+                "4k3/8/8/8/8/8/7/4K3 w - - 0 1",
+                FenError::WrongRankLength {
+                    rank: 2,
+                    squares: 7,
+                },
+            ),
+            (
+                "4k3/8/8/8/8/8/8/4K3 x - - 0 1",
+                FenError::BadSideToMove("x".into()),
+            ),
+            (
+                "4k3/8/8/8/8/8/8/4K3 w KQx - 0 1",
+                FenError::BadCastling("KQx".into()),
+            ),
+            (
+                "4k3/8/8/8/8/8/8/4K3 w - e3 0 1",
+                FenError::BadEnPassant("e3".into()),
+            ),
+            (
+                "4k3/8/8/8/8/8/8/4K3 b - z9 0 1",
+                FenError::BadEnPassant("z9".into()),
+            ),
+            (
+                "4k3/8/8/8/8/8/8/4K3 w - - -1 1",
+                FenError::BadCounter("-1".into()),
+            ),
+        ];
+        for (fen, expected) in cases {
+            assert_eq!(Some(expected), PSBoard::try_from_fen(fen).err(), "{fen}");
+        }
+    }
+
+    #[test]
+    fn castling_files_are_read() {
+        for (fen, expected) in [
+            ("r3k2r/8/8/8/8/8/8/R3K2R w HAha - 0 1", "KQkq"),
+            ("r3k2r/8/8/8/8/8/8/R3K2R w Ha - 0 1", "Kq"),
+            ("r3k2r/8/8/8/8/8/8/R3K2R w KAh - 0 1", "KQk"),
+        ] {
+            let board = PSBoard::try_from_fen(fen).unwrap();
+            assert_eq!(
+                format!("r3k2r/8/8/8/8/8/8/R3K2R w {expected} - 0 1"),
+                board.to_fen()
+            );
+            assert_eq!(fen.replace("KAh", "HAh"), board.to_shredder_fen());
+        }
+        assert_eq!(
+            Some(FenError::BadCastling("HAhb".into())),
+            PSBoard::try_from_fen("r3k2r/8/8/8/8/8/8/R3K2R w HAhb - 0 1").err()
+        );
+    }
+
+    #[test]
+    fn chess960_castling_is_read_back() {
+        for (fen, shredder) in [
+            (
+                "qrkbbnrn/pppppppp/8/8/8/8/PPPPPPPP/QRKBBNRN w KQkq - 0 1",
+                "qrkbbnrn/pppppppp/8/8/8/8/PPPPPPPP/QRKBBNRN w GBgb - 0 1",
+            ),
+            (
+                "1rk3r1/8/8/8/8/8/8/RRK3R1 w Bkq - 0 1",
+                "1rk3r1/8/8/8/8/8/8/RRK3R1 w Bgb - 0 1",
+            ),
+        ] {
+            let board = PSBoard::try_from_fen(fen).unwrap();
+            assert!(board.castling_files().chess960);
+            assert_eq!(1, board.castling_files().queen_side_rook);
+            assert_eq!(fen, board.to_fen());
+            assert_eq!(shredder, board.to_shredder_fen());
+            assert_eq!(fen, PSBoard::try_from_fen(shredder).unwrap().to_fen());
+        }
+    }
+
+    const CORPUS: [&str; 9] = [
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+        "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1",
+        "r3k2r/8/8/8/8/8/8/R3K2R w Kq - 12 40",
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+        "r2q1rk1/pP1p2pp/Q4n2/bbp1p3/Np6/1B3NBn/pPPP1PPP/R3K2R b KQ - 0 1",
+        "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+        "8/8/8/8/8/4k3/8/4K3 b - - 99 187",
+    ];
+
+    #[test]
+    fn valid_fens_are_read_back() {
+        for fen in CORPUS {
+            assert_eq!(fen, PSBoard::try_from_fen(fen).unwrap().to_fen());
+        }
+        assert_eq!(CORPUS[0], PSBoard::default().to_fen());
+    }
+
+    #[test]
+    fn played_positions_survive_the_round_trip() {
+        for fen in CORPUS {
+            let mut board = PSBoard::try_from_fen(fen).unwrap();
+            for ply in 0..40 {
+                let read = PSBoard::try_from_fen(&board.to_fen()).unwrap();
+                assert_eq!(board.to_fen(), read.to_fen());
+                assert_eq!(board.zobrist, read.zobrist, "{}", board.to_fen());
+                assert_eq!(board.ep, read.ep);
+                assert_eq!(board.castling, read.castling);
+                assert_eq!(board.move_count, read.move_count);
+                assert_eq!(board.halfmove_clock(), read.halfmove_clock());
+                let mut moves = MoveList::new();
+                board.gen_legal_moves(&mut moves);
+                if moves.is_empty() {
+                    break;
+                }
+                // Mixing the moves a bit, to have double pawn steps and castling too
+                let a_move = moves[(ply * 7 + fen.len()) % moves.len()];
+                board.make_move_in_place(&a_move);
+            }
         }
-        ret.push(' ');
-        ret.push_str(self.ep.map_or("-".into(), |p| p.to_string()).as_str());
-        ret.push_str(format!(" {} {}", self.half_moves_since_pawn, self.move_count).as_str());
-        ret
     }
 }
@@ -0,0 +1,354 @@
+/*
+ *  ========================================================================
+ *  DBCE chess bot, games imported for annotation
+ *  ========================================================================
+ *
+ *  This file is part of DBCE.
+ *
+ *  DBCE is free software: you can redistribute it and/or
+ *  modify it under the terms of the GNU General Public License as published
+ *  by the Free Software Foundation, either version 3 of the License, or (at
+ *  your option) any later version.
+ *
+ *  DBCE is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ *  General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License along
+ *  with DBCE.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ *  (C) Copyright 2022-3, Gabor Kecskemeti
+ */
+use crate::baserules::board::PSBoard;
+use crate::baserules::board_rep::PossibleMove;
+use crate::baserules::game::{Game, GameStatus};
+use crate::baserules::piece_color::PieceColor::{Black, White};
+use crate::baserules::score::Score;
+use crate::human_facing::analysis::GameAnalysis;
+use crate::util::IntResult;
+use reqwest::header::ACCEPT;
+use reqwest::Client;
+use serde_json::Value;
+use std::fmt::Write;
+use std::time::Duration;
+
+/// Moves losing this many times the suspicion threshold are marked as blunders instead of mistakes
+const BLUNDER_MULTIPLIER: i32 = 3;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum GameResult {
+    WhiteWon,
+    BlackWon,
+    Draw,
+    Unfinished,
+}
+
+impl GameResult {
+    /// The result as written at the end of a pgn
+    pub fn notation(&self) -> &'static str {
+        match self {
+            GameResult::WhiteWon => "1-0",
+            GameResult::BlackWon => "0-1",
+            GameResult::Draw => "1/2-1/2",
+            GameResult::Unfinished => "*",
+        }
+    }
+
+    /// Reads the result as written at the end of a pgn
+    pub fn from_notation(notation: &str) -> Option<GameResult> {
+        [
+            GameResult::WhiteWon,
+            GameResult::BlackWon,
+            GameResult::Draw,
+            GameResult::Unfinished,
+        ]
+        .into_iter()
+        .find(|result| result.notation() == notation)
+    }
+
+    /// The result from the winner and the status lichess reports for a game
+    ///
+    /// # Errors
+    /// When the winner contradicts the status
+    pub fn from_lichess(winner: Option<&str>, status: &str) -> IntResult<GameResult> {
+        Ok(match (winner, status) {
+            (None, "created" | "started" | "aborted" | "noStart") => GameResult::Unfinished,
+            (Some("white"), "mate" | "resign" | "outoftime" | "timeout" | "variantEnd") => {
+                GameResult::WhiteWon
+            }
+            (Some("black"), "mate" | "resign" | "outoftime" | "timeout" | "variantEnd") => {
+                GameResult::BlackWon
+            }
+            // Running out of time is a draw when the opponent has no mating material
+            (None, "draw" | "stalemate" | "outoftime") => GameResult::Draw,
+            (winner, status) => {
+                return Err(format!("Result {winner:?} contradicts the status {status}").into())
+            }
+        })
+    }
+}
+
+impl From<GameStatus> for GameResult {
+    fn from(status: GameStatus) -> Self {
+        match status {
+            GameStatus::Ongoing => GameResult::Unfinished,
+            GameStatus::Checkmate(White) | GameStatus::Timeout(White) => GameResult::WhiteWon,
+            GameStatus::Checkmate(Black) | GameStatus::Timeout(Black) => GameResult::BlackWon,
+            GameStatus::Stalemate | GameStatus::DrawBy(_) => GameResult::Draw,
+        }
+    }
+}
+
+/// A game played elsewhere, with its moves checked against our rules
+pub struct GameRecord {
+    pub start: PSBoard,
+    pub moves: Vec<PossibleMove>,
+    /// The time left on the mover's clock after each move, empty for games without a clock
+    pub clocks: Vec<Duration>,
+    pub result: GameResult,
+}
+
+/// The record of a game played with our rules, without its clocks
+impl From<&Game> for GameRecord {
+    fn from(game: &Game) -> Self {
+        GameRecord {
+            start: game.start().clone(),
+            moves: game.history().to_vec(),
+            clocks: Vec::new(),
+            result: game.status().into(),
+        }
+    }
+}
+
+impl GameRecord {
+    /// Rebuilds a game from the json format of the lichess game export api, see
+    /// <https://lichess.org/api#tag/Games/operation/gamePgn>
+    ///
+    /// # Errors
+    /// When the game is not standard chess, its starting position is not legal, a move is not
+    /// possible in its position, there are more clock readings than moves, or the result
+    /// contradicts the status of the game
+    pub async fn from_lichess_json(json: &Value) -> IntResult<GameRecord> {
+        let variant = json["variant"].as_str().unwrap_or("standard");
+        if variant != "standard" && variant != "fromPosition" {
+            return Err(format!("Unsupported variant: {variant}").into());
+        }
+        let start = match json["initialFen"].as_str() {
+            Some(fen) => PSBoard::try_from_fen(fen)?,
+            None => PSBoard::default(),
+        };
+        start.validate()?;
+        let moves = replay_notation(
+            &start,
+            json["moves"]
+                .as_str()
+                .unwrap_or_default()
+                .split_whitespace(),
+        )
+        .await?;
+        let clocks: Vec<Duration> = json["clocks"]
+            .as_array()
+            .map(|clocks| {
+                clocks
+                    .iter()
+                    .filter_map(Value::as_u64)
+                    .map(|centis| Duration::from_millis(centis * 10))
+                    .collect()
+            })
+            .unwrap_or_default();
+        if clocks.len() > moves.len() {
+            return Err(
+                format!("{} clock readings for {} moves", clocks.len(), moves.len()).into(),
+            );
+        }
+        let result = GameResult::from_lichess(
+            json["winner"].as_str(),
+            json["status"].as_str().unwrap_or("started"),
+        )?;
+        Ok(GameRecord {
+            start,
+            moves,
+            clocks,
+            result,
+        })
+    }
+
+    /// Pgn move text with the evaluations and clocks as comments. Suspicious moves of the
+    /// analysis are marked as mistakes, or blunders when they lose a lot more. The only moves of
+    /// their positions get the singular move glyph ($8).
+    pub fn annotate(&self, analysis: &GameAnalysis, suspicion_threshold: Score) -> String {
+        let mut annotated = String::new();
+        let mut board = self.start.clone();
+        let first_move = self.start.move_count.max(1); // Some fens count the moves from 0
+        let black_starts = self.start.who_moves.mate_multiplier() < 0;
+        for (ply, evaluation) in analysis.moves.iter().enumerate() {
+            let ply_from_white = ply + usize::from(black_starts);
+            if ply_from_white % 2 == 0 {
+                write!(annotated, "{}. ", first_move as usize + ply_from_white / 2).unwrap();
+            } else if ply == 0 {
+                write!(annotated, "{}... ", first_move).unwrap();
+            }
+            let mark = if evaluation.is_suspicious(suspicion_threshold * BLUNDER_MULTIPLIER) {
+                "??"
+            } else if evaluation.is_suspicious(suspicion_threshold) {
+                "?"
+            } else {
+                ""
+            };
+            let glyph = if evaluation.only_move { " $8" } else { "" };
+            write!(
+                annotated,
+                "{}{mark}{glyph} {{{:+.2}",
+                evaluation.the_move.to_san(&board),
+                evaluation.after.score
+            )
+            .unwrap();
+            if !mark.is_empty() {
+                if let Some(best_move) = evaluation.before.best_move {
+                    write!(annotated, ", best: {}", best_move.to_san(&board)).unwrap();
+                }
+            }
+            board.make_move_in_place(&evaluation.the_move);
+            if let Some(clock) = self.clocks.get(ply) {
+                let seconds = clock.as_secs();
+                write!(annotated, " [{}:{:02}]", seconds / 60, seconds % 60).unwrap();
+            }
+            annotated.push_str("} ");
+        }
+        annotated.push_str(self.result.notation());
+        annotated
+    }
+}
+
+/// Finds the moves in short algebraic notation of the given starting position
+pub(crate) async fn replay_notation<'a>(
+    start: &PSBoard,
+    notations: impl Iterator<Item = &'a str>,
+) -> IntResult<Vec<PossibleMove>> {
+    let mut moves = Vec::new();
+    let mut board: Option<PSBoard> = None;
+    for (ply, notation) in notations.enumerate() {
+        let current = board.as_ref().unwrap_or(start);
+        let a_move = PossibleMove::from_san(notation, current).map_err(|error| {
+            format!(
+                "Ply {} ({notation}) in {}: {error}",
+                ply + 1,
+                current.to_fen()
+            )
+        })?;
+        board = Some(current.make_move_noncached(&a_move).await);
+        moves.push(a_move);
+    }
+    Ok(moves)
+}
+
+/// Downloads a game by its id with the lichess game export api
+pub async fn fetch_lichess_game(client: &Client, game_id: &str) -> IntResult<GameRecord> {
+    let json: Value = client
+        .get(format!("https://lichess.org/game/export/{game_id}"))
+        .header(ACCEPT, "application/json")
+        .query(&[("moves", "true"), ("clocks", "true"), ("evals", "false")])
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    GameRecord::from_lichess_json(&json).await
+}
+
+#[cfg(test)]
+mod test {
+    use crate::baserules::board::PSBoard;
+    use crate::baserules::board_rep::PossibleMove;
+    use crate::baserules::piece_color::PieceColor::{Black, White};
+    use crate::baserules::score::Score;
+    use crate::human_facing::analysis::{GameAnalysis, MoveEvaluation, PositionEvaluation};
+    use crate::human_facing::game::{GameRecord, GameResult};
+    use serde_json::json;
+    use std::time::Duration;
+    use tokio::test;
+
+    #[test]
+    async fn imports_lichess_export() {
+        let game = GameRecord::from_lichess_json(&json!({
+            "id": "q7ZvsdUF",
+            "variant": "standard",
+            "status": "mate",
+            "winner": "white",
+            "moves": "e4 e5 Bc4 Nc6 Qh5 Nf6 Qxf7#",
+            "clocks": [18003, 18003, 17915, 17843, 17707, 17634, 17600],
+        }))
+        .await
+        .unwrap();
+        assert_eq!(GameResult::WhiteWon, game.result);
+        assert_eq!(
+            "e2e4 e7e5 f1c4 b8c6 d1h5 g8f6 h5f7",
+            game.moves
+                .iter()
+                .map(PossibleMove::to_string)
+                .collect::<Vec<_>>()
+                .join(" ")
+        );
+        assert_eq!(Duration::from_millis(179_150), game.clocks[2]);
+    }
+
+    #[test]
+    async fn rejects_invalid_games() {
+        let illegal = json!({"status": "resign", "winner": "black", "moves": "e4 e5 Ke3"});
+        let error = GameRecord::from_lichess_json(&illegal).await.err().unwrap();
+        assert!(error.to_string().starts_with("Ply 3 (Ke3)"));
+
+        let clocks = json!({"status": "draw", "moves": "e4", "clocks": [100, 100]});
+        assert!(GameRecord::from_lichess_json(&clocks).await.is_err());
+
+        let winner = json!({"status": "stalemate", "winner": "white", "moves": "e4"});
+        assert!(GameRecord::from_lichess_json(&winner).await.is_err());
+        let no_winner = json!({"status": "mate", "moves": "e4"});
+        assert!(GameRecord::from_lichess_json(&no_winner).await.is_err());
+
+        let variant = json!({"variant": "chess960", "status": "started", "moves": ""});
+        assert!(GameRecord::from_lichess_json(&variant).await.is_err());
+    }
+
+    #[test]
+    async fn marks_mistakes() {
+        let game = GameRecord::from_lichess_json(&json!({
+            "status": "resign",
+            "winner": "black",
+            "moves": "f3 e5",
+            "clocks": [6000, 5950],
+        }))
+        .await
+        .unwrap();
+        let evaluation = |pawns, best: &str| PositionEvaluation {
+            score: Score::from_pawns(pawns),
+            best_move: PossibleMove::simple_from_uci(best).ok(),
+            depth: 3,
+            time_used: Duration::ZERO,
+        };
+        let analysis = GameAnalysis {
+            moves: vec![
+                MoveEvaluation {
+                    the_move: game.moves[0],
+                    mover: White,
+                    before: evaluation(0.3, "e2e4"),
+                    after: evaluation(-1.5, "e7e5"),
+                    only_move: false,
+                },
+                MoveEvaluation {
+                    the_move: game.moves[1],
+                    mover: Black,
+                    before: evaluation(-1.5, "e7e5"),
+                    after: evaluation(-1.4, "g2g4"),
+                    only_move: true,
+                },
+            ],
+        };
+        assert_eq!(
+            "1. f3? {-1.50, best: e4 [1:00]} e5 $8 {-1.40 [0:59]} 0-1",
+            game.annotate(&analysis, Score::centipawns(100))
+        );
+        assert_eq!(PSBoard::default().to_fen(), game.start.to_fen());
+    }
+}
@@ -21,42 +21,77 @@
  *  (C) Copyright 2022-3, Gabor Kecskemeti
  */
 
+use crate::baserules::board::PSBoard;
 use crate::baserules::board_rep::PossibleMove;
 use crate::baserules::piece_color::PieceColor;
-use crate::engine::{gamestate::GameState, Engine};
+use crate::baserules::score::Score;
+use crate::engine::timing::TimeAllocation;
+use crate::engine::{gamestate::GameState, Engine, Verbosity};
+use crate::util::IntResult;
 use std::time::Duration;
 use tokio::time::Instant;
 
+/// The engine resigns when it is behind by more than this
+const RESIGNATION_THRESHOLD: Score = Score::centipawns(10_000);
+
 impl PieceColor {
-    pub fn is_this_resignable(&self, score: f32) -> bool {
+    pub fn is_this_resignable(&self, score: Score) -> bool {
+        match self {
+            PieceColor::White => score < -RESIGNATION_THRESHOLD,
+            PieceColor::Black => score > RESIGNATION_THRESHOLD,
+        }
+    }
+
+    /// Tells if a draw is better for this side than playing on with the expected score
+    pub fn is_draw_preferable(&self, score: Score) -> bool {
         match self {
-            PieceColor::White => score < -100.0,
-            PieceColor::Black => score > 100.0,
+            PieceColor::White => score < Score::DRAW,
+            PieceColor::Black => score > Score::DRAW,
         }
     }
 }
 
+/// Plays the space separated uci moves from the starting position of the game, e.g. to rebuild
+/// the game a server knows about
+pub async fn replay_uci_moves(start: &PSBoard, moves: &str) -> IntResult<GameState> {
+    let mut state = GameState::new(start.clone());
+    for a_move in moves.split_ascii_whitespace() {
+        state.make_an_uci_move(a_move).await?;
+    }
+    Ok(state)
+}
+
 pub async fn calculate_move_for_console(
     engine: &Engine,
     gamestate: &mut GameState,
-    deadline: &Duration,
-) -> (Duration, (Option<PossibleMove>, f32)) {
-    println!("Set a deadline of: {deadline:?}");
+    time: impl Into<TimeAllocation>,
+) -> (Duration, (Option<PossibleMove>, Score)) {
+    let time = time.into();
+    engine.report(Verbosity::Full, || {
+        format!(
+            "Set a deadline of: {:?}, at most {:?}",
+            time.target, time.maximum
+        )
+    });
     let ins = Instant::now();
-    let machine_eval = engine.best_move_for(gamestate, deadline).await;
+    let machine_eval = engine.best_move_within(gamestate, time).await;
     let machine_move = machine_eval.0.as_ref().unwrap();
     let taken_this_much_time = ins.elapsed();
     let taken_this_much_time_ms = taken_this_much_time.as_millis();
     // visualise_explored_moves(gamestate.get_board());
-    println!("Move took {} ms", taken_this_much_time_ms);
-    println!("Went to depth {}", machine_eval.3);
-    println!(
-        "{} kNodes/sec",
-        (machine_eval.2 as u128) / 1.max(taken_this_much_time_ms)
-    );
-    println!(
-        "Evaluation result: {machine_move}, score: {}",
-        machine_eval.1
-    );
+    engine.report(Verbosity::Full, || {
+        format!(
+            "Move took {taken_this_much_time_ms} ms\nWent to depth {}\n{} kNodes/sec",
+            machine_eval.3,
+            (machine_eval.2 as u128) / 1.max(taken_this_much_time_ms)
+        )
+    });
+    engine.report(Verbosity::Scores, || {
+        format!(
+            "Evaluation result: {machine_move}, score: {}, confidence: {}",
+            machine_eval.1,
+            engine.best_move_stability()
+        )
+    });
     (taken_this_much_time, (machine_eval.0, machine_eval.1))
 }
@@ -20,139 +20,260 @@
  *
  *  (C) Copyright 2022-3, Gabor Kecskemeti
  */
+use crate::baserules::board::PSBoard;
 use crate::baserules::board_rep::{BaseMove, PossibleMove};
 use crate::baserules::castling::Castling;
+use crate::baserules::move_list::MoveList;
 use crate::baserules::piece_kind::PieceKind;
-use crate::baserules::piece_kind::PieceKind::King;
+use crate::baserules::piece_kind::PieceKind::{King, Pawn};
 use crate::baserules::positions::AbsoluteBoardPos;
 use crate::engine::continuation::BoardContinuation;
-use crate::util::TryWithPanic;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
 
 pub type BoardParseResult = Result<BoardContinuation, (String, BoardContinuation)>;
 
-fn accept_promotion(promote_kind: &Option<PieceKind>, candidate_move: &PossibleMove) -> bool {
-    promote_kind.map_or_else(
-        || candidate_move.pawn_promotion.is_none(),
-        |knd| {
-            candidate_move
-                .pawn_promotion
-                .map_or(false, |ppm| knd == ppm)
-        },
-    )
+/// What is wrong with a move in short algebraic notation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SanError {
+    /// The notation is neither a castling nor a piece moving to a square, with the optional
+    /// file or rank of the piece, capture, promotion and check or mate sign
+    BadNotation(String),
+    /// None of the legal moves of the position is written this way
+    NoSuchMove(String),
+    /// More than one legal move is written this way, the file or the rank of the piece is missing
+    Ambiguous(String),
 }
 
-/// Reads short algebraic notation and translates it to our internal structures
-/// <https://en.wikipedia.org/wiki/Algebraic_notation_(chess)>
-pub async fn make_a_human_move(board: BoardContinuation, the_move: &str) -> BoardParseResult {
-    let mut rev_move: String = the_move.chars().rev().collect();
-    let first_char = rev_move.pop().unwrap();
-    let mut col = Vec::new();
-    let mut piece_kind = PieceKind::Pawn;
-    let mut castling = false;
-    match first_char {
-        'a' | 'b' | 'c' | 'd' | 'e' | 'f' | 'g' | 'h' => {
-            col.push(AbsoluteBoardPos::parse_column(first_char.try_into().unwrap()).unwrap())
-        }
-        'K' | 'Q' | 'N' | 'R' | 'B' => piece_kind = first_char.transform(),
-        'O' => castling = true,
-        _ => {
-            return Err((
-                format!("Unexpected chess notation: {first_char} in {the_move}"),
-                board,
-            ));
+impl Display for SanError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SanError::BadNotation(san) => write!(f, "unexpected chess notation: {san}"),
+            SanError::NoSuchMove(san) => write!(f, "there is no legal move {san}"),
+            SanError::Ambiguous(san) => write!(f, "more than one piece can make the move {san}"),
         }
     }
-    let mut all_moves = Vec::new();
-    board.gen_potential_moves(&mut all_moves);
-    let found_move = if castling {
-        let castling_done = match Castling::from_notation(the_move, board.who_moves) {
-            Ok(castle_kind) => castle_kind,
-            Err(issue) => {
-                return Err((
-                    format!("Unexpected castling notation: {the_move}, reason: {issue:?}"),
-                    board,
-                ))
+}
+
+impl Error for SanError {}
+
+/// A move in short algebraic notation apart from castling: the piece, what is known of its
+/// square, the square it moves to and the piece a pawn is promoted to
+struct SanParts {
+    kind: PieceKind,
+    from_col: Option<u8>,
+    from_row: Option<u8>,
+    capture: bool,
+    to: AbsoluteBoardPos,
+    promotion: Option<PieceKind>,
+}
+
+fn file_of(file: char) -> Option<u8> {
+    ('a'..='h').contains(&file).then(|| file as u8 - b'a')
+}
+
+fn rank_of(rank: char) -> Option<u8> {
+    ('1'..='8').contains(&rank).then(|| rank as u8 - b'1')
+}
+
+impl SanParts {
+    /// Splits the notation without its check or mate sign, None when it is malformed
+    fn parse(notation: &str) -> Option<SanParts> {
+        let mut chars: Vec<char> = notation.chars().collect();
+        let kind = match chars.first() {
+            Some(&piece @ ('K' | 'Q' | 'R' | 'B' | 'N')) => {
+                chars.remove(0);
+                piece.try_into().ok()?
             }
+            _ => Pawn,
         };
-        let castling_move: &PossibleMove = castling_done.into();
-        if all_moves.contains(castling_move) {
-            Some(*castling_move)
-        } else {
-            None
-        }
-    } else {
-        let mut row = Vec::new();
-        let mut promotion = false;
-        let mut promote_kind = None;
-        loop {
-            let first_char = rev_move.pop();
-            if let Some(consecutive) = first_char {
-                match consecutive {
-                    'a' | 'b' | 'c' | 'd' | 'e' | 'f' | 'g' | 'h' => col.push(
-                        AbsoluteBoardPos::parse_column(consecutive.try_into().unwrap()).unwrap(),
-                    ),
-                    'x' | '+' | '#' => {
-                        // Takes, check, and mate are not relevant here, we already handle them with move-gen below
-                    }
-                    '1' | '2' | '3' | '4' | '5' | '6' | '7' | '8' => row.push(
-                        AbsoluteBoardPos::parse_row(consecutive.try_into().unwrap()).unwrap(),
-                    ),
-                    '=' => promotion = true,
-                    'Q' | 'R' | 'B' | 'K' => {
-                        if promotion {
-                            promote_kind = Some(consecutive.transform());
-                        }
-                    }
-                    _ => {
-                        return Err((
-                            format!(
-                                "Unexpected chess notation in char: {consecutive} in {the_move}"
-                            ),
-                            board,
-                        ));
-                    }
+        let promotion = match chars.last() {
+            Some(&piece @ ('Q' | 'R' | 'B' | 'N')) if kind == Pawn => {
+                chars.pop();
+                if chars.last() == Some(&'=') {
+                    chars.pop();
                 }
+                Some(piece.try_into().ok()?)
+            }
+            _ => None,
+        };
+        let to_rank = rank_of(chars.pop()?)?;
+        let to_file = file_of(chars.pop()?)?;
+        let capture = chars.last() == Some(&'x');
+        if capture {
+            chars.pop();
+        }
+        let (mut from_col, mut from_row) = (None, None);
+        for disambiguation in chars {
+            if let (Some(file), None) = (file_of(disambiguation), from_col) {
+                from_col = Some(file);
+            } else if let (Some(rank), None) = (rank_of(disambiguation), from_row) {
+                from_row = Some(rank);
             } else {
-                break;
+                return None;
             }
         }
-        let target = (row.pop().unwrap(), col.pop().unwrap()).transform();
-        let filter: Box<dyn Fn(&Option<PieceKind>, &PossibleMove) -> bool> = if row.is_empty() {
-            if col.is_empty() {
-                // regular move, no clarification needed for the moving piece
-                Box::new(accept_promotion)
+        Some(SanParts {
+            kind,
+            from_col,
+            from_row,
+            capture,
+            to: AbsoluteBoardPos(to_rank, to_file),
+            promotion,
+        })
+    }
+
+    fn matches(&self, board: &PSBoard, a_move: &PossibleMove) -> bool {
+        let BaseMove { from, to } = a_move.the_move;
+        let takes = board[to].is_some() || (self.kind == Pawn && board.ep == Some(to));
+        a_move.rook.is_none()
+            && to == self.to
+            && board[from].is_some_and(|piece| piece.kind == self.kind)
+            && self.from_col.is_none_or(|col| col == from.1)
+            && self.from_row.is_none_or(|row| row == from.0)
+            && (takes || !self.capture)
+            && a_move.pawn_promotion == self.promotion
+    }
+}
+
+impl PossibleMove {
+    /// Reads a move in short algebraic notation, the only legal move of the position written this
+    /// way. The capture is checked when it is marked, the check and mate signs and the
+    /// annotations after them are ignored. Castling may be written with zeros too.
+    /// <https://en.wikipedia.org/wiki/Algebraic_notation_(chess)>
+    ///
+    /// # Example
+    /// ```
+    /// use dbce::baserules::board::PSBoard;
+    /// use dbce::baserules::board_rep::PossibleMove;
+    /// use dbce::human_facing::moves::SanError;
+    /// let board = PSBoard::try_from_fen("4k3/P7/8/8/8/8/8/R3K2R w KQ - 0 1").unwrap();
+    /// assert_eq!("a7a8q", PossibleMove::from_san("a8=Q+", &board).unwrap().to_string());
+    /// assert_eq!("e1c1", PossibleMove::from_san("O-O-O", &board).unwrap().to_string());
+    /// assert_eq!("a1d1", PossibleMove::from_san("Rd1", &board).unwrap().to_string());
+    /// assert_eq!(
+    ///     Err(SanError::NoSuchMove("Ra8".into())),
+    ///     PossibleMove::from_san("Ra8", &board)
+    /// );
+    /// ```
+    pub fn from_san(san: &str, board: &PSBoard) -> Result<PossibleMove, SanError> {
+        let notation = san.trim().trim_end_matches(['+', '#', '!', '?']);
+        let mut legal_moves = MoveList::new();
+        board.gen_legal_moves(&mut legal_moves);
+        let candidates: Vec<PossibleMove> = if matches!(notation, "O-O" | "O-O-O" | "0-0" | "0-0-0")
+        {
+            let castling = Castling::from_notation(notation, board.who_moves)
+                .map_err(|_| SanError::BadNotation(san.into()))?;
+            let castling_move = board.castling_move(castling);
+            legal_moves
+                .into_iter()
+                .filter(|a_move| *a_move == castling_move)
+                .collect()
+        } else {
+            let parts =
+                SanParts::parse(notation).ok_or_else(|| SanError::BadNotation(san.into()))?;
+            legal_moves
+                .into_iter()
+                .filter(|a_move| parts.matches(board, a_move))
+                .collect()
+        };
+        match candidates.as_slice() {
+            [a_move] => Ok(*a_move),
+            [] => Err(SanError::NoSuchMove(san.into())),
+            _ => Err(SanError::Ambiguous(san.into())),
+        }
+    }
+
+    /// Writes the legal move of the position in short algebraic notation, with the file or the
+    /// rank of the piece only when another piece of the same kind could move to the same square,
+    /// and with the check and mate signs.
+    /// <https://en.wikipedia.org/wiki/Algebraic_notation_(chess)>
+    /// # Panics
+    /// When there is no piece to move on the board
+    ///
+    /// # Example
+    /// ```
+    /// use dbce::baserules::board::PSBoard;
+    /// use dbce::baserules::board_rep::PossibleMove;
+    /// let board = PSBoard::try_from_fen("6k1/5ppp/8/8/8/1N6/8/1N1RK3 w - - 0 1").unwrap();
+    /// let san = |uci| PossibleMove::simple_from_uci(uci).unwrap().to_san(&board);
+    /// assert_eq!("N1d2", san("b1d2"));
+    /// assert_eq!("Nc3", san("b1c3"));
+    /// assert_eq!("Rd8#", san("d1d8"));
+    /// ```
+    pub fn to_san(&self, board: &PSBoard) -> String {
+        let BaseMove { from, to } = self.the_move;
+        let kind = board[from].expect("There is no piece to move").kind;
+        let mut san = if self.rook.is_some() {
+            // Castling puts the king on the g file on the king side, whichever file it came from
+            if to.1 == 6 {
+                "O-O".to_string()
             } else {
-                // Clarification enough to contain extra column detail
-                let source_col = col.pop().unwrap();
-                Box::new(move |promote_kind, candidate_move| {
-                    candidate_move.the_move.from.1 == source_col
-                        && accept_promotion(promote_kind, candidate_move)
-                })
+                "O-O-O".to_string()
             }
-        } else if col.is_empty() {
-            // Clarification enough to contain extra row detail, can't apply to pawns, no promotion handling needed
-            let source_row = row.pop().unwrap();
-            Box::new(move |_, candidate_move| candidate_move.the_move.from.0 == source_row)
         } else {
-            // Move needs all coordinates as it would be ambiguous otherwise, can't apply to pawns, no promotion handling needed
-            let source = (row.pop().unwrap(), col.pop().unwrap()).transform();
-            Box::new(move |_, candidate_move| candidate_move.the_move.from == source)
+            let takes = board[to].is_some() || (kind == Pawn && board.ep == Some(to));
+            let mut san = String::new();
+            if kind == Pawn {
+                if takes {
+                    san.push(char::from(b'a' + from.1));
+                }
+            } else {
+                san.push(kind.to_char().to_ascii_uppercase());
+                let mut legal_moves = MoveList::new();
+                board.gen_legal_moves(&mut legal_moves);
+                let rivals: Vec<AbsoluteBoardPos> = legal_moves
+                    .into_iter()
+                    .filter(|a_move| a_move.rook.is_none() && a_move.the_move.to == to)
+                    .map(|a_move| a_move.the_move.from)
+                    .filter(|rival| {
+                        *rival != from && board[*rival].is_some_and(|piece| piece.kind == kind)
+                    })
+                    .collect();
+                if !rivals.is_empty() {
+                    let same_file = rivals.iter().any(|rival| rival.1 == from.1);
+                    let same_rank = rivals.iter().any(|rival| rival.0 == from.0);
+                    if same_file {
+                        if same_rank {
+                            san.push_str(&from.to_string());
+                        } else {
+                            san.push(char::from(b'1' + from.0));
+                        }
+                    } else {
+                        san.push(char::from(b'a' + from.1));
+                    }
+                }
+            }
+            if takes {
+                san.push('x');
+            }
+            san.push_str(&to.to_string());
+            if let Some(promotion) = self.pawn_promotion {
+                san.push('=');
+                san.push(promotion.to_char().to_ascii_uppercase());
+            }
+            san
         };
+        let mut after = board.clone();
+        after.make_move_in_place(self);
+        if after.is_in_check() {
+            san.push(if after.has_legal_move() { '+' } else { '#' });
+        }
+        san
+    }
+}
 
-        all_moves.into_iter().find(|candidate_move| {
-            let moving_piece = board[candidate_move.the_move.from].as_ref().unwrap();
-            moving_piece.kind == piece_kind
-                && candidate_move.the_move.to == target
-                && filter(&promote_kind, candidate_move)
-        })
-    };
-    if let Some(move_to_take) = found_move {
-        Ok(board.make_cached_move(&move_to_take).await)
-    } else {
-        Err((
-            format!("Impossible move, but apparently good notation: {the_move}"),
-            board,
-        ))
+/// Reads short algebraic notation and translates it to our internal structures, see
+/// `PossibleMove::from_san`
+pub fn find_human_move(board: &PSBoard, the_move: &str) -> Result<PossibleMove, String> {
+    PossibleMove::from_san(the_move, board).map_err(|error| error.to_string())
+}
+
+/// Makes a move given in short algebraic notation, see `find_human_move`
+pub async fn make_a_human_move(board: BoardContinuation, the_move: &str) -> BoardParseResult {
+    match find_human_move(&board, the_move) {
+        Ok(move_to_take) => Ok(board.make_cached_move(&move_to_take).await),
+        Err(error) => Err((error, board)),
     }
 }
 
@@ -203,11 +324,7 @@ pub async fn make_an_uci_move(board: BoardContinuation, the_move: &str) -> Board
         let moving_piece = board[the_move.from].as_ref().unwrap();
         let simple_move = the_move.into();
         if moving_piece.kind == King {
-            board
-                .castling
-                .iter()
-                .find_map(|c| c.move_via_king_move(the_move))
-                .unwrap_or(simple_move)
+            board.castling_of_king_move(the_move).unwrap_or(simple_move)
         } else {
             simple_move
         }
@@ -218,10 +335,137 @@ pub async fn make_an_uci_move(board: BoardContinuation, the_move: &str) -> Board
 #[cfg(test)]
 mod test {
     use crate::baserules::board::PSBoard;
+    use crate::baserules::board_rep::PossibleMove;
+    use crate::baserules::move_list::MoveList;
     use crate::engine::continuation::BoardContinuation;
-    use crate::human_facing::moves::{make_a_human_move, make_an_uci_move};
+    use crate::human_facing::moves::{make_a_human_move, make_an_uci_move, SanError};
     use tokio::test;
 
+    #[test]
+    async fn san_moves_are_read() {
+        let board =
+            PSBoard::try_from_fen("r3k2r/1P4P1/8/3pP3/8/1N3N2/8/R3K2R w KQkq d6 0 1").unwrap();
+        for (san, uci) in [
+            ("exd6", "e5d6"),
+            ("e6", "e5e6"),
+            ("bxa8=N", "b7a8n"),
+            ("b8=Q+", "b7b8q"),
+            ("g8R", "g7g8r"),
+            ("gxh8=Q#", "g7h8q"),
+            ("Nbd2", "b3d2"),
+            ("Nbd4", "b3d4"),
+            ("Nfd4", "f3d4"),
+            ("Rxa8+", "a1a8"),
+            ("Kf2", "e1f2"),
+            ("O-O", "e1g1"),
+            ("0-0-0!?", "e1c1"),
+        ] {
+            assert_eq!(
+                uci,
+                PossibleMove::from_san(san, &board).unwrap().to_string(),
+                "{san}"
+            );
+        }
+        for (san, error) in [
+            ("Nd2", SanError::Ambiguous("Nd2".into())),
+            ("Nd4", SanError::Ambiguous("Nd4".into())),
+            ("b8", SanError::NoSuchMove("b8".into())),
+            ("Nfxd4", SanError::NoSuchMove("Nfxd4".into())),
+            ("Kg1", SanError::NoSuchMove("Kg1".into())),
+            ("Qd1", SanError::NoSuchMove("Qd1".into())),
+            ("Nb3d4e", SanError::BadNotation("Nb3d4e".into())),
+            ("Zf3", SanError::BadNotation("Zf3".into())),
+            ("", SanError::BadNotation("".into())),
+        ] {
+            assert_eq!(Err(error), PossibleMove::from_san(san, &board), "{san}");
+        }
+    }
+
+    #[test]
+    async fn san_moves_are_written() {
+        let board =
+            PSBoard::try_from_fen("r3k2r/1P4P1/8/3pP3/8/1N3N1N/5Q1Q/R3K2R w KQkq d6 0 1").unwrap();
+        for (uci, san) in [
+            ("e5d6", "exd6"),
+            ("e5e6", "e6"),
+            ("b7a8n", "bxa8=N"),
+            ("b7b8q", "b8=Q+"),
+            ("g7h8q", "gxh8=Q+"),
+            ("b3d2", "Nbd2"),
+            ("f3g1", "Nfg1"),
+            ("h3g1", "Nhg1"),
+            ("h3g5", "Nhg5"),
+            ("f3g5", "Nfg5"),
+            ("f2g3", "Qfg3"),
+            ("h2g3", "Qhg3"),
+            ("f2h4", "Qh4"),
+            ("f3h4", "Nh4"),
+            ("f2g2", "Qfg2"),
+            ("a1a8", "Rxa8+"),
+            ("e1g1", "O-O"),
+            ("e1c1", "O-O-O"),
+        ] {
+            let a_move = board.find_uci_move(uci).unwrap();
+            assert_eq!(san, a_move.to_san(&board), "{uci}");
+        }
+        // One more queen on the file and one on the rank need the whole square
+        let board = PSBoard::try_from_fen("8/k7/8/8/4Q2Q/8/K7/7Q w - - 0 1").unwrap();
+        for (uci, san) in [("h4e1", "Qh4e1"), ("h1e1", "Q1e1"), ("e4e1", "Qee1")] {
+            let a_move = PossibleMove::simple_from_uci(uci).unwrap();
+            assert_eq!(san, a_move.to_san(&board), "{uci}");
+        }
+    }
+
+    #[test]
+    async fn written_moves_are_read_back() {
+        for fen in [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "r3k2r/1P4P1/8/3pP3/8/1N3N1N/5Q1Q/R3K2R w KQkq d6 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+            "2k5/8/8/8/1Q1Q1Q2/8/1Q1Q1Q2/K7 w - - 0 1",
+        ] {
+            let board = PSBoard::try_from_fen(fen).unwrap();
+            let mut moves = MoveList::new();
+            board.gen_legal_moves(&mut moves);
+            for a_move in moves {
+                let san = a_move.to_san(&board);
+                assert_eq!(
+                    Ok(a_move),
+                    PossibleMove::from_san(&san, &board),
+                    "{fen} {san}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    async fn only_legal_moves_are_read() {
+        // The knight on e2 is pinned by the rook on e8
+        let board = PSBoard::try_from_fen("4r1k1/8/8/8/8/8/4N3/2N1K3 w - - 0 1").unwrap();
+        assert_eq!(
+            "c1d3",
+            PossibleMove::from_san("Nd3", &board).unwrap().to_string()
+        );
+        let board = PSBoard::try_from_fen("4k3/8/8/R7/8/8/8/R3K3 w - - 0 1").unwrap();
+        assert_eq!(
+            "a1a3",
+            PossibleMove::from_san("R1a3", &board).unwrap().to_string()
+        );
+        let board = PSBoard::try_from_fen("r3k2r/8/8/8/8/8/8/R3K2R b kq - 0 1").unwrap();
+        assert_eq!(
+            "e8g8",
+            PossibleMove::from_san("O-O", &board).unwrap().to_string()
+        );
+        assert_eq!(
+            Err(SanError::NoSuchMove("O-O".into())),
+            PossibleMove::from_san(
+                "O-O",
+                &PSBoard::try_from_fen("4k3/8/8/8/8/8/8/R3K2R w Q - 0 1").unwrap()
+            )
+        );
+    }
+
     #[test]
     async fn test_rook_takes() {
         let board = PSBoard::from_fen("1Rb1r1k1/p4ppp/3p4/P7/2RPPP2/2K5/7r/8 w - - 0 30")
@@ -0,0 +1,320 @@
+/*
+ *  ========================================================================
+ *  DBCE chess bot, memory of the opponents met
+ *  ========================================================================
+ *
+ *  This file is part of DBCE.
+ *
+ *  DBCE is free software: you can redistribute it and/or
+ *  modify it under the terms of the GNU General Public License as published
+ *  by the Free Software Foundation, either version 3 of the License, or (at
+ *  your option) any later version.
+ *
+ *  DBCE is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ *  General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License along
+ *  with DBCE.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ *  (C) Copyright 2022-3, Gabor Kecskemeti
+ */
+use crate::baserules::piece_color::PieceColor;
+use crate::baserules::score::Score;
+use crate::human_facing::game::GameResult;
+use crate::util::IntResult;
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Where the bot keeps what it learns between runs, relative to its working directory
+pub const DEFAULT_DATA_DIR: &str = "dbce_data";
+
+/// The file of the opponent memory inside the data directory
+const OPPONENTS_FILE: &str = "opponents.json";
+
+/// The version of the opponent memory's format, saved with the opponents
+const OPPONENTS_VERSION: u64 = 1;
+
+/// The number of plies of each game remembered as the opening the opponent played
+pub const OPENING_PLIES: usize = 8;
+
+/// An opponent's move improving our evaluation at least this much is counted as a blunder
+pub const BLUNDER_SWING: Score = Score::centipawns(300);
+
+/// The randomness of the root move choice against opponents who could have prepared for us
+pub const ANTI_PREPARATION_RANDOMNESS: Score = Score::centipawns(20);
+
+/// An opponent blundering in more than one of this many moves is not given any extra variety
+const BLUNDER_PRONE_MOVES: u32 = 20;
+
+/// What the bot remembers of its games against an opponent, the results are from the bot's side
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct OpponentRecord {
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+    /// The moves the opponent made in the recorded games
+    pub moves: u32,
+    /// The opponent's moves improving our evaluation by at least `BLUNDER_SWING`
+    pub blunders: u32,
+    /// The first plies of the games in uci notation, with the number of games they were played in
+    pub openings: HashMap<String, u32>,
+}
+
+/// The end of a game against an opponent, as the bot saw it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FinishedGame<'a> {
+    pub our_color: PieceColor,
+    pub result: GameResult,
+    /// All moves of the game in uci notation, separated by spaces
+    pub uci_moves: &'a str,
+    pub opponent_blunders: u32,
+}
+
+impl OpponentRecord {
+    pub fn games(&self) -> u32 {
+        self.wins + self.draws + self.losses
+    }
+
+    /// Tells if the opponent played the same opening in more than half of at least two games
+    pub fn repeats_openings(&self) -> bool {
+        let games = self.games();
+        games >= 2 && self.openings.values().any(|&count| 2 * count > games)
+    }
+
+    pub fn is_blunder_prone(&self) -> bool {
+        self.blunders * BLUNDER_PRONE_MOVES > self.moves
+    }
+
+    /// The randomness of the root move choice to use against the opponent. Those who have beaten
+    /// us or keep playing the same opening could have prepared a line against us, so we vary our
+    /// play more, unless they blunder often enough that we better stick to the best moves.
+    pub fn variety(&self, base: Score) -> Score {
+        if !self.is_blunder_prone() && (self.losses > 0 || self.repeats_openings()) {
+            base.max(ANTI_PREPARATION_RANDOMNESS)
+        } else {
+            base
+        }
+    }
+
+    fn record(&mut self, game: &FinishedGame) {
+        match (game.result, game.our_color) {
+            (GameResult::Unfinished, _) => return,
+            (GameResult::Draw, _) => self.draws += 1,
+            (GameResult::WhiteWon, PieceColor::White)
+            | (GameResult::BlackWon, PieceColor::Black) => self.wins += 1,
+            _ => self.losses += 1,
+        }
+        let plies = game.uci_moves.split_ascii_whitespace().count() as u32;
+        self.moves += match game.our_color {
+            PieceColor::White => plies / 2,
+            PieceColor::Black => plies.div_ceil(2),
+        };
+        self.blunders += game.opponent_blunders;
+        let opening = game
+            .uci_moves
+            .split_ascii_whitespace()
+            .take(OPENING_PLIES)
+            .collect::<Vec<_>>()
+            .join(" ");
+        *self.openings.entry(opening).or_default() += 1;
+    }
+
+    fn to_json(&self) -> Value {
+        json!({
+            "wins": self.wins,
+            "draws": self.draws,
+            "losses": self.losses,
+            "moves": self.moves,
+            "blunders": self.blunders,
+            "openings": self.openings,
+        })
+    }
+
+    fn from_json(record: &Value) -> Self {
+        let counter = |name: &str| record[name].as_u64().unwrap_or(0) as u32;
+        Self {
+            wins: counter("wins"),
+            draws: counter("draws"),
+            losses: counter("losses"),
+            moves: counter("moves"),
+            blunders: counter("blunders"),
+            openings: record["openings"]
+                .as_object()
+                .map(|openings| {
+                    openings
+                        .iter()
+                        .map(|(line, games)| (line.clone(), games.as_u64().unwrap_or(0) as u32))
+                        .collect()
+                })
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// Tells if the opponent's move between our two evaluations was a blunder, the scores are white's
+pub fn is_blunder_swing(before: Score, after: Score, our_color: PieceColor) -> bool {
+    let multiplier = our_color.mate_multiplier();
+    multiplier * after - multiplier * before >= BLUNDER_SWING
+}
+
+/// The opponents met so far by their account names, kept in a json file of the data directory
+#[derive(Debug, Default)]
+pub struct OpponentMemory {
+    file: PathBuf,
+    opponents: HashMap<String, OpponentRecord>,
+}
+
+impl OpponentMemory {
+    /// Reads the memory from the data directory, it starts empty if nothing was saved there yet.
+    /// The memories saved by the earlier versions are migrated.
+    ///
+    /// # Errors
+    /// When the saved memory cannot be read or it was saved by a newer version
+    pub fn load(data_dir: &Path) -> IntResult<Self> {
+        let file = data_dir.join(OPPONENTS_FILE);
+        let opponents = if file.exists() {
+            let saved: Value = serde_json::from_str(&fs::read_to_string(&file)?)?;
+            // The memories saved before the versions only had the object of the opponents
+            let (version, opponents) = match saved["version"].as_u64() {
+                Some(version) => (version, &saved["opponents"]),
+                None => (0, &saved),
+            };
+            if version > OPPONENTS_VERSION {
+                return Err(format!(
+                    "Cannot read the opponent memory of format version {version}, only up to {OPPONENTS_VERSION}"
+                )
+                .into());
+            }
+            opponents
+                .as_object()
+                .ok_or("The opponent memory is not a json object")?
+                .iter()
+                .map(|(name, record)| (name.clone(), OpponentRecord::from_json(record)))
+                .collect()
+        } else {
+            HashMap::new()
+        };
+        Ok(Self { file, opponents })
+    }
+
+    /// Writes the memory to the data directory, creating the directory if needed
+    ///
+    /// # Errors
+    /// When the file cannot be written
+    pub fn save(&self) -> IntResult<()> {
+        if let Some(data_dir) = self.file.parent() {
+            fs::create_dir_all(data_dir)?;
+        }
+        let saved: Map<String, Value> = self
+            .opponents
+            .iter()
+            .map(|(name, record)| (name.clone(), record.to_json()))
+            .collect();
+        let saved = json!({ "version": OPPONENTS_VERSION, "opponents": saved });
+        fs::write(&self.file, serde_json::to_string_pretty(&saved)?)?;
+        Ok(())
+    }
+
+    pub fn get(&self, opponent: &str) -> Option<&OpponentRecord> {
+        self.opponents.get(opponent)
+    }
+
+    /// Adds the game to the record of the opponent, unfinished games are not counted
+    pub fn record_game(&mut self, opponent: &str, game: &FinishedGame) {
+        if game.result != GameResult::Unfinished {
+            self.opponents
+                .entry(opponent.to_owned())
+                .or_default()
+                .record(game);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::baserules::piece_color::PieceColor::{Black, White};
+    use crate::baserules::score::Score;
+    use crate::human_facing::game::GameResult;
+    use crate::human_facing::opponents::{
+        is_blunder_swing, FinishedGame, OpponentMemory, ANTI_PREPARATION_RANDOMNESS, OPPONENTS_FILE,
+    };
+    use std::env;
+    use tokio::test;
+
+    const OPENING: &str = "e2e4 e7e5 g1f3 b8c6 f1b5 a7a6 b5a4 g8f6";
+
+    #[test]
+    async fn games_are_remembered_across_runs() {
+        let data_dir = env::temp_dir().join(format!("dbce_opponents_{}", std::process::id()));
+        let mut memory = OpponentMemory::load(&data_dir).unwrap();
+        assert!(memory.get("rival").is_none());
+        for result in [
+            GameResult::BlackWon,
+            GameResult::Draw,
+            GameResult::Unfinished,
+        ] {
+            memory.record_game(
+                "rival",
+                &FinishedGame {
+                    our_color: White,
+                    result,
+                    uci_moves: &format!("{OPENING} e1g1"),
+                    opponent_blunders: 0,
+                },
+            );
+        }
+        memory.save().unwrap();
+
+        let reloaded = OpponentMemory::load(&data_dir).unwrap();
+        let rival = reloaded.get("rival").unwrap();
+        assert_eq!(memory.get("rival"), Some(rival));
+        assert_eq!((0, 1, 1), (rival.wins, rival.draws, rival.losses));
+        assert_eq!(8, rival.moves);
+        assert_eq!(Some(&2), rival.openings.get(OPENING));
+        assert!(rival.repeats_openings());
+        assert_eq!(ANTI_PREPARATION_RANDOMNESS, rival.variety(Score::DRAW));
+
+        // The memories saved before the versions are migrated
+        let file = data_dir.join(OPPONENTS_FILE);
+        std::fs::write(&file, r#"{"version": {"wins": 2}, "rival": {"losses": 1}}"#).unwrap();
+        let legacy = OpponentMemory::load(&data_dir).unwrap();
+        assert_eq!(2, legacy.get("version").unwrap().wins);
+        assert_eq!(1, legacy.get("rival").unwrap().losses);
+        std::fs::write(&file, r#"{"version": 2, "opponents": {}}"#).unwrap();
+        assert!(OpponentMemory::load(&data_dir).is_err());
+        std::fs::remove_dir_all(data_dir).unwrap();
+    }
+
+    #[test]
+    async fn blunder_prone_opponents_get_no_variety() {
+        let mut memory = OpponentMemory::default();
+        memory.record_game(
+            "patzer",
+            &FinishedGame {
+                our_color: Black,
+                result: GameResult::WhiteWon,
+                uci_moves: OPENING,
+                opponent_blunders: 1,
+            },
+        );
+        let patzer = memory.get("patzer").unwrap();
+        assert_eq!(1, patzer.losses);
+        assert!(patzer.is_blunder_prone());
+        assert_eq!(Score::centipawns(5), patzer.variety(Score::centipawns(5)));
+
+        assert!(is_blunder_swing(
+            Score::centipawns(100),
+            Score::centipawns(-250),
+            Black
+        ));
+        assert!(!is_blunder_swing(
+            Score::centipawns(100),
+            Score::centipawns(-150),
+            Black
+        ));
+    }
+}
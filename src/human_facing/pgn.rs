@@ -0,0 +1,784 @@
+/*
+ *  ========================================================================
+ *  DBCE chess bot, reader and writer of games in portable game notation
+ *  ========================================================================
+ *
+ *  This file is part of DBCE.
+ *
+ *  DBCE is free software: you can redistribute it and/or
+ *  modify it under the terms of the GNU General Public License as published
+ *  by the Free Software Foundation, either version 3 of the License, or (at
+ *  your option) any later version.
+ *
+ *  DBCE is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ *  General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License along
+ *  with DBCE.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ *  (C) Copyright 2022-3, Gabor Kecskemeti
+ */
+use crate::baserules::board::PSBoard;
+use crate::baserules::score::Score;
+use crate::human_facing::game::{replay_notation, GameRecord, GameResult};
+use crate::util::IntResult;
+use std::fmt::Write;
+use std::io;
+use std::io::BufRead;
+use std::time::Duration;
+
+/// The glyphs of the move suffixes, from $1 to $6
+const SUFFIX_GLYPHS: [&str; 6] = ["!", "?", "!!", "??", "!?", "?!"];
+
+/// The tags starting every pgn in this order, with their values when they are not known
+const SEVEN_TAG_ROSTER: [(&str, &str); 7] = [
+    ("Event", "?"),
+    ("Site", "?"),
+    ("Date", "????.??.??"),
+    ("Round", "?"),
+    ("White", "?"),
+    ("Black", "?"),
+    ("Result", "*"),
+];
+
+/// The movetext is wrapped to lines of at most this many characters
+const PGN_LINE_LENGTH: usize = 80;
+
+/// What is written into the pgn of a game besides its moves, clocks and result
+#[derive(Debug, Clone, Default)]
+pub struct PgnExport {
+    /// The values of the seven tag roster and the tags written after it. The result tag is
+    /// always the result of the game.
+    pub tags: Vec<(String, String)>,
+    /// The evaluations after the moves, from the point of view of white, written as %eval
+    /// comments like lichess does
+    pub evals: Vec<Score>,
+}
+
+/// What the pgn tells about a move apart from the move itself
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MoveAnnotation {
+    /// The numeric annotation glyphs, the move suffixes like "?!" included
+    pub nags: Vec<u8>,
+    /// The comments after the move, without their braces
+    pub comments: Vec<String>,
+}
+
+/// A game of a pgn file with its tags, comments and annotation glyphs. The variations are
+/// skipped.
+/// See also: <https://www.saremba.de/chessgml/standards/pgn/pgn-complete.htm>
+pub struct PgnGame {
+    /// The tag pairs in the order of the file
+    pub tags: Vec<(String, String)>,
+    pub game: GameRecord,
+    /// The comments before the first move
+    pub opening_comments: Vec<String>,
+    /// The annotations of each move of the game
+    pub annotations: Vec<MoveAnnotation>,
+}
+
+/// A token of the movetext, the move numbers and the variations are left out
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum MovetextToken {
+    Move(String),
+    Nag(u8),
+    Comment(String),
+    Result(GameResult),
+}
+
+/// The tokens of a word of the movetext, a move can have its number before it and its suffix
+/// glyph after it
+fn word_tokens(word: &str) -> Vec<MovetextToken> {
+    if let Some(result) = GameResult::from_notation(word) {
+        return vec![MovetextToken::Result(result)];
+    }
+    if let Some(glyph) = word.strip_prefix('$') {
+        return glyph.parse().map(MovetextToken::Nag).into_iter().collect();
+    }
+    let notation = match word.rfind('.') {
+        Some(dot) if word[..dot].chars().all(|c| c.is_ascii_digit() || c == '.') => {
+            &word[dot + 1..]
+        }
+        _ => word,
+    };
+    let suffix_start = notation.trim_end_matches(['!', '?']).len();
+    let (notation, suffix) = notation.split_at(suffix_start);
+    let mut tokens = Vec::new();
+    if !notation.is_empty() {
+        // Castling is sometimes written with zeros
+        let notation = if notation.starts_with("0-0") {
+            notation.replace('0', "O")
+        } else {
+            notation.to_string()
+        };
+        tokens.push(MovetextToken::Move(notation));
+    }
+    if let Some(glyph) = SUFFIX_GLYPHS.iter().position(|glyph| *glyph == suffix) {
+        tokens.push(MovetextToken::Nag(glyph as u8 + 1));
+    }
+    tokens
+}
+
+fn movetext_tokens(movetext: &str) -> Vec<MovetextToken> {
+    let mut tokens = Vec::new();
+    let mut variation_depth = 0usize;
+    let mut chars = movetext.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' => {
+                let comment: String = chars.by_ref().take_while(|c| *c != '}').collect();
+                if variation_depth == 0 {
+                    tokens.push(MovetextToken::Comment(comment.trim().to_string()));
+                }
+            }
+            ';' => {
+                let comment: String = chars.by_ref().take_while(|c| *c != '\n').collect();
+                if variation_depth == 0 {
+                    tokens.push(MovetextToken::Comment(comment.trim().to_string()));
+                }
+            }
+            '(' => variation_depth += 1,
+            ')' => variation_depth = variation_depth.saturating_sub(1),
+            _ if c.is_whitespace() => {}
+            _ => {
+                let mut word = String::from(c);
+                while let Some(next) = chars.next_if(|c| !c.is_whitespace() && !"{;()".contains(*c))
+                {
+                    word.push(next);
+                }
+                if variation_depth == 0 {
+                    tokens.extend(word_tokens(&word));
+                }
+            }
+        }
+    }
+    tokens
+}
+
+/// The moves of the pgn movetext without the move numbers, comments, variations, annotations and
+/// the result
+pub(crate) fn pgn_move_tokens(movetext: &str) -> Vec<String> {
+    movetext_tokens(movetext)
+        .into_iter()
+        .filter_map(|token| match token {
+            MovetextToken::Move(notation) => Some(notation),
+            _ => None,
+        })
+        .collect()
+}
+
+/// The name and the value of a tag pair line, the value may have escaped quotes and backslashes
+fn parse_tag(line: &str) -> Option<(String, String)> {
+    let tag = line.strip_prefix('[')?.strip_suffix(']')?;
+    let (name, value) = tag.split_once(char::is_whitespace)?;
+    let value = value.trim().strip_prefix('"')?.strip_suffix('"')?;
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        unescaped.push(if c == '\\' { chars.next()? } else { c });
+    }
+    Some((name.to_string(), unescaped))
+}
+
+/// The time left on the clock in a comment like lichess writes it: [%clk 0:02:59.5]
+fn clock_of(comment: &str) -> Option<Duration> {
+    let (_, clock) = comment.split_once("[%clk ")?;
+    let (clock, _) = clock.split_once(']')?;
+    let seconds = clock.trim().split(':').try_fold(0.0, |total, part| {
+        part.parse::<f64>().ok().map(|part| total * 60.0 + part)
+    })?;
+    Duration::try_from_secs_f64(seconds).ok()
+}
+
+/// Splits the text of a pgn file into the texts of its games
+pub fn split_pgn(pgn: &str) -> Vec<&str> {
+    let mut games = Vec::new();
+    let mut game_start = None;
+    let mut in_movetext = false;
+    let mut offset = 0;
+    for line in pgn.split_inclusive('\n') {
+        let trimmed = line.trim();
+        if trimmed.starts_with('[') {
+            if in_movetext || game_start.is_none() {
+                if let Some(start) = game_start {
+                    games.push(pgn[start..offset].trim());
+                }
+                game_start = Some(offset);
+                in_movetext = false;
+            }
+        } else if !trimmed.is_empty() {
+            game_start.get_or_insert(offset);
+            in_movetext = true;
+        }
+        offset += line.len();
+    }
+    if let Some(start) = game_start {
+        games.push(pgn[start..].trim());
+    }
+    games
+}
+
+/// The text of a game of a pgn file with its place in the file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PgnText {
+    /// The number of the game in the file, from 1
+    pub number: usize,
+    /// The line of the file the game starts on, from 1
+    pub line: usize,
+    pub text: String,
+}
+
+/// Reads the games of a pgn file one by one, only the text of the current game is kept in
+/// memory, so it suits the databases too large to be read at once. The games are split as by
+/// `split_pgn`, and the lines which are not valid utf-8 are read with replacement characters.
+/// An error of the input ends the reading.
+///
+/// # Example
+/// ```
+/// use dbce::human_facing::pgn::PgnReader;
+/// let pgn = "[Event \"First\"]\n\n1. e4 *\n\n[Event \"Second\"]\n\n1. d4 *\n";
+/// let games: Vec<_> = PgnReader::new(pgn.as_bytes()).map(Result::unwrap).collect();
+/// assert_eq!(2, games.len());
+/// assert_eq!(5, games[1].line);
+/// assert_eq!("[Event \"Second\"]\n\n1. d4 *", games[1].text);
+/// ```
+pub struct PgnReader<R> {
+    input: R,
+    /// The tag line starting the next game with its number, read while looking for the end of
+    /// the current game
+    next_start: Option<(usize, String)>,
+    lines_read: usize,
+    games_read: usize,
+    failed: bool,
+}
+
+impl<R: BufRead> PgnReader<R> {
+    pub fn new(input: R) -> Self {
+        PgnReader {
+            input,
+            next_start: None,
+            lines_read: 0,
+            games_read: 0,
+            failed: false,
+        }
+    }
+
+    fn read_line(&mut self) -> io::Result<Option<String>> {
+        let mut bytes = Vec::new();
+        if self.input.read_until(b'\n', &mut bytes)? == 0 {
+            return Ok(None);
+        }
+        self.lines_read += 1;
+        Ok(Some(String::from_utf8_lossy(&bytes).into_owned()))
+    }
+
+    fn read_game(&mut self) -> io::Result<Option<PgnText>> {
+        let mut game = self.next_start.take();
+        let mut in_movetext = false;
+        while let Some(line) = self.read_line()? {
+            let trimmed = line.trim();
+            if trimmed.starts_with('[') {
+                if in_movetext {
+                    self.next_start = Some((self.lines_read, line));
+                    break;
+                }
+            } else if !trimmed.is_empty() {
+                in_movetext = true;
+            } else if game.is_none() {
+                continue;
+            }
+            game.get_or_insert_with(|| (self.lines_read, String::new()))
+                .1
+                .push_str(&line);
+        }
+        Ok(game.map(|(line, text)| {
+            self.games_read += 1;
+            PgnText {
+                number: self.games_read,
+                line,
+                text: text.trim().to_string(),
+            }
+        }))
+    }
+
+    /// Reads and parses the next game. The games which can not be parsed are returned as errors
+    /// telling where they start, and the reading goes on with the game after them.
+    pub async fn next_game(&mut self) -> Option<IntResult<PgnGame>> {
+        let game = match self.next()? {
+            Ok(game) => game,
+            Err(error) => return Some(Err(error.into())),
+        };
+        Some(
+            PgnGame::parse(&game.text).await.map_err(|error| {
+                format!("Game {} at line {}: {error}", game.number, game.line).into()
+            }),
+        )
+    }
+}
+
+impl<R: BufRead> Iterator for PgnReader<R> {
+    type Item = io::Result<PgnText>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        self.read_game().transpose().inspect(|game| {
+            self.failed = game.is_err();
+        })
+    }
+}
+
+impl PgnGame {
+    /// Reads a single game of a pgn file. The clocks of the game are read from the comments of
+    /// its moves, as long as each move has one.
+    ///
+    /// # Errors
+    /// When the starting position or a move is not valid, the result is unknown, or the result
+    /// tag contradicts the result at the end of the movetext
+    pub async fn parse(pgn: &str) -> IntResult<PgnGame> {
+        let mut tags = Vec::new();
+        let mut movetext = String::new();
+        for line in pgn.lines().map(str::trim) {
+            if line.starts_with('%') {
+                // Escaped lines are not part of the game
+            } else if let Some(tag) = parse_tag(line) {
+                tags.push(tag);
+            } else {
+                movetext.push_str(line);
+                movetext.push('\n');
+            }
+        }
+        let tag = |name: &str| {
+            tags.iter()
+                .find(|(tag, _)| tag == name)
+                .map(|(_, value)| value.as_str())
+        };
+        let mut start = match tag("FEN") {
+            Some(fen) => PSBoard::try_from_fen(fen)?,
+            None => PSBoard::default(),
+        };
+        start.validate()?;
+        if tag("Variant").is_some_and(|variant| variant.eq_ignore_ascii_case("chess960")) {
+            start.set_chess960(true);
+        }
+        let tagged_result = tag("Result")
+            .map(|notation| {
+                GameResult::from_notation(notation)
+                    .ok_or_else(|| format!("Unknown result: {notation}"))
+            })
+            .transpose()?;
+        let mut notations = Vec::new();
+        let mut opening_comments = Vec::new();
+        let mut annotations: Vec<MoveAnnotation> = Vec::new();
+        let mut movetext_result = None;
+        for token in movetext_tokens(&movetext) {
+            match token {
+                MovetextToken::Move(notation) => {
+                    notations.push(notation);
+                    annotations.push(MoveAnnotation::default());
+                }
+                MovetextToken::Nag(nag) => {
+                    if let Some(annotation) = annotations.last_mut() {
+                        annotation.nags.push(nag);
+                    }
+                }
+                MovetextToken::Comment(comment) => match annotations.last_mut() {
+                    Some(annotation) => annotation.comments.push(comment),
+                    None => opening_comments.push(comment),
+                },
+                MovetextToken::Result(result) => movetext_result = Some(result),
+            }
+        }
+        let result = match (tagged_result, movetext_result) {
+            (Some(tagged), Some(ending)) if tagged != ending => {
+                return Err(format!(
+                    "The result tag {} contradicts the result {} of the movetext",
+                    tagged.notation(),
+                    ending.notation()
+                )
+                .into())
+            }
+            (tagged, ending) => tagged.or(ending).unwrap_or(GameResult::Unfinished),
+        };
+        let moves = replay_notation(&start, notations.iter().map(String::as_str)).await?;
+        let clocks = annotations
+            .iter()
+            .map_while(|annotation| annotation.comments.iter().find_map(|c| clock_of(c)))
+            .collect();
+        Ok(PgnGame {
+            tags,
+            game: GameRecord {
+                start,
+                moves,
+                clocks,
+                result,
+            },
+            opening_comments,
+            annotations,
+        })
+    }
+
+    /// The value of the tag with the given name
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(tag, _)| tag == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Reads all the games of a pgn file, the errors are kept in the place of the games that
+    /// could not be read
+    pub async fn parse_all(pgn: &str) -> Vec<IntResult<PgnGame>> {
+        let mut games = Vec::new();
+        for game in split_pgn(pgn) {
+            games.push(PgnGame::parse(game).await);
+        }
+        games
+    }
+}
+
+impl GameRecord {
+    /// Reads a single game of a pgn file without its tags, comments and annotation glyphs, see
+    /// `PgnGame::parse`
+    ///
+    /// # Errors
+    /// When the starting position or a move is not valid, or the result is unknown
+    pub async fn from_pgn(pgn: &str) -> IntResult<GameRecord> {
+        Ok(PgnGame::parse(pgn).await?.game)
+    }
+
+    /// The pgn of the game with the seven tag roster, see `GameRecord::to_pgn_with`
+    pub fn to_pgn(&self) -> String {
+        self.to_pgn_with(&PgnExport::default())
+    }
+
+    /// The pgn of the game: the seven tag roster, the starting position unless it is the
+    /// standard one, the other tags, and the moves in short algebraic notation wrapped to 80
+    /// columns. The evaluations and the clocks are written in a comment after each move.
+    ///
+    /// # Example
+    /// ```
+    /// use dbce::baserules::board::PSBoard;
+    /// use dbce::baserules::board_rep::PossibleMove;
+    /// use dbce::human_facing::game::{GameRecord, GameResult};
+    /// let game = GameRecord {
+    ///     start: PSBoard::default(),
+    ///     moves: vec![PossibleMove::simple_from_uci("e2e4").unwrap()],
+    ///     clocks: Vec::new(),
+    ///     result: GameResult::Unfinished,
+    /// };
+    /// assert!(game.to_pgn().ends_with("[Result \"*\"]\n\n1. e4 *\n"));
+    /// ```
+    pub fn to_pgn_with(&self, export: &PgnExport) -> String {
+        let mut tags: Vec<(&str, String)> = SEVEN_TAG_ROSTER
+            .iter()
+            .map(|(name, unknown)| {
+                let value = match *name {
+                    "Result" => self.result.notation(),
+                    _ => export
+                        .tags
+                        .iter()
+                        .find(|(tag, _)| tag == name)
+                        .map_or(*unknown, |(_, value)| value.as_str()),
+                };
+                (*name, value.to_string())
+            })
+            .collect();
+        if self.start.to_fen() != PSBoard::default().to_fen() {
+            tags.push(("SetUp", "1".to_string()));
+            tags.push(("FEN", self.start.to_fen()));
+        }
+        if self.start.castling_files().chess960 {
+            tags.push(("Variant", "Chess960".to_string()));
+        }
+        for (name, value) in &export.tags {
+            if tags.iter().all(|(tag, _)| tag != name) {
+                tags.push((name, value.clone()));
+            }
+        }
+        let mut pgn = String::new();
+        for (name, value) in tags {
+            let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+            writeln!(pgn, "[{name} \"{value}\"]").unwrap();
+        }
+        pgn.push('\n');
+
+        let mut tokens = Vec::new();
+        let mut board = self.start.clone();
+        let first_move = self.start.move_count.max(1); // Some fens count the moves from 0
+        let black_starts = self.start.who_moves.mate_multiplier() < 0;
+        for (ply, a_move) in self.moves.iter().enumerate() {
+            let ply_from_white = ply + usize::from(black_starts);
+            if ply_from_white % 2 == 0 {
+                tokens.push(format!("{}.", first_move as usize + ply_from_white / 2));
+            } else if ply == 0 {
+                tokens.push(format!("{first_move}..."));
+            }
+            tokens.push(a_move.to_san(&board));
+            let mut commands = Vec::new();
+            if let Some(eval) = export.evals.get(ply) {
+                commands.push(format!("[%eval {eval:.2}]"));
+            }
+            if let Some(clock) = self.clocks.get(ply) {
+                let tenths = clock.as_millis() / 100;
+                let seconds = tenths / 10;
+                let mut clock = format!(
+                    "[%clk {}:{:02}:{:02}",
+                    seconds / 3600,
+                    seconds / 60 % 60,
+                    seconds % 60
+                );
+                if tenths % 10 != 0 {
+                    write!(clock, ".{}", tenths % 10).unwrap();
+                }
+                clock.push(']');
+                commands.push(clock);
+            }
+            if !commands.is_empty() {
+                tokens.push(format!("{{{}}}", commands.join(" ")));
+            }
+            board.make_move_in_place(a_move);
+        }
+        tokens.push(self.result.notation().to_string());
+        let mut line = String::new();
+        for token in tokens {
+            if !line.is_empty() && line.len() + 1 + token.len() > PGN_LINE_LENGTH {
+                pgn.push_str(&line);
+                pgn.push('\n');
+                line.clear();
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(&token);
+        }
+        pgn.push_str(&line);
+        pgn.push('\n');
+        pgn
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::baserules::board::PSBoard;
+    use crate::baserules::game::Game;
+    use crate::baserules::score::Score;
+    use crate::human_facing::game::{GameRecord, GameResult};
+    use crate::human_facing::pgn::{pgn_move_tokens, split_pgn, PgnExport, PgnGame, PgnReader};
+    use std::io::{BufReader, Read};
+    use std::time::Duration;
+    use tokio::test;
+
+    #[test]
+    async fn streams_games_past_the_broken_ones() {
+        let mut pgn = b"\n\n[Event \"First\"]\n[Result \"1-0\"]\n\n1. e4 e5 2. Qh5 Nc6 3. Bc4 Nf6\n4. Qxf7# 1-0\n\n"
+            .to_vec();
+        pgn.extend(b"[Event \"Broken \xff\"]\n\n1. e4 e5 2. Ke3 *\n\n");
+        pgn.extend(b"1. d4 d5 *\n[Event \"Last\"]\n1. c4 *");
+        let texts: Vec<_> = PgnReader::new(pgn.as_slice()).map(Result::unwrap).collect();
+        assert_eq!(
+            vec![(1, 3), (2, 9), (3, 14)],
+            texts
+                .iter()
+                .map(|game| (game.number, game.line))
+                .collect::<Vec<_>>()
+        );
+        assert!(texts[1].text.starts_with("[Event \"Broken \u{FFFD}\"]"));
+        assert_eq!("[Event \"Last\"]\n1. c4 *", texts[2].text);
+
+        let mut reader = PgnReader::new(pgn.as_slice());
+        let first = reader.next_game().await.unwrap().unwrap();
+        assert_eq!(GameResult::WhiteWon, first.game.result);
+        let broken = reader.next_game().await.unwrap().err().unwrap();
+        assert!(broken
+            .to_string()
+            .starts_with("Game 2 at line 9: Ply 3 (Ke3)"));
+        let last = reader.next_game().await.unwrap().unwrap();
+        assert_eq!(Some("Last"), last.tag("Event"));
+        assert_eq!(1, last.game.moves.len());
+        assert!(reader.next_game().await.is_none());
+    }
+
+    #[test]
+    async fn stops_at_input_errors() {
+        struct Failing;
+        impl Read for Failing {
+            fn read(&mut self, _: &mut [u8]) -> std::io::Result<usize> {
+                Err(std::io::ErrorKind::BrokenPipe.into())
+            }
+        }
+        let mut reader = PgnReader::new(BufReader::new("1. e4 *\n".as_bytes().chain(Failing)));
+        assert!(reader.next().unwrap().is_err());
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    async fn writes_pgn_games() {
+        let game = GameRecord::from_pgn(
+            "1. e4 {[%clk 0:03:00]} e5 {[%clk 0:02:59.5]} 2. Qh5 Nc6 3. Bc4 Nf6 4. Qxf7# 1-0",
+        )
+        .await
+        .unwrap();
+        let export = PgnExport {
+            tags: vec![
+                ("TimeControl".into(), "180+0".into()),
+                ("White".into(), "dbce".into()),
+                ("Event".into(), "Casual \"game\"".into()),
+            ],
+            evals: vec![Score::centipawns(30), Score::centipawns(-25)],
+        };
+        let pgn = game.to_pgn_with(&export);
+        assert_eq!(
+            r#"[Event "Casual \"game\""]
+[Site "?"]
+[Date "????.??.??"]
+[Round "?"]
+[White "dbce"]
+[Black "?"]
+[Result "1-0"]
+[TimeControl "180+0"]
+
+1. e4 {[%eval 0.30] [%clk 0:03:00]} e5 {[%eval -0.25] [%clk 0:02:59.5]} 2. Qh5
+Nc6 3. Bc4 Nf6 4. Qxf7# 1-0
+"#,
+            pgn
+        );
+        let read_back = PgnGame::parse(&pgn).await.unwrap();
+        assert_eq!(Some("Casual \"game\""), read_back.tag("Event"));
+        assert_eq!(game.moves, read_back.game.moves);
+        assert_eq!(game.clocks, read_back.game.clocks);
+        assert_eq!(GameResult::WhiteWon, read_back.game.result);
+    }
+
+    #[test]
+    async fn written_games_are_read_back() {
+        let start = PSBoard::try_from_fen("4k3/8/8/8/8/8/4P3/4K3 b - - 0 7").unwrap();
+        let moves = "Kd7 Kd2 Kd6 Kd3 Kd5 e4+ Ke5 Ke3 Kd6 Kd4 Ke6 e5 Ke7 Kd5 Kd7 e6+ Ke7 Ke5 Ke8";
+        let game = GameRecord::from_pgn(&format!("[FEN \"{}\"]\n\n{moves} *", start.to_fen()))
+            .await
+            .unwrap();
+        let pgn = game.to_pgn();
+        assert!(pgn.contains("[SetUp \"1\"]\n[FEN \"4k3/8/8/8/8/8/4P3/4K3 b - - 0 7\"]\n"));
+        assert!(pgn.contains("\n\n7... Kd7 8. Kd2 Kd6"));
+        assert!(pgn.lines().all(|line| line.len() <= 80));
+        let read_back = GameRecord::from_pgn(&pgn).await.unwrap();
+        assert_eq!(start.to_fen(), read_back.start.to_fen());
+        assert_eq!(game.moves, read_back.moves);
+
+        let mut chess960 = GameRecord::from_pgn("1. e4 *").await.unwrap();
+        chess960.start = PSBoard::chess960(0);
+        chess960.moves.clear();
+        let read_back = PgnGame::parse(&chess960.to_pgn()).await.unwrap();
+        assert_eq!(Some("Chess960"), read_back.tag("Variant"));
+        assert!(read_back.game.start.castling_files().chess960);
+    }
+
+    #[test]
+    async fn played_games_are_written() {
+        let mut played = Game::new(PSBoard::default()).await;
+        for uci in ["f2f3", "e7e5", "g2g4", "d8h4"] {
+            let a_move = played.board().find_uci_move(uci).unwrap();
+            played.make_move(&a_move).await.unwrap();
+        }
+        let pgn = GameRecord::from(&played).to_pgn();
+        assert!(pgn.contains("[Result \"0-1\"]"));
+        assert!(pgn.ends_with("\n\n1. f3 e5 2. g4 Qh4# 0-1\n"));
+    }
+
+    #[test]
+    async fn reads_pgn_games() {
+        let pgn = r#"[Event "Casual game"]
+[Result "1-0"]
+
+1. e4 {[%clk 0:03:00]} e5 2. Qh5?! (2. Nf3 Nc6) Nc6 3. Bc4 Nf6?? 4. Qxf7# $1 1-0
+
+[Event "Second"]
+[Result "*"]
+
+1. Nf3 Nf6 2. g3 g6 3. Bg2 Bg7 *
+"#;
+        let games = split_pgn(pgn);
+        assert_eq!(2, games.len());
+        let first = GameRecord::from_pgn(games[0]).await.unwrap();
+        assert_eq!(7, first.moves.len());
+        assert_eq!("h5f7", first.moves[6].to_string());
+        assert_eq!(GameResult::WhiteWon, first.result);
+        let second = GameRecord::from_pgn(games[1]).await.unwrap();
+        assert_eq!(6, second.moves.len());
+        assert_eq!(GameResult::Unfinished, second.result);
+        assert_eq!(vec!["O-O", "O-O-O"], pgn_move_tokens("12. 0-0 0-0-0 1-0"));
+        assert!(GameRecord::from_pgn("1. e4 e5 2. Ke3").await.is_err());
+    }
+
+    #[test]
+    async fn reads_tags_comments_and_glyphs() {
+        let pgn = r#"[Event "Rated \"blitz\" game"]
+[White "dbce"]
+[Black "someone"]
+[FEN "4k3/8/8/8/8/8/4P3/4K3 w - - 0 1"]
+% Not part of the game
+{The pawn wins} 1.e4 {[%clk 0:03:00.5]} 1...Kd7 {[%clk 1:00:02]} ; a side line comment
+2.Kd2!? $14 (2.e5 Ke6) Kd6 $2 { Shorter [%clk 0:00:59] } 3.Kd3 1/2-1/2"#;
+        let parsed = PgnGame::parse(pgn).await.unwrap();
+        assert_eq!(Some("Rated \"blitz\" game"), parsed.tag("Event"));
+        assert_eq!(Some("dbce"), parsed.tag("White"));
+        assert_eq!(None, parsed.tag("Result"));
+        assert_eq!(GameResult::Draw, parsed.game.result);
+        assert_eq!(vec!["The pawn wins"], parsed.opening_comments);
+        assert_eq!(
+            "e2e4 e8d7 e1d2 d7d6 d2d3",
+            parsed
+                .game
+                .moves
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(" ")
+        );
+        assert_eq!(5, parsed.annotations.len());
+        assert_eq!(
+            vec!["[%clk 1:00:02]", "a side line comment"],
+            parsed.annotations[1].comments
+        );
+        assert_eq!(vec![5, 14], parsed.annotations[2].nags);
+        assert_eq!(vec![2], parsed.annotations[3].nags);
+        // The third move has no clock, so the clock of the fourth is not kept either
+        assert_eq!(
+            vec![Duration::from_millis(180_500), Duration::from_secs(3602)],
+            parsed.game.clocks
+        );
+    }
+
+    #[test]
+    async fn bad_clocks_are_left_out() {
+        for clock in ["-5", "nan", "inf", "0:-1:00"] {
+            let pgn = format!("1. e4 {{[%clk 0:03:00]}} e5 {{[%clk {clock}]}} 2. Nf3 *");
+            let parsed = PgnGame::parse(&pgn).await.unwrap();
+            assert_eq!(3, parsed.game.moves.len());
+            assert_eq!(vec![Duration::from_secs(180)], parsed.game.clocks);
+        }
+        let mut reader = PgnReader::new("1. e4 {[%clk nan]} *".as_bytes());
+        let game = reader.next_game().await.unwrap().unwrap();
+        assert!(game.game.clocks.is_empty());
+    }
+
+    #[test]
+    async fn rejects_contradicting_results() {
+        assert!(PgnGame::parse("[Result \"1-0\"]\n\n1. e4 e5 0-1")
+            .await
+            .is_err());
+        assert!(PgnGame::parse("[Result \"2-0\"]\n\n1. e4 e5")
+            .await
+            .is_err());
+        let games = PgnGame::parse_all("1. e4 *\n\n[Event \"?\"]\n\n1. e5 *\n\n1. d4 1-0").await;
+        assert_eq!(2, games.len());
+        assert_eq!(
+            GameResult::Unfinished,
+            games[0].as_ref().unwrap().game.result
+        );
+        assert!(games[1].is_err());
+    }
+}
@@ -0,0 +1,322 @@
+/*
+ *  ========================================================================
+ *  DBCE chess bot, board renderers
+ *  ========================================================================
+ *
+ *  This file is part of DBCE.
+ *
+ *  DBCE is free software: you can redistribute it and/or
+ *  modify it under the terms of the GNU General Public License as published
+ *  by the Free Software Foundation, either version 3 of the License, or (at
+ *  your option) any later version.
+ *
+ *  DBCE is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ *  General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License along
+ *  with DBCE.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ *  (C) Copyright 2022-3, Gabor Kecskemeti
+ */
+use crate::baserules::board::PSBoard;
+use crate::baserules::board_rep::BaseMove;
+use crate::baserules::piece_color::PieceColor;
+use crate::baserules::piece_color::PieceColor::White;
+use crate::baserules::piece_kind::PieceKind::King;
+use crate::baserules::positions::AbsoluteBoardPos;
+use crate::baserules::rawboard::RawBoard;
+use std::fmt::Write;
+
+/// The settings shared by all renderers
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct RenderOptions {
+    /// Shows the file letters and rank numbers around the board
+    pub coordinates: bool,
+    /// The side whose pieces start at the bottom of the rendered board
+    pub bottom: PieceColor,
+    /// The squares of this move are highlighted
+    pub last_move: Option<BaseMove>,
+    /// Highlights the king of the side to move when it is attacked
+    pub highlight_check: bool,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        Self {
+            coordinates: true,
+            bottom: White,
+            last_move: None,
+            highlight_check: true,
+        }
+    }
+}
+
+/// How a square is emphasised in the render
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) enum Highlight {
+    None,
+    LastMove,
+    Check,
+}
+
+impl RenderOptions {
+    /// Lists the squares in drawing order: row by row from the top left corner
+    pub(crate) fn squares(&self) -> impl Iterator<Item = AbsoluteBoardPos> + '_ {
+        (0..8u8).flat_map(move |display_row| {
+            (0..8u8).map(move |display_col| {
+                if self.bottom == White {
+                    AbsoluteBoardPos(7 - display_row, display_col)
+                } else {
+                    AbsoluteBoardPos(display_row, 7 - display_col)
+                }
+            })
+        })
+    }
+
+    /// The file letters in the order they are drawn from left to right
+    fn files(&self) -> impl Iterator<Item = char> + '_ {
+        (0..8u8).map(move |display_col| {
+            let col = if self.bottom == White {
+                display_col
+            } else {
+                7 - display_col
+            };
+            (b'a' + col) as char
+        })
+    }
+
+    pub(crate) fn highlight(
+        &self,
+        board: &PSBoard,
+        in_check: bool,
+        pos: AbsoluteBoardPos,
+    ) -> Highlight {
+        let checked_king = in_check
+            && board[pos].is_some_and(|piece| piece.kind == King && piece.color == board.who_moves);
+        if checked_king {
+            Highlight::Check
+        } else {
+            self.last_move_highlight(pos)
+        }
+    }
+
+    fn last_move_highlight(&self, pos: AbsoluteBoardPos) -> Highlight {
+        if self
+            .last_move
+            .is_some_and(|last_move| last_move.from == pos || last_move.to == pos)
+        {
+            Highlight::LastMove
+        } else {
+            Highlight::None
+        }
+    }
+}
+
+pub trait BoardRenderer {
+    fn render(&self, board: &PSBoard, options: &RenderOptions) -> String;
+}
+
+/// Renders the board with plain ascii characters, using the fen letters of the pieces.
+/// The squares of the last move are put in square brackets, the checked king in angle brackets.
+///
+/// # Example
+/// ```
+/// use dbce::baserules::board::PSBoard;
+/// use dbce::human_facing::render::{AsciiRenderer, BoardRenderer, RenderOptions};
+/// let options = RenderOptions { coordinates: false, ..Default::default() };
+/// let rendered = AsciiRenderer.render(&PSBoard::default(), &options);
+/// assert_eq!(" r  n  b  q  k  b  n  r ", rendered.lines().next().unwrap());
+/// ```
+pub struct AsciiRenderer;
+
+impl AsciiRenderer {
+    /// Renders the pieces of a raw board, which has no side to move, so only the last move is
+    /// highlighted
+    pub fn render_raw(&self, raw: &RawBoard, options: &RenderOptions) -> String {
+        Self::render_squares(raw, options, |pos| options.last_move_highlight(pos))
+    }
+
+    fn render_squares(
+        raw: &RawBoard,
+        options: &RenderOptions,
+        highlight: impl Fn(AbsoluteBoardPos) -> Highlight,
+    ) -> String {
+        let file_line: String = options.files().map(|file| format!(" {file} ")).collect();
+        let mut rendered = String::new();
+        if options.coordinates {
+            writeln!(rendered, "  {file_line}").unwrap();
+        }
+        let squares: Vec<_> = options.squares().collect();
+        for row_squares in squares.chunks(8) {
+            let rank = row_squares[0].0 + 1;
+            if options.coordinates {
+                write!(rendered, "{rank} ").unwrap();
+            }
+            for pos in row_squares {
+                let piece = raw[*pos].map_or('.', |piece| piece.to_string().pop().unwrap());
+                let (open, close) = match highlight(*pos) {
+                    Highlight::None => (' ', ' '),
+                    Highlight::LastMove => ('[', ']'),
+                    Highlight::Check => ('<', '>'),
+                };
+                write!(rendered, "{open}{piece}{close}").unwrap();
+            }
+            if options.coordinates {
+                write!(rendered, " {rank}").unwrap();
+            }
+            rendered.push('\n');
+        }
+        if options.coordinates {
+            writeln!(rendered, "  {file_line}").unwrap();
+        }
+        rendered
+    }
+}
+
+impl BoardRenderer for AsciiRenderer {
+    fn render(&self, board: &PSBoard, options: &RenderOptions) -> String {
+        let in_check = options.highlight_check && board.is_in_check();
+        Self::render_squares(&board.raw, options, |pos| {
+            options.highlight(board, in_check, pos)
+        })
+    }
+}
+
+/// Renders the board as a standalone svg image, with unicode chess symbols as pieces
+pub struct SvgRenderer {
+    /// The width and height of a square in pixels
+    pub square_size: u32,
+}
+
+impl Default for SvgRenderer {
+    fn default() -> Self {
+        Self { square_size: 45 }
+    }
+}
+
+impl SvgRenderer {
+    fn square_colour(pos: AbsoluteBoardPos, highlight: Highlight) -> &'static str {
+        let light = (pos.0 + pos.1) % 2 == 1;
+        match (highlight, light) {
+            (Highlight::Check, _) => "#e06060",
+            (Highlight::LastMove, true) => "#cdd26a",
+            (Highlight::LastMove, false) => "#aaa23a",
+            (Highlight::None, true) => "#f0d9b5",
+            (Highlight::None, false) => "#b58863",
+        }
+    }
+}
+
+impl BoardRenderer for SvgRenderer {
+    fn render(&self, board: &PSBoard, options: &RenderOptions) -> String {
+        let size = self.square_size;
+        let margin = if options.coordinates { size / 2 } else { 0 };
+        let total = 8 * size + 2 * margin;
+        let in_check = options.highlight_check && board.is_in_check();
+        let mut svg = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{total}\" height=\"{total}\" viewBox=\"0 0 {total} {total}\">\n"
+        );
+        for (idx, pos) in options.squares().enumerate() {
+            let x = margin + (idx as u32 % 8) * size;
+            let y = margin + (idx as u32 / 8) * size;
+            let colour = Self::square_colour(pos, options.highlight(board, in_check, pos));
+            writeln!(
+                svg,
+                "<rect x=\"{x}\" y=\"{y}\" width=\"{size}\" height=\"{size}\" fill=\"{colour}\"/>"
+            )
+            .unwrap();
+            if let Some(piece) = board[pos] {
+                writeln!(
+                    svg,
+                    "<text x=\"{}\" y=\"{}\" font-size=\"{}\" text-anchor=\"middle\" dominant-baseline=\"central\">{}</text>",
+                    x + size / 2,
+                    y + size / 2,
+                    size * 4 / 5,
+                    piece.to_unicode()
+                )
+                .unwrap();
+            }
+        }
+        if options.coordinates {
+            let font_size = size / 3;
+            for (idx, file) in options.files().enumerate() {
+                let x = margin + idx as u32 * size + size / 2;
+                writeln!(
+                    svg,
+                    "<text x=\"{x}\" y=\"{}\" font-size=\"{font_size}\" text-anchor=\"middle\" dominant-baseline=\"central\">{file}</text>",
+                    total - margin / 2
+                )
+                .unwrap();
+            }
+            for (idx, pos) in options.squares().step_by(8).enumerate() {
+                let y = margin + idx as u32 * size + size / 2;
+                writeln!(
+                    svg,
+                    "<text x=\"{}\" y=\"{y}\" font-size=\"{font_size}\" text-anchor=\"middle\" dominant-baseline=\"central\">{}</text>",
+                    margin / 2,
+                    pos.0 + 1
+                )
+                .unwrap();
+            }
+        }
+        svg.push_str("</svg>\n");
+        svg
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::baserules::board::PSBoard;
+    use crate::baserules::board_rep::BaseMove;
+    use crate::baserules::piece_color::PieceColor::Black;
+    use crate::human_facing::render::{AsciiRenderer, BoardRenderer, RenderOptions, SvgRenderer};
+    use tokio::test;
+
+    #[test]
+    async fn ascii_orientation_and_coordinates() {
+        let board = PSBoard::default();
+        let white_view = AsciiRenderer.render(&board, &RenderOptions::default());
+        let lines: Vec<_> = white_view.lines().collect();
+        assert_eq!(10, lines.len());
+        assert_eq!("   a  b  c  d  e  f  g  h ", lines[0]);
+        assert_eq!("8  r  n  b  q  k  b  n  r  8", lines[1]);
+        assert_eq!("1  R  N  B  Q  K  B  N  R  1", lines[8]);
+
+        let options = RenderOptions {
+            bottom: Black,
+            coordinates: false,
+            ..Default::default()
+        };
+        let black_view = AsciiRenderer.render(&board, &options);
+        assert_eq!(
+            " R  N  B  K  Q  B  N  R ",
+            black_view.lines().next().unwrap()
+        );
+    }
+
+    #[test]
+    async fn highlights_last_move_and_check() {
+        let board = PSBoard::from_fen("4k3/8/8/8/8/8/8/4R1K1 b - - 1 1")
+            .await
+            .unwrap();
+        let options = RenderOptions {
+            coordinates: false,
+            last_move: Some(BaseMove::from_uci("a1e1").unwrap()),
+            ..Default::default()
+        };
+        let rendered = AsciiRenderer.render(&board, &options);
+        let lines: Vec<_> = rendered.lines().collect();
+        assert_eq!(" .  .  .  . <k> .  .  . ", lines[0]);
+        assert_eq!("[.] .  .  . [R] .  K  . ", lines[7]);
+
+        let svg = SvgRenderer::default().render(&board, &options);
+        assert_eq!(1, svg.matches("#e06060").count());
+        assert_eq!(
+            2,
+            svg.matches("#cdd26a").count() + svg.matches("#aaa23a").count()
+        );
+        assert!(svg.starts_with("<svg"));
+    }
+}
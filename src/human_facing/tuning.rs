@@ -0,0 +1,269 @@
+/*
+ *  ========================================================================
+ *  DBCE chess bot, tuning the weights of the evaluation terms
+ *  ========================================================================
+ *
+ *  This file is part of DBCE.
+ *
+ *  DBCE is free software: you can redistribute it and/or
+ *  modify it under the terms of the GNU General Public License as published
+ *  by the Free Software Foundation, either version 3 of the License, or (at
+ *  your option) any later version.
+ *
+ *  DBCE is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ *  General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License along
+ *  with DBCE.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ *  (C) Copyright 2022-3, Gabor Kecskemeti
+ */
+use crate::baserules::board::PSBoard;
+use crate::baserules::evaluation::{Evaluator, TermWeights};
+use crate::util::IntResult;
+use std::collections::BTreeMap;
+
+/// The rounds of the weight search unless set otherwise
+pub const DEFAULT_TUNING_ROUNDS: usize = 200;
+
+/// The first change tried on each weight, halved whenever none of the changes help
+const FIRST_STEP: f64 = 0.1;
+/// The search stops once the changes tried get this small
+const LAST_STEP: f64 = 0.001;
+/// The range the scaling of the scores into the expected results is searched in
+const SCALING_RANGE: (f64, f64) = (0.05, 5.0);
+
+/// A position with the result of the game it was played in: 1 when white won, 0.5 for a draw and
+/// 0 when black won
+#[derive(Debug, Clone, PartialEq)]
+pub struct LabelledPosition {
+    pub fen: String,
+    pub result: f64,
+}
+
+/// Reads the positions one per line, each a FEN followed by the result, e.g.
+/// `4k3/8/8/8/8/8/4P3/4K3 w - - 0 1 1-0`. The results may also be written as 1.0, 0.5 or 0.0,
+/// and in brackets or quotes ending with a `;`, as in the usual labelled position sets. Empty
+/// lines and the ones starting with `#` are skipped.
+///
+/// # Errors
+/// When a line does not end with a result
+pub fn read_labelled_positions(text: &str) -> IntResult<Vec<LabelledPosition>> {
+    let mut positions = Vec::new();
+    for (line_number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (fen, result) = line.rsplit_once(char::is_whitespace).unwrap_or(("", line));
+        let result = match result.trim_matches(|c| matches!(c, '[' | ']' | '"' | ';')) {
+            "1-0" => 1.0,
+            "0-1" => 0.0,
+            "1/2-1/2" => 0.5,
+            number => match number.parse::<f64>() {
+                Ok(result) if (0.0..=1.0).contains(&result) => result,
+                _ => {
+                    return Err(format!(
+                        "Line {} does not end with a game result: {line}",
+                        line_number + 1
+                    )
+                    .into())
+                }
+            },
+        };
+        positions.push(LabelledPosition {
+            fen: fen.trim().to_string(),
+            result,
+        });
+    }
+    Ok(positions)
+}
+
+/// What the tuning found
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tuning {
+    pub weights: TermWeights,
+    /// The scaling of the scores into the expected results, fitted to the untuned evaluation
+    pub scaling: f64,
+    /// The mean squared error of the expected results with the untuned weights
+    pub initial_error: f64,
+    /// The mean squared error of the expected results with the tuned weights
+    pub error: f64,
+}
+
+/// The terms of the evaluation of a position in centipawns, with the result of its game
+struct Sample {
+    terms: Vec<f64>,
+    result: f64,
+}
+
+/// The expected result of the game from the score in centipawns, the usual logistic curve of the
+/// Texel tuning
+fn expected_result(centipawns: f64, scaling: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-scaling * centipawns / 400.0))
+}
+
+fn mean_error(samples: &[Sample], weights: &[f64], scaling: f64) -> f64 {
+    let total: f64 = samples
+        .iter()
+        .map(|sample| {
+            let score = sample.terms.iter().zip(weights).map(|(t, w)| t * w).sum();
+            (sample.result - expected_result(score, scaling)).powi(2)
+        })
+        .sum();
+    total / samples.len() as f64
+}
+
+/// The scaling with the smallest error, searched by narrowing the range down to the lower error
+fn fit_scaling(samples: &[Sample], weights: &[f64]) -> f64 {
+    let (mut low, mut high) = SCALING_RANGE;
+    for _ in 0..100 {
+        let third = (high - low) / 3.0;
+        if mean_error(samples, weights, low + third) < mean_error(samples, weights, high - third) {
+            high -= third;
+        } else {
+            low += third;
+        }
+    }
+    (low + high) / 2.0
+}
+
+/// Tunes the weights of the evaluator's terms to predict the results of the positions' games by
+/// their static evaluations, the Texel method: the scores are turned into the expected results
+/// with a logistic curve fitted to the untuned scores, then each weight is moved by a step while
+/// it lowers the squared error, halving the steps when none of them helps. The positions should
+/// be quiet ones, as the static evaluation misses the pending captures.
+///
+/// # Errors
+/// When there are no positions or one of them is not a valid FEN
+pub async fn tune(
+    positions: &[LabelledPosition],
+    evaluator: &dyn Evaluator,
+    rounds: usize,
+) -> IntResult<Tuning> {
+    let mut names = Vec::new();
+    let mut samples = Vec::with_capacity(positions.len());
+    for position in positions {
+        let board = PSBoard::try_from_fen(&position.fen)?;
+        let breakdown = evaluator.explain(&board);
+        if names.is_empty() {
+            names = breakdown.terms.iter().map(|(name, _)| *name).collect();
+        }
+        samples.push(Sample {
+            terms: breakdown
+                .terms
+                .iter()
+                .map(|(_, score)| f64::from(score.as_pawns()) * 100.0)
+                .collect(),
+            result: position.result,
+        });
+    }
+    if samples.is_empty() {
+        return Err("There are no positions to tune the weights with".into());
+    }
+    let mut weights = vec![1.0; names.len()];
+    let scaling = fit_scaling(&samples, &weights);
+    let initial_error = mean_error(&samples, &weights, scaling);
+    let mut error = initial_error;
+    let mut step = FIRST_STEP;
+    for _ in 0..rounds {
+        let mut improved = false;
+        for term in 0..weights.len() {
+            for change in [step, -step] {
+                weights[term] += change;
+                let changed_error = mean_error(&samples, &weights, scaling);
+                if changed_error < error {
+                    error = changed_error;
+                    improved = true;
+                    break;
+                }
+                weights[term] -= change;
+            }
+        }
+        if !improved {
+            step /= 2.0;
+            if step < LAST_STEP {
+                break;
+            }
+        }
+    }
+    Ok(Tuning {
+        weights: TermWeights(
+            names
+                .into_iter()
+                .map(str::to_string)
+                .zip(weights)
+                .collect::<BTreeMap<_, _>>(),
+        ),
+        scaling,
+        initial_error,
+        error,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use crate::baserules::board::PSBoard;
+    use crate::baserules::evaluation::{
+        Evaluator, MaterialCount, PieceSquareTables, TermWeights, Weighted,
+    };
+    use crate::human_facing::tuning::{read_labelled_positions, tune, LabelledPosition};
+    use std::sync::Arc;
+    use tokio::test;
+
+    #[test]
+    async fn positions_are_read_with_their_results() {
+        let positions = read_labelled_positions(
+            "# quiet positions\n\
+             4k3/8/8/8/8/8/4P3/4K3 w - - 0 1 1-0\n\
+             \n\
+             4k3/4p3/8/8/8/8/8/4K3 b - - 0 1 \"1/2-1/2\";\n\
+             4k3/4p3/8/8/8/8/8/4K3 w - - 0 1 [0.0]",
+        )
+        .unwrap();
+        assert_eq!(3, positions.len());
+        assert_eq!("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1", positions[0].fen);
+        assert_eq!(
+            vec![1.0, 0.5, 0.0],
+            positions.iter().map(|p| p.result).collect::<Vec<_>>()
+        );
+        let Err(error) = read_labelled_positions("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1 white") else {
+            panic!("The result is missing");
+        };
+        assert!(error.to_string().contains("Line 1"), "{error}");
+    }
+
+    #[test]
+    async fn overrated_terms_lose_weight() {
+        // The extra pawn wins, while the centralised knight of the other positions does not
+        let labelled = |fen: &str, result| LabelledPosition {
+            fen: fen.to_string(),
+            result,
+        };
+        let positions = vec![
+            labelled("4k3/pp6/8/8/8/8/PPP5/4K3 w - - 0 1", 1.0),
+            labelled("4k3/pp6/8/8/8/8/PPP5/4K3 b - - 0 1", 1.0),
+            labelled("4k3/ppp5/8/8/8/8/PP6/4K3 w - - 0 1", 0.0),
+            labelled("4k3/8/8/3N4/8/8/8/4K1n1 w - - 0 1", 0.5),
+            labelled("4k1N1/8/8/8/3n4/8/8/4K3 w - - 0 1", 0.5),
+        ];
+        let tuning = tune(&positions, &PieceSquareTables, 100).await.unwrap();
+        assert!(tuning.error < tuning.initial_error);
+        assert!(tuning.weights.weight("piece-square") < 1.0);
+        assert!(tuning.weights.weight("material") > tuning.weights.weight("piece-square"));
+
+        let read_back: TermWeights = tuning.weights.to_string().parse().unwrap();
+        assert_eq!(
+            tuning.weights.0.keys().collect::<Vec<_>>(),
+            read_back.0.keys().collect::<Vec<_>>()
+        );
+        let weighted = Weighted {
+            base: Arc::new(MaterialCount),
+            weights: "\"material\" = 0.5".parse().unwrap(),
+        };
+        let board = PSBoard::from_fen(&positions[0].fen).await.unwrap();
+        assert_eq!(MaterialCount.eval(&board) / 2, weighted.eval(&board));
+    }
+}
@@ -70,3 +70,54 @@ impl DurationAverage {
         self.0.len()
     }
 }
+
+/// The start of the binary files written by the crate: the magic telling what kind of file it
+/// is, then the version of its format. Each format change comes with a new version, and the
+/// readers migrate the files written in the earlier versions.
+pub struct FileFormat {
+    /// What the file holds, as used in the error messages
+    pub name: &'static str,
+    pub magic: &'static [u8; 8],
+    /// The version the files are written in
+    pub version: u16,
+}
+
+impl FileFormat {
+    /// The header of the files written in the current version
+    pub fn header(&self) -> Vec<u8> {
+        let mut bytes = self.magic.to_vec();
+        bytes.extend_from_slice(&self.version.to_le_bytes());
+        bytes
+    }
+
+    /// Splits the file to the version it was written in and its contents. The files written
+    /// before the formats had versions are version 0, `legacy` tells them apart by the bytes
+    /// following their magic.
+    ///
+    /// # Errors
+    /// When the file is not of this format, or it was written in a newer version
+    pub fn read<'a>(
+        &self,
+        bytes: &'a [u8],
+        legacy: impl FnOnce(&[u8]) -> bool,
+    ) -> IntResult<(u16, &'a [u8])> {
+        let rest = bytes
+            .strip_prefix(self.magic)
+            .ok_or_else(|| format!("Not {}", self.name))?;
+        if legacy(rest) {
+            return Ok((0, rest));
+        }
+        let (version, contents) = rest
+            .split_first_chunk::<2>()
+            .ok_or_else(|| format!("Missing the format version of {}", self.name))?;
+        let version = u16::from_le_bytes(*version);
+        if version > self.version {
+            return Err(format!(
+                "Cannot read {} of format version {version}, only up to {}",
+                self.name, self.version
+            )
+            .into());
+        }
+        Ok((version, contents))
+    }
+}